@@ -0,0 +1,357 @@
+use crate::config;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use log::{debug, error};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const CACHE_FILE: &str = "responses.db";
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How API responses are cached for this invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Serve fresh cached responses, fetching and storing the rest
+    Use,
+    /// Always fetch, but store what was fetched for later
+    Refresh,
+    /// Neither read nor write the cache
+    Off,
+}
+
+/// Raw API responses stored in a SQLite database in the user cache
+/// directory, with the time each was fetched, so repeated invocations within
+/// their TTL don't hit the API again. Interactive and headless runs share
+/// it, and a response being written is never read half finished.
+pub struct Cache {
+    db: Option<PathBuf>,
+    ttl: Duration,
+    pets_ttl: Duration,
+    devices_ttl: Duration,
+    history_ttl: Duration,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS responses (
+    key TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL
+);
+-- hits and misses across every run, for `cache stats`
+CREATE TABLE IF NOT EXISTS counters (
+    name TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);
+";
+
+/// Which responses `cache clear` forgets.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum CacheKind {
+    Pets,
+    /// Devices, and the pets each flap knows
+    Devices,
+    /// Pets' feeding, drinking and movement reports
+    History,
+}
+
+impl CacheKind {
+    /// The kind of response cached under `key`.
+    fn of(key: &str) -> Option<Self> {
+        match key {
+            "pets" => Some(CacheKind::Pets),
+            key if key.starts_with("devices") => Some(CacheKind::Devices),
+            key if key.starts_with("report_") => Some(CacheKind::History),
+            _ => None,
+        }
+    }
+
+    /// A LIKE pattern matching the kind's keys.
+    fn pattern(self) -> &'static str {
+        match self {
+            CacheKind::Pets => "pets",
+            CacheKind::Devices => "devices%",
+            CacheKind::History => "report\\_%",
+        }
+    }
+}
+
+/// What `cache stats` shows.
+#[derive(Debug)]
+pub struct CacheStats {
+    pub path: PathBuf,
+    pub entries: Vec<CacheEntry>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub key: String,
+    pub bytes: usize,
+    pub age: Duration,
+    /// Older than its TTL, so it won't be used again
+    pub expired: bool,
+}
+
+impl Cache {
+    pub fn new(cfg: &config::Cache) -> Self {
+        let db = if cfg.enabled {
+            config::cache_dir()
+                .inspect_err(|e| error!("cache disabled: {}", e))
+                .ok()
+                .map(|dir| dir.join(CACHE_FILE))
+        } else {
+            None
+        };
+        let ttl = |secs: Option<u64>| Duration::from_secs(secs.unwrap_or(cfg.ttl_secs));
+        Cache {
+            db,
+            ttl: ttl(None),
+            pets_ttl: ttl(cfg.pets_ttl_secs),
+            devices_ttl: ttl(cfg.devices_ttl_secs),
+            history_ttl: ttl(cfg.history_ttl_secs),
+        }
+    }
+
+    /// The cached response for `key` and its age, if it is younger than the TTL.
+    pub fn read(&self, key: &str, mode: CacheMode) -> Option<(String, Duration)> {
+        if mode != CacheMode::Use {
+            return None;
+        }
+        let conn = self.open()?;
+        let cached: Option<(String, i64)> = conn
+            .query_row(
+                "SELECT body, fetched_at FROM responses WHERE key = ?1",
+                [key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .inspect_err(|e| error!("failed to read cached {}: {}", key, e))
+            .ok()?;
+        let fresh = cached
+            .map(|(body, fetched_at)| (body, age(fetched_at)))
+            .filter(|(_, age)| *age <= self.ttl(key));
+        count(&conn, if fresh.is_some() { "hits" } else { "misses" });
+
+        let (body, age) = fresh?;
+        debug!("Using cached {} from {}s ago", key, age.as_secs());
+        Some((body, age))
+    }
+
+    /// The last response cached under `key` however old, and when it was
+    /// fetched, for when the API can't be reached.
+    pub fn read_snapshot(&self, key: &str) -> Option<(String, DateTime<Utc>)> {
+        let conn = self.open()?;
+        let (body, fetched_at): (String, i64) = conn
+            .query_row(
+                "SELECT body, fetched_at FROM responses WHERE key = ?1",
+                [key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .inspect_err(|e| error!("failed to read cached {}: {}", key, e))
+            .ok()??;
+        Some((body, DateTime::from_timestamp_millis(fetched_at)?))
+    }
+
+    pub fn write(&self, key: &str, mode: CacheMode, text: &str) {
+        if mode == CacheMode::Off {
+            return;
+        }
+        let Some(conn) = self.open() else {
+            return;
+        };
+        if let Err(e) = conn.execute(
+            "INSERT OR REPLACE INTO responses (key, body, fetched_at) VALUES (?1, ?2, ?3)",
+            params![key, text, Utc::now().timestamp_millis()],
+        ) {
+            error!("failed to cache {}: {}", key, e);
+        }
+    }
+
+    /// Forget every cached response, after a change makes them stale.
+    pub fn clear(&self) {
+        let Some(conn) = self.open() else {
+            return;
+        };
+        if let Err(e) = conn.execute("DELETE FROM responses", []) {
+            error!("failed to clear the cache: {}", e);
+        }
+    }
+
+    /// Forget the cached responses of one kind, returning how many there were.
+    pub fn clear_kind(&self, kind: CacheKind) -> rusqlite::Result<usize> {
+        let Some(conn) = self.open() else {
+            return Ok(0);
+        };
+        conn.execute(
+            "DELETE FROM responses WHERE key LIKE ?1 ESCAPE '\\'",
+            [kind.pattern()],
+        )
+    }
+
+    /// Every cached response, oldest first, and the hit and miss counters.
+    /// None when the cache is disabled.
+    pub fn stats(&self) -> rusqlite::Result<Option<CacheStats>> {
+        let (Some(path), Some(conn)) = (&self.db, self.open()) else {
+            return Ok(None);
+        };
+        let mut stmt = conn
+            .prepare("SELECT key, length(body), fetched_at FROM responses ORDER BY fetched_at")?;
+        let entries = stmt
+            .query_map([], |row| {
+                let key: String = row.get(0)?;
+                let age = age(row.get(2)?);
+                Ok(CacheEntry {
+                    expired: age > self.ttl(&key),
+                    key,
+                    bytes: row.get(1)?,
+                    age,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        let counter = |name: &str| {
+            conn.query_row(
+                "SELECT value FROM counters WHERE name = ?1",
+                [name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::unwrap_or_default)
+        };
+        Ok(Some(CacheStats {
+            path: path.clone(),
+            entries,
+            hits: counter("hits")?,
+            misses: counter("misses")?,
+        }))
+    }
+
+    /// How long the response cached under `key` stays fresh.
+    pub fn ttl(&self, key: &str) -> Duration {
+        match CacheKind::of(key) {
+            Some(CacheKind::Pets) => self.pets_ttl,
+            Some(CacheKind::Devices) => self.devices_ttl,
+            Some(CacheKind::History) => self.history_ttl,
+            None => self.ttl,
+        }
+    }
+
+    fn open(&self) -> Option<Connection> {
+        let conn = Connection::open(self.db.as_ref()?)
+            .and_then(|conn| {
+                // another run may be writing, such as the daemon
+                conn.busy_timeout(BUSY_TIMEOUT)?;
+                conn.execute_batch(SCHEMA)?;
+                Ok(conn)
+            })
+            .inspect_err(|e| error!("failed to open the cache: {}", e));
+        conn.ok()
+    }
+}
+
+/// How long ago `fetched_at`, in milliseconds since the epoch, was.
+fn age(fetched_at: i64) -> Duration {
+    Duration::from_millis((Utc::now().timestamp_millis() - fetched_at).max(0) as u64)
+}
+
+fn count(conn: &Connection, counter: &str) {
+    if let Err(e) = conn.execute(
+        "INSERT INTO counters (name, value) VALUES (?1, 1)
+         ON CONFLICT (name) DO UPDATE SET value = value + 1",
+        [counter],
+    ) {
+        error!("failed to count cache {}: {}", counter, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str) -> Cache {
+        let dir = std::env::temp_dir().join(format!("rusty_pet_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Cache {
+            db: Some(dir.join(CACHE_FILE)),
+            ttl: Duration::from_secs(60),
+            pets_ttl: Duration::from_secs(1),
+            devices_ttl: Duration::from_secs(2),
+            history_ttl: Duration::from_secs(3),
+        }
+    }
+
+    fn keys(cache: &Cache) -> Vec<String> {
+        let mut keys: Vec<String> = cache
+            .stats()
+            .unwrap()
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|e| e.key)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    fn remove(cache: Cache) {
+        let _ = std::fs::remove_dir_all(cache.db.unwrap().parent().unwrap());
+    }
+
+    #[test]
+    fn each_kind_of_response_has_its_own_ttl() {
+        let cache = cache("cache_ttl");
+        assert_eq!(cache.ttl("pets"), Duration::from_secs(1));
+        assert_eq!(cache.ttl("devices"), Duration::from_secs(2));
+        assert_eq!(cache.ttl("devices_42_tags"), Duration::from_secs(2));
+        assert_eq!(
+            cache.ttl("report_7_20240301_20240302"),
+            Duration::from_secs(3)
+        );
+        // anything else falls back to the general TTL
+        assert_eq!(cache.ttl("pets_photo"), Duration::from_secs(60));
+        assert_eq!(cache.ttl("reports"), Duration::from_secs(60));
+        assert_eq!(cache.ttl("timeline"), Duration::from_secs(60));
+        remove(cache);
+    }
+
+    #[test]
+    fn clearing_a_kind_leaves_the_others() {
+        let cache = cache("cache_clear_kind");
+        for key in [
+            "pets",
+            "devices",
+            "devices_42_tags",
+            "report_7_20240301_20240302",
+            // not a report, though LIKE would match it without the escape
+            "reportX",
+            "timeline",
+        ] {
+            cache.write(key, CacheMode::Use, "{}");
+        }
+
+        assert_eq!(cache.clear_kind(CacheKind::History).unwrap(), 1);
+        assert_eq!(
+            keys(&cache),
+            ["devices", "devices_42_tags", "pets", "reportX", "timeline"]
+        );
+        assert_eq!(cache.clear_kind(CacheKind::Devices).unwrap(), 2);
+        assert_eq!(keys(&cache), ["pets", "reportX", "timeline"]);
+        assert_eq!(cache.clear_kind(CacheKind::Pets).unwrap(), 1);
+        assert_eq!(keys(&cache), ["reportX", "timeline"]);
+
+        cache.clear();
+        assert!(keys(&cache).is_empty());
+        remove(cache);
+    }
+
+    #[test]
+    fn a_disabled_cache_clears_nothing() {
+        let mut cache = cache("cache_disabled");
+        let _ = std::fs::remove_dir_all(cache.db.take().unwrap().parent().unwrap());
+        assert_eq!(cache.clear_kind(CacheKind::Pets).unwrap(), 0);
+        assert!(cache.stats().unwrap().is_none());
+    }
+}
@@ -1,8 +1,39 @@
+use crate::api::cache::{Cache, CacheMode};
+use crate::api::throttle::Throttle;
+use crate::api::SurePetApi;
 use crate::config;
-use log::debug;
-use reqwest::StatusCode;
-use serde::Deserialize;
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+pub const PRODUCT_PET_FLAP: u32 = 3;
+pub const PRODUCT_FEEDER: u32 = 4;
+pub const PRODUCT_CAT_FLAP: u32 = 6;
+pub const PRODUCT_FEEDER_LITE: u32 = 7;
+pub const PRODUCT_WATER_STATION: u32 = 8;
+
+const BATTERY_CELL_FULL: f64 = 1.6;
+const BATTERY_CELL_LOW: f64 = 1.2;
+/// Fields that identify the household, its hardware or a pet's microchip,
+/// blanked in recorded fixtures so they can be shared in bug reports.
+const SCRUBBED_FIELDS: &[&str] = &[
+    "serial_number",
+    "mac_address",
+    "household_id",
+    "user_id",
+    "email_address",
+    "tag",
+];
 
 #[derive(Deserialize, Debug)]
 pub struct LoginResp {
@@ -11,11 +42,13 @@ pub struct LoginResp {
 
 #[derive(Deserialize, Debug)]
 pub struct Data {
+    #[allow(dead_code)]
     pub user: User,
     pub token: String,
 }
 
 #[derive(Deserialize, Debug)]
+#[allow(dead_code)]
 pub struct User {
     pub id: u32,
     pub email_address: String,
@@ -32,24 +65,839 @@ pub struct User {
     pub updated_at: String,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct DataResp<T> {
+    pub data: T,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Pet {
+    pub id: u32,
+    pub name: String,
+    pub household_id: u32,
+    pub tag_id: Option<u32>,
+    pub position: Option<PetPosition>,
+    pub photo: Option<Photo>,
+}
+
+/// A pet's profile picture, which SurePet hosts outside the API.
+#[derive(Deserialize, Debug)]
+pub struct Photo {
+    /// Where the image can be downloaded from, without a token
+    pub location: String,
+}
+
+/// What uploading a picture answers with, to point a pet at it.
+#[derive(Deserialize, Debug)]
+struct UploadedPhoto {
+    id: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PetPosition {
+    #[serde(rename = "where")]
+    pub location: Location,
+    pub since: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "i32", into = "i32")]
+pub enum Location {
+    Inside,
+    Outside,
+    /// A code this version doesn't know, kept so it can still be shown
+    Unknown(i32),
+}
+
+impl From<i32> for Location {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => Location::Inside,
+            2 => Location::Outside,
+            code => Location::Unknown(code),
+        }
+    }
+}
+
+impl From<Location> for i32 {
+    fn from(location: Location) -> Self {
+        location.code()
+    }
+}
+
+impl Location {
+    pub fn code(self) -> i32 {
+        match self {
+            Location::Inside => 1,
+            Location::Outside => 2,
+            Location::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Inside => write!(f, "inside"),
+            Location::Outside => write!(f, "outside"),
+            Location::Unknown(code) => write!(f, "unknown_{}", code),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Device {
+    pub id: u32,
+    #[serde(default)]
+    pub name: String,
+    pub product_id: u32,
+    pub serial_number: Option<String>,
+    pub mac_address: Option<String>,
+    pub status: Option<DeviceStatus>,
+    pub control: Option<DeviceControl>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeviceControl {
+    /// Pet flaps have a single curfew, cat flaps a list of them
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub curfew: Vec<Curfew>,
+}
+
+/// A daily window, in the household's local time, during which a flap locks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Curfew {
+    pub enabled: bool,
+    /// HH:MM
+    pub lock_time: String,
+    /// HH:MM
+    pub unlock_time: String,
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Curfew>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Curfew),
+        Many(Vec<Curfew>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(curfew) => vec![curfew],
+        OneOrMany::Many(curfews) => curfews,
+    })
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DeviceStatus {
+    pub battery: Option<f64>,
+    pub online: Option<bool>,
+    pub locking: Option<Locking>,
+    pub version: Option<Versions>,
+    pub signal: Option<Signal>,
+    /// Set while the hub holds newer firmware the device hasn't installed
+    pub update_available: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Versions {
+    pub device: Option<Version>,
+}
+
+/// Numbers for some products and strings for others, so kept as given.
+#[derive(Deserialize, Debug)]
+pub struct Version {
+    pub hardware: Option<serde_json::Value>,
+    pub firmware: Option<serde_json::Value>,
+}
+
+/// How well the device and its hub hear each other, in dBm.
+#[derive(Deserialize, Debug)]
+pub struct Signal {
+    pub device_rssi: Option<f64>,
+    pub hub_rssi: Option<f64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Locking {
+    pub mode: LockMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "i32", into = "i32")]
+pub enum LockMode {
+    Unlocked,
+    LockedIn,
+    LockedOut,
+    LockedAll,
+    Curfew,
+    /// A code this version doesn't know, kept so it can still be shown
+    Unknown(i32),
+}
+
+impl From<i32> for LockMode {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => LockMode::Unlocked,
+            1 => LockMode::LockedIn,
+            2 => LockMode::LockedOut,
+            3 => LockMode::LockedAll,
+            4 => LockMode::Curfew,
+            code => LockMode::Unknown(code),
+        }
+    }
+}
+
+impl From<LockMode> for i32 {
+    fn from(mode: LockMode) -> Self {
+        match mode {
+            LockMode::Curfew => 4,
+            LockMode::Unknown(code) => code,
+            mode => mode.code().unwrap_or_default(),
+        }
+    }
+}
+
+impl LockMode {
+    /// The API code for a mode that can be set on a flap; curfew is set by
+    /// configuring curfew times instead.
+    pub fn code(self) -> Option<i32> {
+        match self {
+            LockMode::Unlocked => Some(0),
+            LockMode::LockedIn => Some(1),
+            LockMode::LockedOut => Some(2),
+            LockMode::LockedAll => Some(3),
+            LockMode::Curfew | LockMode::Unknown(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for LockMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockMode::Unlocked => write!(f, "unlocked"),
+            LockMode::LockedIn => write!(f, "locked_in"),
+            LockMode::LockedOut => write!(f, "locked_out"),
+            LockMode::LockedAll => write!(f, "locked_all"),
+            LockMode::Curfew => write!(f, "curfew"),
+            LockMode::Unknown(code) => write!(f, "unknown_{}", code),
+        }
+    }
+}
+
+impl Device {
+    /// Flaps are the only devices that can restrict pets to indoors.
+    pub fn is_flap(&self) -> bool {
+        self.product_id == PRODUCT_PET_FLAP || self.product_id == PRODUCT_CAT_FLAP
+    }
+
+    pub fn is_feeder(&self) -> bool {
+        self.product_id == PRODUCT_FEEDER || self.product_id == PRODUCT_FEEDER_LITE
+    }
+
+    /// The Felaqua, which records drinking.
+    pub fn is_water_station(&self) -> bool {
+        self.product_id == PRODUCT_WATER_STATION
+    }
+
+    /// Battery level as a percentage, estimated from the voltage of the
+    /// four AA cells the same way the SurePet app does.
+    pub fn battery_percent(&self) -> Option<u8> {
+        let voltage = self.status.as_ref()?.battery?;
+        let per_cell = voltage / 4.0;
+        let percent =
+            (per_cell - BATTERY_CELL_LOW) / (BATTERY_CELL_FULL - BATTERY_CELL_LOW) * 100.0;
+        Some(percent.clamp(0.0, 100.0).round() as u8)
+    }
+
+    pub fn online(&self) -> Option<bool> {
+        self.status.as_ref()?.online
+    }
+
+    pub fn lock_mode(&self) -> Option<LockMode> {
+        Some(self.status.as_ref()?.locking.as_ref()?.mode)
+    }
+
+    pub fn firmware(&self) -> Option<String> {
+        self.version()?.firmware.as_ref().map(version_text)
+    }
+
+    pub fn hardware(&self) -> Option<String> {
+        self.version()?.hardware.as_ref().map(version_text)
+    }
+
+    fn version(&self) -> Option<&Version> {
+        self.status.as_ref()?.version.as_ref()?.device.as_ref()
+    }
+
+    pub fn signal(&self) -> Option<&Signal> {
+        self.status.as_ref()?.signal.as_ref()
+    }
+
+    /// Whether a firmware update is waiting to be installed.
+    pub fn update_pending(&self) -> bool {
+        self.status
+            .as_ref()
+            .and_then(|s| s.update_available)
+            .unwrap_or(false)
+    }
+}
+
+fn version_text(version: &serde_json::Value) -> String {
+    match version {
+        serde_json::Value::String(version) => version.clone(),
+        version => version.to_string(),
+    }
+}
+
+/// A pet's tag as a flap knows it, with the profile it has on that flap.
+#[derive(Deserialize, Debug)]
+pub struct DeviceTag {
+    pub id: u32,
+    pub profile: Option<u32>,
+}
+
+impl DeviceTag {
+    pub fn pet_profile(&self) -> Option<PetProfile> {
+        match self.profile? {
+            2 => Some(PetProfile::Outdoor),
+            3 => Some(PetProfile::IndoorOnly),
+            _ => None,
+        }
+    }
+}
+
+/// Per-pet access profile for a flap.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PetProfile {
+    #[default]
+    Outdoor = 2,
+    IndoorOnly = 3,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PetReport {
+    pub feeding: Option<ReportSection<FeedingEvent>>,
+    pub drinking: Option<ReportSection<DrinkingEvent>>,
+    pub movement: Option<ReportSection<MovementEvent>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReportSection<T: DeserializeOwned> {
+    #[serde(deserialize_with = "skip_unreadable")]
+    pub datapoints: Vec<T>,
+}
+
+/// Drop datapoints of a shape this version doesn't understand rather than
+/// failing the whole report, so new kinds of event don't break history.
+fn skip_unreadable<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let values = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| {
+            serde_json::from_value(value)
+                .inspect_err(|e| debug!("skipping unreadable datapoint: {}", e))
+                .ok()
+        })
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FeedingEvent {
+    pub from: DateTime<Utc>,
+    pub device_id: u32,
+    #[serde(default)]
+    pub weights: Vec<BowlWeight>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DrinkingEvent {
+    pub from: DateTime<Utc>,
+    pub device_id: u32,
+    #[serde(default)]
+    pub weights: Vec<BowlWeight>,
+}
+
+/// A trip outside, from leaving through a flap until coming back in.
+#[derive(Deserialize, Debug)]
+pub struct MovementEvent {
+    pub from: DateTime<Utc>,
+    /// The flap the pet went out through
+    #[serde(default)]
+    pub device_id: Option<u32>,
+    /// Missing while the pet is still outside
+    pub to: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BowlWeight {
+    pub change: f64,
+}
+
+impl FeedingEvent {
+    /// Grams eaten across all bowls; positive changes are refills, not meals.
+    pub fn grams_eaten(&self) -> f64 {
+        self.weights
+            .iter()
+            .filter(|w| w.change < 0.0)
+            .map(|w| -w.change)
+            .sum()
+    }
+}
+
+impl DrinkingEvent {
+    /// Millilitres drunk, using the 1g = 1ml weight of water.
+    pub fn ml_drunk(&self) -> f64 {
+        self.weights
+            .iter()
+            .filter(|w| w.change < 0.0)
+            .map(|w| -w.change)
+            .sum()
+    }
+}
+
+/// Whether `e` means the API couldn't be reached at all, rather than that it
+/// refused the request, so the request is worth queueing or serving from the
+/// cache instead.
+pub fn is_unreachable(e: &reqwest::Error) -> bool {
+    e.is_connect() || e.is_timeout() || e.status().is_some_and(|s| matches!(s.as_u16(), 502..=504))
+}
+
+/// [`is_unreachable`] for a request's error passed on as an `io::Error`.
+pub fn is_unreachable_io(e: &std::io::Error) -> bool {
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<reqwest::Error>())
+        .is_some_and(is_unreachable)
+}
+
+/// Blank every scalar [SCRUBBED_FIELDS] in `value`, keeping its type so
+/// the fixture still decodes.
+fn scrub(value: &mut serde_json::Value) {
+    use serde_json::Value;
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                match field {
+                    Value::String(_) if SCRUBBED_FIELDS.contains(&key.as_str()) => {
+                        *field = Value::from("redacted")
+                    }
+                    Value::Number(_) if SCRUBBED_FIELDS.contains(&key.as_str()) => {
+                        *field = Value::from(0)
+                    }
+                    _ => scrub(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(scrub),
+        _ => {}
+    }
+}
+
+/// An HTTP client that also trusts the certificates in `ca_bundle`, for
+/// proxies that intercept TLS with their own certificate authority.
+fn http_client(cfg: &config::Api) -> std::io::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(path) = &cfg.ca_bundle {
+        let pem = fs::read(path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("reading [api] ca_bundle {}: {}", path.display(), e),
+            )
+        })?;
+        let invalid = |reason: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid [api] ca_bundle {}: {}", path.display(), reason),
+            )
+        };
+        let certificates =
+            reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| invalid(e.to_string()))?;
+        if certificates.is_empty() {
+            return Err(invalid("no PEM certificates in it".to_string()));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    builder.build().map_err(io::Error::other)
+}
+
+/// Counters for the API requests a client has made.
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct RequestStats {
+    pub requests: u32,
+    pub failures: u32,
+    pub latency_ms: u128,
+    /// Responses served from the cache instead of the API
+    pub cache_hits: u32,
+    /// Age of the oldest cached response served
+    pub cache_age_secs: Option<u64>,
+}
+
 pub struct Client {
     pub client: reqwest::Client,
     pub cfg: config::Config,
+    stats: Mutex<RequestStats>,
+    cache: Cache,
+    cache_mode: Mutex<CacheMode>,
+    oldest_cached: Mutex<Option<Duration>>,
+    offline_fallback: Mutex<bool>,
+    offline_as_of: Mutex<Option<DateTime<Utc>>>,
+    throttle: Throttle,
+    /// GETs being made now, which callers asking for the same share
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<Option<String>>>>>,
+    /// Where responses are saved as fixtures, for `--record`
+    record_dir: Option<PathBuf>,
+    dry_run: bool,
+    /// Requests a dry run would have sent, until the caller takes them
+    planned: Mutex<Vec<String>>,
 }
 
 impl Client {
-    pub fn new(cfg: config::Config) -> Self {
-        Client {
-            client: reqwest::Client::new(),
+    /// Fails when [api] ca_bundle can't be read or holds no certificates.
+    pub fn new(cfg: config::Config) -> std::io::Result<Self> {
+        Ok(Client {
+            client: http_client(&cfg.api)?,
+            cache: Cache::new(&cfg.cache),
+            stats: Mutex::new(RequestStats::default()),
+            cache_mode: Mutex::new(CacheMode::Use),
+            oldest_cached: Mutex::new(None),
+            offline_fallback: Mutex::new(false),
+            offline_as_of: Mutex::new(None),
+            throttle: Throttle::new(cfg.api.requests_per_minute, cfg.api.request_burst),
+            in_flight: Mutex::new(HashMap::new()),
+            record_dir: None,
+            dry_run: false,
+            planned: Mutex::new(Vec::new()),
             cfg,
+        })
+    }
+
+    /// Save the data of every response read from now on in `dir`, as the
+    /// fixtures `MockClient` serves.
+    pub fn record_to(&mut self, dir: PathBuf) {
+        self.record_dir = Some(dir);
+    }
+
+    /// Plan the PUTs that would change anything instead of sending them,
+    /// for the caller to show from [`SurePetApi::take_planned`]. Reads still
+    /// go to the API, so commands work out the same changes.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    fn plan(&self, request: String) {
+        self.planned.lock().unwrap().push(request);
+    }
+
+    /// Save the `data` of the response `text` as the fixture `name`. Only
+    /// responses are recorded, never the token or credentials, and with
+    /// [SCRUBBED_FIELDS] blanked.
+    fn record(&self, name: &str, text: &str) {
+        let Some(dir) = &self.record_dir else {
+            return;
+        };
+        let data = serde_json::from_str::<serde_json::Value>(text)
+            .ok()
+            .and_then(|mut v| v.get_mut("data").map(serde_json::Value::take));
+        let Some(mut data) = data else {
+            return;
+        };
+        scrub(&mut data);
+        let path = dir.join(name);
+        let written = fs::create_dir_all(dir)
+            .and_then(|_| fs::write(&path, serde_json::to_string_pretty(&data).unwrap()));
+        match written {
+            Ok(()) => debug!("Recorded {}", path.display()),
+            Err(e) => warn!("failed to record {}: {}", path.display(), e),
+        }
+    }
+
+    fn record_cache_hit(&self, age: Duration) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.cache_hits += 1;
+        stats.cache_age_secs = stats.cache_age_secs.max(Some(age.as_secs()));
+
+        let mut oldest = self.oldest_cached.lock().unwrap();
+        *oldest = (*oldest).max(Some(age));
+    }
+
+    fn record_request(&self, started: Instant, resp: &Result<reqwest::Response, reqwest::Error>) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.requests += 1;
+        stats.latency_ms += started.elapsed().as_millis();
+        if !resp.as_ref().is_ok_and(|r| r.status().is_success()) {
+            stats.failures += 1;
+        }
+    }
+
+    /// GET `path`, going through the response cache when given a `cache_key`,
+    /// and saving it as the fixture `fixture` when recording.
+    async fn get<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        path: &str,
+        query: &[(&str, String)],
+        cache_key: Option<&str>,
+        fixture: &str,
+    ) -> Result<T, reqwest::Error> {
+        let cache_mode = match cache_key {
+            Some(_) => self.cache_mode(),
+            None => CacheMode::Off,
+        };
+        let cached = cache_key.and_then(|key| self.cache.read(key, cache_mode));
+        if let Some((text, age)) = cached {
+            if let Ok(value) = serde_json::from_str(&text) {
+                self.record_cache_hit(age);
+                self.record(fixture, &text);
+                return Ok(value);
+            }
+        }
+
+        let text = match self.get_shared(token, path, query).await {
+            Ok(text) => text,
+            Err(e) => return self.offline_snapshot(cache_key, e),
+        };
+        if let Some(key) = cache_key.filter(|_| cache_mode != CacheMode::Off) {
+            self.cache.write(key, cache_mode, &text);
+        }
+        self.record(fixture, &text);
+        // decode through reqwest so a bad body is still a reqwest::Error
+        reqwest::Response::from(http::Response::new(text))
+            .json::<T>()
+            .await
+    }
+
+    /// GET `path` once however many callers ask for it at the same time, so
+    /// concurrent views and fetches share one request and its response.
+    async fn get_shared(
+        &self,
+        token: &str,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<String, reqwest::Error> {
+        let key = format!("{}{:?}", path, query);
+        let flight = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let mut failure = None;
+        let text = flight
+            .get_or_init(|| async {
+                match self.get_text(token, path, query).await {
+                    Ok(text) => Some(text),
+                    Err(e) => {
+                        failure = Some(e);
+                        None
+                    }
+                }
+            })
+            .await
+            .clone();
+
+        // requests made after this one finished go to the API again
+        {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if in_flight.get(&key).is_some_and(|f| Arc::ptr_eq(f, &flight)) {
+                in_flight.remove(&key);
+            }
+        }
+
+        match (text, failure) {
+            (Some(text), _) => Ok(text),
+            (None, Some(e)) => Err(e),
+            // the request waited on failed, and its error can't be shared
+            (None, None) => self.get_text(token, path, query).await,
+        }
+    }
+
+    async fn get_text(
+        &self,
+        token: &str,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<String, reqwest::Error> {
+        let get_url: String = self.cfg.api.surepy_url.to_owned() + path;
+
+        debug!("Getting: {}", get_url);
+
+        self.send(Retry::Transient, || {
+            self.client
+                .get(&get_url)
+                .query(query)
+                .bearer_auth(token)
+                .header("Accept", "application/json")
+                .header("User-Agent", "RustyPet")
+        })
+        .await?
+        .text()
+        .await
+    }
+
+    /// The last response cached under `cache_key` in place of a request that
+    /// failed with `e`, if the API is unreachable and fallback is on.
+    fn offline_snapshot<T: DeserializeOwned>(
+        &self,
+        cache_key: Option<&str>,
+        e: reqwest::Error,
+    ) -> Result<T, reqwest::Error> {
+        let fallback = *self.offline_fallback.lock().unwrap()
+            && is_unreachable(&e)
+            && self.cache_mode() != CacheMode::Off;
+        let Some((text, fetched_at)) = cache_key
+            .filter(|_| fallback)
+            .and_then(|key| self.cache.read_snapshot(key))
+        else {
+            return Err(e);
+        };
+        let Ok(value) = serde_json::from_str(&text) else {
+            return Err(e);
+        };
+        debug!("API unreachable ({}), using data as of {}", e, fetched_at);
+        let mut as_of = self.offline_as_of.lock().unwrap();
+        *as_of = Some(as_of.map_or(fetched_at, |t| t.min(fetched_at)));
+        Ok(value)
+    }
+
+    async fn change(
+        &self,
+        method: Method,
+        token: &str,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<(), reqwest::Error> {
+        let change_url: String = self.cfg.api.surepy_url.to_owned() + path;
+
+        debug!("Sending {} to: {}", method, change_url);
+        debug!("Body to send: {}", body);
+
+        // nothing is sent, so the cache still matches the API
+        if self.dry_run {
+            self.plan(format!("{} {} {}", method, change_url, body));
+            return Ok(());
         }
+
+        self.send(Retry::Unsent, || {
+            self.client
+                .request(method.clone(), &change_url)
+                .bearer_auth(token)
+                .header("Accept", "application/json")
+                .header("User-Agent", "RustyPet")
+                .json(body)
+        })
+        .await?;
+        // cached devices, profiles and positions no longer match what the API has
+        self.cache.clear();
+        Ok(())
     }
 
-    pub async fn login(
+    /// Send the request `build` makes, retrying the failures `retry` allows
+    /// up to [api] retries times, with exponential backoff and jitter so
+    /// several clients don't retry in step. Requests wait their turn under
+    /// the rate limit, and a 429 holds back every request for as long as
+    /// its Retry-After asks.
+    async fn send(
         &self,
-        username: &String,
-        password: &String,
-    ) -> Result<LoginResp, reqwest::Error> {
+        retry: Retry,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let cfg = &self.cfg.api;
+        let mut delay = Duration::from_millis(cfg.retry_delay_ms);
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            self.throttle.acquire().await;
+            let started = Instant::now();
+            let resp = build().send().await;
+            self.record_request(started, &resp);
+            if let Ok(r) = &resp {
+                // paused even when it isn't retried, for the requests after it
+                if r.status() == StatusCode::TOO_MANY_REQUESTS {
+                    let wait = self
+                        .throttle
+                        .pause(retry_after(r.headers()).unwrap_or(delay));
+                    warn!("SurePet is rate limiting requests, waiting {:?}", wait);
+                    if attempt <= cfg.retries {
+                        delay = (delay * 2).min(Duration::from_millis(cfg.retry_max_delay_ms));
+                        continue;
+                    }
+                }
+            }
+            let resp = resp.and_then(|resp| {
+                debug!("Response Status: {:?}", resp.status());
+                resp.error_for_status()
+            });
+
+            match resp {
+                Err(e) if attempt <= cfg.retries && retry.allows(&e) => {
+                    // somewhere between half and all of the delay
+                    let wait = delay.mul_f64(0.5 + fastrand::f64() / 2.0);
+                    debug!("Request failed, retrying in {:?}: {}", wait, e);
+                    tokio::time::sleep(wait).await;
+                    delay = (delay * 2).min(Duration::from_millis(cfg.retry_max_delay_ms));
+                }
+                resp => return resp,
+            }
+        }
+    }
+}
+
+impl SurePetApi for Client {
+    fn cfg(&self) -> &config::Config {
+        &self.cfg
+    }
+
+    fn http(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn cache_mode(&self) -> CacheMode {
+        *self.cache_mode.lock().unwrap()
+    }
+
+    fn set_cache_mode(&self, mode: CacheMode) {
+        *self.cache_mode.lock().unwrap() = mode;
+    }
+
+    fn take_cache_age(&self) -> Option<Duration> {
+        self.oldest_cached.lock().unwrap().take()
+    }
+
+    fn set_offline_fallback(&self, enabled: bool) {
+        *self.offline_fallback.lock().unwrap() = enabled;
+    }
+
+    fn take_offline_as_of(&self) -> Option<DateTime<Utc>> {
+        self.offline_as_of.lock().unwrap().take()
+    }
+
+    fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn take_planned(&self) -> Vec<String> {
+        std::mem::take(&mut *self.planned.lock().unwrap())
+    }
+
+    fn cache(&self) -> Option<&Cache> {
+        Some(&self.cache)
+    }
+
+    fn stats(&self) -> RequestStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<LoginResp, reqwest::Error> {
         let uuid: String = "a1b96664-399d-4c2f-8eaa-b6b5e47c6f31".to_string();
         let post_url: String = self.cfg.api.surepy_url.to_owned() + "/auth/login";
 
@@ -58,10 +906,9 @@ impl Client {
         let mut map = HashMap::new();
         map.insert("email_address", username);
         map.insert("password", password);
-        map.insert("device_id", &uuid);
-
-        debug!("Body to post: {:?}", map);
+        map.insert("device_id", uuid.as_str());
 
+        let started = Instant::now();
         let resp = self
             .client
             .post(post_url)
@@ -74,18 +921,386 @@ impl Client {
             .header("X-Device-Id", &uuid)
             .json(&map)
             .send()
-            .await?;
+            .await;
+        self.record_request(started, &resp);
+        let resp = resp?;
 
         debug!("Response Status: {:?}", resp.status());
 
-        if resp.status() == StatusCode::OK {
-            let text = resp.text().await?;
-            debug!("Response Text: {}", &text);
-            let login_resp: LoginResp = serde_json::from_str(&text).unwrap();
+        // a body that isn't a login response is a decode error, not a panic
+        resp.error_for_status()?.json::<LoginResp>().await
+    }
+
+    async fn get_pets(&self, token: &str) -> Result<Vec<Pet>, reqwest::Error> {
+        let query = [
+            ("with[]", "position".to_string()),
+            ("with[]", "photo".to_string()),
+        ];
+        let resp: DataResp<Vec<Pet>> = self
+            .get(token, "/pet", &query, Some("pets"), "pets.json")
+            .await?;
+        Ok(resp.data)
+    }
+
+    async fn get_devices(&self, token: &str) -> Result<Vec<Device>, reqwest::Error> {
+        let query = [
+            ("with[]", "status".to_string()),
+            ("with[]", "control".to_string()),
+        ];
+        let resp: DataResp<Vec<Device>> = self
+            .get(token, "/device", &query, Some("devices"), "devices.json")
+            .await?;
+        Ok(resp.data)
+    }
+
+    async fn get_device_tags(
+        &self,
+        token: &str,
+        device_id: u32,
+    ) -> Result<Vec<DeviceTag>, reqwest::Error> {
+        let path = format!("/device/{}/tag", device_id);
+        let cache_key = format!("devices_{}_tags", device_id);
+        let fixture = format!("tags_{}.json", device_id);
+        let resp: DataResp<Vec<DeviceTag>> = self
+            .get(token, &path, &[], Some(&cache_key), &fixture)
+            .await?;
+        Ok(resp.data)
+    }
+
+    async fn get_pet_report(
+        &self,
+        token: &str,
+        pet: &Pet,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<PetReport, reqwest::Error> {
+        let path = format!(
+            "/report/household/{}/pet/{}/aggregate",
+            pet.household_id, pet.id
+        );
+        let query = [("from", from.to_rfc3339()), ("to", to.to_rfc3339())];
+        // a window ending now moves on with every call, so both ends are
+        // rounded down to the TTL for "the last 7 days" to be reused within it
+        let bucket = (self.cache.ttl("report_").as_secs() as i64).max(1);
+        let live = super::ends_now(to);
+        let stamp = |time: DateTime<Utc>| {
+            let secs = time.timestamp();
+            if live {
+                secs - secs.rem_euclid(bucket)
+            } else {
+                secs
+            }
+        };
+        let cache_key = format!("report_{}_{}_{}", pet.id, stamp(from), stamp(to));
+        let fixture = super::report_fixture(pet.id, from, to);
+        let resp: DataResp<PetReport> = self
+            .get(token, &path, &query, Some(&cache_key), &fixture)
+            .await?;
+        Ok(resp.data)
+    }
+
+    async fn set_pet_profile(
+        &self,
+        token: &str,
+        device_id: u32,
+        tag_id: u32,
+        profile: PetProfile,
+    ) -> Result<(), reqwest::Error> {
+        let path = format!("/device/{}/tag/{}", device_id, tag_id);
+        let body = serde_json::json!({ "profile": profile as u32 });
+        self.change(Method::PUT, token, &path, &body).await
+    }
+
+    async fn set_lock_mode(
+        &self,
+        token: &str,
+        device_id: u32,
+        mode: LockMode,
+    ) -> Result<(), reqwest::Error> {
+        let code = mode.code().expect("lock mode can be set directly");
+        let path = format!("/device/{}/control", device_id);
+        let body = serde_json::json!({ "locking": code });
+        self.change(Method::PUT, token, &path, &body).await
+    }
+
+    async fn set_curfew(
+        &self,
+        token: &str,
+        device: &Device,
+        curfews: &[Curfew],
+    ) -> Result<(), reqwest::Error> {
+        let path = format!("/device/{}/control", device.id);
+        // the same shapes the API reads curfews back in
+        let body = match device.product_id {
+            PRODUCT_CAT_FLAP => serde_json::json!({ "curfew": curfews }),
+            _ => serde_json::json!({ "curfew": curfews.first() }),
+        };
+        self.change(Method::PUT, token, &path, &body).await
+    }
+
+    async fn set_pet_position(
+        &self,
+        token: &str,
+        pet_id: u32,
+        location: Location,
+    ) -> Result<(), reqwest::Error> {
+        let path = format!("/pet/{}/position", pet_id);
+        let since = Utc::now().format("%Y-%m-%d %H:%M").to_string();
+        let body = serde_json::json!({ "where": location.code(), "since": since });
+        self.change(Method::POST, token, &path, &body).await
+    }
+
+    /// Uploaded first, then set as the pet's photo by its id, as the app
+    /// does.
+    async fn set_pet_photo(
+        &self,
+        token: &str,
+        pet_id: u32,
+        image: Vec<u8>,
+        content_type: &'static str,
+    ) -> Result<(), reqwest::Error> {
+        let upload_url: String = self.cfg.api.surepy_url.to_owned() + "/photo";
+        let pet_path = format!("/pet/{}", pet_id);
+        debug!("Uploading {} bytes to: {}", image.len(), upload_url);
+
+        if self.dry_run {
+            self.plan(format!(
+                "POST {} <{} bytes of {}>",
+                upload_url,
+                image.len(),
+                content_type
+            ));
+            self.plan(format!(
+                "PUT {}{} {{\"photo_id\": <the uploaded photo's id>}}",
+                self.cfg.api.surepy_url, pet_path
+            ));
+            return Ok(());
+        }
+
+        let uploaded: DataResp<UploadedPhoto> = self
+            .send(Retry::Unsent, || {
+                self.client
+                    .post(&upload_url)
+                    .bearer_auth(token)
+                    .header("Accept", "application/json")
+                    .header("User-Agent", "RustyPet")
+                    .header(reqwest::header::CONTENT_TYPE, content_type)
+                    .body(image.clone())
+            })
+            .await?
+            .json()
+            .await?;
+        let body = serde_json::json!({ "photo_id": uploaded.data.id });
+        self.change(Method::PUT, token, &pet_path, &body).await
+    }
+}
+
+/// How long a 429 response asks to wait, given in seconds or as a date.
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(secs) = value.trim().parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    (at.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// Which failures a request may be retried after.
+#[derive(Debug, Clone, Copy)]
+enum Retry {
+    /// Reads, which are safe to repeat after any transient failure
+    Transient,
+    /// Commands, repeated only when they can't have reached the API, so a
+    /// lock that timed out after being applied isn't applied again
+    Unsent,
+}
+
+impl Retry {
+    fn allows(self, e: &reqwest::Error) -> bool {
+        let status = e.status().map(|s| s.as_u16());
+        match self {
+            Retry::Transient => {
+                e.is_connect()
+                    || e.is_timeout()
+                    || e.is_request()
+                    || status.is_some_and(|s| (500..600).contains(&s))
+            }
+            // a 502 or 503 comes from a gateway that never passed it on
+            Retry::Unsent => e.is_connect() || matches!(status, Some(502 | 503)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_after_as_a_date() {
+        let at = (Utc::now() + chrono::Duration::seconds(90)).to_rfc2822();
+        let wait = retry_after(&headers(&at)).unwrap();
+        assert!(wait > Duration::from_secs(80) && wait <= Duration::from_secs(90));
+        // a date already past leaves the usual backoff delay to apply
+        assert_eq!(retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")), None);
+    }
+
+    #[test]
+    fn retry_after_missing_or_unreadable() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&headers("-5")), None);
+    }
+
+    fn status_error(status: u16) -> reqwest::Error {
+        let resp = http::Response::builder().status(status).body("").unwrap();
+        reqwest::Response::from(resp)
+            .error_for_status()
+            .unwrap_err()
+    }
 
-            return Ok(login_resp);
+    #[test]
+    fn reads_retry_server_errors_and_commands_only_gateway_ones() {
+        for status in [500, 502, 503, 504] {
+            assert!(Retry::Transient.allows(&status_error(status)), "{}", status);
         }
+        assert!(!Retry::Unsent.allows(&status_error(500)));
+        assert!(Retry::Unsent.allows(&status_error(502)));
+        assert!(Retry::Unsent.allows(&status_error(503)));
+        assert!(!Retry::Unsent.allows(&status_error(504)));
+        for status in [400, 401, 404, 429] {
+            assert!(
+                !Retry::Transient.allows(&status_error(status)),
+                "{}",
+                status
+            );
+            assert!(!Retry::Unsent.allows(&status_error(status)), "{}", status);
+        }
+    }
+
+    #[tokio::test]
+    async fn a_refused_connection_is_retried_either_way() {
+        // a port that was just free, so nothing is listening on it
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let e = reqwest::get(format!("http://127.0.0.1:{}/", port))
+            .await
+            .unwrap_err();
+        assert!(Retry::Transient.allows(&e));
+        assert!(Retry::Unsent.allows(&e));
+    }
+
+    #[tokio::test]
+    async fn a_timeout_is_retried_only_for_reads() {
+        // accepts the connection but never answers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let e = reqwest::Client::new()
+            .get(url)
+            .timeout(Duration::from_millis(100))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(Retry::Transient.allows(&e));
+        assert!(!Retry::Unsent.allows(&e));
+    }
+
+    /// Serve `body` to every request after `delay`, one connection each,
+    /// counting the requests. Returns the base URL and the count.
+    async fn slow_server(body: &'static str, delay: Duration) -> (String, Arc<Mutex<u32>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let served = Arc::new(Mutex::new(0));
+        let count = served.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                *count.lock().unwrap() += 1;
+                tokio::spawn(async move {
+                    let mut request = [0; 4096];
+                    let _ = stream.read(&mut request).await;
+                    tokio::time::sleep(delay).await;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (url, served)
+    }
+
+    fn client_for(url: String) -> Client {
+        let mut cfg = config::built_in();
+        cfg.api.surepy_url = url;
+        cfg.cache.enabled = false;
+        Client::new(cfg).unwrap()
+    }
+
+    #[tokio::test]
+    async fn concurrent_identical_gets_share_one_request() {
+        let (url, served) = slow_server(r#"{"data":[]}"#, Duration::from_millis(200)).await;
+        let client = client_for(url);
+        let query = [("page", "1".to_string())];
+
+        let (a, b, c) = tokio::join!(
+            client.get_shared("token", "/api/pet", &query),
+            client.get_shared("token", "/api/pet", &query),
+            client.get_shared("token", "/api/pet", &query),
+        );
+        assert_eq!(a.unwrap(), r#"{"data":[]}"#);
+        assert_eq!(b.unwrap(), r#"{"data":[]}"#);
+        assert_eq!(c.unwrap(), r#"{"data":[]}"#);
+        assert_eq!(*served.lock().unwrap(), 1);
+        assert!(client.in_flight.lock().unwrap().is_empty());
+
+        // finished requests aren't reused
+        client
+            .get_shared("token", "/api/pet", &query)
+            .await
+            .unwrap();
+        assert_eq!(*served.lock().unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn different_gets_are_not_shared() {
+        let (url, served) = slow_server(r#"{"data":[]}"#, Duration::from_millis(100)).await;
+        let client = client_for(url);
+        let first = [("page", "1".to_string())];
+        let second = [("page", "2".to_string())];
+
+        let (a, b, c) = tokio::join!(
+            client.get_shared("token", "/api/pet", &first),
+            client.get_shared("token", "/api/pet", &second),
+            client.get_shared("token", "/api/device", &first),
+        );
+        assert!(a.is_ok() && b.is_ok() && c.is_ok());
+        assert_eq!(*served.lock().unwrap(), 3);
+    }
 
-        return Err(resp.error_for_status().err().unwrap());
+    #[tokio::test]
+    async fn a_login_response_without_a_token_is_an_error() {
+        let (url, _) = slow_server(r#"{"data":{}}"#, Duration::ZERO).await;
+        let e = client_for(url).login("tom", "secret").await.unwrap_err();
+        assert!(e.is_decode());
     }
 }
@@ -0,0 +1,288 @@
+use crate::api::client::{
+    Curfew, Device, DeviceTag, Location, LockMode, LoginResp, Pet, PetProfile, PetReport,
+};
+use crate::api::{self, SurePetApi};
+use crate::config::Config;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Serves the household from JSON files in [api] mock_dir, or saved with
+/// `--record` and given to `--replay`, instead of the API. Changes are only
+/// logged, so commands can be tried and bugs reproduced without an account
+/// or a network.
+pub struct MockClient {
+    cfg: Config,
+    http: reqwest::Client,
+    dir: PathBuf,
+}
+
+impl MockClient {
+    pub fn new(cfg: Config, dir: PathBuf) -> Self {
+        if !dir.is_dir() {
+            error!(
+                "{} isn't a directory, so every fixture is missing and the household is empty",
+                dir.display()
+            );
+        }
+        MockClient {
+            cfg,
+            http: reqwest::Client::new(),
+            dir,
+        }
+    }
+
+    /// Decode `file`, or `missing` when there's no such fixture. A fixture
+    /// that is there but can't be read fails like a server error.
+    async fn load<T: DeserializeOwned>(
+        &self,
+        file: &str,
+        missing: &str,
+    ) -> Result<T, reqwest::Error> {
+        let path = self.dir.join(file);
+        debug!("Reading fixture: {}", path.display());
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => missing.to_string(),
+            Err(e) => {
+                error!("Failed to read fixture {}: {}", path.display(), e);
+                let response = http::Response::builder()
+                    .status(500)
+                    .body(String::new())
+                    .expect("a bare 500 response builds");
+                return Err(reqwest::Response::from(response)
+                    .error_for_status()
+                    .unwrap_err());
+            }
+        };
+        // decode through reqwest so a bad fixture fails like a bad response
+        reqwest::Response::from(http::Response::new(text))
+            .json::<T>()
+            .await
+    }
+}
+
+impl SurePetApi for MockClient {
+    fn cfg(&self) -> &Config {
+        &self.cfg
+    }
+
+    fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    async fn login(&self, username: &str, _password: &str) -> Result<LoginResp, reqwest::Error> {
+        let login = serde_json::json!({
+            "data": {
+                "token": "mock",
+                "user": {
+                    "id": 1,
+                    "email_address": username,
+                    "first_name": "Mock",
+                    "last_name": "User",
+                    "country_id": 77,
+                    "language_id": 37,
+                    "marketing_opt_in": false,
+                    "terms_accepted": "",
+                    "weight_units": 0,
+                    "time_format": 0,
+                    "version": 1,
+                    "created_at": "",
+                    "updated_at": "",
+                },
+            },
+        });
+        Ok(serde_json::from_value(login).expect("mock login matches LoginResp"))
+    }
+
+    async fn get_pets(&self, _token: &str) -> Result<Vec<Pet>, reqwest::Error> {
+        self.load("pets.json", "[]").await
+    }
+
+    async fn get_devices(&self, _token: &str) -> Result<Vec<Device>, reqwest::Error> {
+        self.load("devices.json", "[]").await
+    }
+
+    async fn get_device_tags(
+        &self,
+        _token: &str,
+        device_id: u32,
+    ) -> Result<Vec<DeviceTag>, reqwest::Error> {
+        self.load(&format!("tags_{}.json", device_id), "[]").await
+    }
+
+    async fn get_pet_report(
+        &self,
+        _token: &str,
+        pet: &Pet,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<PetReport, reqwest::Error> {
+        // one report_<id>.json serves every window when there's no fixture
+        // for this one
+        let fixture = api::report_fixture(pet.id, from, to);
+        if self.dir.join(&fixture).exists() {
+            return self.load(&fixture, "{}").await;
+        }
+        self.load(&format!("report_{}.json", pet.id), "{}").await
+    }
+
+    async fn set_pet_profile(
+        &self,
+        _token: &str,
+        device_id: u32,
+        tag_id: u32,
+        profile: PetProfile,
+    ) -> Result<(), reqwest::Error> {
+        info!(
+            "mock: tag {} on device {} set to profile {}",
+            tag_id, device_id, profile as u32
+        );
+        Ok(())
+    }
+
+    async fn set_lock_mode(
+        &self,
+        _token: &str,
+        device_id: u32,
+        mode: LockMode,
+    ) -> Result<(), reqwest::Error> {
+        info!("mock: device {} set to {}", device_id, mode);
+        Ok(())
+    }
+
+    async fn set_curfew(
+        &self,
+        _token: &str,
+        device: &Device,
+        curfews: &[Curfew],
+    ) -> Result<(), reqwest::Error> {
+        for curfew in curfews {
+            info!(
+                "mock: device {} curfew set to {} to {}, {}",
+                device.id,
+                curfew.lock_time,
+                curfew.unlock_time,
+                if curfew.enabled { "on" } else { "off" }
+            );
+        }
+        Ok(())
+    }
+
+    async fn set_pet_position(
+        &self,
+        _token: &str,
+        pet_id: u32,
+        location: Location,
+    ) -> Result<(), reqwest::Error> {
+        info!("mock: pet {} set to {}", pet_id, location);
+        Ok(())
+    }
+
+    async fn set_pet_photo(
+        &self,
+        _token: &str,
+        pet_id: u32,
+        image: Vec<u8>,
+        content_type: &'static str,
+    ) -> Result<(), reqwest::Error> {
+        info!(
+            "mock: pet {} photo set to {} bytes of {}",
+            pet_id,
+            image.len(),
+            content_type
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use crate::config;
+    use crate::error::RustyPetError;
+    use crate::headless;
+    use clap::Parser;
+    use std::path::Path;
+
+    const PETS: &str = r#"[{"id": 1, "name": "Tom", "household_id": 9, "tag_id": 5,
+        "position": {"where": 1, "since": "2026-10-16T08:00:00Z"}}]"#;
+    const DEVICES: &str = r#"[
+        {"id": 3, "name": "Feeder", "product_id": 4, "status": {"online": true}},
+        {"id": 2, "name": "Back door", "product_id": 6,
+            "status": {"online": true, "battery": 5.6},
+            "control": {"curfew": {"enabled": true, "lock_time": "22:00", "unlock_time": "07:00"}}}
+    ]"#;
+
+    /// A fresh fixtures directory holding `files`, as (name, contents).
+    fn fixtures(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rusty_pet_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        dir
+    }
+
+    /// Run `args` as a headless command against the fixtures in `dir`.
+    async fn run(dir: &Path, args: &[&str]) -> std::io::Result<()> {
+        let cli = Cli::parse_from(std::iter::once("rusty_pet").chain(args.iter().copied()));
+        let mock = MockClient::new(config::built_in(), dir.to_path_buf());
+        let command = cli.command.expect("a command was given");
+        headless::run(&mock, "mock", command, &cli.global).await
+    }
+
+    fn household(name: &str) -> PathBuf {
+        fixtures(name, &[("pets.json", PETS), ("devices.json", DEVICES)])
+    }
+
+    #[tokio::test]
+    async fn lists_the_household_from_fixtures() {
+        let dir = household("list");
+        run(&dir, &["list"]).await.unwrap();
+        run(&dir, &["status"]).await.unwrap();
+        run(&dir, &["pet", "info", "Tom", "--json"]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unknown_names_are_not_found() {
+        let dir = household("not_found");
+        let e = run(&dir, &["pet", "info", "Jerry"]).await.unwrap_err();
+        assert!(matches!(RustyPetError::from(e), RustyPetError::NotFound(_)));
+        let e = run(&dir, &["device", "info", "Front door"])
+            .await
+            .unwrap_err();
+        assert!(matches!(RustyPetError::from(e), RustyPetError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn missing_fixtures_are_empty() {
+        let mock = MockClient::new(config::built_in(), fixtures("missing", &[]));
+        assert!(mock.get_pets("mock").await.unwrap().is_empty());
+        assert!(mock.get_devices("mock").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unreadable_fixtures_fail() {
+        let dir = fixtures("unreadable", &[]);
+        // a directory where the file should be can't be read
+        fs::create_dir(dir.join("pets.json")).unwrap();
+        let mock = MockClient::new(config::built_in(), dir);
+        let e = mock.get_pets("mock").await.unwrap_err();
+        assert_eq!(e.status().map(|s| s.as_u16()), Some(500));
+    }
+
+    #[tokio::test]
+    async fn bad_fixtures_fail_to_decode() {
+        let mock = MockClient::new(
+            config::built_in(),
+            fixtures("bad", &[("pets.json", "{not json")]),
+        );
+        assert!(mock.get_pets("mock").await.unwrap_err().is_decode());
+    }
+}
@@ -1 +1,169 @@
+pub mod cache;
 pub mod client;
+pub mod mock;
+pub mod throttle;
+
+use crate::api::cache::{Cache, CacheMode};
+use crate::api::client::{
+    Curfew, Device, DeviceTag, Location, LockMode, LoginResp, Pet, PetProfile, PetReport,
+    RequestStats,
+};
+use crate::config::Config;
+use chrono::{DateTime, Utc};
+use std::future::Future;
+use std::time::Duration;
+
+/// How near now a report window has to end to be "the last so many hours",
+/// which moves on with every call.
+const RECENT_WINDOW_SECS: i64 = 10 * 60;
+
+/// Whether a report window ending at `to` ends about now.
+pub fn ends_now(to: DateTime<Utc>) -> bool {
+    (Utc::now() - to).num_seconds().abs() <= RECENT_WINDOW_SECS
+}
+
+/// The fixture a pet's report over `from`..`to` is recorded to and replayed
+/// from. A window ending now is named by its length, so "the last 7 days"
+/// replays whenever it is asked for; any other by where it starts and ends.
+pub fn report_fixture(pet_id: u32, from: DateTime<Utc>, to: DateTime<Utc>) -> String {
+    if ends_now(to) {
+        format!("report_{}_last_{}h.json", pet_id, (to - from).num_hours())
+    } else {
+        format!(
+            "report_{}_{}_{}.json",
+            pet_id,
+            from.format("%Y%m%dT%H%M%SZ"),
+            to.format("%Y%m%dT%H%M%SZ")
+        )
+    }
+}
+
+/// What commands need from SurePet. [`client::Client`] talks to the real
+/// API and [`mock::MockClient`] serves fixtures; commands are generic over
+/// it so other transports can be dropped in.
+///
+/// Only the calls are required. The caching and offline hooks default to
+/// doing nothing, for implementations that have no cache.
+pub trait SurePetApi {
+    fn cfg(&self) -> &Config;
+
+    /// For the other requests commands make, such as webhooks and InfluxDB
+    fn http(&self) -> &reqwest::Client;
+
+    fn login(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> impl Future<Output = Result<LoginResp, reqwest::Error>> + Send;
+
+    fn get_pets(
+        &self,
+        token: &str,
+    ) -> impl Future<Output = Result<Vec<Pet>, reqwest::Error>> + Send;
+
+    fn get_devices(
+        &self,
+        token: &str,
+    ) -> impl Future<Output = Result<Vec<Device>, reqwest::Error>> + Send;
+
+    /// The pets' tags a flap knows, and each one's profile on it
+    fn get_device_tags(
+        &self,
+        token: &str,
+        device_id: u32,
+    ) -> impl Future<Output = Result<Vec<DeviceTag>, reqwest::Error>> + Send;
+
+    fn get_pet_report(
+        &self,
+        token: &str,
+        pet: &Pet,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> impl Future<Output = Result<PetReport, reqwest::Error>> + Send;
+
+    fn set_pet_profile(
+        &self,
+        token: &str,
+        device_id: u32,
+        tag_id: u32,
+        profile: PetProfile,
+    ) -> impl Future<Output = Result<(), reqwest::Error>> + Send;
+
+    fn set_lock_mode(
+        &self,
+        token: &str,
+        device_id: u32,
+        mode: LockMode,
+    ) -> impl Future<Output = Result<(), reqwest::Error>> + Send;
+
+    /// Replace a flap's curfews. Pet flaps only keep the first
+    fn set_curfew(
+        &self,
+        token: &str,
+        device: &Device,
+        curfews: &[Curfew],
+    ) -> impl Future<Output = Result<(), reqwest::Error>> + Send;
+
+    /// Say where a pet is now, as when it came through a door the flap
+    /// didn't see
+    fn set_pet_position(
+        &self,
+        token: &str,
+        pet_id: u32,
+        location: Location,
+    ) -> impl Future<Output = Result<(), reqwest::Error>> + Send;
+
+    /// Upload `image` as `content_type` and make it the pet's profile
+    /// picture
+    fn set_pet_photo(
+        &self,
+        token: &str,
+        pet_id: u32,
+        image: Vec<u8>,
+        content_type: &'static str,
+    ) -> impl Future<Output = Result<(), reqwest::Error>> + Send;
+
+    fn cache_mode(&self) -> CacheMode {
+        CacheMode::Off
+    }
+
+    /// Change how later requests use the response cache.
+    fn set_cache_mode(&self, _mode: CacheMode) {}
+
+    /// Age of the oldest cached response used since the last call, so a
+    /// view can say its data isn't live.
+    fn take_cache_age(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Serve the last cached responses, however old, when the API can't be
+    /// reached, rather than failing. Only for views that say so, never for
+    /// anything that records or acts on the state.
+    fn set_offline_fallback(&self, _enabled: bool) {}
+
+    /// When the oldest response served offline since the last call was
+    /// fetched, so a view can say its data isn't live.
+    fn take_offline_as_of(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    /// Whether changes are only planned, never sent, for `--dry-run`.
+    fn dry_run(&self) -> bool {
+        false
+    }
+
+    /// The requests a dry run planned since the last call, one line each,
+    /// for the caller to show.
+    fn take_planned(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The response cache, if there is one.
+    fn cache(&self) -> Option<&Cache> {
+        None
+    }
+
+    fn stats(&self) -> RequestStats {
+        RequestStats::default()
+    }
+}
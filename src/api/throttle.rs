@@ -0,0 +1,149 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The longest a 429 pauses requests for, whatever its Retry-After says, so
+/// a bad header can't stall the daemon for a day.
+pub const MAX_PAUSE: Duration = Duration::from_secs(300);
+
+/// A token bucket shared by every request a client makes, so polling loops
+/// and concurrent fetches stay under the rate SurePet tolerates. It holds
+/// up to `burst` tokens and gains `per_minute` of them a minute.
+pub struct Throttle {
+    state: Mutex<Bucket>,
+    burst: f64,
+    per_sec: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    /// Set after a 429, until when no request may go out
+    paused_until: Option<Instant>,
+}
+
+impl Throttle {
+    /// No limit when `per_minute` is 0.
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Throttle {
+            state: Mutex::new(Bucket {
+                tokens: burst,
+                refilled: Instant::now(),
+                paused_until: None,
+            }),
+            burst,
+            per_sec: f64::from(per_minute) / 60.0,
+        }
+    }
+
+    /// Wait until a request may be sent, and take its token.
+    pub async fn acquire(&self) {
+        if self.per_sec == 0.0 {
+            return self.wait_for_pause().await;
+        }
+        loop {
+            let wait = {
+                let mut bucket = self.state.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.per_sec).min(self.burst);
+                bucket.refilled = now;
+                match bucket.paused_until.filter(|until| *until > now) {
+                    Some(until) => until - now,
+                    None if bucket.tokens >= 1.0 => {
+                        bucket.tokens -= 1.0;
+                        return;
+                    }
+                    None => Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_sec),
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Hold back every request for `wait`, at most [`MAX_PAUSE`], after
+    /// SurePet said to slow down. Returns how long that is.
+    pub fn pause(&self, wait: Duration) -> Duration {
+        let wait = wait.min(MAX_PAUSE);
+        let mut bucket = self.state.lock().unwrap();
+        let until = Instant::now().checked_add(wait);
+        bucket.paused_until = bucket.paused_until.max(until);
+        bucket.tokens = 0.0;
+        wait
+    }
+
+    async fn wait_for_pause(&self) {
+        let until = self.state.lock().unwrap().paused_until;
+        if let Some(until) = until {
+            tokio::time::sleep_until(until.into()).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn timed(throttle: &Throttle) -> Duration {
+        let start = Instant::now();
+        throttle.acquire().await;
+        start.elapsed()
+    }
+
+    #[tokio::test]
+    async fn a_burst_goes_out_at_once_then_waits_for_a_token() {
+        // 10 tokens a second, so one more every 100ms
+        let throttle = Throttle::new(600, 3);
+        for _ in 0..3 {
+            assert!(timed(&throttle).await < Duration::from_millis(50));
+        }
+        assert!(timed(&throttle).await >= Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    async fn tokens_refill_only_up_to_the_burst() {
+        let throttle = Throttle::new(600, 2);
+        throttle.acquire().await;
+        throttle.acquire().await;
+        // long enough for 3 tokens, but the bucket holds 2
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(timed(&throttle).await < Duration::from_millis(50));
+        assert!(timed(&throttle).await < Duration::from_millis(50));
+        assert!(timed(&throttle).await >= Duration::from_millis(80));
+    }
+
+    #[tokio::test]
+    async fn no_limit_without_a_rate() {
+        let throttle = Throttle::new(0, 1);
+        for _ in 0..100 {
+            assert!(timed(&throttle).await < Duration::from_millis(50));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_pause_holds_back_requests() {
+        for throttle in [Throttle::new(0, 1), Throttle::new(6000, 5)] {
+            assert_eq!(
+                throttle.pause(Duration::from_millis(100)),
+                Duration::from_millis(100)
+            );
+            assert!(timed(&throttle).await >= Duration::from_millis(90));
+        }
+    }
+
+    #[test]
+    fn a_pause_is_capped() {
+        let throttle = Throttle::new(60, 1);
+        assert_eq!(throttle.pause(Duration::MAX), MAX_PAUSE);
+        assert_eq!(throttle.pause(Duration::from_secs(86_400)), MAX_PAUSE);
+    }
+
+    #[test]
+    fn a_shorter_pause_does_not_cut_a_longer_one() {
+        let throttle = Throttle::new(60, 1);
+        throttle.pause(Duration::from_secs(60));
+        throttle.pause(Duration::from_secs(1));
+        let until = throttle.state.lock().unwrap().paused_until.unwrap();
+        assert!(until > Instant::now() + Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::api::SurePetApi;
+use crate::error::RustyPetError;
+use crate::i18n;
+use log::debug;
+use std::env;
+use std::io::{self, BufRead, Error, ErrorKind, IsTerminal};
+
+pub const TOKEN_ENV: &str = "SUREPY_TOKEN";
+const USERNAME_ENV: &str = "SUREPY_USERNAME";
+const PASSWORD_ENV: &str = "SUREPY_PASSWORD";
+
+/// The token from SUREPY_TOKEN, or from signing in. Credentials come from
+/// SUREPY_USERNAME and SUREPY_PASSWORD when set, otherwise from prompts.
+pub async fn check_token(api_client: &impl SurePetApi) -> std::io::Result<String> {
+    // check if authentication token has been set in environment
+    if let Ok(token) = env::var(TOKEN_ENV) {
+        debug!("{} found", TOKEN_ENV);
+        return Ok(token);
+    }
+
+    // if no token, sign in with username and password then return the token
+    debug!("{} not found", TOKEN_ENV);
+    let username = match env::var(USERNAME_ENV) {
+        Ok(username) => username,
+        Err(_) => cliclack::input("Provide your username").interact()?,
+    };
+    let password = match env::var(PASSWORD_ENV) {
+        Ok(password) => password,
+        Err(_) => cliclack::password("Provide your password")
+            .mask('▪')
+            .interact()?,
+    };
+
+    let token = login(api_client, &username, &password).await?;
+
+    // Set the token in the environment for use in same session
+    env::set_var(TOKEN_ENV, &token);
+    debug!("Token ENV set");
+
+    Ok(token)
+}
+
+/// Sign in without prompting, for `login`. The password is read from stdin
+/// with `password_stdin`, otherwise from SUREPY_PASSWORD.
+pub async fn login_headless(
+    api_client: &impl SurePetApi,
+    username: Option<String>,
+    password_stdin: bool,
+) -> std::io::Result<String> {
+    let username = username
+        .or_else(|| env::var(USERNAME_ENV).ok())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                i18n::message("login_needs_username", &[("env", USERNAME_ENV)]),
+            )
+        })?;
+    let password = if password_stdin {
+        read_password_line(io::stdin().lock())?
+    } else {
+        match env::var(PASSWORD_ENV) {
+            Ok(password) => password,
+            Err(_) if io::stdin().is_terminal() => cliclack::password("Provide your password")
+                .mask('▪')
+                .interact()?,
+            Err(_) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    i18n::message("login_needs_password", &[("env", PASSWORD_ENV)]),
+                ))
+            }
+        }
+    };
+
+    login(api_client, &username, &password).await
+}
+
+async fn login(
+    api_client: &impl SurePetApi,
+    username: &str,
+    password: &str,
+) -> std::io::Result<String> {
+    let resp = api_client
+        .login(username, password)
+        .await
+        // the error names the URL, never the request body
+        .map_err(|e| match e.status() {
+            // the credentials were refused, rather than a token expiring
+            Some(status) if status.is_client_error() => Error::other(RustyPetError::Auth {
+                message: format!("failed to log in: {}", e),
+                expired: false,
+            }),
+            _ => Error::other(e),
+        })?;
+    // the cache holds the pets and devices of whoever signed in before
+    if let Some(cache) = api_client.cache() {
+        cache.clear();
+    }
+    Ok(resp.data.token)
+}
+
+/// The first line of `input`, without its line ending.
+fn read_password_line(mut input: impl BufRead) -> std::io::Result<String> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let password = line.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            i18n::message("no_password_on_stdin", &[]),
+        ));
+    }
+    Ok(password.to_string())
+}
@@ -0,0 +1,236 @@
+use crate::api::client::Device;
+use crate::config;
+use crate::range;
+use crate::sqlite::DeviceState;
+use crate::units::Units;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+/// A rise in level big enough that the batteries must have been changed,
+/// after which the levels before no longer say how fast they drain.
+pub const REPLACED_RISE: f64 = 15.0;
+/// A drain slower than this, in percent a day, is noise rather than a
+/// battery running down.
+const STEADY_RATE: f64 = 0.01;
+/// Characters in the widest bar of a discharge curve, a full battery.
+const CURVE_WIDTH: usize = 40;
+
+/// A device's recorded battery levels and how long it has left, for
+/// `battery`.
+pub struct Outlook {
+    pub device: String,
+    pub battery: u8,
+    /// Oldest first, as (time, percent)
+    pub levels: Vec<(DateTime<Utc>, f64)>,
+    /// When the batteries were last changed, if that's within the levels
+    pub replaced: Option<DateTime<Utc>>,
+    /// Negative while draining, missing without a day of levels since the
+    /// batteries were changed
+    pub percent_per_day: Option<f64>,
+    /// [notifications] battery_threshold
+    pub threshold: u8,
+    /// When it falls below `threshold`
+    pub low: Option<DateTime<Utc>>,
+    /// When it reaches 0%
+    pub flat: Option<DateTime<Utc>>,
+    /// Whether `low` is within [notifications] battery_warning_days
+    pub draining_fast: bool,
+}
+
+/// Work out how fast `device` drains from its levels since `from`. Missing
+/// when the device doesn't report a battery.
+pub fn outlook(
+    device: &Device,
+    states: &[DeviceState],
+    from: DateTime<Utc>,
+    now: DateTime<Utc>,
+    cfg: &config::Notifications,
+) -> Option<Outlook> {
+    let battery = device.battery_percent()?;
+    let levels = levels(states, device.id, from);
+    let recent = since_replaced(&levels);
+    let replaced = (recent.len() < levels.len()).then(|| recent[0].0);
+    let percent_per_day = drain_per_day(recent);
+    let down_to = |level: u8| {
+        percent_per_day.and_then(|rate| reaches(now, f64::from(battery), rate, f64::from(level)))
+    };
+    let low = down_to(cfg.battery_threshold);
+    Some(Outlook {
+        device: device.name.clone(),
+        battery,
+        replaced,
+        percent_per_day,
+        threshold: cfg.battery_threshold,
+        low,
+        flat: down_to(0),
+        draining_fast: cfg.battery_warning_days > 0
+            && low.is_some_and(|low| low - now <= Duration::days(cfg.battery_warning_days)),
+        levels,
+    })
+}
+
+/// The battery levels recorded for a device since `from`, oldest first, as
+/// (time, percent).
+pub fn levels(
+    states: &[DeviceState],
+    device_id: u32,
+    from: DateTime<Utc>,
+) -> Vec<(DateTime<Utc>, f64)> {
+    states
+        .iter()
+        .filter(|s| s.device_id == device_id && s.timestamp >= from)
+        .filter_map(|s| Some((s.timestamp, f64::from(s.battery?))))
+        .collect()
+}
+
+/// The levels since the batteries were last changed.
+pub fn since_replaced(levels: &[(DateTime<Utc>, f64)]) -> &[(DateTime<Utc>, f64)] {
+    let start = levels
+        .windows(2)
+        .rposition(|pair| pair[1].1 - pair[0].1 >= REPLACED_RISE)
+        .map_or(0, |i| i + 1);
+    &levels[start..]
+}
+
+/// The slope of a least squares line through the levels, in percent a day.
+/// Needs levels spread over at least a day to mean anything.
+pub fn drain_per_day(levels: &[(DateTime<Utc>, f64)]) -> Option<f64> {
+    let (first, last) = (levels.first()?.0, levels.last()?.0);
+    if last - first < Duration::days(1) {
+        return None;
+    }
+    let points: Vec<(f64, f64)> = levels
+        .iter()
+        .map(|(at, level)| ((*at - first).num_seconds() as f64 / 86400.0, *level))
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = points.iter().fold(0.0, |sum, (x, _)| sum + x) / n;
+    let mean_y = points.iter().fold(0.0, |sum, (_, y)| sum + y) / n;
+    let (covariance, variance) = points.iter().fold((0.0, 0.0), |(c, v), (x, y)| {
+        (c + (x - mean_x) * (y - mean_y), v + (x - mean_x).powi(2))
+    });
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// When a battery at `battery`% draining at `rate`% a day gets down to
+/// `down_to`%, missing unless it is draining and still above it, or when
+/// that's too far off to say.
+pub fn reaches(now: DateTime<Utc>, battery: f64, rate: f64, down_to: f64) -> Option<DateTime<Utc>> {
+    if rate > -STEADY_RATE || battery <= down_to {
+        return None;
+    }
+    range::days_after(now, (battery - down_to) / -rate)
+}
+
+/// The level at the end of each day in the [units] timezone, from the
+/// first one recorded up to `to`. Levels are only recorded when they
+/// change, so a quiet day carries on from the day before.
+pub fn daily(
+    levels: &[(DateTime<Utc>, f64)],
+    to: DateTime<Utc>,
+    units: &Units,
+) -> Vec<(NaiveDate, f64)> {
+    let mut days = Vec::new();
+    let Some((first, _)) = levels.first() else {
+        return days;
+    };
+    let mut date = units.day_of(first);
+    while date <= units.day_of(&to) {
+        if let Some((_, level)) = levels.iter().rev().find(|(at, _)| units.day_of(at) <= date) {
+            days.push((date, *level));
+        }
+        date += Duration::days(1);
+    }
+    days
+}
+
+/// How far off `at` is, such as "in about 12 days".
+pub fn in_days(now: DateTime<Utc>, at: DateTime<Utc>) -> String {
+    match (at - now).num_days() {
+        0 => "within a day".to_string(),
+        1 => "in about a day".to_string(),
+        days => format!("in about {} days", days),
+    }
+}
+
+/// A bar as long as `level` is full, out of [CURVE_WIDTH].
+pub fn bar(level: f64) -> String {
+    let filled = ((level.clamp(0.0, 100.0) / 100.0) * CURVE_WIDTH as f64).round() as usize;
+    format!("{}{}", "█".repeat(filled), "░".repeat(CURVE_WIDTH - filled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    /// A level a day from `start`, one for each of `levels`.
+    fn daily_levels(start: DateTime<Utc>, levels: &[f64]) -> Vec<(DateTime<Utc>, f64)> {
+        levels
+            .iter()
+            .enumerate()
+            .map(|(day, level)| (start + Duration::days(day as i64), *level))
+            .collect()
+    }
+
+    #[test]
+    fn drain_is_the_slope_in_percent_a_day() {
+        let start = at("2024-06-01T00:00:00Z");
+        let levels = daily_levels(start, &[80.0, 78.0, 76.0, 74.0]);
+        let rate = drain_per_day(&levels).unwrap();
+        assert!((rate + 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn drain_needs_a_day_of_levels() {
+        let start = at("2024-06-01T00:00:00Z");
+        assert_eq!(drain_per_day(&[]), None);
+        assert_eq!(drain_per_day(&[(start, 80.0)]), None);
+        let hours = [(start, 80.0), (start + Duration::hours(23), 79.0)];
+        assert_eq!(drain_per_day(&hours), None);
+    }
+
+    #[test]
+    fn levels_since_the_batteries_were_last_changed() {
+        let start = at("2024-06-01T00:00:00Z");
+        let levels = daily_levels(start, &[30.0, 20.0, 100.0, 95.0, 40.0, 99.0, 98.0]);
+        let recent = since_replaced(&levels);
+        assert_eq!(recent, &levels[5..]);
+        let steady = daily_levels(start, &[60.0, 70.0, 65.0]);
+        assert_eq!(since_replaced(&steady), &steady[..]);
+    }
+
+    #[test]
+    fn reaches_a_level_at_the_rate_it_drains() {
+        let now = at("2024-06-01T00:00:00Z");
+        assert_eq!(
+            reaches(now, 50.0, -2.0, 20.0),
+            Some(now + Duration::days(15))
+        );
+        assert_eq!(
+            reaches(now, 50.0, -2.0, 0.0),
+            Some(now + Duration::days(25))
+        );
+    }
+
+    #[test]
+    fn reaches_nothing_unless_draining_and_above_the_level() {
+        let now = at("2024-06-01T00:00:00Z");
+        assert_eq!(reaches(now, 50.0, 1.0, 20.0), None);
+        assert_eq!(reaches(now, 50.0, 0.0, 20.0), None);
+        assert_eq!(reaches(now, 20.0, -2.0, 20.0), None);
+        assert_eq!(reaches(now, 10.0, -2.0, 20.0), None);
+    }
+
+    #[test]
+    fn reaches_gives_up_on_a_rate_near_zero() {
+        let now = at("2024-06-01T00:00:00Z");
+        assert_eq!(reaches(now, 100.0, -STEADY_RATE / 2.0, 0.0), None);
+        assert_eq!(reaches(now, 100.0, -f64::MIN_POSITIVE, 0.0), None);
+        // as slow as counts as draining, decades away but still a time
+        let slowest = reaches(now, 100.0, -STEADY_RATE, 0.0).unwrap();
+        assert_eq!(slowest, now + Duration::days(10000));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::api::client::Device;
+use crate::api::SurePetApi;
+use std::io::{Error, ErrorKind};
+
+/// Something only some households have the devices for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// Letting pets in and out, and restricting them indoors
+    Flap,
+    /// Feeding history
+    Feeder,
+    /// Drinking history and the health check built on it
+    WaterStation,
+}
+
+/// What the household's devices can do, so actions that can't succeed are
+/// hidden or refused up front rather than failing against the API.
+#[derive(Debug, Default)]
+pub struct Capabilities {
+    pub flaps: bool,
+    pub feeders: bool,
+    pub water_stations: bool,
+}
+
+impl Capabilities {
+    pub fn from_devices(devices: &[Device]) -> Self {
+        Capabilities {
+            flaps: devices.iter().any(|d| d.is_flap()),
+            feeders: devices.iter().any(|d| d.is_feeder()),
+            water_stations: devices.iter().any(|d| d.is_water_station()),
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Flap => self.flaps,
+            Capability::Feeder => self.feeders,
+            Capability::WaterStation => self.water_stations,
+        }
+    }
+
+    pub fn require(&self, capability: Capability) -> std::io::Result<()> {
+        if self.has(capability) {
+            return Ok(());
+        }
+        let missing = match capability {
+            Capability::Flap => "no pet or cat flap, so pets can't be let in or kept in",
+            Capability::Feeder => "no feeder, so there is no feeding history",
+            Capability::WaterStation => {
+                "no water station, so there is no drinking history to check"
+            }
+        };
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("This household has {}", missing),
+        ))
+    }
+}
+
+/// Work out the household's capabilities from its devices. The device list
+/// is cached like any other response, so this rarely costs a request.
+pub async fn probe(api_client: &impl SurePetApi, token: &str) -> std::io::Result<Capabilities> {
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    Ok(Capabilities::from_devices(&devices))
+}
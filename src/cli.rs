@@ -0,0 +1,1006 @@
+use crate::api::cache::CacheKind;
+use crate::api::cache::CacheMode;
+use crate::api::client::Location;
+use crate::capabilities::Capability;
+use crate::compress::Compression;
+use crate::config::Config;
+use crate::curfew;
+use crate::events::EventType;
+use crate::export::{ExportFormat, TimeGranularity};
+use crate::filter::Filter;
+use crate::history::GroupBy;
+use crate::i18n::{self, Lang};
+use crate::output::{OutputMode, Verbosity};
+use crate::range::{self, parse_age, parse_days, parse_when, TimeRange, When};
+use crate::report::ReportFormat;
+use crate::search::DeviceSort;
+use crate::stats::StatsRange;
+use crate::subscriptions::PetEvent;
+use crate::units::Units;
+use chrono::{Duration, NaiveDate, NaiveTime};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ops::Range;
+use std::path::PathBuf;
+
+/// RustyPet - Your SurePet CLI. Runs interactively when no command is given.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Run interactively as a series of prompts instead of the full-screen
+    /// interface
+    #[arg(long)]
+    pub simple: bool,
+    #[command(flatten)]
+    pub global: GlobalArgs,
+}
+
+impl Cli {
+    /// Parse the arguments, with help and messages in the language from
+    /// `--lang` or the locale.
+    pub fn parse_localized() -> Cli {
+        let lang = Lang::from_args(std::env::args_os()).unwrap_or_else(Lang::from_env);
+        // argument validation reports its errors while parsing
+        i18n::set_lang(lang);
+        let matches = i18n::localize(Cli::command(), lang).get_matches();
+        let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        i18n::set_lang(cli.global.lang.unwrap_or(lang));
+        cli
+    }
+}
+
+/// Flags accepted by every command.
+#[derive(Args, Debug, Clone)]
+pub struct GlobalArgs {
+    /// Language for help and messages, instead of the one from LANG
+    #[arg(long, global = true, value_enum)]
+    pub lang: Option<Lang>,
+    /// Include a _meta block (API timing, request counts, partial data) in JSON output
+    #[arg(long, global = true)]
+    pub meta: bool,
+    /// Don't ask before destructive or bulk changes
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+    /// Neither read nor update the API response cache
+    #[arg(long, global = true, conflicts_with = "refresh")]
+    pub no_cache: bool,
+    /// Fetch fresh data, updating the API response cache
+    #[arg(long, global = true)]
+    pub refresh: bool,
+    /// Print only results, no confirmations of what was done
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Also print API timing and cache hits, and debug logging
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    /// Print the API calls that changes would make, without making them
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Save the API's responses in DIR as fixtures for --replay, without
+    /// the token or credentials
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Serve the fixtures saved in DIR with --record instead of calling the
+    /// API; changes are only logged
+    #[arg(long, global = true, value_name = "DIR")]
+    pub replay: Option<PathBuf>,
+}
+
+impl GlobalArgs {
+    /// Whether confirmations are skipped, by `--yes` or the config.
+    pub fn assume_yes(&self, cfg: &Config) -> bool {
+        self.yes || cfg.interactive.assume_yes
+    }
+
+    pub fn verbosity(&self) -> Verbosity {
+        if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    pub fn cache_mode(&self) -> CacheMode {
+        if self.no_cache {
+            CacheMode::Off
+        } else if self.refresh {
+            CacheMode::Refresh
+        } else {
+            CacheMode::Use
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Sign in and print the token, to be used as SUREPY_TOKEN
+    Login {
+        /// Account email, defaults to SUREPY_USERNAME
+        #[arg(long)]
+        username: Option<String>,
+        /// Read the password from the first line of stdin instead of
+        /// SUREPY_PASSWORD or a prompt
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Show every device's connection, battery and lock state
+    Status {
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputMode,
+        /// Handlebars template rendered for each device, one per line, such as
+        /// "{{device.name}}: {{device.battery}}%"
+        #[arg(long, conflicts_with = "output")]
+        template: Option<String>,
+        /// Only show these fields, comma separated, such as name,online,battery
+        #[arg(long, value_delimiter = ',', conflicts_with = "template")]
+        columns: Vec<String>,
+        /// Order the devices, instead of [headless] device_sort
+        #[arg(long, value_enum)]
+        sort: Option<DeviceSort>,
+        /// Reverse the --sort or [headless] device_sort order
+        #[arg(long)]
+        desc: bool,
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Show where every pet is
+    List {
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputMode,
+        /// Print the pets as a JSON document
+        #[arg(long, conflicts_with_all = ["output", "template", "columns"])]
+        json: bool,
+        /// Handlebars template rendered for each pet, one per line, such as
+        /// "{{pet.name}}: {{location}}"
+        #[arg(long, conflicts_with = "output")]
+        template: Option<String>,
+        /// Only show these fields, comma separated, such as name,location
+        #[arg(long, value_delimiter = ',', conflicts_with = "template")]
+        columns: Vec<String>,
+        /// Add computed fields such as minutes since the last meal, which
+        /// fetches each pet's recent feeding history
+        #[arg(long, requires = "json")]
+        enrich: bool,
+        /// Only list pets matching an expression, such as
+        /// 'location==outside AND name~"tom"'
+        #[arg(long, value_parser = Filter::parse)]
+        query: Option<Filter>,
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Find pets and devices whose name or state matches any pattern
+    #[command(args_conflicts_with_subcommands = true)]
+    Search {
+        #[command(subcommand)]
+        command: Option<SearchCommand>,
+        /// Text to look for, ignoring case
+        #[arg(required_unless_present_any = ["history", "query"])]
+        patterns: Vec<String>,
+        /// Only pets and devices matching an expression, such as
+        /// 'kind==device AND battery<30 AND name~"door"'. Fields are kind,
+        /// id, name, location, online, battery and lock
+        #[arg(long, value_parser = Filter::parse, conflicts_with = "history")]
+        query: Option<Filter>,
+        /// Also save the patterns under this name, to run again with
+        /// `search saved run`
+        #[arg(long, value_name = "NAME", conflicts_with = "history")]
+        save: Option<String>,
+        /// Treat patterns as regular expressions
+        #[arg(long, conflicts_with_all = ["fuzzy", "history"])]
+        regex: bool,
+        /// Match patterns whose letters appear in order, best matches first
+        #[arg(long, conflicts_with = "history")]
+        fuzzy: bool,
+        #[command(flatten)]
+        history: HistorySearchArgs,
+    },
+    /// Find feeding, drinking or activity events in pets' recent history
+    SearchEvents {
+        #[arg(long = "type", value_enum)]
+        event_type: EventType,
+        /// Pet name or id, every pet when not given
+        #[arg(long)]
+        pet: Option<String>,
+        /// How far back to look, such as 12h or 7d
+        #[arg(long, value_parser = parse_age, default_value = "7d", conflicts_with = "from")]
+        range: Duration,
+        #[command(flatten)]
+        between: RangeArgs,
+        /// Smallest amount, in grams, millilitres or minutes
+        #[arg(long)]
+        min_amount: Option<f64>,
+        /// Largest amount, in grams, millilitres or minutes
+        #[arg(long)]
+        max_amount: Option<f64>,
+        /// Device name or id, the bowl, fountain or flap used
+        #[arg(long)]
+        device: Option<String>,
+        /// inside or outside, for activity
+        #[arg(long, value_parser = parse_location)]
+        location: Option<Location>,
+        /// Print the events as a JSON document
+        #[arg(long)]
+        json: bool,
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Show a pet's feeding, drinking or activity history, with totals
+    History {
+        /// Pet name or id
+        pet: String,
+        /// Number of days of history to show
+        #[arg(long, default_value_t = 7, conflicts_with = "from", value_parser = parse_days)]
+        days: i64,
+        #[command(flatten)]
+        range: RangeArgs,
+        #[arg(long = "type", value_enum, default_value = "feeding")]
+        event_type: EventType,
+        /// Show totals for each day or week instead of every event
+        #[arg(long, value_enum)]
+        group_by: Option<GroupBy>,
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputMode,
+        /// Handlebars template rendered for each event, one per line, such as
+        /// "{{meal.time}} {{meal.grams}}g", "{{drink.time}} {{drink.ml}}ml"
+        /// or "{{outing.from}} {{outing.minutes}}"
+        #[arg(long, conflicts_with = "output")]
+        template: Option<String>,
+        /// Read history from a database kept by `sync` instead of the API,
+        /// to go back further than SurePet keeps history
+        #[arg(long)]
+        db: Option<PathBuf>,
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Show a pet's trips outside
+    ActivityHistory {
+        /// Pet name or id
+        pet: String,
+        /// Number of days of history to show
+        #[arg(long, default_value_t = 7, value_parser = parse_days)]
+        days: i64,
+        /// Show the time outside each day, the average and the longest
+        /// outing instead of each trip
+        #[arg(long)]
+        stats: bool,
+        /// Show when the pet uses the flaps, as a grid of hours by day of
+        /// the week
+        #[arg(long, conflicts_with = "stats")]
+        heatmap: bool,
+        /// Print the trips, statistics or heatmap as JSON
+        #[arg(long)]
+        json: bool,
+        /// Read trips from a database kept by `sync` instead of the API
+        #[arg(long)]
+        db: Option<PathBuf>,
+        #[command(flatten)]
+        page: PageArgs,
+    },
+    /// Line pets up side by side: meals, food, drinks, water and time
+    /// outside a day, calling out where they differ a lot
+    Compare {
+        /// Two or more pet names or ids
+        #[arg(required = true, num_args = 2..)]
+        pets: Vec<String>,
+        /// Number of days to compare, today included
+        #[arg(long, default_value_t = 7, value_parser = parse_days)]
+        days: i64,
+        /// Print the comparison as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage a single pet
+    Pet {
+        #[command(subcommand)]
+        command: PetCommand,
+    },
+    /// Look into a device's recorded history
+    Device {
+        #[command(subcommand)]
+        command: DeviceCommand,
+    },
+    /// Poll devices every few seconds and plot how well each hears the hub
+    /// and the hub hears it, for finding a good spot for the hub. Runs
+    /// until stopped
+    Signal {
+        /// Device name or id, defaults to every device reporting its signal
+        device: Option<String>,
+        /// Seconds between polls
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+        /// Stop after this many polls
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// Show a device's battery level day by day from the levels recorded in
+    /// [daemon] status_db, how fast it drains and how many days it has left
+    Battery {
+        /// Device name or id
+        device: String,
+        /// Days of levels to show, instead of [forecast] battery_days
+        #[arg(long, value_parser = parse_days)]
+        days: Option<i64>,
+    },
+    /// Show, set or snooze a flap's curfew
+    Curfew {
+        #[command(subcommand)]
+        command: CurfewCommand,
+    },
+    /// Show the household at a glance: where the pets are, what they've
+    /// had today, device health and curfews
+    Summary {
+        /// Print the summary as a JSON document
+        #[arg(long)]
+        json: bool,
+    },
+    /// List what is unusual about each pet right now: missed meals, long
+    /// stays inside or out, and changes in drinking
+    Anomalies {
+        /// Print the anomalies as a JSON document
+        #[arg(long)]
+        json: bool,
+    },
+    /// Estimate when the bag of food and each device's batteries will run
+    /// out, from recent use
+    Forecast {
+        /// Print the forecast as a JSON document
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check a pet's drinking trend for early signs of illness
+    Health {
+        /// Pet name or id
+        pet: String,
+    },
+    /// Export feeding and drinking history for every pet
+    #[command(args_conflicts_with_subcommands = true)]
+    Export {
+        #[command(subcommand)]
+        command: Option<ExportCommand>,
+        /// One or more formats, comma separated, all written from a single fetch
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "csv")]
+        format: Vec<ExportFormat>,
+        /// Write every format
+        #[arg(long, conflicts_with = "format")]
+        all_formats: bool,
+        /// Print a sample of each format and the estimated size instead of exporting
+        #[arg(long)]
+        preview: bool,
+        /// Records of each data type to show with --preview
+        #[arg(long, default_value_t = 5, requires = "preview")]
+        rows: usize,
+        /// Number of days of history to export
+        #[arg(long, default_value_t = 30, conflicts_with = "from", value_parser = parse_days)]
+        days: i64,
+        #[command(flatten)]
+        range: RangeArgs,
+        /// Directory to write the export files to
+        #[arg(long, default_value = ".")]
+        output_dir: PathBuf,
+        /// Also send the export to the InfluxDB server in the [influxdb] config
+        #[arg(long, conflicts_with = "preview")]
+        influxdb: bool,
+        /// Only fetch events newer than the last incremental export to the
+        /// same --output-dir, and add them to the existing csv, sqlite or
+        /// influx files. Trips still under way are left for a later run
+        #[arg(long, conflicts_with = "preview")]
+        incremental: bool,
+        /// Compress csv and json files, with gzip unless zstd is given
+        #[arg(
+            long,
+            value_enum,
+            num_args = 0..=1,
+            default_missing_value = "gzip",
+            conflicts_with = "preview"
+        )]
+        compress: Option<Compression>,
+        /// Compress csv and json files with gzip, as --compress gzip does
+        #[arg(long, conflicts_with_all = ["compress", "preview"])]
+        gzip: bool,
+        /// Decimal places to round amounts to, in the units they are written
+        /// in, at most 15 as more is beyond what the numbers hold
+        #[arg(long, value_parser = clap::value_parser!(u32).range(..=15))]
+        precision: Option<u32>,
+        /// Truncate timestamps to the second, minute or hour
+        #[arg(long, value_enum)]
+        time_granularity: Option<TimeGranularity>,
+        /// Write csv and json timestamps in UTC instead of the [units]
+        /// timezone
+        #[arg(long)]
+        utc: bool,
+        /// Pets whose history is fetched at the same time, instead of
+        /// [export] concurrency
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        concurrency: Option<u32>,
+        /// Only export the pets and devices matching an expression, such as
+        /// 'name~"tom" OR kind==device'
+        #[arg(long, value_parser = Filter::parse)]
+        query: Option<Filter>,
+    },
+    /// Write an HTML summary of every pet and device, optionally as PDF too
+    Report {
+        /// Number of days the report covers
+        #[arg(long, default_value_t = 30, conflicts_with = "from", value_parser = parse_days)]
+        days: i64,
+        #[command(flatten)]
+        range: RangeArgs,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Html)]
+        format: ReportFormat,
+        /// Where to write the report, report.html or report.md by default
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Also convert the report to PDF, which needs wkhtmltopdf installed
+        #[arg(long)]
+        pdf: bool,
+        /// Read pets' history from a database kept by `sync` instead of the API
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Write a printable HTML report on one pet for a vet visit: daily
+    /// food, water and time outside, and anything that stands out
+    VetReport {
+        /// Pet name or id
+        pet: String,
+        /// How far back the report goes, such as 30d or 90d
+        #[arg(long, value_parser = parse_age, default_value = "90d")]
+        range: Duration,
+        /// Where to write the report, <pet>-vet-report.html by default
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Also convert the report to PDF, which needs wkhtmltopdf installed
+        #[arg(long)]
+        pdf: bool,
+        /// Read the pet's history from a database kept by `sync` instead of
+        /// the API, which keeps less
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Print a short summary of the last day or week, or send it through
+    /// the configured notification backends, for a cron job
+    Digest {
+        /// The last day, the default
+        #[arg(long, conflicts_with = "weekly")]
+        daily: bool,
+        /// The last seven days
+        #[arg(long)]
+        weekly: bool,
+        /// Send the digest as a notification instead of printing it
+        #[arg(long)]
+        send: bool,
+        /// Read pets' history from a database kept by `sync` instead of the API
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Summarise door traffic, food, water and device uptime across the household
+    Stats {
+        #[arg(long, value_enum, default_value_t = StatsRange::Month)]
+        range: StatsRange,
+        /// Database the daemon records device status in, for uptime, instead
+        /// of [daemon] status_db
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// List what has changed since a past time: who moved, what locked, battery drops
+    Diff {
+        /// How long ago to compare against, such as 30m, 1h or 2d
+        #[arg(long, value_parser = parse_age)]
+        since: Duration,
+        /// Database with the exported trips and the daemon's device states,
+        /// instead of [daemon] status_db
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+    /// Add feeding, drinking and activity events newer than those already
+    /// stored to a local database, which keeps them after SurePet drops them
+    Sync {
+        /// Database to add to, the same as `export --format sqlite` writes,
+        /// instead of [daemon] history_db
+        #[arg(long)]
+        db: Option<PathBuf>,
+        /// Days of history to fetch for pets with nothing stored yet
+        #[arg(long, default_value_t = 30, value_parser = parse_days)]
+        days: i64,
+    },
+    /// Check the config, cache, token, API and devices, for when something
+    /// isn't working
+    Doctor,
+    /// Run continuously, publishing SurePet data elsewhere
+    Serve {
+        #[command(subcommand)]
+        command: ServeCommand,
+    },
+    /// Poll for state changes and send notifications to the configured webhooks
+    Watch {
+        /// Seconds between API polls
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: Option<u64>,
+        #[arg(long, value_enum, default_value_t)]
+        output: OutputMode,
+    },
+    /// Run the automation rules in rules.toml on every poll, take its
+    /// scheduled actions when they come due, and send notifications
+    Daemon {
+        /// Seconds between API polls
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: Option<u64>,
+        /// Rules file to use instead of rules.toml in the config directory
+        #[arg(long)]
+        rules: Option<PathBuf>,
+    },
+    /// List the actions scheduled in rules.toml and when the daemon next
+    /// takes each
+    Schedule {
+        /// Rules file to use instead of rules.toml in the config directory
+        #[arg(long)]
+        rules: Option<PathBuf>,
+    },
+    /// Choose which pets and events send notifications
+    Notify {
+        #[command(subcommand)]
+        command: NotifyCommand,
+    },
+    /// Changes queued while the SurePet API couldn't be reached
+    Pending {
+        #[command(subcommand)]
+        command: PendingCommand,
+    },
+    /// Inspect, clear or fill the API response cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+}
+
+/// Showing part of a long listing. Rows are skipped with `--offset`, or
+/// whole pages of `--limit` rows with `--page`.
+#[derive(Args, Debug, Clone, Copy)]
+pub struct PageArgs {
+    /// Show at most this many rows
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Skip this many rows first
+    #[arg(long, default_value_t = 0, conflicts_with = "page")]
+    pub offset: usize,
+    /// Show this page of --limit rows, counting from 1
+    #[arg(long, requires = "limit", value_parser = clap::value_parser!(u64).range(1..))]
+    pub page: Option<u64>,
+}
+
+impl PageArgs {
+    /// The rows of `items` on the requested page.
+    pub fn apply<T>(&self, items: &mut Vec<T>) {
+        let rows = self.rows(items.len());
+        items.truncate(rows.end);
+        items.drain(..rows.start);
+    }
+
+    /// The rows of `items` on the requested page, leaving `items` whole for
+    /// totals.
+    pub fn slice<'a, T>(&self, items: &'a [T]) -> &'a [T] {
+        &items[self.rows(items.len())]
+    }
+
+    /// Which of `len` rows are on the requested page.
+    fn rows(&self, len: usize) -> Range<usize> {
+        let skip = match (self.page, self.limit) {
+            (Some(page), Some(limit)) => (page as usize - 1).saturating_mul(limit),
+            _ => self.offset,
+        };
+        let start = skip.min(len);
+        let end = self
+            .limit
+            .map_or(len, |limit| start.saturating_add(limit).min(len));
+        start..end
+    }
+
+    /// At most `limit` rows when `--limit` isn't given, 0 for no limit.
+    pub fn or_limit(mut self, limit: usize) -> Self {
+        if self.limit.is_none() && limit > 0 {
+            self.limit = Some(limit);
+        }
+        self
+    }
+}
+
+/// An explicit range of history, instead of the last so many days.
+#[derive(Args, Debug, Clone, Copy)]
+pub struct RangeArgs {
+    /// Start of the range: a date such as 2024-01-01, an RFC 3339 time, how
+    /// long ago such as 3d or 12h, or today, yesterday, this-week,
+    /// last-week, this-month or last-month
+    #[arg(long, value_parser = parse_when)]
+    pub from: Option<When>,
+    /// End of the range, in the same forms, now when not given; a date
+    /// includes the whole day
+    #[arg(long, value_parser = parse_when)]
+    pub to: Option<When>,
+}
+
+impl RangeArgs {
+    /// The range asked for, `default` long when --from isn't given.
+    pub fn resolve(&self, default: Duration, units: &Units) -> std::io::Result<TimeRange> {
+        range::resolve(self.from, self.to, default, units)
+    }
+}
+
+/// Searching the events kept by `export --format sqlite` rather than the
+/// current state, so long periods can be searched without the API.
+#[derive(Args, Debug)]
+pub struct HistorySearchArgs {
+    /// Search stored events instead of current pets and devices
+    #[arg(long)]
+    pub history: bool,
+    /// Database written by `export --format sqlite`, instead of [daemon]
+    /// history_db
+    #[arg(long, requires = "history")]
+    pub db: Option<PathBuf>,
+    /// Only events on or after this date, as YYYY-MM-DD
+    #[arg(long, requires = "history")]
+    pub since: Option<NaiveDate>,
+    /// Only events on or before this date, as YYYY-MM-DD
+    #[arg(long, requires = "history")]
+    pub until: Option<NaiveDate>,
+    /// Events per page
+    #[arg(
+        long,
+        default_value_t = 50,
+        requires = "history",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub limit: u32,
+    /// Show this page of --limit events, counting from 1
+    #[arg(
+        long,
+        default_value_t = 1,
+        requires = "history",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub page: u32,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NotifyCommand {
+    /// Send notifications about these events for a pet
+    Subscribe {
+        /// Pet name or id
+        pet: String,
+        #[arg(value_enum, required = true)]
+        events: Vec<PetEvent>,
+    },
+    /// Stop notifications about these events, or all events, for a pet
+    Unsubscribe {
+        /// Pet name or id
+        pet: String,
+        #[arg(value_enum)]
+        events: Vec<PetEvent>,
+    },
+    /// Show the current subscriptions
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PendingCommand {
+    /// Show the queued changes
+    List,
+    /// Make the queued changes now, skipping any that conflict with how the
+    /// household has changed since
+    Replay,
+    /// Drop every queued change without making it
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Show what is cached, how old it is, and how often it was used
+    Stats,
+    /// Forget cached responses, every one unless a kind is given
+    Clear {
+        #[arg(value_enum)]
+        kind: Option<CacheKind>,
+    },
+    /// Fetch pets, devices and their recent history into the cache, so the
+    /// next interactive session starts without waiting on the API
+    Warm,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ExportCommand {
+    /// Print the columns or JSON structure an export produces
+    Schema {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SearchCommand {
+    /// Run, list or delete searches saved with --save
+    Saved {
+        #[command(subcommand)]
+        command: SavedSearchCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SavedSearchCommand {
+    /// Show saved searches, most used first
+    List,
+    /// Run a saved search
+    Run {
+        /// Name the search was saved as
+        name: String,
+    },
+    /// Forget a saved search
+    Delete {
+        /// Name the search was saved as
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PetCommand {
+    /// Show everything known about a pet: where it is and for how long, its
+    /// access on each flap, today's food and water, recent events and alerts
+    Info {
+        /// Pet name or id
+        pet: String,
+        /// Print it as a JSON document
+        #[arg(long)]
+        json: bool,
+    },
+    /// Keep a pet indoors on a flap
+    Restrict {
+        /// Pet name or id
+        pet: String,
+        /// Flap name or id, defaults to every flap
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// Let a pet go outdoors through a flap again
+    Allow {
+        /// Pet name or id
+        pet: String,
+        /// Flap name or id, defaults to every flap
+        #[arg(long)]
+        device: Option<String>,
+    },
+    /// The pet's profile picture
+    Photo {
+        #[command(subcommand)]
+        command: PhotoCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PhotoCommand {
+    /// Download the profile picture
+    Get {
+        /// Pet name or id
+        pet: String,
+        /// File to write, defaults to the pet's name with the picture's extension
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Upload a JPEG or PNG as the profile picture
+    Set {
+        /// Pet name or id
+        pet: String,
+        /// Picture to upload
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeviceCommand {
+    /// Show everything known about a device: hardware, battery, curfews,
+    /// the pets a flap knows, and what happened at it this week
+    Info {
+        /// Device name or id
+        device: String,
+    },
+    /// List the times a device went offline and the total downtime, from the
+    /// states the daemon records in [daemon] status_db
+    OfflineHistory {
+        /// Device name or id, defaults to every device
+        device: Option<String>,
+        /// Number of days of history to show
+        #[arg(long, default_value_t = 30, value_parser = parse_days)]
+        days: i64,
+        /// Database the daemon records device status in, instead of
+        /// [daemon] status_db
+        #[arg(long)]
+        db: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CurfewCommand {
+    /// Show each flap's curfews as configured, whether they have it locked
+    /// now and when it next locks, and any snooze
+    Show {
+        /// Device name or id, defaults to every flap
+        device: Option<String>,
+    },
+    /// Set the daily window a flap locks in, which may run past midnight.
+    /// Shows when it will lock in [units] timezone and in UTC, and asks
+    /// before replacing the flap's curfews
+    Set {
+        /// Device name or id
+        device: String,
+        /// Time to lock, as HH:MM in the household's timezone
+        #[arg(long, value_parser = curfew::parse_time, required_unless_present = "off")]
+        lock: Option<NaiveTime>,
+        /// Time to unlock, the next day when it's before --lock
+        #[arg(long, value_parser = curfew::parse_time, required_unless_present = "off")]
+        unlock: Option<NaiveTime>,
+        /// Turn the curfew off, keeping its times
+        #[arg(long, conflicts_with_all = ["lock", "unlock"])]
+        off: bool,
+    },
+    /// Unlock a flap and turn its curfew off for a while, then put both
+    /// back. The daemon does that, or this waits to with --wait
+    Snooze {
+        /// Device name or id
+        device: String,
+        /// How long, such as 30m or 2h. Snoozing again starts it over
+        #[arg(long = "for", value_parser = parse_age)]
+        length: Duration,
+        /// Wait here and put the flap back when the time is up
+        #[arg(long)]
+        wait: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ServeCommand {
+    /// Poll the API and publish state and events to an MQTT broker
+    Mqtt(MqttArgs),
+}
+
+/// Overrides for the [mqtt] config section. The broker password is read
+/// from MQTT_PASSWORD so it never shows up in the process list.
+#[derive(Args, Debug)]
+pub struct MqttArgs {
+    #[arg(long)]
+    pub host: Option<String>,
+    #[arg(long)]
+    pub port: Option<u16>,
+    #[arg(long)]
+    pub username: Option<String>,
+    /// Prefix for every published topic
+    #[arg(long)]
+    pub topic_prefix: Option<String>,
+    /// Seconds between API polls
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: Option<u64>,
+}
+
+impl Command {
+    /// Devices the household must have for the command to make sense.
+    pub fn needs_capability(&self) -> Option<Capability> {
+        match self {
+            Command::History { event_type, .. } => Some(match event_type {
+                EventType::Feeding => Capability::Feeder,
+                EventType::Drinking => Capability::WaterStation,
+                EventType::Activity => Capability::Flap,
+            }),
+            Command::Health { .. } => Some(Capability::WaterStation),
+            // only changing a pet's access involves a flap
+            Command::Pet {
+                command: PetCommand::Restrict { .. } | PetCommand::Allow { .. },
+            }
+            | Command::Curfew { .. } => Some(Capability::Flap),
+            _ => None,
+        }
+    }
+
+    /// Whether the command writes JSON to stdout, so its errors should too.
+    pub fn writes_json(&self) -> bool {
+        match self {
+            Command::List { json: true, .. }
+            | Command::SearchEvents { json: true, .. }
+            | Command::ActivityHistory { json: true, .. }
+            | Command::Compare { json: true, .. }
+            | Command::Summary { json: true, .. }
+            | Command::Anomalies { json: true, .. }
+            | Command::Forecast { json: true, .. }
+            | Command::Pet {
+                command: PetCommand::Info { json: true, .. },
+            } => true,
+            Command::Status { output, .. }
+            | Command::List { output, .. }
+            | Command::History { output, .. }
+            | Command::Watch { output, .. } => *output == OutputMode::Ndjson,
+            _ => false,
+        }
+    }
+
+    /// Whether the command talks to the SurePet API and so needs a token.
+    pub fn needs_auth(&self) -> bool {
+        !matches!(
+            self,
+            Command::Login { .. }
+                | Command::Doctor
+                | Command::Schedule { .. }
+                | Command::Export {
+                    command: Some(ExportCommand::Schema { .. }),
+                    ..
+                }
+                | Command::Notify {
+                    command: NotifyCommand::Unsubscribe { .. } | NotifyCommand::List
+                }
+                | Command::Search {
+                    history: HistorySearchArgs { history: true, .. },
+                    ..
+                }
+                | Command::Search {
+                    command: Some(SearchCommand::Saved {
+                        command: SavedSearchCommand::List | SavedSearchCommand::Delete { .. }
+                    }),
+                    ..
+                }
+                | Command::Device {
+                    command: DeviceCommand::OfflineHistory { .. }
+                }
+                | Command::Cache {
+                    command: CacheCommand::Stats | CacheCommand::Clear { .. }
+                }
+                | Command::Pending {
+                    command: PendingCommand::List | PendingCommand::Clear
+                }
+        )
+    }
+}
+
+fn parse_location(location: &str) -> Result<Location, String> {
+    match location.to_ascii_lowercase().as_str() {
+        "inside" => Ok(Location::Inside),
+        "outside" => Ok(Location::Outside),
+        _ => Err(format!("expected inside or outside, not {}", location)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capability(args: &[&str]) -> Option<Capability> {
+        let cli = Cli::try_parse_from([&["rusty_pet"], args].concat()).unwrap();
+        cli.command.unwrap().needs_capability()
+    }
+
+    #[test]
+    fn only_pet_access_changes_need_a_flap() {
+        assert_eq!(
+            capability(&["pet", "restrict", "Tom"]),
+            Some(Capability::Flap)
+        );
+        assert_eq!(capability(&["pet", "allow", "Tom"]), Some(Capability::Flap));
+        assert_eq!(capability(&["pet", "info", "Tom"]), None);
+        assert_eq!(capability(&["pet", "photo", "get", "Tom"]), None);
+        assert_eq!(capability(&["pet", "photo", "set", "Tom", "tom.jpg"]), None);
+    }
+
+    #[test]
+    fn stored_history_pages_start_at_one() {
+        let parse = |args: &[&str]| {
+            Cli::try_parse_from([&["rusty_pet", "search", "--history", "Tom"], args].concat())
+        };
+        assert!(parse(&["--limit", "10", "--page", "4000000000"]).is_ok());
+        assert!(parse(&["--page", "0"]).is_err());
+        assert!(parse(&["--limit", "0"]).is_err());
+    }
+
+    #[test]
+    fn exports_compress_with_gzip_either_way() {
+        let parse = |args: &[&str]| Cli::try_parse_from([&["rusty_pet", "export"], args].concat());
+        assert!(parse(&["--gzip"]).is_ok());
+        assert!(parse(&["--compress"]).is_ok());
+        assert!(parse(&["--compress", "zstd"]).is_ok());
+        assert!(parse(&["--gzip", "--compress", "zstd"]).is_err());
+        assert!(parse(&["--gzip", "--preview"]).is_err());
+    }
+}
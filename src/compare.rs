@@ -0,0 +1,136 @@
+use crate::api::client::Pet;
+use crate::api::SurePetApi;
+use crate::outings;
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Error;
+
+/// How a metric's values are shown.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+    Count,
+    Weight,
+    Volume,
+    Minutes,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Metric {
+    pub name: &'static str,
+    pub kind: MetricKind,
+    /// A day on average, one per pet in the order they were compared
+    pub values: Vec<f64>,
+    /// Highest over lowest, missing when the lowest is nothing
+    pub ratio: Option<f64>,
+    pub diverges: bool,
+}
+
+/// Pets side by side over the same days, for `compare`.
+#[derive(Serialize, Debug)]
+pub struct Comparison {
+    pub days: i64,
+    pub pets: Vec<String>,
+    pub metrics: Vec<Metric>,
+    /// Such as "Kitty eats 2.1 times as much as Tom"
+    pub divergences: Vec<String>,
+}
+
+/// Each metric, with how the pet with more of it differs from the one with
+/// less, such as "eats {} times as much as".
+const METRICS: [(&str, MetricKind, &str); 6] = [
+    ("Meals", MetricKind::Count, "eats {} times as many meals as"),
+    ("Food", MetricKind::Weight, "eats {} times as much as"),
+    ("Drinks", MetricKind::Count, "drinks {} times as often as"),
+    ("Water", MetricKind::Volume, "drinks {} times as much as"),
+    (
+        "Trips outside",
+        MetricKind::Count,
+        "goes out {} times as often as",
+    ),
+    (
+        "Time outside",
+        MetricKind::Minutes,
+        "spends {} times as long outside as",
+    ),
+];
+
+/// Fetch each pet's history for the last `days` days, today included, and
+/// line up their daily averages.
+pub async fn compare(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pets: &[&Pet],
+    days: i64,
+) -> std::io::Result<Comparison> {
+    let units = &api_client.cfg().units;
+    let now = Utc::now();
+    let first_day = units.start_of_day(&now) - Duration::days(days - 1);
+
+    // a row of values per pet, in the order of METRICS
+    let mut rows = Vec::new();
+    for pet in pets {
+        let report = api_client
+            .get_pet_report(token, pet, first_day, now)
+            .await
+            .map_err(Error::other)?;
+        let feeding = report.feeding.map(|f| f.datapoints).unwrap_or_default();
+        let drinking = report.drinking.map(|d| d.datapoints).unwrap_or_default();
+        let movement = report.movement.map(|m| m.datapoints).unwrap_or_default();
+        let outside = outings::outing_stats(&movement, days, now, &HashMap::new(), units);
+
+        let meals: Vec<_> = feeding.iter().filter(|e| e.from >= first_day).collect();
+        let drinks: Vec<_> = drinking.iter().filter(|e| e.from >= first_day).collect();
+        let totals = [
+            meals.len() as f64,
+            meals.iter().fold(0.0, |sum, e| sum + e.grams_eaten()),
+            drinks.len() as f64,
+            drinks.iter().fold(0.0, |sum, e| sum + e.ml_drunk()),
+            outside.outings as f64,
+            outside.total_minutes as f64,
+        ];
+        rows.push(totals.map(|total| total / days as f64));
+    }
+
+    let mut comparison = Comparison {
+        days,
+        pets: pets.iter().map(|p| p.name.clone()).collect(),
+        metrics: Vec::new(),
+        divergences: Vec::new(),
+    };
+    for (i, (name, kind, phrase)) in METRICS.into_iter().enumerate() {
+        let values: Vec<f64> = rows.iter().map(|row| row[i]).collect();
+        let order = |a: &usize, b: &usize| values[*a].total_cmp(&values[*b]);
+        let most = (0..values.len()).max_by(order);
+        let least = (0..values.len()).min_by(order);
+        let (Some(most), Some(least)) = (most, least) else {
+            continue;
+        };
+        let ratio = (values[least] > 0.0).then(|| values[most] / values[least]);
+        let diverges = match ratio {
+            Some(ratio) => ratio >= api_client.cfg().health.divergence_ratio,
+            None => values[most] > 0.0,
+        };
+        if diverges {
+            let (more, less) = (&comparison.pets[most], &comparison.pets[least]);
+            comparison.divergences.push(match ratio {
+                Some(ratio) => format!(
+                    "{} {} {}",
+                    more,
+                    phrase.replace("{}", &format!("{:.1}", ratio)),
+                    less
+                ),
+                None => format!("{} has no {} recorded", less, name.to_lowercase()),
+            });
+        }
+        comparison.metrics.push(Metric {
+            name,
+            kind,
+            values,
+            ratio,
+            diverges,
+        });
+    }
+    Ok(comparison)
+}
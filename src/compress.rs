@@ -0,0 +1,97 @@
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// The first line of `path`, decompressed, without its line ending. None
+/// when the file is missing or empty.
+pub fn first_line(
+    path: &Path,
+    compression: Option<Compression>,
+) -> std::io::Result<Option<String>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    let reader: Box<dyn Read> = match compression {
+        None => Box::new(file),
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(file)),
+        Some(Compression::Zstd) => Box::new(zstd::Decoder::new(file)?),
+    };
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line)?;
+    let line = line.trim_end_matches(['\r', '\n']);
+    Ok((!line.is_empty()).then(|| line.to_string()))
+}
+
+/// A file written through the chosen compression, so records are compressed
+/// as they are serialized rather than held in memory first. Uncompressed
+/// files are buffered, as the encoders buffer the others.
+pub enum Output {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl Output {
+    /// Appending to a compressed file adds another gzip member or zstd
+    /// frame, which decompressors read as one continuous stream.
+    pub fn new(file: File, compression: Option<Compression>) -> std::io::Result<Self> {
+        Ok(match compression {
+            None => Output::Plain(BufWriter::new(file)),
+            Some(Compression::Gzip) => {
+                Output::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+            }
+            Some(Compression::Zstd) => Output::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    /// Flush everything and write the compression trailer. Errors writing
+    /// out the buffer are only seen here, as dropping it ignores them.
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            Output::Plain(writer) => writer.into_inner().map_err(|e| e.into_error())?.flush(),
+            Output::Gzip(encoder) => encoder.finish()?.flush(),
+            Output::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Plain(writer) => writer.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+            Output::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Plain(writer) => writer.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+            Output::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
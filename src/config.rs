@@ -1,16 +1,383 @@
+use crate::range::MAX_DAYS;
+use crate::search::DeviceSort;
+use crate::units::Units;
+use chrono::format::{Item, StrftimeItems};
+use chrono::NaiveDate;
 use serde::Deserialize;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+const USER_CONFIG_FILE: &str = "config.toml";
+const BUILT_IN: &str = include_str!("./assets/client_config.toml");
+/// The database `export --format sqlite` writes, which commands reading
+/// stored history fall back to when neither `--db` nor [daemon] names one.
+const DEFAULT_DB: &str = "export.db";
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub(crate) api: Api,
+    pub(crate) health: Health,
+    pub(crate) mqtt: Mqtt,
+    pub(crate) interactive: Interactive,
+    pub(crate) notifications: Notifications,
+    pub(crate) cache: Cache,
+    pub(crate) daemon: Daemon,
+    pub(crate) headless: Headless,
+    pub(crate) export: Export,
+    pub(crate) forecast: Forecast,
+    pub(crate) units: Units,
+    pub(crate) influxdb: Option<InfluxDb>,
+    pub(crate) weather: Option<Weather>,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Api {
     pub(crate) surepy_url: String,
+    pub(crate) ca_bundle: Option<PathBuf>,
+    pub(crate) mock_dir: Option<PathBuf>,
+    pub(crate) command_concurrency: usize,
+    pub(crate) retries: u32,
+    pub(crate) retry_delay_ms: u64,
+    pub(crate) retry_max_delay_ms: u64,
+    pub(crate) requests_per_minute: u32,
+    pub(crate) request_burst: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Health {
+    pub(crate) drinking_window_days: i64,
+    pub(crate) drinking_baseline_days: i64,
+    pub(crate) drinking_sustained_days: i64,
+    pub(crate) drinking_sensitivity: f64,
+    pub(crate) meal_learning_days: i64,
+    pub(crate) meal_regularity: f64,
+    pub(crate) meal_grace_minutes: i64,
+    pub(crate) low_intake_percent: f64,
+    pub(crate) intake_change_percent: f64,
+    pub(crate) divergence_ratio: f64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Mqtt {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) client_id: String,
+    pub(crate) topic_prefix: String,
+    pub(crate) poll_interval_secs: u64,
+    pub(crate) discovery: bool,
+    pub(crate) discovery_prefix: String,
+    pub(crate) discovery_device_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Interactive {
+    pub(crate) auto_refresh_interval: u64,
+    pub(crate) min_refresh_interval: u64,
+    pub(crate) assume_yes: bool,
+    pub(crate) alert_bell: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Notifications {
+    pub(crate) webhooks: Vec<String>,
+    pub(crate) webhook_template: Option<String>,
+    pub(crate) webhook_retries: u32,
+    pub(crate) discord_webhooks: Vec<String>,
+    pub(crate) slack_webhooks: Vec<String>,
+    pub(crate) telegram: Option<Telegram>,
+    pub(crate) ntfy: Option<Ntfy>,
+    pub(crate) battery_threshold: u8,
+    pub(crate) battery_critical: u8,
+    pub(crate) battery_warning_days: i64,
+    pub(crate) outside_alert_hours: i64,
+    pub(crate) inactive_alert_hours: i64,
+    pub(crate) desktop: bool,
+    pub(crate) watch_interval_secs: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Telegram {
+    pub(crate) bot_token: String,
+    pub(crate) chat_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Ntfy {
+    pub(crate) server: String,
+    pub(crate) topic: String,
+    pub(crate) token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Daemon {
+    pub(crate) poll_interval_secs: u64,
+    pub(crate) status_db: Option<PathBuf>,
+    pub(crate) history_db: Option<PathBuf>,
+    pub(crate) sync_interval_secs: u64,
+}
+
+impl Daemon {
+    /// The database to read device states from: `db` when given, then
+    /// status_db.
+    pub fn status_db_or(&self, db: Option<PathBuf>) -> PathBuf {
+        db.or_else(|| self.status_db.clone())
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DB))
+    }
+
+    /// The database to read stored events from: `db` when given, then
+    /// history_db.
+    pub fn history_db_or(&self, db: Option<PathBuf>) -> PathBuf {
+        db.or_else(|| self.history_db.clone())
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DB))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Export {
+    pub(crate) concurrency: usize,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Forecast {
+    pub(crate) burn_days: i64,
+    pub(crate) bag_grams: f64,
+    pub(crate) bag_opened: Option<NaiveDate>,
+    pub(crate) battery_days: i64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Headless {
+    pub(crate) summary_line: bool,
+    pub(crate) device_sort: Option<DeviceSort>,
+    pub(crate) device_sort_desc: bool,
+    pub(crate) max_history_items: usize,
+    pub(crate) compact: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InfluxDb {
+    pub(crate) url: String,
+    pub(crate) org: String,
+    pub(crate) bucket: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Weather {
+    pub(crate) url: String,
+    pub(crate) latitude: f64,
+    pub(crate) longitude: f64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Cache {
+    pub(crate) enabled: bool,
+    pub(crate) ttl_secs: u64,
+    pub(crate) pets_ttl_secs: Option<u64>,
+    pub(crate) devices_ttl_secs: Option<u64>,
+    pub(crate) history_ttl_secs: Option<u64>,
+}
+
+/// Read the built-in config, overridden by any `config.toml` in the user
+/// config directory. Only the keys present in the user file are replaced.
+/// A user file that doesn't parse or fails [Config::validate] is
+/// `InvalidData`, naming every problem.
+pub fn read_config() -> std::io::Result<Config> {
+    let mut config: toml::Table = toml::from_str(BUILT_IN).expect("built-in config parses");
+
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+    if let Ok(path) = user_config_file() {
+        if let Ok(user_file) = fs::read_to_string(&path) {
+            let user: toml::Table = toml::from_str(&user_file)
+                .map_err(|e| invalid(format!("invalid {}: {}", path.display(), e)))?;
+            merge_tables(&mut config, user);
+        }
+    }
+
+    let config: Config = toml::Value::Table(config)
+        .try_into()
+        .map_err(|e| invalid(format!("invalid config: {}", e)))?;
+    config
+        .validate()
+        .map_err(|problems| invalid(format!("invalid config: {}", problems.join("; "))))?;
+    Ok(config)
+}
+
+/// The built-in config without any user file, for tests.
+#[cfg(test)]
+pub fn built_in() -> Config {
+    toml::from_str(BUILT_IN).expect("built-in config parses")
+}
+
+impl Config {
+    /// Check the thresholds make sense together, as a wrong one silently
+    /// stops or floods alerts. Returns every problem found.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: &str| {
+            if !ok {
+                problems.push(problem.to_string());
+            }
+        };
+
+        let health = &self.health;
+        check(
+            health.drinking_window_days >= 1
+                && health.drinking_baseline_days >= 1
+                && health.drinking_sustained_days >= 1,
+            "[health] drinking_window_days, drinking_baseline_days and \
+             drinking_sustained_days must be at least 1",
+        );
+        check(
+            health.drinking_sensitivity > 1.0,
+            "[health] drinking_sensitivity must be more than 1",
+        );
+        check(
+            health.meal_learning_days >= 0,
+            "[health] meal_learning_days can't be negative",
+        );
+        check(
+            health.meal_regularity > 0.0 && health.meal_regularity <= 1.0,
+            "[health] meal_regularity must be more than 0 and at most 1",
+        );
+        check(
+            health.meal_grace_minutes >= 0,
+            "[health] meal_grace_minutes can't be negative",
+        );
+        check(
+            (0.0..100.0).contains(&health.low_intake_percent),
+            "[health] low_intake_percent must be from 0 to below 100",
+        );
+        check(
+            health.intake_change_percent > 0.0,
+            "[health] intake_change_percent must be more than 0",
+        );
+        check(
+            health.divergence_ratio > 1.0,
+            "[health] divergence_ratio must be more than 1",
+        );
+
+        let days = [
+            health.drinking_window_days,
+            health.drinking_baseline_days,
+            health.drinking_sustained_days,
+            health.meal_learning_days,
+            self.forecast.burn_days,
+            self.forecast.battery_days,
+            self.notifications.battery_warning_days,
+        ];
+        check(
+            days.iter().all(|days| *days <= MAX_DAYS),
+            &format!("settings in days can be at most {}", MAX_DAYS),
+        );
+
+        let forecast = &self.forecast;
+        check(
+            forecast.burn_days >= 1 && forecast.battery_days >= 1,
+            "[forecast] burn_days and battery_days must be at least 1",
+        );
+        check(
+            forecast.bag_grams >= 0.0,
+            "[forecast] bag_grams can't be negative",
+        );
+
+        if let Some(weather) = &self.weather {
+            check(
+                (-90.0..=90.0).contains(&weather.latitude)
+                    && (-180.0..=180.0).contains(&weather.longitude),
+                "[weather] latitude and longitude must be in degrees",
+            );
+        }
+
+        let units = &self.units;
+        for (name, format) in [
+            ("date_format", Some(&units.date_format)),
+            ("time_format", units.time_format.as_ref()),
+        ] {
+            if let Some(format) = format {
+                check(
+                    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error)),
+                    &format!(
+                        "[units] {} {:?} isn't a valid strftime format",
+                        name, format
+                    ),
+                );
+            }
+        }
+
+        check(
+            self.mqtt.poll_interval_secs >= 1,
+            "[mqtt] poll_interval_secs must be at least 1",
+        );
+        let daemon = &self.daemon;
+        check(
+            daemon.poll_interval_secs >= 1 && daemon.sync_interval_secs >= 1,
+            "[daemon] poll_interval_secs and sync_interval_secs must be at least 1",
+        );
+
+        let notifications = &self.notifications;
+        check(
+            notifications.battery_threshold <= 100,
+            "[notifications] battery_threshold is a percentage, at most 100",
+        );
+        check(
+            notifications.battery_critical <= notifications.battery_threshold,
+            "[notifications] battery_critical can't be above battery_threshold",
+        );
+        check(
+            notifications.battery_warning_days >= 0,
+            "[notifications] battery_warning_days can't be negative",
+        );
+        check(
+            notifications.watch_interval_secs >= 1,
+            "[notifications] watch_interval_secs must be at least 1",
+        );
+        check(
+            notifications.outside_alert_hours >= 0 && notifications.inactive_alert_hours >= 0,
+            "[notifications] outside_alert_hours and inactive_alert_hours can't be negative",
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+fn merge_tables(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => {
+                merge_tables(base, value)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Directory for files the user can change, such as notification
+/// subscriptions. Created on first use.
+pub fn config_dir() -> std::io::Result<PathBuf> {
+    let dir = dirs::config_dir()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no config directory on this platform"))?
+        .join("rusty_pet");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Where the user's `config.toml` is, or would be.
+pub fn user_config_file() -> std::io::Result<PathBuf> {
+    Ok(config_dir()?.join(USER_CONFIG_FILE))
 }
 
-pub fn read_config() -> Config {
-    let config_file: &str = include_str!("./assets/client_config.toml");
-    return toml::from_str(&config_file).unwrap();
+/// Directory for cached API responses. Created on first use.
+pub fn cache_dir() -> std::io::Result<PathBuf> {
+    let dir = dirs::cache_dir()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no cache directory on this platform"))?
+        .join("rusty_pet");
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
 }
@@ -0,0 +1,24 @@
+use crate::i18n;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, IsTerminal};
+
+/// Ask before a destructive or bulk operation goes ahead. `assume_yes` comes
+/// from `--yes` or the `assume_yes` config key and skips the question. With
+/// no terminal to ask on, the operation is refused rather than assumed.
+pub fn confirm(prompt: impl Display, assume_yes: bool) -> std::io::Result<bool> {
+    if assume_yes {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            i18n::message("confirm_needs_yes", &[("prompt", &prompt.to_string())]),
+        ));
+    }
+
+    let confirmed = cliclack::confirm(prompt.to_string()).interact()?;
+    if !confirmed {
+        println!("Cancelled");
+    }
+    Ok(confirmed)
+}
@@ -0,0 +1,474 @@
+use crate::api::client::Curfew;
+use crate::stats::format_duration;
+use crate::units::Units;
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, LocalResult, NaiveDate, NaiveTime, Offset, TimeZone,
+    Utc,
+};
+use std::io::{Error, ErrorKind};
+
+/// How far ahead a change of the clocks is warned about.
+const DST_WARNING_DAYS: i64 = 14;
+/// Longest a flap can be locked each day before the times look swapped.
+const LONGEST_LOCKED_HOURS: i64 = 18;
+
+/// A time of day such as 22:00.
+pub fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .map_err(|_| format!("expected a time such as 22:00, not {}", time))
+}
+
+/// An enabled curfew, refusing one that would unlock as soon as it locked.
+pub fn curfew(lock: NaiveTime, unlock: NaiveTime) -> std::io::Result<Curfew> {
+    if lock == unlock {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "--lock and --unlock are both {}, so the flap would never stay locked",
+                lock.format("%H:%M")
+            ),
+        ));
+    }
+    Ok(Curfew {
+        enabled: true,
+        lock_time: lock.format("%H:%M").to_string(),
+        unlock_time: unlock.format("%H:%M").to_string(),
+    })
+}
+
+/// A flap's curfews in a few characters, such as 22:00-07:00, for tables.
+/// Missing when the device has none.
+pub fn text(curfews: &[Curfew]) -> Option<String> {
+    let enabled: Vec<String> = curfews
+        .iter()
+        .filter(|c| c.enabled)
+        .map(|c| format!("{}-{}", c.lock_time, c.unlock_time))
+        .collect();
+    match (enabled.is_empty(), curfews.is_empty()) {
+        (_, true) => None,
+        (true, false) => Some("off".to_string()),
+        (false, false) => Some(enabled.join(", ")),
+    }
+}
+
+/// Whether a curfew from `lock` to `unlock` has the flap locked at `time`.
+/// Overnight curfews lock in the evening and unlock the next day.
+pub fn locks_at(lock: NaiveTime, unlock: NaiveTime, time: NaiveTime) -> bool {
+    if lock <= unlock {
+        lock <= time && time < unlock
+    } else {
+        time >= lock || time < unlock
+    }
+}
+
+/// One of a flap's curfews as it stands, such as "22:00 to 07:00 overnight,
+/// locked 9h 0m a day, next locks at 22:00 Europe/London (21:00 UTC)".
+pub fn describe(curfew: &Curfew, now: DateTime<Utc>, units: &Units) -> String {
+    let mut text = format!("{} to {}", curfew.lock_time, curfew.unlock_time);
+    let (Ok(lock), Ok(unlock)) = (
+        parse_time(&curfew.lock_time),
+        parse_time(&curfew.unlock_time),
+    ) else {
+        text.push_str(", unreadable");
+        return text;
+    };
+    if unlock < lock {
+        text.push_str(" overnight");
+    }
+    text.push_str(&format!(
+        ", locked {} a day",
+        format_duration(locked_for(lock, unlock))
+    ));
+    if !curfew.enabled {
+        text.push_str(", off");
+        return text;
+    }
+
+    let time = units.time_of_day(&now);
+    let today = units.day_of(&now);
+    let next = |at: NaiveTime| {
+        if time < at {
+            today
+        } else {
+            today + Duration::days(1)
+        }
+    };
+    if locks_at(lock, unlock, time) {
+        text.push_str(&format!(
+            ", locked now until {}",
+            local_and_utc(next(unlock), unlock, units)
+        ));
+    } else {
+        text.push_str(&format!(
+            ", next locks at {}",
+            local_and_utc(next(lock), lock, units)
+        ));
+    }
+    text
+}
+
+/// What a curfew will do, shown before it is applied.
+pub struct Preview {
+    pub lines: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// When `device` will next lock and unlock, in the [units] timezone the flap
+/// is taken to keep and in UTC, with warnings for a computer on another
+/// zone and for the clocks changing in the next couple of weeks.
+pub fn preview(
+    device: &str,
+    lock: NaiveTime,
+    unlock: NaiveTime,
+    now: DateTime<Utc>,
+    units: &Units,
+) -> Preview {
+    let zone = zone_name(units);
+    let today = units.day_of(&now);
+    // tonight's lock unless it has already passed
+    let first = if units.time_of_day(&now) < lock {
+        today
+    } else {
+        today + Duration::days(1)
+    };
+    let overnight = unlock < lock;
+    let unlock_day = |date: NaiveDate| {
+        if overnight {
+            date + Duration::days(1)
+        } else {
+            date
+        }
+    };
+
+    let locked = locked_for(lock, unlock);
+    let lines = vec![
+        format!(
+            "{} will lock at {}",
+            device,
+            local_and_utc(first, lock, units)
+        ),
+        format!(
+            "and unlock at {}{}",
+            local_and_utc(unlock_day(first), unlock, units),
+            if overnight { ", the next day" } else { "" }
+        ),
+        format!(
+            "Locked for {} a day from {}",
+            format_duration(locked),
+            units.date(&units.start_of_date(first))
+        ),
+    ];
+
+    let mut warnings = Vec::new();
+    if locked > Duration::hours(LONGEST_LOCKED_HOURS) {
+        warnings.push(format!(
+            "Locked for {} a day, are --lock and --unlock the wrong way round?",
+            format_duration(locked)
+        ));
+    }
+    if let Some(locks) = instant(first, lock, units).earliest() {
+        let computer = locks.with_timezone(&Local).offset().fix();
+        let flap = zone_offset(locks, units);
+        if units.timezone.is_none() {
+            warnings.push(format!(
+                "[units] timezone isn't set, so the flap is taken to keep this computer's time \
+                 ({}). Set it to your household's zone if that's somewhere else",
+                utc_offset(computer)
+            ));
+        } else if flap != computer {
+            warnings.push(format!(
+                "This computer is on {}, not {} ({}), so the flap will lock at {} here",
+                utc_offset(computer),
+                zone,
+                utc_offset(flap),
+                locks.with_timezone(&Local).format("%H:%M")
+            ));
+        }
+    }
+    warnings.extend(clock_changes(first, lock, unlock, &zone, units));
+
+    Preview { lines, warnings }
+}
+
+/// Nights over the next couple of weeks when the clocks change under the
+/// curfew: a lock or unlock time skipped or repeated, or the time in UTC
+/// moving afterwards.
+fn clock_changes(
+    first: NaiveDate,
+    lock: NaiveTime,
+    unlock: NaiveTime,
+    zone: &str,
+    units: &Units,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut offset = None;
+    for date in (0..DST_WARNING_DAYS).map(|days| first + Duration::days(days)) {
+        // an overnight curfew unlocks the next day
+        let unlock_date = if unlock < lock {
+            date + Duration::days(1)
+        } else {
+            date
+        };
+        let day = |date: NaiveDate| units.date(&units.start_of_date(date));
+        for (what, date, time) in [("lock", date, lock), ("unlock", unlock_date, unlock)] {
+            match instant(date, time, units) {
+                LocalResult::None => warnings.push(format!(
+                    "There's no {} on {} as the clocks go forward, so the flap may not {} \
+                     when expected that night",
+                    time.format("%H:%M"),
+                    day(date),
+                    what
+                )),
+                LocalResult::Ambiguous(..) => warnings.push(format!(
+                    "{} happens twice on {} as the clocks go back, so the flap may {} an hour \
+                     out that night",
+                    time.format("%H:%M"),
+                    day(date),
+                    what
+                )),
+                LocalResult::Single(_) => {}
+            }
+        }
+
+        let Some(locks) = instant(date, lock, units).single() else {
+            continue;
+        };
+        let now_offset = zone_offset(locks, units);
+        match offset {
+            Some(before) if before != now_offset => {
+                warnings.push(format!(
+                    "The clocks change before {}, after which the flap locks at {} {} ({} UTC)",
+                    day(date),
+                    lock.format("%H:%M"),
+                    zone,
+                    locks.format("%H:%M")
+                ));
+                break;
+            }
+            _ => offset = Some(now_offset),
+        }
+    }
+    warnings
+}
+
+fn locked_for(lock: NaiveTime, unlock: NaiveTime) -> Duration {
+    if unlock < lock {
+        unlock - lock + Duration::days(1)
+    } else {
+        unlock - lock
+    }
+}
+
+/// `time` on `date` in the [units] timezone and in UTC, such as
+/// "22:00 Europe/London (21:00 UTC)".
+fn local_and_utc(date: NaiveDate, time: NaiveTime, units: &Units) -> String {
+    let utc = instant(date, time, units)
+        .earliest()
+        .map_or(String::new(), |t| format!(" ({} UTC)", t.format("%H:%M")));
+    format!("{} {}{}", time.format("%H:%M"), zone_name(units), utc)
+}
+
+fn zone_name(units: &Units) -> String {
+    units
+        .timezone
+        .map_or("local time".to_string(), |tz| tz.name().to_string())
+}
+
+/// The moment `time` on `date` comes round in the [units] timezone.
+fn instant(date: NaiveDate, time: NaiveTime, units: &Units) -> LocalResult<DateTime<Utc>> {
+    let at = date.and_time(time);
+    match units.timezone {
+        Some(tz) => tz.from_local_datetime(&at).map(|t| t.to_utc()),
+        None => Local.from_local_datetime(&at).map(|t| t.to_utc()),
+    }
+}
+
+fn zone_offset(time: DateTime<Utc>, units: &Units) -> FixedOffset {
+    match units.timezone {
+        Some(tz) => time.with_timezone(&tz).offset().fix(),
+        None => time.with_timezone(&Local).offset().fix(),
+    }
+}
+
+fn utc_offset(offset: FixedOffset) -> String {
+    format!("UTC{}", offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn london() -> Units {
+        let mut units = config::built_in().units;
+        units.timezone = Some(chrono_tz::Europe::London);
+        units
+    }
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn time(text: &str) -> NaiveTime {
+        parse_time(text).unwrap()
+    }
+
+    #[test]
+    fn daytime_curfews_lock_between_their_times() {
+        let (lock, unlock) = (time("09:00"), time("17:00"));
+        assert!(!locks_at(lock, unlock, time("08:59")));
+        assert!(locks_at(lock, unlock, time("09:00")));
+        assert!(locks_at(lock, unlock, time("16:59")));
+        assert!(!locks_at(lock, unlock, time("17:00")));
+    }
+
+    #[test]
+    fn overnight_curfews_lock_either_side_of_midnight() {
+        let (lock, unlock) = (time("22:00"), time("07:00"));
+        assert!(locks_at(lock, unlock, time("22:00")));
+        assert!(locks_at(lock, unlock, time("00:00")));
+        assert!(locks_at(lock, unlock, time("06:59")));
+        assert!(!locks_at(lock, unlock, time("07:00")));
+        assert!(!locks_at(lock, unlock, time("12:00")));
+    }
+
+    #[test]
+    fn a_curfew_must_stay_locked_for_a_while() {
+        let e = curfew(time("22:00"), time("22:00")).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        let set = curfew(time("22:00"), time("07:00")).unwrap();
+        assert!(set.enabled);
+        assert_eq!(
+            (set.lock_time.as_str(), set.unlock_time.as_str()),
+            ("22:00", "07:00")
+        );
+    }
+
+    #[test]
+    fn preview_gives_tonights_times_in_the_zone_and_utc() {
+        let preview = preview(
+            "Back door",
+            time("22:00"),
+            time("07:00"),
+            at("2024-06-10T12:00:00Z"),
+            &london(),
+        );
+        assert_eq!(
+            preview.lines[0],
+            "Back door will lock at 22:00 Europe/London (21:00 UTC)"
+        );
+        assert_eq!(
+            preview.lines[1],
+            "and unlock at 07:00 Europe/London (06:00 UTC), the next day"
+        );
+        assert!(preview.lines[2].starts_with("Locked for 9h 0m a day"));
+        assert!(!preview.warnings.iter().any(|w| w.contains("clocks")));
+    }
+
+    #[test]
+    fn preview_starts_tomorrow_once_tonights_lock_has_passed() {
+        let preview = preview(
+            "Back door",
+            time("09:00"),
+            time("17:00"),
+            at("2024-06-10T12:00:00Z"),
+            &london(),
+        );
+        assert_eq!(
+            preview.lines[1],
+            "and unlock at 17:00 Europe/London (16:00 UTC)"
+        );
+        assert!(preview.lines[2].ends_with(&london().date(&at("2024-06-11T12:00:00Z"))));
+    }
+
+    #[test]
+    fn preview_warns_of_times_that_look_swapped() {
+        let preview = preview(
+            "Back door",
+            time("07:00"),
+            time("05:00"),
+            at("2024-06-10T12:00:00Z"),
+            &london(),
+        );
+        assert!(preview
+            .warnings
+            .iter()
+            .any(|w| w.contains("wrong way round")));
+    }
+
+    #[test]
+    fn preview_warns_of_the_clocks_changing() {
+        let units = london();
+        let now = at("2024-10-20T12:00:00Z");
+        let moved = preview("Back door", time("22:00"), time("07:00"), now, &units);
+        assert!(moved
+            .warnings
+            .iter()
+            .any(|w| w.contains("locks at 22:00 Europe/London (22:00 UTC)")));
+
+        let repeated = preview("Back door", time("01:30"), time("07:00"), now, &units);
+        assert!(repeated
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("01:30 happens twice")));
+
+        let spring = at("2024-03-25T12:00:00Z");
+        let skipped = preview("Back door", time("22:00"), time("01:30"), spring, &units);
+        assert!(skipped
+            .warnings
+            .iter()
+            .any(|w| w.starts_with("There's no 01:30") && w.contains("may not unlock")));
+    }
+
+    fn set(lock: &str, unlock: &str, enabled: bool) -> Curfew {
+        Curfew {
+            enabled,
+            lock_time: lock.to_string(),
+            unlock_time: unlock.to_string(),
+        }
+    }
+
+    #[test]
+    fn text_lists_enabled_curfews() {
+        assert_eq!(text(&[]), None);
+        assert_eq!(
+            text(&[set("22:00", "07:00", false)]),
+            Some("off".to_string())
+        );
+        let both = [
+            set("22:00", "07:00", true),
+            set("12:00", "13:00", false),
+            set("15:00", "16:00", true),
+        ];
+        assert_eq!(text(&both), Some("22:00-07:00, 15:00-16:00".to_string()));
+    }
+
+    #[test]
+    fn describe_says_when_it_next_locks_or_unlocks() {
+        let units = london();
+        let overnight = set("22:00", "07:00", true);
+        assert_eq!(
+            describe(&overnight, at("2024-06-10T12:00:00Z"), &units),
+            "22:00 to 07:00 overnight, locked 9h 0m a day, \
+             next locks at 22:00 Europe/London (21:00 UTC)"
+        );
+        assert_eq!(
+            describe(&overnight, at("2024-06-10T23:30:00Z"), &units),
+            "22:00 to 07:00 overnight, locked 9h 0m a day, \
+             locked now until 07:00 Europe/London (06:00 UTC)"
+        );
+    }
+
+    #[test]
+    fn describe_notes_curfews_that_are_off_or_unreadable() {
+        let now = at("2024-06-10T12:00:00Z");
+        assert_eq!(
+            describe(&set("09:00", "17:00", false), now, &london()),
+            "09:00 to 17:00, locked 8h 0m a day, off"
+        );
+        assert_eq!(
+            describe(&set("9pm", "17:00", true), now, &london()),
+            "9pm to 17:00, unreadable"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+
+const LAYOUT_FILE: &str = "layout.toml";
+
+/// Sections of the full-screen interface.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Panel {
+    #[default]
+    Pets,
+    Devices,
+    /// Every pet's recent events
+    History,
+    Alerts,
+    /// What each pet ate and drank today and over the week
+    Intake,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 5] = [
+        Panel::Pets,
+        Panel::Devices,
+        Panel::History,
+        Panel::Alerts,
+        Panel::Intake,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Panel::Pets => "Pets",
+            Panel::Devices => "Devices",
+            Panel::History => "History",
+            Panel::Alerts => "Alerts",
+            Panel::Intake => "Intake",
+        }
+    }
+}
+
+/// Which panels the full-screen interface shows and in what order, saved
+/// from its layout editor.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Layout {
+    pub panels: Vec<Panel>,
+    /// Only the columns that matter most, for small households and narrow
+    /// terminals
+    #[serde(default)]
+    pub compact: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            panels: Panel::ALL.to_vec(),
+            compact: false,
+        }
+    }
+}
+
+impl Layout {
+    pub fn load() -> std::io::Result<Self> {
+        let path = layout_path()?;
+        if !path.exists() {
+            return Ok(Layout::default());
+        }
+        let mut layout: Layout =
+            toml::from_str(&fs::read_to_string(path)?).map_err(Error::other)?;
+        if layout.panels.is_empty() {
+            layout.panels = Panel::ALL.to_vec();
+        }
+        Ok(layout)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = toml::to_string_pretty(self).map_err(Error::other)?;
+        fs::write(layout_path()?, contents)
+    }
+}
+
+/// Ask which panels to show, in what order and how detailed, starting from
+/// `current`.
+pub fn edit(current: &Layout) -> std::io::Result<Layout> {
+    let mut shown = cliclack::multiselect("Which panels should be shown?")
+        .initial_values(current.panels.clone())
+        .required(true);
+    for panel in Panel::ALL {
+        shown = shown.item(panel, panel.title(), "");
+    }
+    let chosen = shown.interact()?;
+
+    // offered in their current order, with newly shown panels after
+    let mut remaining = Vec::new();
+    for panel in current.panels.iter().chain(&Panel::ALL) {
+        if chosen.contains(panel) && !remaining.contains(panel) {
+            remaining.push(*panel);
+        }
+    }
+
+    let mut panels = Vec::new();
+    while remaining.len() > 1 {
+        let prompt = if panels.is_empty() {
+            "Which panel comes first?"
+        } else {
+            "Which comes next?"
+        };
+        let mut next = cliclack::select(prompt);
+        for panel in &remaining {
+            next = next.item(*panel, panel.title(), "");
+        }
+        let panel = next.interact()?;
+        remaining.retain(|p| *p != panel);
+        panels.push(panel);
+    }
+    panels.extend(remaining);
+
+    let compact = cliclack::confirm("Use compact panels, with fewer columns?")
+        .initial_value(current.compact)
+        .interact()?;
+
+    Ok(Layout { panels, compact })
+}
+
+fn layout_path() -> std::io::Result<PathBuf> {
+    Ok(config::config_dir()?.join(LAYOUT_FILE))
+}
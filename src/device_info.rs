@@ -0,0 +1,111 @@
+use crate::api::client::{Device, Pet, PetProfile};
+use crate::api::SurePetApi;
+use crate::summary;
+use chrono::{DateTime, Duration, Utc};
+use log::error;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::io::Error;
+
+/// How far back recent events are looked for, and how many are kept.
+const RECENT_DAYS: i64 = 7;
+const RECENT_EVENTS: usize = 10;
+
+/// Everything known about one device, for `device info` and its
+/// interactive screen.
+pub struct DeviceInfo<'a> {
+    pub device: &'a Device,
+    /// Each pet the flap knows and what it may do; empty for other devices
+    pub permissions: Vec<(String, Option<PetProfile>)>,
+    /// Newest first
+    pub events: Vec<DeviceEvent>,
+    /// Whether some pets' history couldn't be fetched, so events are missing
+    pub partial: bool,
+}
+
+pub struct DeviceEvent {
+    pub at: DateTime<Utc>,
+    pub pet: String,
+    pub activity: Activity,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "kind", content = "value", rename_all = "snake_case")]
+pub enum Activity {
+    /// Grams eaten
+    Ate(f64),
+    /// Millilitres drunk
+    Drank(f64),
+    /// Went out, and came back at the time given if it has
+    Outside(Option<DateTime<Utc>>),
+}
+
+/// Fetch the flap's pets and every pet's recent history, keeping what
+/// happened at `device`. A pet whose history can't be fetched is left out
+/// and the result marked partial.
+pub async fn gather<'a>(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device: &'a Device,
+    pets: &[Pet],
+) -> std::io::Result<DeviceInfo<'a>> {
+    let mut permissions = Vec::new();
+    if device.is_flap() {
+        let tags = api_client
+            .get_device_tags(token, device.id)
+            .await
+            .map_err(Error::other)?;
+        for tag in tags {
+            let name = pets
+                .iter()
+                .find(|p| p.tag_id == Some(tag.id))
+                .map_or_else(|| format!("tag {}", tag.id), |p| p.name.clone());
+            permissions.push((name, tag.pet_profile()));
+        }
+    }
+
+    let to = Utc::now();
+    let from = to - Duration::days(RECENT_DAYS);
+    let mut events = Vec::new();
+    let mut partial = false;
+    for pet in pets {
+        let report = match api_client.get_pet_report(token, pet, from, to).await {
+            Ok(report) => report,
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                partial = true;
+                continue;
+            }
+        };
+        let event = |at, activity| DeviceEvent {
+            at,
+            pet: pet.name.clone(),
+            activity,
+        };
+        for feeding in report.feeding.into_iter().flat_map(|s| s.datapoints) {
+            if feeding.device_id == device.id {
+                events.push(event(feeding.from, Activity::Ate(feeding.grams_eaten())));
+            }
+        }
+        for drinking in report.drinking.into_iter().flat_map(|s| s.datapoints) {
+            if drinking.device_id == device.id {
+                events.push(event(drinking.from, Activity::Drank(drinking.ml_drunk())));
+            }
+        }
+        for trip in report.movement.into_iter().flat_map(|s| s.datapoints) {
+            if trip.device_id == Some(device.id) {
+                events.push(event(trip.from, Activity::Outside(trip.to)));
+            }
+        }
+    }
+    events.sort_by_key(|e| Reverse(e.at));
+    events.truncate(RECENT_EVENTS);
+
+    Ok(DeviceInfo {
+        device,
+        permissions,
+        events,
+        partial,
+    })
+}
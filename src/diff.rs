@@ -0,0 +1,76 @@
+use crate::api::client::{Device, Location, Pet};
+use crate::sqlite::{self, DeviceState};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// What changed between the state stored in `db` at `since` and the current
+/// pets and devices, one line per change. Pets are compared using the trips
+/// exported to the database, devices using the states the daemon records.
+pub fn changes(
+    db: &Path,
+    since: DateTime<Utc>,
+    pets: &[Pet],
+    devices: &[Device],
+) -> rusqlite::Result<Vec<String>> {
+    let mut changes = Vec::new();
+
+    let locations = sqlite::locations_at(db, since)?;
+    for pet in pets {
+        let (Some(then), Some(now)) = (
+            locations.get(&pet.id),
+            pet.position.as_ref().map(|p| p.location),
+        ) else {
+            continue;
+        };
+        match (then, now) {
+            (Location::Inside, Location::Outside) => {
+                changes.push(format!("{} went outside", pet.name))
+            }
+            (Location::Outside, Location::Inside) => {
+                changes.push(format!("{} came inside", pet.name))
+            }
+            _ => {}
+        }
+    }
+
+    // the last state recorded before `since` is the state at that time
+    let mut states: HashMap<u32, DeviceState> = HashMap::new();
+    for state in sqlite::device_states(db, since)? {
+        states.insert(state.device_id, state);
+    }
+    for device in devices {
+        let Some(then) = states.get(&device.id) else {
+            continue;
+        };
+        match (then.online, device.online()) {
+            (Some(true), Some(false)) => changes.push(format!("{} went offline", device.name)),
+            (Some(false), Some(true)) => changes.push(format!("{} came back online", device.name)),
+            _ => {}
+        }
+        let lock = device.lock_mode().map(|m| m.to_string());
+        if let (Some(before), Some(after)) = (&then.lock, &lock) {
+            if before != after {
+                changes.push(format!(
+                    "{} changed from {} to {}",
+                    device.name, before, after
+                ));
+            }
+        }
+        if let (Some(before), Some(after)) = (then.battery, device.battery_percent()) {
+            if after < before {
+                changes.push(format!(
+                    "{} battery dropped from {}% to {}%",
+                    device.name, before, after
+                ));
+            } else if after > before {
+                changes.push(format!(
+                    "{} battery rose from {}% to {}%",
+                    device.name, before, after
+                ));
+            }
+        }
+    }
+
+    Ok(changes)
+}
@@ -0,0 +1,246 @@
+use crate::api::cache::CacheMode;
+use crate::api::client;
+use crate::api::SurePetApi;
+use crate::auth::TOKEN_ENV;
+use crate::config::{self, Config};
+use crate::summary;
+use console::style;
+use std::env;
+use std::fs;
+use std::time::Instant;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Pass,
+    Warn,
+    Fail,
+}
+
+struct Check {
+    name: &'static str,
+    verdict: Verdict,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, verdict: Verdict, detail: impl Into<String>) -> Self {
+        Check {
+            name,
+            verdict,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Check the config, cache, token, API and devices in turn, and print what
+/// passed and what didn't. Never signs in or prompts, so it still works
+/// when signing in is what's broken.
+pub async fn run(api_client: &impl SurePetApi) -> std::io::Result<()> {
+    let mut checks = vec![
+        check_config(api_client.cfg()),
+        check_cache(api_client.cfg()),
+    ];
+
+    // a cached response would say nothing about the API today
+    api_client.set_cache_mode(CacheMode::Off);
+    let token = env::var(TOKEN_ENV).ok();
+    let started = Instant::now();
+    let pets = api_client
+        .get_pets(token.as_deref().unwrap_or_default())
+        .await;
+    let latency = started.elapsed().as_millis();
+    let rejected = |e: &reqwest::Error| e.status().is_some_and(|s| matches!(s.as_u16(), 401 | 403));
+
+    checks.push(match &pets {
+        Err(e) if !rejected(e) && client::is_unreachable(e) => {
+            Check::new("API", Verdict::Fail, format!("can't be reached: {}", e))
+        }
+        Err(e) if !rejected(e) => Check::new("API", Verdict::Fail, e.to_string()),
+        _ if latency > 2000 => Check::new(
+            "API",
+            Verdict::Warn,
+            format!("reachable but slow, {} ms", latency),
+        ),
+        _ => Check::new("API", Verdict::Pass, format!("reachable, {} ms", latency)),
+    });
+    checks.push(match (&token, &pets) {
+        (None, _) => Check::new(
+            "Token",
+            Verdict::Fail,
+            format!("{} isn't set, sign in with `rusty_pet login`", TOKEN_ENV),
+        ),
+        (Some(_), Ok(pets)) => Check::new(
+            "Token",
+            Verdict::Pass,
+            format!("valid, {} pet(s) in the household", pets.len()),
+        ),
+        (Some(_), Err(e)) if rejected(e) => Check::new(
+            "Token",
+            Verdict::Fail,
+            "rejected, sign in again with `rusty_pet login`",
+        ),
+        (Some(_), Err(_)) => Check::new("Token", Verdict::Warn, "not checked, see API"),
+    });
+
+    if let (Some(token), Ok(_)) = (&token, &pets) {
+        match api_client.get_devices(token).await {
+            Ok(devices) => checks.extend(check_devices(api_client.cfg(), &devices)),
+            Err(e) => checks.push(Check::new("Devices", Verdict::Fail, e.to_string())),
+        }
+    }
+
+    print(&checks);
+    Ok(())
+}
+
+/// Print the checks when the config, or the client built from it, is too
+/// broken to check anything that needs them.
+pub fn report_unusable(error: &std::io::Error) {
+    let checks = vec![
+        Check::new(
+            "Config",
+            Verdict::Fail,
+            format!("{}: {}", config_source(), error),
+        ),
+        Check::new("API", Verdict::Warn, "not checked, see Config"),
+        match env::var_os(TOKEN_ENV) {
+            Some(_) => Check::new("Token", Verdict::Warn, "not checked, see Config"),
+            None => Check::new(
+                "Token",
+                Verdict::Fail,
+                format!("{} isn't set, sign in with `rusty_pet login`", TOKEN_ENV),
+            ),
+        },
+    ];
+    print(&checks);
+}
+
+fn config_source() -> String {
+    match config::user_config_file() {
+        Ok(path) if path.exists() => path.display().to_string(),
+        _ => "built-in defaults".to_string(),
+    }
+}
+
+/// The config loaded and the client was built from it, or it wouldn't have
+/// got this far, so only what it points at can be wrong.
+fn check_config(cfg: &Config) -> Check {
+    let source = config_source();
+    let mut problems = Vec::new();
+    if !cfg.api.surepy_url.starts_with("http://") && !cfg.api.surepy_url.starts_with("https://") {
+        problems.push(format!(
+            "[api] surepy_url {} isn't a URL",
+            cfg.api.surepy_url
+        ));
+    }
+    if let Some(path) = cfg.api.mock_dir.as_ref() {
+        problems.push(format!(
+            "[api] mock_dir {} is set, so the API isn't used",
+            path.display()
+        ));
+    }
+    if cfg.api.command_concurrency == 0 || cfg.export.concurrency == 0 {
+        problems.push("concurrency of 0 is treated as 1".to_string());
+    }
+
+    if problems.is_empty() {
+        Check::new("Config", Verdict::Pass, source)
+    } else {
+        let detail = format!("{}: {}", source, problems.join("; "));
+        Check::new("Config", Verdict::Warn, detail)
+    }
+}
+
+fn check_cache(cfg: &Config) -> Check {
+    if !cfg.cache.enabled {
+        return Check::new("Cache", Verdict::Pass, "disabled");
+    }
+    let written = config::cache_dir().and_then(|dir| {
+        let probe = dir.join(".doctor");
+        fs::write(&probe, b"")?;
+        fs::remove_file(&probe)?;
+        Ok(dir)
+    });
+    match written {
+        Ok(dir) => Check::new(
+            "Cache",
+            Verdict::Pass,
+            format!("{} is writable", dir.display()),
+        ),
+        Err(e) => Check::new("Cache", Verdict::Fail, format!("can't be written: {}", e)),
+    }
+}
+
+fn check_devices(cfg: &Config, devices: &[client::Device]) -> Vec<Check> {
+    if devices.is_empty() {
+        return vec![Check::new(
+            "Devices",
+            Verdict::Warn,
+            "none in the household",
+        )];
+    }
+    let threshold = cfg.notifications.battery_threshold;
+    let mut checks = Vec::new();
+    for device in devices {
+        if device.online() == Some(false) {
+            checks.push(Check::new(
+                "Device",
+                Verdict::Fail,
+                format!("{} is offline", device.name),
+            ));
+        }
+        if let Some(battery) = device.battery_percent().filter(|b| *b < threshold) {
+            let verdict = if battery < cfg.notifications.battery_critical {
+                Verdict::Fail
+            } else {
+                Verdict::Warn
+            };
+            checks.push(Check::new(
+                "Battery",
+                verdict,
+                format!("{} is at {}%", device.name, battery),
+            ));
+        }
+        if device.update_pending() {
+            checks.push(Check::new(
+                "Firmware",
+                Verdict::Warn,
+                format!(
+                    "{} has an update waiting to install, keep it near the hub",
+                    device.name
+                ),
+            ));
+        }
+    }
+    if checks.is_empty() {
+        checks.push(Check::new(
+            "Devices",
+            Verdict::Pass,
+            format!("{} online, batteries above {}%", devices.len(), threshold),
+        ));
+    }
+    checks
+}
+
+fn print(checks: &[Check]) {
+    for check in checks {
+        let mark = match check.verdict {
+            Verdict::Pass => style("ok  ").green(),
+            Verdict::Warn => style("warn").yellow(),
+            Verdict::Fail => style("FAIL").red(),
+        };
+        println!("{} {:<8} {}", mark, check.name, check.detail);
+    }
+
+    let count = |verdict| checks.iter().filter(|c| c.verdict == verdict).count();
+    let failed = count(Verdict::Fail);
+    for _ in 0..failed {
+        summary::failed();
+    }
+    println!(
+        "\n{} passed, {} warning(s), {} failed",
+        count(Verdict::Pass),
+        count(Verdict::Warn),
+        failed
+    );
+}
@@ -0,0 +1,154 @@
+use crate::exit::Exit;
+use std::io::ErrorKind;
+use thiserror::Error;
+
+/// What went wrong with a command, by what the user can do about it.
+/// Commands work with `io::Error` and `reqwest::Error` and are converted
+/// to this where they finish, for the exit code and JSON error output.
+#[derive(Error, Debug)]
+pub enum RustyPetError {
+    /// Signing in failed, or the API rejected the token
+    #[error("{message}")]
+    Auth {
+        message: String,
+        /// The API rejected a token that may have worked before
+        expired: bool,
+    },
+    /// The API answered, but with an error
+    #[error("{0}")]
+    Network(String),
+    /// A pet, device or file named doesn't exist, or the household has no
+    /// device of the kind the command needs
+    #[error("{0}")]
+    NotFound(String),
+    /// An argument, config file or input was invalid
+    #[error("{0}")]
+    Validation(String),
+    /// SurePet kept refusing requests for being too frequent
+    #[error("{0}")]
+    RateLimited(String),
+    /// No answer came back from the API, as when it can't be reached
+    #[error("{0}")]
+    Offline(String),
+    #[error(transparent)]
+    Other(std::io::Error),
+}
+
+impl RustyPetError {
+    pub fn exit(&self) -> Exit {
+        match self {
+            RustyPetError::Auth { .. } => Exit::Auth,
+            RustyPetError::Network(_)
+            | RustyPetError::RateLimited(_)
+            | RustyPetError::Offline(_) => Exit::Network,
+            RustyPetError::NotFound(_) => Exit::NotFound,
+            RustyPetError::Validation(_) => Exit::Invalid,
+            RustyPetError::Other(_) => Exit::Failure,
+        }
+    }
+
+    /// Stable, for scripts to match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RustyPetError::Auth { expired: true, .. } => "AUTH_EXPIRED",
+            RustyPetError::Auth { expired: false, .. } => "AUTH_FAILED",
+            RustyPetError::Network(_) => "API_ERROR",
+            RustyPetError::NotFound(_) => "NOT_FOUND",
+            RustyPetError::Validation(_) => "INVALID_INPUT",
+            RustyPetError::RateLimited(_) => "RATE_LIMITED",
+            RustyPetError::Offline(_) => "NETWORK_ERROR",
+            RustyPetError::Other(_) => "ERROR",
+        }
+    }
+
+    /// What to try next, when there's something to suggest.
+    pub fn remediation(&self) -> Option<&'static str> {
+        match self {
+            RustyPetError::Auth { expired: true, .. } => {
+                Some("sign in again with `rusty_pet login` and update SUREPY_TOKEN")
+            }
+            RustyPetError::Auth { expired: false, .. } => {
+                Some("check SUREPY_USERNAME and SUREPY_PASSWORD")
+            }
+            RustyPetError::Network(_) => Some("try again later"),
+            RustyPetError::NotFound(_) => {
+                Some("check the name against `rusty_pet list` or `rusty_pet status`")
+            }
+            RustyPetError::Validation(_) => Some("see the command's --help"),
+            RustyPetError::RateLimited(_) => {
+                Some("wait a few minutes, or lower [api] requests_per_minute")
+            }
+            RustyPetError::Offline(_) => {
+                Some("check the connection and [api] surepy_url, then try again")
+            }
+            RustyPetError::Other(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for RustyPetError {
+    fn from(e: reqwest::Error) -> Self {
+        let message = e.to_string();
+        match e.status().map(|s| s.as_u16()) {
+            Some(401 | 403) => RustyPetError::Auth {
+                message,
+                expired: true,
+            },
+            Some(404) => RustyPetError::NotFound(message),
+            Some(429) => RustyPetError::RateLimited(message),
+            // as before this enum: any other answer, gateway errors
+            // included, is API_ERROR and no answer is NETWORK_ERROR
+            Some(_) => RustyPetError::Network(message),
+            None => RustyPetError::Offline(message),
+        }
+    }
+}
+
+/// Classify by the error's kind, or by the API error or `RustyPetError`
+/// it wraps.
+impl From<std::io::Error> for RustyPetError {
+    fn from(e: std::io::Error) -> Self {
+        match e.get_ref() {
+            Some(inner) if inner.is::<RustyPetError>() => {
+                return *e.into_inner().unwrap().downcast().unwrap()
+            }
+            Some(inner) if inner.is::<reqwest::Error>() => {
+                return RustyPetError::from(
+                    *e.into_inner()
+                        .unwrap()
+                        .downcast::<reqwest::Error>()
+                        .unwrap(),
+                )
+            }
+            _ => {}
+        }
+        let message = e.to_string();
+        match e.kind() {
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => RustyPetError::Validation(message),
+            ErrorKind::NotFound | ErrorKind::Unsupported => RustyPetError::NotFound(message),
+            ErrorKind::NotConnected | ErrorKind::TimedOut | ErrorKind::ConnectionRefused => {
+                RustyPetError::Offline(message)
+            }
+            _ => RustyPetError::Other(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Error;
+
+    #[test]
+    fn only_a_refused_login_is_an_auth_error() {
+        let login = Error::other(RustyPetError::Auth {
+            message: "failed to log in".to_string(),
+            expired: false,
+        });
+        assert_eq!(RustyPetError::from(login).exit(), Exit::Auth);
+
+        // such as an unreadable config or output directory
+        let file = Error::new(ErrorKind::PermissionDenied, "permission denied");
+        assert_eq!(RustyPetError::from(file).exit(), Exit::Failure);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::api::client::{Location, Pet, PetReport};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Kinds of event in a pet's history.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    Feeding,
+    Drinking,
+    /// Time spent inside or outside, between trips through a flap
+    Activity,
+}
+
+/// What events must match, every criterion that is set.
+#[derive(Debug)]
+pub struct EventCriteria {
+    pub event_type: EventType,
+    /// Grams, millilitres or minutes
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub device_id: Option<u32>,
+    /// Only applies to activity
+    pub location: Option<Location>,
+}
+
+/// An event that matched, in the shape `search-events --json` prints.
+#[derive(Serialize, Debug)]
+pub struct EventHit {
+    #[serde(rename = "type")]
+    pub event_type: EventType,
+    pub pet_id: u32,
+    pub pet: String,
+    /// The bowl or fountain, or the flap an activity period started at
+    pub device_id: Option<u32>,
+    pub device: Option<String>,
+    pub from: DateTime<Utc>,
+    /// When an activity period ended, missing while it goes on
+    pub to: Option<DateTime<Utc>>,
+    /// Grams eaten, millilitres drunk, or minutes spent inside or outside
+    pub amount: f64,
+    pub location: Option<String>,
+}
+
+impl EventCriteria {
+    /// The events in `pet`'s report that match, oldest first. Activity is
+    /// each trip outside and each stay inside between trips, up to `now`.
+    pub fn matching(
+        &self,
+        pet: &Pet,
+        report: PetReport,
+        device_names: &HashMap<u32, String>,
+        now: DateTime<Utc>,
+    ) -> Vec<EventHit> {
+        let hit = |device_id: Option<u32>,
+                   from: DateTime<Utc>,
+                   to: Option<DateTime<Utc>>,
+                   amount: f64,
+                   location: Option<Location>| EventHit {
+            event_type: self.event_type,
+            pet_id: pet.id,
+            pet: pet.name.clone(),
+            device_id,
+            device: device_id.and_then(|id| device_names.get(&id).cloned()),
+            from,
+            to,
+            amount,
+            location: location.map(|l| l.to_string()),
+        };
+
+        let mut hits: Vec<EventHit> = match self.event_type {
+            EventType::Feeding => report
+                .feeding
+                .map(|f| f.datapoints)
+                .unwrap_or_default()
+                .iter()
+                .map(|e| hit(Some(e.device_id), e.from, None, e.grams_eaten(), None))
+                .collect(),
+            EventType::Drinking => report
+                .drinking
+                .map(|d| d.datapoints)
+                .unwrap_or_default()
+                .iter()
+                .map(|e| hit(Some(e.device_id), e.from, None, e.ml_drunk(), None))
+                .collect(),
+            EventType::Activity => {
+                let mut trips = report.movement.map(|m| m.datapoints).unwrap_or_default();
+                trips.sort_by_key(|t| t.from);
+                let minutes = |from: DateTime<Utc>, to: Option<DateTime<Utc>>| {
+                    (to.unwrap_or(now) - from).num_seconds() as f64 / 60.0
+                };
+                let mut periods = Vec::new();
+                for (i, trip) in trips.iter().enumerate() {
+                    let outside = minutes(trip.from, trip.to);
+                    periods.push(hit(
+                        trip.device_id,
+                        trip.from,
+                        trip.to,
+                        outside,
+                        Some(Location::Outside),
+                    ));
+                    // back in until the next trip, or still
+                    if let Some(back) = trip.to {
+                        let next = trips.get(i + 1).map(|t| t.from);
+                        let inside = minutes(back, next);
+                        periods.push(hit(None, back, next, inside, Some(Location::Inside)));
+                    }
+                }
+                periods
+            }
+        };
+
+        hits.retain(|hit| {
+            self.min_amount.is_none_or(|min| hit.amount >= min)
+                && self.max_amount.is_none_or(|max| hit.amount <= max)
+                && self.device_id.is_none_or(|id| hit.device_id == Some(id))
+                && self
+                    .location
+                    .is_none_or(|l| hit.location.as_deref() == Some(&*l.to_string()))
+        });
+        hits.sort_by_key(|hit| hit.from);
+        hits
+    }
+}
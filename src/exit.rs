@@ -0,0 +1,69 @@
+use crate::error::RustyPetError;
+use crate::summary;
+use serde::Serialize;
+use std::process::ExitCode;
+
+/// What a headless command's exit code means, so scripts can branch on
+/// the outcome. Usage errors exit with 2, from clap, before anything runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    Success = 0,
+    /// Anything not covered below
+    Failure = 1,
+    /// Signing in failed, or the API rejected the token
+    Auth = 3,
+    /// The API couldn't be reached or gave an unexpected response
+    Network = 4,
+    /// An argument, config file or input was invalid
+    Invalid = 5,
+    /// A pet, device or file named doesn't exist, or the household has no
+    /// device of the kind the command needs
+    NotFound = 6,
+    /// The command finished, but part of it failed, such as one pet's
+    /// history in an export
+    Partial = 7,
+}
+
+impl Exit {
+    pub fn from_result(result: &Result<(), RustyPetError>) -> Self {
+        match result {
+            Ok(()) if summary::failures() > 0 => Exit::Partial,
+            Ok(()) => Exit::Success,
+            Err(e) => e.exit(),
+        }
+    }
+}
+
+/// An error as JSON, `{"error": {"code": ..., "message": ..., "remediation": ...}}`,
+/// for commands whose output is JSON.
+#[derive(Serialize, Debug)]
+pub struct ErrorEnvelope {
+    pub error: ErrorBody,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ErrorBody {
+    /// Stable, for scripts to match on
+    pub code: &'static str,
+    pub message: String,
+    /// What to try next, when there's something to suggest
+    pub remediation: Option<&'static str>,
+}
+
+impl ErrorEnvelope {
+    pub fn new(e: &RustyPetError) -> Self {
+        ErrorEnvelope {
+            error: ErrorBody {
+                code: e.code(),
+                message: e.to_string(),
+                remediation: e.remediation(),
+            },
+        }
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
@@ -0,0 +1,806 @@
+use crate::api::client::Device;
+use crate::api::SurePetApi;
+use crate::compress::{self, Compression, Output};
+use crate::curfew;
+use crate::filter::Filter;
+use crate::ics;
+use crate::influx;
+use crate::output::{self, Meta, Verbosity};
+use crate::range::TimeRange;
+use crate::sqlite;
+use crate::summary;
+use crate::units::{Units, Volume, Weight};
+use crate::watermarks::Watermarks;
+use chrono::{DateTime, Duration, DurationRound, FixedOffset, NaiveTime, Utc};
+use clap::ValueEnum;
+use console::Term;
+use futures::stream::{self, StreamExt};
+use log::{debug, error};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::Serialize;
+use serde_reflection::{ContainerFormat, Format, Samples, Tracer, TracerConfig};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Days of history `export --preview` fetches to sample from.
+pub const PREVIEW_DAYS: i64 = 1;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    /// iCalendar, with curfews and trips outside as events
+    Ics,
+    /// A SQLite database, added to on every export
+    Sqlite,
+    /// InfluxDB line protocol, with device battery and connection telemetry
+    Influx,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct FeedingRecord {
+    pub pet_id: u32,
+    pub pet: String,
+    pub device_id: u32,
+    pub device: String,
+    pub timestamp: DateTime<Utc>,
+    /// Eaten, in `weight`
+    pub amount: f64,
+    pub weight: Weight,
+    /// Written at this offset from UTC, or in UTC when None
+    pub offset: Option<FixedOffset>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DrinkingRecord {
+    pub pet_id: u32,
+    pub pet: String,
+    pub device_id: u32,
+    pub device: String,
+    pub timestamp: DateTime<Utc>,
+    /// Drunk, in `volume`
+    pub amount: f64,
+    pub volume: Volume,
+    /// Written at this offset from UTC, or in UTC when None
+    pub offset: Option<FixedOffset>,
+}
+
+// the amount's column is named after its unit, such as grams or fl_oz, so a
+// file says what its numbers are, and the timestamp carries its offset
+impl Serialize for FeedingRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut record = serializer.serialize_struct("FeedingRecord", 6)?;
+        record.serialize_field("pet_id", &self.pet_id)?;
+        record.serialize_field("pet", &self.pet)?;
+        record.serialize_field("device_id", &self.device_id)?;
+        record.serialize_field("device", &self.device)?;
+        match self.offset {
+            Some(offset) => {
+                record.serialize_field("timestamp", &self.timestamp.with_timezone(&offset))?
+            }
+            None => record.serialize_field("timestamp", &self.timestamp)?,
+        }
+        record.serialize_field(self.weight.column(), &self.amount)?;
+        record.end()
+    }
+}
+
+impl Serialize for DrinkingRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut record = serializer.serialize_struct("DrinkingRecord", 6)?;
+        record.serialize_field("pet_id", &self.pet_id)?;
+        record.serialize_field("pet", &self.pet)?;
+        record.serialize_field("device_id", &self.device_id)?;
+        record.serialize_field("device", &self.device)?;
+        match self.offset {
+            Some(offset) => {
+                record.serialize_field("timestamp", &self.timestamp.with_timezone(&offset))?
+            }
+            None => record.serialize_field("timestamp", &self.timestamp)?,
+        }
+        record.serialize_field(self.volume.column(), &self.amount)?;
+        record.end()
+    }
+}
+
+#[derive(Debug)]
+pub struct PetRecord {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct DeviceRecord {
+    pub id: u32,
+    pub name: String,
+    pub product_id: u32,
+    /// State when exported, for device telemetry and the daemon's history
+    pub battery: Option<u8>,
+    pub online: Option<bool>,
+    pub lock: Option<String>,
+    /// Enabled curfews such as 22:00-07:00, or off
+    pub curfew: Option<String>,
+}
+
+impl From<&Device> for DeviceRecord {
+    fn from(device: &Device) -> Self {
+        DeviceRecord {
+            id: device.id,
+            name: device.name.clone(),
+            product_id: device.product_id,
+            battery: device.battery_percent(),
+            online: device.online(),
+            lock: device.lock_mode().map(|m| m.to_string()),
+            curfew: device
+                .control
+                .as_ref()
+                .and_then(|c| curfew::text(&c.curfew)),
+        }
+    }
+}
+
+/// A trip outside, for the calendar and database exports.
+#[derive(Debug, Clone)]
+pub struct MovementRecord {
+    pub pet_id: u32,
+    pub pet: String,
+    pub outside_from: DateTime<Utc>,
+    pub outside_to: Option<DateTime<Utc>>,
+}
+
+/// An enabled flap curfew, in local time, for the calendar export.
+#[derive(Debug)]
+pub struct CurfewRecord {
+    pub device_id: u32,
+    pub device: String,
+    pub lock_time: NaiveTime,
+    pub unlock_time: NaiveTime,
+}
+
+/// How finely exported timestamps are kept.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum TimeGranularity {
+    Second,
+    Minute,
+    Hour,
+}
+
+/// Coarser numbers and times for tools that can't cope with full precision.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rounding {
+    /// Decimal places kept in amounts
+    pub precision: Option<u32>,
+    pub granularity: Option<TimeGranularity>,
+}
+
+/// Everything written by a single export, one entry per data type.
+#[derive(Serialize, Debug, Default)]
+pub struct ExportData {
+    pub feeding: Vec<FeedingRecord>,
+    pub drinking: Vec<DrinkingRecord>,
+    /// A pet's history could not be fetched and is missing from the export
+    #[serde(skip)]
+    pub partial: bool,
+    /// Only written to calendars and databases
+    #[serde(skip)]
+    pub movements: Vec<MovementRecord>,
+    #[serde(skip)]
+    pub curfews: Vec<CurfewRecord>,
+    #[serde(skip)]
+    pub pets: Vec<PetRecord>,
+    #[serde(skip)]
+    pub devices: Vec<DeviceRecord>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+impl ExportFormat {
+    /// Whether amounts are written in `[units]`. The database and line
+    /// protocol keep grams and millilitres, which their columns are named
+    /// after, so appending to them is consistent wherever it's run.
+    pub fn in_units(self) -> bool {
+        matches!(self, ExportFormat::Csv | ExportFormat::Json)
+    }
+}
+
+impl ExportData {
+    /// The feeding and drinking records with their amounts in `units` and
+    /// timestamps in its zone, for the formats that are written in them.
+    /// Convert before rounding, so it's the converted amounts that are
+    /// rounded.
+    pub fn in_units(&self, units: &Units) -> ExportData {
+        ExportData {
+            feeding: self
+                .feeding
+                .iter()
+                .map(|r| FeedingRecord {
+                    amount: units.weight.amount(r.amount),
+                    weight: units.weight,
+                    offset: Some(units.offset_at(&r.timestamp)),
+                    ..r.clone()
+                })
+                .collect(),
+            drinking: self
+                .drinking
+                .iter()
+                .map(|r| DrinkingRecord {
+                    amount: units.volume.amount(r.amount),
+                    volume: units.volume,
+                    offset: Some(units.offset_at(&r.timestamp)),
+                    ..r.clone()
+                })
+                .collect(),
+            partial: self.partial,
+            ..Default::default()
+        }
+    }
+
+    /// Round amounts and truncate timestamps in every record.
+    pub fn round(&mut self, rounding: Rounding) {
+        let amount = |value: f64| match rounding.precision {
+            Some(places) => {
+                let scale = 10f64.powi(places as i32);
+                (value * scale).round() / scale
+            }
+            None => value,
+        };
+        let time = |time: DateTime<Utc>| {
+            let step = match rounding.granularity {
+                Some(TimeGranularity::Second) => Duration::seconds(1),
+                Some(TimeGranularity::Minute) => Duration::minutes(1),
+                Some(TimeGranularity::Hour) => Duration::hours(1),
+                None => return time,
+            };
+            time.duration_trunc(step).unwrap_or(time)
+        };
+
+        for record in &mut self.feeding {
+            record.amount = amount(record.amount);
+            record.timestamp = time(record.timestamp);
+        }
+        for record in &mut self.drinking {
+            record.amount = amount(record.amount);
+            record.timestamp = time(record.timestamp);
+        }
+        for record in &mut self.movements {
+            record.outside_from = time(record.outside_from);
+            record.outside_to = record.outside_to.map(time);
+        }
+    }
+}
+
+/// Fetch the history in `range`, or with `watermarks` only the events newer
+/// than those already exported, however long ago that was. Up to
+/// `concurrency` pets' histories are fetched at once. With `filter` only the
+/// pets and devices it matches are exported.
+pub async fn fetch(
+    api_client: &impl SurePetApi,
+    token: &str,
+    range: TimeRange,
+    watermarks: Option<&Watermarks>,
+    concurrency: usize,
+    filter: Option<&Filter>,
+) -> std::io::Result<ExportData> {
+    let mut pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    if let Some(filter) = filter {
+        pets.retain(|p| filter.matches_pet(p));
+    }
+    // every device still names the events of the pets that are kept
+    let kept = |d: &&Device| filter.is_none_or(|f| f.matches_device(d));
+
+    let mut data = ExportData {
+        pets: pets
+            .iter()
+            .map(|p| PetRecord {
+                id: p.id,
+                name: p.name.clone(),
+            })
+            .collect(),
+        devices: devices
+            .iter()
+            .filter(kept)
+            .map(DeviceRecord::from)
+            .collect(),
+        ..Default::default()
+    };
+    for device in devices.iter().filter(kept) {
+        let curfews = device.control.as_ref().map(|c| c.curfew.as_slice());
+        for curfew in curfews.unwrap_or_default().iter().filter(|c| c.enabled) {
+            let time = |t: &str| NaiveTime::parse_from_str(t, "%H:%M");
+            match (time(&curfew.lock_time), time(&curfew.unlock_time)) {
+                (Ok(lock_time), Ok(unlock_time)) => data.curfews.push(CurfewRecord {
+                    device_id: device.id,
+                    device: device.name.clone(),
+                    lock_time,
+                    unlock_time,
+                }),
+                _ => {
+                    error!("skipping unreadable curfew on {}", device.name);
+                    summary::failed();
+                }
+            }
+        }
+    }
+
+    let device_names: HashMap<u32, String> = devices.into_iter().map(|d| (d.id, d.name)).collect();
+    let device_name = |id: u32| device_names.get(&id).cloned().unwrap_or_default();
+
+    let TimeRange { from, to } = range;
+
+    // in the order of the pets, whichever finishes first
+    let mut reports = stream::iter(&pets)
+        .map(|pet| async move {
+            debug!("Fetching export data for {}", pet.name);
+            let marks = watermarks.and_then(|w| w.pet(pet.id));
+            let from = marks.and_then(|m| m.oldest()).unwrap_or(from);
+            (pet, api_client.get_pet_report(token, pet, from, to).await)
+        })
+        .buffered(concurrency.max(1));
+    let mut progress = Progress::new("Fetching history", pets.len());
+
+    while let Some((pet, report)) = reports.next().await {
+        progress.advance();
+        let marks = watermarks.and_then(|w| w.pet(pet.id));
+        let newer =
+            |mark: Option<DateTime<Utc>>, time: DateTime<Utc>| mark.is_none_or(|m| time > m);
+
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                data.partial = true;
+                continue;
+            }
+        };
+
+        for event in report.feeding.map(|f| f.datapoints).unwrap_or_default() {
+            if !newer(marks.and_then(|m| m.feeding), event.from) {
+                continue;
+            }
+            data.feeding.push(FeedingRecord {
+                pet_id: pet.id,
+                pet: pet.name.clone(),
+                device_id: event.device_id,
+                device: device_name(event.device_id),
+                timestamp: event.from,
+                amount: event.grams_eaten(),
+                weight: Weight::Grams,
+                offset: None,
+            });
+        }
+        for event in report.drinking.map(|d| d.datapoints).unwrap_or_default() {
+            if !newer(marks.and_then(|m| m.drinking), event.from) {
+                continue;
+            }
+            data.drinking.push(DrinkingRecord {
+                pet_id: pet.id,
+                pet: pet.name.clone(),
+                device_id: event.device_id,
+                device: device_name(event.device_id),
+                timestamp: event.from,
+                amount: event.ml_drunk(),
+                volume: Volume::Ml,
+                offset: None,
+            });
+        }
+        for event in report.movement.map(|m| m.datapoints).unwrap_or_default() {
+            if !newer(marks.and_then(|m| m.movement), event.from) {
+                continue;
+            }
+            data.movements.push(MovementRecord {
+                pet_id: pet.id,
+                pet: pet.name.clone(),
+                outside_from: event.from,
+                outside_to: event.to,
+            });
+        }
+    }
+
+    progress.finish();
+
+    Ok(data)
+}
+
+/// A "Fetching history 2/4" line on stderr, redrawn as work completes. Only
+/// shown on a terminal, and not with --quiet.
+struct Progress {
+    term: Option<Term>,
+    label: &'static str,
+    done: usize,
+    total: usize,
+}
+
+impl Progress {
+    fn new(label: &'static str, total: usize) -> Self {
+        let term = Term::stderr();
+        let shown = term.is_term() && output::verbosity() != Verbosity::Quiet;
+        let progress = Progress {
+            term: shown.then_some(term),
+            label,
+            done: 0,
+            total,
+        };
+        progress.draw();
+        progress
+    }
+
+    fn advance(&mut self) {
+        self.done += 1;
+        self.draw();
+    }
+
+    fn draw(&self) {
+        if let Some(term) = &self.term {
+            let line = format!("{} {}/{}", self.label, self.done, self.total);
+            let _ = term.clear_line().and_then(|()| term.write_str(&line));
+        }
+    }
+
+    fn finish(self) {
+        if let Some(term) = &self.term {
+            let _ = term.clear_line();
+        }
+    }
+}
+
+/// How `write` writes its files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriteOptions {
+    /// Add to existing CSV and line protocol files rather than replacing them
+    pub append: bool,
+    /// Compress CSV and JSON files, adding `.gz` or `.zst` to their names
+    pub compression: Option<Compression>,
+}
+
+/// Write the export to `dir`, as `feeding.csv` and `drinking.csv`, a single
+/// `export.json`, `export.ics` or `export.lp`, or into `export.db`. Returns the paths written.
+pub fn write(
+    data: &ExportData,
+    format: ExportFormat,
+    dir: &Path,
+    options: WriteOptions,
+) -> std::io::Result<Vec<String>> {
+    let mut written = Vec::new();
+    let compressed = |name: &str| match options.compression {
+        Some(compression) => dir.join(format!("{}.{}", name, compression.extension())),
+        None => dir.join(name),
+    };
+
+    match format {
+        ExportFormat::Csv => {
+            let feeding = compressed("feeding.csv");
+            write_csv(&data.feeding, &feeding, options)?;
+            written.push(feeding.display().to_string());
+
+            let drinking = compressed("drinking.csv");
+            write_csv(&data.drinking, &drinking, options)?;
+            written.push(drinking.display().to_string());
+        }
+        ExportFormat::Json => {
+            let path = compressed("export.json");
+            let mut out = Output::new(File::create(&path)?, options.compression)?;
+            serde_json::to_writer_pretty(&mut out, data)?;
+            out.finish()?;
+            written.push(path.display().to_string());
+        }
+        ExportFormat::Ics => {
+            let path = dir.join("export.ics");
+            fs::write(&path, ics::calendar(&data.curfews, &data.movements))?;
+            written.push(path.display().to_string());
+        }
+        ExportFormat::Sqlite => {
+            let path = dir.join("export.db");
+            let added = sqlite::append(data, &path).map_err(Error::other)?;
+            written.push(format!("{} ({} new events)", path.display(), added));
+        }
+        ExportFormat::Influx => {
+            let path = dir.join("export.lp");
+            open(&path, options.append)?.write_all(influx::lines(data, Utc::now()).as_bytes())?;
+            written.push(path.display().to_string());
+        }
+    }
+
+    Ok(written)
+}
+
+/// The first `rows` records of each data type, as `write` would format them.
+pub fn preview(data: &ExportData, format: ExportFormat, rows: usize) -> std::io::Result<String> {
+    let feeding = &data.feeding[..rows.min(data.feeding.len())];
+    let drinking = &data.drinking[..rows.min(data.drinking.len())];
+
+    match format {
+        ExportFormat::Csv => {
+            let mut out = String::new();
+            out.push_str("feeding.csv\n");
+            out.push_str(&csv_string(feeding)?);
+            out.push_str("drinking.csv\n");
+            out.push_str(&csv_string(drinking)?);
+            Ok(out)
+        }
+        ExportFormat::Json => {
+            let sample = serde_json::json!({ "feeding": feeding, "drinking": drinking });
+            Ok(serde_json::to_string_pretty(&sample)? + "\n")
+        }
+        ExportFormat::Ics => {
+            let movements = &data.movements[..rows.min(data.movements.len())];
+            Ok(ics::calendar(&data.curfews, movements))
+        }
+        ExportFormat::Sqlite => Ok(format!(
+            "export.db\n  {} pets, {} devices, {} feeding, {} drinking and {} activity events\n",
+            data.pets.len(),
+            data.devices.len(),
+            data.feeding.len(),
+            data.drinking.len(),
+            data.movements.len()
+        )),
+        ExportFormat::Influx => {
+            let sample = ExportData {
+                feeding: feeding.to_vec(),
+                drinking: drinking.to_vec(),
+                movements: data.movements[..rows.min(data.movements.len())].to_vec(),
+                devices: data.devices.clone(),
+                ..Default::default()
+            };
+            Ok(format!("export.lp\n{}", influx::lines(&sample, Utc::now())))
+        }
+    }
+}
+
+fn csv_string<T: Serialize>(records: &[T]) -> std::io::Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in records {
+        writer.serialize(record)?;
+    }
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    String::from_utf8(bytes).map_err(Error::other)
+}
+
+fn write_csv<T: Serialize>(
+    records: &[T],
+    path: &Path,
+    options: WriteOptions,
+) -> std::io::Result<()> {
+    // an appended file already has its header row, which has to match, or
+    // amounts in another unit would end up under its columns
+    let header = match options.append {
+        true => compress::first_line(path, options.compression)?,
+        false => None,
+    };
+    if let (Some(header), Some(record)) = (&header, records.first()) {
+        let columns: Vec<_> = fields_of(record)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        if *header != columns.join(",") {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} has the columns {}, not {}; export with the [units] it was written in or move it aside",
+                    path.display(),
+                    header,
+                    columns.join(",")
+                ),
+            ));
+        }
+    }
+    let has_header = header.is_some();
+    let out = Output::new(open(path, options.append)?, options.compression)?;
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(!has_header)
+        .from_writer(out);
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
+
+fn open(path: &Path, append: bool) -> std::io::Result<File> {
+    if append {
+        OpenOptions::new().create(true).append(true).open(path)
+    } else {
+        File::create(path)
+    }
+}
+
+/// Describe the files `write` produces for `format`, using the record structs
+/// themselves so the documentation can't drift from the exporter.
+/// CSV and JSON amounts are in `units`, named in their columns.
+pub fn schema(format: ExportFormat, units: &Units) -> String {
+    let feeding = FeedingRecord {
+        weight: units.weight,
+        ..Default::default()
+    };
+    let drinking = DrinkingRecord {
+        volume: units.volume,
+        ..Default::default()
+    };
+    let tables = [
+        ("feeding", fields_of(&feeding)),
+        ("drinking", fields_of(&drinking)),
+    ];
+
+    match format {
+        ExportFormat::Csv => {
+            let mut out = String::new();
+            for (name, fields) in &tables {
+                out.push_str(&format!("{}.csv\n", name));
+                for (field, kind) in fields {
+                    out.push_str(&format!("  {:<12} {}\n", field, kind));
+                }
+            }
+            out
+        }
+        ExportFormat::Json => {
+            let mut root = serde_json::Map::new();
+            for (name, fields) in &tables {
+                let record: serde_json::Map<String, serde_json::Value> = fields
+                    .iter()
+                    .map(|(field, kind)| (field.clone(), serde_json::json!(kind)))
+                    .collect();
+                root.insert(name.to_string(), serde_json::json!([record]));
+            }
+            serde_json::to_string_pretty(&root).unwrap()
+        }
+        ExportFormat::Sqlite => sqlite::SCHEMA.to_string(),
+        ExportFormat::Influx => influx::SCHEMA.to_string(),
+        ExportFormat::Ics => "export.ics
+  curfew    a daily recurring event for each enabled flap curfew
+  movement  a timed event for each trip outside
+"
+        .to_string(),
+    }
+}
+
+/// The name and JSON type of each field of a record struct.
+pub fn record_fields<T: Serialize + Default>() -> Vec<(String, String)> {
+    fields_of(&T::default())
+}
+
+/// The name and JSON type of each field of `record`, as it's serialized.
+fn fields_of<T: Serialize>(record: &T) -> Vec<(String, String)> {
+    let mut tracer = Tracer::new(TracerConfig::default());
+    let (format, _) = tracer
+        .trace_value(&mut Samples::new(), record)
+        .expect("export records are plain structs");
+    // a None field traces as an option of unknown type, which is still a field
+    let registry = tracer.registry_unchecked();
+
+    let container = match format {
+        Format::TypeName(name) => registry.get(&name),
+        _ => None,
+    };
+    match container {
+        Some(ContainerFormat::Struct(fields)) => fields
+            .iter()
+            .map(|f| (f.name.clone(), type_name(&f.value).to_string()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn type_name(format: &Format) -> &'static str {
+    match format {
+        Format::Bool => "boolean",
+        Format::Str | Format::Char => "string",
+        Format::F32 | Format::F64 => "number",
+        Format::I8 | Format::I16 | Format::I32 | Format::I64 | Format::I128 => "integer",
+        Format::U8 | Format::U16 | Format::U32 | Format::U64 | Format::U128 => "integer",
+        Format::Option(format) => type_name(format),
+        _ => "value",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn ounces() -> Units {
+        let mut units = config::built_in().units;
+        units.weight = Weight::Ounces;
+        units.volume = Volume::FlOz;
+        units
+    }
+
+    fn data() -> ExportData {
+        let timestamp = "2024-03-01T08:00:00Z".parse().unwrap();
+        ExportData {
+            feeding: vec![FeedingRecord {
+                pet: "Tom".to_string(),
+                timestamp,
+                amount: 56.69904625,
+                ..Default::default()
+            }],
+            drinking: vec![DrinkingRecord {
+                pet: "Tom".to_string(),
+                timestamp,
+                amount: 29.5735295625,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn amounts_are_converted_and_named_after_their_unit() {
+        let data = data();
+        let mut converted = data.in_units(&ounces());
+        converted.round(Rounding {
+            precision: Some(2),
+            granularity: None,
+        });
+
+        let feeding = csv_string(&converted.feeding).unwrap();
+        assert!(
+            feeding.starts_with("pet_id,pet,device_id,device,timestamp,ounces\n"),
+            "{}",
+            feeding
+        );
+        assert!(feeding.contains(",2.0\n"), "{}", feeding);
+        let drinking = csv_string(&converted.drinking).unwrap();
+        assert!(
+            drinking.starts_with("pet_id,pet,device_id,device,timestamp,fl_oz\n"),
+            "{}",
+            drinking
+        );
+        assert!(drinking.contains(",1.0\n"), "{}", drinking);
+
+        // the database keeps grams whatever is shown
+        assert!(csv_string(&data.feeding).unwrap().contains(",grams\n"));
+        assert!(schema(ExportFormat::Csv, &ounces()).contains("ounces"));
+        assert!(schema(ExportFormat::Csv, &config::built_in().units).contains("grams"));
+    }
+
+    #[test]
+    fn timestamps_are_in_the_preferred_zone() {
+        let mut units = config::built_in().units;
+        units.timezone = Some("Europe/London".parse().unwrap());
+        let mut data = data();
+        data.feeding[0].timestamp = "2024-07-01T08:00:00Z".parse().unwrap();
+
+        let london = data.in_units(&units);
+        assert!(csv_string(&london.feeding)
+            .unwrap()
+            .contains(",2024-07-01T09:00:00+01:00,"));
+        // winter in London is UTC
+        assert!(csv_string(&london.drinking)
+            .unwrap()
+            .contains(",2024-03-01T08:00:00Z,"));
+        // the database keeps UTC
+        assert!(csv_string(&data.feeding)
+            .unwrap()
+            .contains(",2024-07-01T08:00:00Z,"));
+    }
+
+    #[test]
+    fn appending_in_another_unit_is_refused() {
+        let dir =
+            std::env::temp_dir().join(format!("rusty_pet_{}_export_units", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let options = WriteOptions {
+            append: true,
+            compression: None,
+        };
+
+        write(&data(), ExportFormat::Csv, &dir, options).unwrap();
+        write(&data(), ExportFormat::Csv, &dir, options).unwrap();
+        let feeding = fs::read_to_string(dir.join("feeding.csv")).unwrap();
+        assert_eq!(feeding.matches("grams").count(), 1, "{}", feeding);
+
+        let err = write(
+            &data().in_units(&ounces()),
+            ExportFormat::Csv,
+            &dir,
+            options,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("grams"), "{}", err);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
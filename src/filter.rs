@@ -0,0 +1,400 @@
+use crate::api::client::{Device, Pet};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A filter expression over pets and devices for `--query`, such as
+/// `location==outside AND battery<30 OR name~"door"`.
+///
+/// A comparison is a field, an operator and a value: `==` (or `=`), `!=`,
+/// `<`, `<=`, `>`, `>=`, or `~` for contains. Text compares ignoring case
+/// and values with spaces are quoted. Comparisons combine with AND, OR and
+/// NOT, AND binding tighter than OR, and group with parentheses. A
+/// comparison on a field something doesn't have, like a pet's battery,
+/// never matches.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct Filter {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    /// `pet` or `device`
+    Kind,
+    Id,
+    Name,
+    Location,
+    /// `true` or `false`
+    Online,
+    Battery,
+    Lock,
+}
+
+const FIELDS: [(&str, Field); 7] = [
+    ("kind", Field::Kind),
+    ("id", Field::Id),
+    ("name", Field::Name),
+    ("location", Field::Location),
+    ("online", Field::Online),
+    ("battery", Field::Battery),
+    ("lock", Field::Lock),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare { field: Field, op: Op, value: String },
+}
+
+/// What a pet or device has for a field.
+enum FieldValue {
+    Text(String),
+    Number(f64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+    Open,
+    Close,
+}
+
+impl Filter {
+    /// Parse an expression, as a clap value parser.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(format!("unexpected {:?} in query", token));
+        }
+        Ok(Filter {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn matches_pet(&self, pet: &Pet) -> bool {
+        self.expr.eval(&|field| match field {
+            Field::Kind => Some(FieldValue::Text("pet".to_string())),
+            Field::Id => Some(FieldValue::Number(pet.id.into())),
+            Field::Name => Some(FieldValue::Text(pet.name.clone())),
+            Field::Location => pet
+                .position
+                .as_ref()
+                .map(|p| FieldValue::Text(p.location.to_string())),
+            Field::Online | Field::Battery | Field::Lock => None,
+        })
+    }
+
+    pub fn matches_device(&self, device: &Device) -> bool {
+        self.expr.eval(&|field| match field {
+            Field::Kind => Some(FieldValue::Text("device".to_string())),
+            Field::Id => Some(FieldValue::Number(device.id.into())),
+            Field::Name => Some(FieldValue::Text(device.name.clone())),
+            Field::Online => device.online().map(|o| FieldValue::Text(o.to_string())),
+            Field::Battery => device
+                .battery_percent()
+                .map(|b| FieldValue::Number(b.into())),
+            Field::Lock => device.lock_mode().map(|m| FieldValue::Text(m.to_string())),
+            Field::Location => None,
+        })
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl TryFrom<String> for Filter {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Self, String> {
+        Filter::parse(&source)
+    }
+}
+
+impl From<Filter> for String {
+    fn from(filter: Filter) -> String {
+        filter.source
+    }
+}
+
+impl Expr {
+    fn eval(&self, get: &dyn Fn(Field) -> Option<FieldValue>) -> bool {
+        match self {
+            Expr::And(a, b) => a.eval(get) && b.eval(get),
+            Expr::Or(a, b) => a.eval(get) || b.eval(get),
+            Expr::Not(a) => !a.eval(get),
+            Expr::Compare { field, op, value } => match get(*field) {
+                None => false,
+                Some(FieldValue::Number(n)) => match value.trim_end_matches('%').parse::<f64>() {
+                    Ok(v) => compare(n.partial_cmp(&v), *op, || n.to_string().contains(value)),
+                    Err(_) => false,
+                },
+                Some(FieldValue::Text(text)) => {
+                    let (text, value) = (text.to_lowercase(), value.to_lowercase());
+                    compare(Some(text.cmp(&value)), *op, || text.contains(&value))
+                }
+            },
+        }
+    }
+}
+
+fn compare(ordering: Option<std::cmp::Ordering>, op: Op, contains: impl Fn() -> bool) -> bool {
+    use std::cmp::Ordering::{Equal, Greater, Less};
+    match (op, ordering) {
+        (Op::Contains, _) => contains(),
+        (_, None) => false,
+        (Op::Eq, Some(o)) => o == Equal,
+        (Op::Ne, Some(o)) => o != Equal,
+        (Op::Lt, Some(o)) => o == Less,
+        (Op::Le, Some(o)) => o != Greater,
+        (Op::Gt, Some(o)) => o == Greater,
+        (Op::Ge, Some(o)) => o != Less,
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => text.extend(chars.next()),
+                        Some(c) => text.push(c),
+                        None => return Err("unclosed quote in query".to_string()),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let equals = chars.next_if_eq(&'=').is_some();
+                let op = match (c, equals) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    ('~', false) => Op::Contains,
+                    ('!', false) => return Err("! must be != in query, use not to negate".into()),
+                    _ => return Err(format!("unknown operator {}= in query", c)),
+                };
+                tokens.push(Token::Op(op));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| !is_special(*c)) {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_special(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '"' | '=' | '!' | '<' | '>' | '~')
+}
+
+/// Recursive descent over the tokens: `or` of `and`s of `unary`s.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(
+            self.tokens.get(self.pos),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword)
+        );
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing ) in query".to_string()),
+                }
+            }
+            Some(Token::Word(name)) => {
+                let field = FIELDS
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+                    .map(|(_, field)| *field)
+                    .ok_or_else(|| {
+                        let known: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+                        format!(
+                            "unknown field {}, expected one of {}",
+                            name,
+                            known.join(", ")
+                        )
+                    })?;
+                let op = match self.next() {
+                    Some(Token::Op(op)) => op,
+                    _ => return Err(format!("expected an operator after {}", name)),
+                };
+                let value = match self.next() {
+                    Some(Token::Word(value) | Token::Quoted(value)) => value,
+                    _ => return Err(format!("expected a value to compare {} with", name)),
+                };
+                Ok(Expr::Compare { field, op, value })
+            }
+            Some(token) => Err(format!("unexpected {:?} in query", token)),
+            None => Err("query ends too early".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pet() -> Pet {
+        serde_json::from_str(
+            r#"{"id": 1, "name": "Tom", "household_id": 9, "tag_id": 5,
+                "position": {"where": 2, "since": "2026-10-16T08:00:00Z"}}"#,
+        )
+        .unwrap()
+    }
+
+    fn flap() -> Device {
+        serde_json::from_str(
+            r#"{"id": 2, "name": "Back door", "product_id": 6,
+                "status": {"online": true, "battery": 5.6}}"#,
+        )
+        .unwrap()
+    }
+
+    fn matches_pet(query: &str) -> bool {
+        Filter::parse(query).unwrap().matches_pet(&pet())
+    }
+
+    #[test]
+    fn comparisons_ignore_case_and_accept_quotes() {
+        assert!(matches_pet("name==tom"));
+        assert!(matches_pet("NAME = TOM"));
+        assert!(matches_pet("name~\"o\""));
+        assert!(matches_pet("location!=inside"));
+        assert!(!matches_pet("name==\"Tom Cat\""));
+        let filter = Filter::parse(r#"name=="Back door""#).unwrap();
+        assert!(filter.matches_device(&flap()));
+    }
+
+    #[test]
+    fn numbers_compare_as_numbers() {
+        assert!(matches_pet("id<2"));
+        assert!(matches_pet("id>=1"));
+        assert!(!matches_pet("id>10"));
+        // 1 isn't less than 10, even though "1" sorts after "10" as text
+        assert!(Filter::parse("id<10").unwrap().matches_pet(&pet()));
+        assert!(!matches_pet("id==one"));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        // read as name==nobody OR (id==1 AND kind==pet)
+        assert!(matches_pet("name==nobody or id==1 and kind==pet"));
+        assert!(!matches_pet("(name==nobody or id==1) and kind==device"));
+        assert!(matches_pet("not kind==device and not (id==2 or id==3)"));
+    }
+
+    #[test]
+    fn fields_something_lacks_never_match() {
+        assert!(!matches_pet("battery<100"));
+        assert!(!matches_pet("battery>=0"));
+        // so the negation does
+        assert!(matches_pet("not battery<100"));
+        assert!(!Filter::parse("location==inside")
+            .unwrap()
+            .matches_device(&flap()));
+    }
+
+    #[test]
+    fn malformed_queries_say_what_is_wrong() {
+        let error = |query: &str| Filter::parse(query).unwrap_err();
+        assert!(error("colour==black").starts_with("unknown field colour"));
+        assert_eq!(error("name"), "expected an operator after name");
+        assert_eq!(error("name=="), "expected a value to compare name with");
+        assert_eq!(error("name==\"Tom"), "unclosed quote in query");
+        assert_eq!(error("(name==Tom"), "missing ) in query");
+        assert_eq!(error("name==Tom and"), "query ends too early");
+        assert_eq!(error(""), "query ends too early");
+        assert!(error("name==Tom)").starts_with("unexpected Close"));
+        assert!(error("name!Tom").starts_with("! must be !="));
+        assert_eq!(error("id~=1"), "unknown operator ~= in query");
+    }
+
+    #[test]
+    fn a_filter_keeps_the_text_it_was_parsed_from() {
+        let query = r#"kind==pet AND name~"to""#;
+        let filter = Filter::try_from(query.to_string()).unwrap();
+        assert_eq!(filter.to_string(), query);
+        assert_eq!(String::from(filter), query);
+    }
+}
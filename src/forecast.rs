@@ -0,0 +1,204 @@
+use crate::api::SurePetApi;
+use crate::battery;
+use crate::range;
+use crate::sqlite;
+use crate::summary;
+use crate::units::Units;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use log::error;
+use serde::Serialize;
+use std::io::Error;
+use std::path::Path;
+
+/// When food and batteries are likely to run out, for `forecast` and the
+/// report. Straight lines through recent use, so rough by design.
+#[derive(Serialize, Debug)]
+pub struct Forecast {
+    pub generated_at: DateTime<Utc>,
+    pub food: FoodForecast,
+    pub batteries: Vec<BatteryForecast>,
+    /// Whether the daemon's recorded battery levels could be read
+    pub battery_history: bool,
+    /// A pet's history could not be fetched and its food is missing
+    pub partial: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FoodForecast {
+    /// Every pet together, over [forecast] burn_days
+    pub grams_per_day: f64,
+    pub days: i64,
+    /// Missing when [forecast] bag_grams isn't set
+    pub bag: Option<BagForecast>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BagForecast {
+    pub grams: f64,
+    pub opened: DateTime<Utc>,
+    pub remaining_grams: f64,
+    /// Missing when the bag is already used up or nothing is being eaten
+    pub runs_out: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct BatteryForecast {
+    pub device: String,
+    pub battery: Option<u8>,
+    /// Negative while draining, missing without enough recorded levels
+    pub percent_per_day: Option<f64>,
+    /// When the battery reaches 0%, missing unless it is draining
+    pub flat: Option<DateTime<Utc>>,
+}
+
+/// Fetch enough feeding history for the burn rate and the current bag, and
+/// read battery levels from the daemon's `status_db` when there is one.
+pub async fn gather(
+    api_client: &impl SurePetApi,
+    token: &str,
+    status_db: Option<&Path>,
+) -> std::io::Result<Forecast> {
+    let cfg = &api_client.cfg().forecast;
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let now = Utc::now();
+
+    let burn_from = now - Duration::days(cfg.burn_days);
+    let opened = cfg
+        .bag_opened
+        .map(|date| date.and_time(NaiveTime::MIN).and_utc())
+        .unwrap_or(now);
+    let mut burnt = 0.0;
+    let mut since_opened = 0.0;
+    let mut partial = false;
+    for pet in &pets {
+        match api_client
+            .get_pet_report(token, pet, burn_from.min(opened), now)
+            .await
+        {
+            Ok(report) => {
+                for meal in report.feeding.map(|f| f.datapoints).unwrap_or_default() {
+                    if meal.from >= burn_from {
+                        burnt += meal.grams_eaten();
+                    }
+                    if meal.from >= opened {
+                        since_opened += meal.grams_eaten();
+                    }
+                }
+            }
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                partial = true;
+            }
+        }
+    }
+    let grams_per_day = burnt / cfg.burn_days as f64;
+    let bag = (cfg.bag_grams > 0.0).then(|| {
+        let remaining_grams = cfg.bag_grams - since_opened;
+        BagForecast {
+            grams: cfg.bag_grams,
+            opened,
+            remaining_grams,
+            runs_out: (remaining_grams > 0.0 && grams_per_day > 0.0)
+                .then(|| range::days_after(now, remaining_grams / grams_per_day))
+                .flatten(),
+        }
+    });
+
+    let states = match status_db.filter(|db| db.exists()) {
+        Some(db) => match sqlite::device_states(db, now) {
+            Ok(states) => Some(states),
+            Err(e) => {
+                error!("failed to read battery history: {}", e);
+                summary::failed();
+                None
+            }
+        },
+        None => None,
+    };
+    let battery_from = now - Duration::days(cfg.battery_days);
+    let batteries = devices
+        .iter()
+        .filter(|d| d.battery_percent().is_some())
+        .map(|device| {
+            let levels = states
+                .as_deref()
+                .map(|states| battery::levels(states, device.id, battery_from))
+                .unwrap_or_default();
+            let battery = device.battery_percent();
+            let percent_per_day = battery::drain_per_day(battery::since_replaced(&levels));
+            BatteryForecast {
+                device: device.name.clone(),
+                battery,
+                percent_per_day,
+                flat: match (battery, percent_per_day) {
+                    (Some(battery), Some(rate)) => {
+                        battery::reaches(now, f64::from(battery), rate, 0.0)
+                    }
+                    _ => None,
+                },
+            }
+        })
+        .collect();
+
+    Ok(Forecast {
+        generated_at: now,
+        food: FoodForecast {
+            grams_per_day,
+            days: cfg.burn_days,
+            bag,
+        },
+        batteries,
+        battery_history: states.is_some(),
+        partial,
+    })
+}
+
+/// The forecast as sentences, shared by the command and the report.
+pub fn describe(forecast: &Forecast, units: &Units) -> Vec<String> {
+    let food = &forecast.food;
+    let mut lines = vec![format!(
+        "Food: {} a day over the last {} days",
+        units.weight(food.grams_per_day, 0),
+        food.days
+    )];
+    if let Some(bag) = &food.bag {
+        lines.push(match bag.runs_out {
+            Some(runs_out) => format!(
+                "Bag of food: about {} left, running out around {}",
+                units.weight(bag.remaining_grams, 0),
+                units.date(&runs_out)
+            ),
+            None if bag.remaining_grams <= 0.0 => {
+                "Bag of food: should be used up by now".to_string()
+            }
+            None => format!(
+                "Bag of food: {} left, nothing eaten lately",
+                units.weight(bag.remaining_grams, 0)
+            ),
+        });
+    }
+    for battery in &forecast.batteries {
+        let level = battery
+            .battery
+            .map_or("-".to_string(), |b| format!("{}%", b));
+        lines.push(match (battery.percent_per_day, battery.flat) {
+            (Some(rate), Some(flat)) => format!(
+                "{} battery: {}, down {:.1}% a day, flat around {}",
+                battery.device,
+                level,
+                -rate,
+                units.date(&flat)
+            ),
+            (Some(_), None) => format!("{} battery: {}, not draining", battery.device, level),
+            (None, _) => format!("{} battery: {}", battery.device, level),
+        });
+    }
+    if !forecast.battery_history && !forecast.batteries.is_empty() {
+        lines.push(
+            "Battery drain is worked out from levels recorded in [daemon] status_db".to_string(),
+        );
+    }
+    lines
+}
@@ -0,0 +1,2320 @@
+use crate::api::cache::{Cache, CacheMode};
+use crate::api::client::{self, Curfew, Device, LockMode, Pet, PetProfile};
+use crate::api::SurePetApi;
+use crate::auth;
+use crate::battery;
+use crate::capabilities;
+use crate::cli::{
+    CacheCommand, Command, CurfewCommand, DeviceCommand, ExportCommand, GlobalArgs,
+    HistorySearchArgs, NotifyCommand, PageArgs, PendingCommand, PetCommand, PhotoCommand,
+    SavedSearchCommand, SearchCommand, ServeCommand,
+};
+use crate::compare;
+use crate::compress::Compression;
+use crate::confirm::confirm;
+use crate::curfew;
+use crate::device_info;
+use crate::diff;
+use crate::doctor;
+use crate::events::{EventCriteria, EventType};
+use crate::export::{
+    self, DeviceRecord, DrinkingRecord, ExportFormat, FeedingRecord, Rounding, WriteOptions,
+};
+use crate::filter::Filter;
+use crate::forecast;
+use crate::health;
+use crate::history::{self, GroupBy};
+use crate::i18n;
+use crate::influx;
+use crate::markdown::MarkdownFormatter;
+use crate::mqtt;
+use crate::notifications::{self, Notification, NotificationKind, StateTracker};
+use crate::outings;
+use crate::output::{
+    self, status, AnomalyList, EventList, Meta, OutputFormatter, OutputMode, PetAnomalies, PetList,
+    PetOutput, PetRow, Verbosity,
+};
+use crate::overview;
+use crate::pending::{Outcome, Pending, PendingOperation, Sent};
+use crate::pet_info;
+use crate::range::TimeRange;
+use crate::report::{self, ReportFormat};
+use crate::rules::{RuleEngine, Rules};
+use crate::schedule::Scheduler;
+use crate::search::{self, MatchMode};
+use crate::searches::SavedSearches;
+use crate::signal::SignalHistory;
+use crate::snooze::{self, Snooze, Snoozes};
+use crate::sqlite;
+use crate::stats;
+use crate::subscriptions::{PetEvent, Subscriptions};
+use crate::summary;
+use crate::template::TemplateFormatter;
+use crate::units::{Units, Volume, Weight};
+use crate::vet_report;
+use crate::views;
+use crate::watermarks::Watermarks;
+use chrono::{NaiveTime, Utc};
+use clap::ValueEnum;
+use console::Term;
+use futures::stream::{self, StreamExt};
+use log::{debug, error};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const REFRESH_HINT: &str = "pass --refresh";
+/// Days of feeding history `list --enrich` looks through for the last meal.
+const ENRICH_DAYS: i64 = 7;
+
+/// The periods the interactive feeding history offers, which `cache warm`
+/// fetches so they're cached under the same keys.
+const WARM_HISTORY_DAYS: [i64; 3] = [1, 7, 30];
+/// Days of history the daemon's first sync into [daemon] history_db fetches,
+/// as `sync` does by default.
+const SYNC_DAYS: i64 = 30;
+
+pub async fn run(
+    api_client: &impl SurePetApi,
+    token: &str,
+    command: Command,
+    global: &GlobalArgs,
+) -> std::io::Result<()> {
+    let assume_yes = global.assume_yes(api_client.cfg());
+    if let Some(capability) = command.needs_capability() {
+        capabilities::probe(api_client, token)
+            .await?
+            .require(capability)?;
+    }
+
+    match command {
+        Command::Login {
+            username,
+            password_stdin,
+        } => {
+            println!(
+                "{}",
+                auth::login_headless(api_client, username, password_stdin).await?
+            );
+            Ok(())
+        }
+        Command::Status {
+            output,
+            template,
+            columns,
+            sort,
+            desc,
+            page,
+        } => {
+            let formatter = template
+                .as_deref()
+                .map(TemplateFormatter::new)
+                .transpose()?;
+            api_client.set_offline_fallback(true);
+            let mut devices = api_client.get_devices(token).await.map_err(Error::other)?;
+            let offline_as_of = api_client.take_offline_as_of();
+            if offline_as_of.is_none() {
+                record_fetched(api_client, &devices);
+            }
+            let preference = &api_client.cfg().headless;
+            if let Some(by) = sort.or(preference.device_sort) {
+                let descending = desc || (sort.is_none() && preference.device_sort_desc);
+                search::sort_devices(&mut devices, by, descending);
+            }
+            page.apply(&mut devices);
+            views::print_offline(offline_as_of, &api_client.cfg().units);
+            if let Some(formatter) = formatter {
+                println!("{}", formatter.devices(&devices));
+                return Ok(());
+            }
+            if !columns.is_empty() {
+                let rows: Vec<_> = devices.iter().map(DeviceRecord::from).collect();
+                return output::print_columns(&rows, &columns, output);
+            }
+            if output == OutputMode::Markdown {
+                println!(
+                    "## Devices\n\n{}",
+                    MarkdownFormatter {
+                        units: &api_client.cfg().units,
+                        compact: false,
+                    }
+                    .devices(&devices)
+                );
+                return Ok(());
+            }
+            if output != OutputMode::Text {
+                let rows: Vec<_> = devices.iter().map(DeviceRecord::from).collect();
+                return output::print_records(&rows, output);
+            }
+            views::print_devices(&devices, &api_client.cfg().units);
+            views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+            Ok(())
+        }
+        Command::List {
+            json: true,
+            enrich,
+            query,
+            page,
+            ..
+        } => {
+            let pets = PetQuery {
+                filter: query.as_ref(),
+                page,
+            };
+            list_json(api_client, token, enrich, pets, global.meta).await
+        }
+        Command::List {
+            output,
+            template,
+            columns,
+            query,
+            page,
+            ..
+        } => {
+            let formatter = template
+                .as_deref()
+                .map(TemplateFormatter::new)
+                .transpose()?;
+            api_client.set_offline_fallback(true);
+            let mut pets = api_client.get_pets(token).await.map_err(Error::other)?;
+            if let Some(query) = &query {
+                pets.retain(|p| query.matches_pet(p));
+            }
+            page.apply(&mut pets);
+            views::print_offline(api_client.take_offline_as_of(), &api_client.cfg().units);
+            if let Some(formatter) = formatter {
+                println!("{}", formatter.pets(&pets));
+                return Ok(());
+            }
+            if !columns.is_empty() {
+                let rows: Vec<_> = pets.iter().map(PetRow::new).collect();
+                return output::print_columns(&rows, &columns, output);
+            }
+            if output == OutputMode::Markdown {
+                println!(
+                    "## Pets\n\n{}",
+                    MarkdownFormatter {
+                        units: &api_client.cfg().units,
+                        compact: false,
+                    }
+                    .pets(&pets)
+                );
+                return Ok(());
+            }
+            if output != OutputMode::Text {
+                let rows: Vec<_> = pets.iter().map(PetRow::new).collect();
+                return output::print_records(&rows, output);
+            }
+            views::print_pets(&pets, &api_client.cfg().units);
+            views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+            Ok(())
+        }
+        Command::Search {
+            command: Some(SearchCommand::Saved { command }),
+            ..
+        } => match command {
+            SavedSearchCommand::List => list_saved_searches(&api_client.cfg().units),
+            SavedSearchCommand::Run { name } => run_saved_search(api_client, token, &name).await,
+            SavedSearchCommand::Delete { name } => delete_saved_search(&name, assume_yes),
+        },
+        Command::Search {
+            patterns, history, ..
+        } if history.history => {
+            let db = api_client.cfg().daemon.history_db_or(history.db.clone());
+            search_history(&patterns, &history, &db, &api_client.cfg().units)
+        }
+        Command::Search {
+            patterns,
+            save,
+            regex,
+            fuzzy,
+            query,
+            ..
+        } => {
+            let mode = match (regex, fuzzy) {
+                (true, _) => MatchMode::Regex,
+                (_, true) => MatchMode::Fuzzy,
+                _ => MatchMode::Substring,
+            };
+            if let Some(name) = save {
+                let mut saved = SavedSearches::load()?;
+                saved.add(&name, &patterns, mode, query.as_ref());
+                saved.save()?;
+                status!("Saved search {}", name);
+                summary::changed();
+            }
+            search(api_client, token, &patterns, mode, query).await
+        }
+        Command::SearchEvents {
+            event_type,
+            pet,
+            range,
+            between,
+            min_amount,
+            max_amount,
+            device,
+            location,
+            json,
+            page,
+        } => {
+            if location.is_some() && event_type != EventType::Activity {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "--location only applies to --type activity",
+                ));
+            }
+            let criteria = EventCriteria {
+                event_type,
+                min_amount,
+                max_amount,
+                device_id: None,
+                location,
+            };
+            let query = EventQuery {
+                pet: pet.as_deref(),
+                device: device.as_deref(),
+                range: between.resolve(range, &api_client.cfg().units)?,
+                page,
+            };
+            search_events(api_client, token, criteria, query, json, global.meta).await
+        }
+        Command::History {
+            pet,
+            days,
+            range,
+            event_type,
+            group_by,
+            output,
+            template,
+            db,
+            page,
+        } => {
+            let formatter = template
+                .as_deref()
+                .map(TemplateFormatter::new)
+                .transpose()?;
+            let query = HistoryQuery {
+                pet: &pet,
+                range: range.resolve(chrono::Duration::days(days), &api_client.cfg().units)?,
+                db: db.as_deref(),
+                page,
+            };
+            history(
+                api_client, token, query, event_type, group_by, output, formatter,
+            )
+            .await
+        }
+        Command::ActivityHistory {
+            pet,
+            days,
+            stats,
+            heatmap,
+            json,
+            db,
+            page,
+        } => {
+            let query = HistoryQuery {
+                pet: &pet,
+                range: TimeRange::last_days(days, Utc::now()),
+                db: db.as_deref(),
+                page,
+            };
+            let view = match (stats, heatmap) {
+                (true, _) => ActivityView::Stats,
+                (_, true) => ActivityView::Heatmap,
+                _ => ActivityView::Outings,
+            };
+            activity_history(api_client, token, query, view, json).await
+        }
+        Command::Compare { pets, days, json } => {
+            compare_pets(api_client, token, &pets, days, json).await
+        }
+        Command::Pet { command } => match command {
+            PetCommand::Info { pet, json } => {
+                pet_info(api_client, token, &pet, json, global.meta).await
+            }
+            PetCommand::Restrict { pet, device } => {
+                set_profile(
+                    api_client,
+                    token,
+                    &pet,
+                    device.as_deref(),
+                    PetProfile::IndoorOnly,
+                    assume_yes,
+                )
+                .await
+            }
+            PetCommand::Allow { pet, device } => {
+                set_profile(
+                    api_client,
+                    token,
+                    &pet,
+                    device.as_deref(),
+                    PetProfile::Outdoor,
+                    assume_yes,
+                )
+                .await
+            }
+            PetCommand::Photo {
+                command: PhotoCommand::Get { pet, output },
+            } => download_photo(api_client, token, &pet, output).await,
+            PetCommand::Photo {
+                command: PhotoCommand::Set { pet, file },
+            } => upload_photo(api_client, token, &pet, &file, assume_yes).await,
+        },
+        Command::Device {
+            command: DeviceCommand::Info { device },
+        } => device_info(api_client, token, &device).await,
+        Command::Device {
+            command: DeviceCommand::OfflineHistory { device, days, db },
+        } => {
+            let cfg = api_client.cfg();
+            let db = cfg.daemon.status_db_or(db);
+            offline_history(device.as_deref(), days, &db, &cfg.units)
+        }
+        Command::Signal {
+            device,
+            interval,
+            count,
+        } => watch_signal(api_client, token, device.as_deref(), interval, count).await,
+        Command::Battery { device, days } => {
+            battery_history(api_client, token, &device, days).await
+        }
+        Command::Curfew { command } => match command {
+            CurfewCommand::Show { device } => {
+                let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+                let flaps = match device {
+                    Some(name) => vec![find_flap(&devices, &name)?],
+                    None => devices.iter().filter(|d| d.is_flap()).collect(),
+                };
+                views::print_curfews(&flaps, &Snoozes::load()?, &api_client.cfg().units);
+                views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+                Ok(())
+            }
+            CurfewCommand::Set {
+                device,
+                lock,
+                unlock,
+                ..
+            } => set_curfew(api_client, token, &device, lock, unlock, assume_yes).await,
+            CurfewCommand::Snooze {
+                device,
+                length,
+                wait,
+            } => snooze_curfew(api_client, token, &device, length, wait).await,
+        },
+        Command::Summary { json } => {
+            let overview = overview::gather(api_client, token).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&overview)?);
+                return Ok(());
+            }
+            views::print_overview(&overview, &api_client.cfg().units);
+            views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+            Ok(())
+        }
+        Command::Anomalies { json } => anomalies(api_client, token, json, global.meta).await,
+        Command::Forecast { json } => {
+            let status_db = api_client.cfg().daemon.status_db.as_deref();
+            let forecast = forecast::gather(api_client, token, status_db).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&forecast)?);
+                return Ok(());
+            }
+            views::print_forecast(&forecast, &api_client.cfg().units);
+            views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+            Ok(())
+        }
+        Command::Health { pet } => check_health(api_client, token, &pet).await,
+        Command::Export {
+            command: Some(ExportCommand::Schema { format }),
+            ..
+        } => {
+            println!("{}", export::schema(format, &api_client.cfg().units));
+            Ok(())
+        }
+        Command::Export {
+            command: None,
+            mut format,
+            all_formats,
+            preview,
+            rows,
+            days,
+            range,
+            output_dir,
+            influxdb,
+            incremental,
+            compress,
+            gzip,
+            precision,
+            time_granularity,
+            utc,
+            concurrency,
+            query,
+        } => {
+            let rounding = Rounding {
+                precision,
+                granularity: time_granularity,
+            };
+            if all_formats {
+                format = ExportFormat::value_variants().to_vec();
+            }
+            format.sort();
+            format.dedup();
+
+            let range = range.resolve(chrono::Duration::days(days), &api_client.cfg().units)?;
+            let mut units = api_client.cfg().units.clone();
+            if utc {
+                units.timezone = Some(chrono_tz::UTC);
+            }
+            if preview {
+                return preview_export(
+                    api_client,
+                    token,
+                    &format,
+                    range,
+                    rows,
+                    rounding,
+                    &units,
+                    query.as_ref(),
+                )
+                .await;
+            }
+
+            // a whole-document format can't be added to
+            if let Some(format) = format
+                .iter()
+                .find(|f| incremental && matches!(f, ExportFormat::Json | ExportFormat::Ics))
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    i18n::message(
+                        "incremental_format",
+                        &[("format", format.to_possible_value().unwrap().get_name())],
+                    ),
+                ));
+            }
+            let mut watermarks = if incremental {
+                Some(Watermarks::load(&output_dir)?)
+            } else {
+                None
+            };
+
+            let concurrency =
+                concurrency.map_or(api_client.cfg().export.concurrency, |c| c as usize);
+            let mut data = export::fetch(
+                api_client,
+                token,
+                range,
+                watermarks.as_ref(),
+                concurrency,
+                query.as_ref(),
+            )
+            .await?;
+            // from the exact times, or rounded-down events would be fetched again
+            if let Some(watermarks) = &mut watermarks {
+                watermarks.update(&data);
+                // appended files can't take back a trip written while the
+                // pet was still out, so it's written once it has ended
+                data.movements.retain(|m| m.outside_to.is_some());
+            }
+            let mut in_units = data.in_units(&units);
+            data.round(rounding);
+            in_units.round(rounding);
+            if global.meta {
+                in_units.meta = Some(Meta::new(api_client, data.partial));
+            }
+            let options = WriteOptions {
+                append: incremental,
+                compression: compress.or(gzip.then_some(Compression::Gzip)),
+            };
+            for format in format {
+                let data = if format.in_units() { &in_units } else { &data };
+                for path in export::write(data, format, &output_dir, options)? {
+                    status!("Wrote {}", path);
+                    summary::changed();
+                }
+            }
+            if let Some(watermarks) = &watermarks {
+                watermarks.save(&output_dir)?;
+                status!(
+                    "Added {} feeding, {} drinking and {} activity events",
+                    data.feeding.len(),
+                    data.drinking.len(),
+                    data.movements.len()
+                );
+            }
+            if influxdb {
+                let body = influx::lines(&data, Utc::now());
+                influx::push(api_client.http(), api_client.cfg().influxdb.as_ref(), body).await?;
+                status!("Sent to InfluxDB");
+                summary::changed();
+            }
+            Ok(())
+        }
+        Command::Report {
+            days,
+            range,
+            format,
+            output,
+            pdf,
+            db,
+        } => {
+            if pdf && format != ReportFormat::Html {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "--pdf needs an HTML report",
+                ));
+            }
+            let output = output.unwrap_or_else(|| format!("report.{}", format.extension()).into());
+            let range = range.resolve(chrono::Duration::days(days), &api_client.cfg().units)?;
+            let report = report::generate(api_client, token, range, db.as_deref()).await?;
+            let contents = match format {
+                ReportFormat::Html => report::render_html(&report, &api_client.cfg().units),
+                ReportFormat::Markdown => report::render_markdown(&report, &api_client.cfg().units),
+            };
+            fs::write(&output, contents)?;
+            status!("Wrote {}", output.display());
+            summary::changed();
+            if pdf {
+                let pdf = output.with_extension("pdf");
+                report::convert_to_pdf(&output, &pdf)?;
+                status!("Wrote {}", pdf.display());
+                summary::changed();
+            }
+            Ok(())
+        }
+        Command::VetReport {
+            pet,
+            range,
+            output,
+            pdf,
+            db,
+        } => {
+            let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+            let pet = find_pet(&pets, &pet)?;
+            let output = output.unwrap_or_else(|| {
+                format!(
+                    "{}-vet-report.html",
+                    output::file_stem(&pet.name.to_lowercase())
+                )
+                .into()
+            });
+            let report = vet_report::generate(api_client, token, pet, range, db.as_deref()).await?;
+            fs::write(
+                &output,
+                vet_report::render_html(&report, &api_client.cfg().units),
+            )?;
+            status!("Wrote {}", output.display());
+            summary::changed();
+            if pdf {
+                let pdf = output.with_extension("pdf");
+                report::convert_to_pdf(&output, &pdf)?;
+                status!("Wrote {}", pdf.display());
+                summary::changed();
+            }
+            Ok(())
+        }
+        Command::Digest {
+            weekly, send, db, ..
+        } => {
+            let (days, title) = if weekly {
+                (7, "Weekly digest")
+            } else {
+                (1, "Daily digest")
+            };
+            let cfg = &api_client.cfg().notifications;
+            if send && !notifications::has_backends(cfg) {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "no notification backends are set up in [notifications]",
+                ));
+            }
+            let range = TimeRange::last_days(days, Utc::now());
+            let report = report::generate(api_client, token, range, db.as_deref()).await?;
+            let digest = report::render_digest(&report, title, &api_client.cfg().units);
+            if !send {
+                print!("{}", digest);
+                return Ok(());
+            }
+            let notification = Notification {
+                event: NotificationKind::Digest,
+                subject: title.to_string(),
+                message: digest,
+                timestamp: Utc::now(),
+            };
+            let failed = notifications::deliver(api_client, &[notification]).await;
+            if failed > 0 {
+                return Err(Error::other(format!(
+                    "the digest could not be delivered to {} backend(s)",
+                    failed
+                )));
+            }
+            status!("Sent the {}", title.to_lowercase());
+            summary::changed();
+            Ok(())
+        }
+        Command::Stats { range, db } => {
+            let db = api_client.cfg().daemon.status_db_or(db);
+            let stats = stats::generate(api_client, token, range, &db).await?;
+            stats::print_stats(&stats, &api_client.cfg().units);
+            views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+            Ok(())
+        }
+        Command::Diff { since, db } => {
+            let db = api_client.cfg().daemon.status_db_or(db);
+            diff(api_client, token, since, &db).await
+        }
+        Command::Doctor => doctor::run(api_client).await,
+        Command::Sync { db, days } => {
+            // events already stored are never fetched again, so fetch them live
+            api_client.set_cache_mode(CacheMode::Off);
+            let db = api_client.cfg().daemon.history_db_or(db);
+            sync(api_client, token, &db, days).await
+        }
+        Command::Serve {
+            command: ServeCommand::Mqtt(args),
+        } => mqtt::serve(api_client, token, args).await,
+        Command::Watch { interval, output } => watch(api_client, token, interval, output).await,
+        Command::Daemon { interval, rules } => {
+            daemon(api_client, token, interval, rules.as_deref()).await
+        }
+        Command::Schedule { rules } => {
+            let rules = Rules::load(rules.as_deref())?;
+            views::print_schedules(&rules.schedules, Utc::now(), &api_client.cfg().units);
+            Ok(())
+        }
+        Command::Notify { command } => match command {
+            NotifyCommand::Subscribe { pet, events } => {
+                subscribe(api_client, token, &pet, &events).await
+            }
+            NotifyCommand::Unsubscribe { pet, events } => unsubscribe(&pet, &events, assume_yes),
+            NotifyCommand::List => list_subscriptions(),
+        },
+        Command::Pending { command } => match command {
+            PendingCommand::List => {
+                views::print_pending(&Pending::load()?, &api_client.cfg().units);
+                Ok(())
+            }
+            PendingCommand::Replay => replay_pending(api_client, token).await,
+            PendingCommand::Clear => {
+                let mut pending = Pending::load()?;
+                let dropped = pending.operations.len();
+                // nothing can bring the changes back
+                if dropped > 0
+                    && !confirm(format!("Drop {} queued change(s)?", dropped), assume_yes)?
+                {
+                    return Ok(());
+                }
+                pending.operations.clear();
+                pending.save()?;
+                status!("Dropped {} queued change(s)", dropped);
+                Ok(())
+            }
+        },
+        Command::Cache { command } => {
+            let Some(cache) = api_client.cache() else {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "there's no response cache with [api] mock_dir set",
+                ));
+            };
+            match command {
+                CacheCommand::Stats => cache_stats(cache),
+                CacheCommand::Clear { kind: None } => {
+                    cache.clear();
+                    status!("Cleared the cache");
+                    Ok(())
+                }
+                CacheCommand::Clear { kind: Some(kind) } => {
+                    let cleared = cache.clear_kind(kind).map_err(Error::other)?;
+                    status!("Cleared {} cached response(s)", cleared);
+                    Ok(())
+                }
+                CacheCommand::Warm => warm_cache(api_client, token).await,
+            }
+        }
+    }
+}
+
+/// Make the changes queued while the API was unreachable.
+async fn replay_pending(api_client: &impl SurePetApi, token: &str) -> std::io::Result<()> {
+    let mut pending = Pending::load()?;
+    if pending.operations.is_empty() {
+        return Ok(());
+    }
+    let outcomes = pending.replay(api_client, token).await?;
+    // nothing was sent, so everything is still to do
+    if !api_client.dry_run() {
+        pending.save()?;
+    }
+    views::print_replay(&outcomes);
+    for (_, outcome) in &outcomes {
+        match outcome {
+            Outcome::Applied => summary::changed(),
+            Outcome::Conflict(_) | Outcome::Failed(_) => summary::failed(),
+            Outcome::Unreachable => {}
+        }
+    }
+    Ok(())
+}
+
+fn cache_stats(cache: &Cache) -> std::io::Result<()> {
+    let Some(stats) = cache.stats().map_err(Error::other)? else {
+        println!("The cache is disabled, see [cache] enabled");
+        return Ok(());
+    };
+    let bytes: usize = stats.entries.iter().map(|e| e.bytes).sum();
+    println!(
+        "{}: {} response(s), {:.1} KiB",
+        stats.path.display(),
+        stats.entries.len(),
+        bytes as f64 / 1024.0
+    );
+    for entry in &stats.entries {
+        let age = chrono::Duration::from_std(entry.age).unwrap_or(chrono::Duration::MAX);
+        println!(
+            "  {:<20} {:>8.1} KiB  {:>8} old{}",
+            entry.key,
+            entry.bytes as f64 / 1024.0,
+            stats::format_duration(age),
+            if entry.expired { ", expired" } else { "" }
+        );
+    }
+    let lookups = stats.hits + stats.misses;
+    if lookups > 0 {
+        println!(
+            "{} hit(s), {} miss(es), {:.0}% served from the cache",
+            stats.hits,
+            stats.misses,
+            stats.hits as f64 * 100.0 / lookups as f64
+        );
+    }
+    Ok(())
+}
+
+/// Fetch what the interactive views show first: pets, devices, and each
+/// pet's history over the periods feeding history offers.
+async fn warm_cache(api_client: &impl SurePetApi, token: &str) -> std::io::Result<()> {
+    api_client.set_cache_mode(CacheMode::Refresh);
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    api_client.get_devices(token).await.map_err(Error::other)?;
+
+    let to = Utc::now();
+    for pet in &pets {
+        for days in WARM_HISTORY_DAYS {
+            let from = to - chrono::Duration::days(days);
+            if let Err(e) = api_client.get_pet_report(token, pet, from, to).await {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+            }
+        }
+    }
+    status!(
+        "Cached {} pet(s), their devices and {} day(s) of history",
+        pets.len(),
+        WARM_HISTORY_DAYS.iter().max().unwrap()
+    );
+    Ok(())
+}
+
+/// Which of the pets `list` shows.
+struct PetQuery<'a> {
+    filter: Option<&'a Filter>,
+    page: PageArgs,
+}
+
+async fn list_json(
+    api_client: &impl SurePetApi,
+    token: &str,
+    enrich: bool,
+    query: PetQuery<'_>,
+    meta: bool,
+) -> std::io::Result<()> {
+    api_client.set_offline_fallback(true);
+    let mut pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    if let Some(filter) = query.filter {
+        pets.retain(|p| filter.matches_pet(p));
+    }
+    query.page.apply(&mut pets);
+    let data_as_of = api_client.take_offline_as_of();
+    let now = Utc::now();
+
+    let mut output = Vec::new();
+    let mut partial = false;
+    for pet in &pets {
+        let mut pet_output = PetOutput::new(pet);
+        if enrich {
+            let from = now - chrono::Duration::days(ENRICH_DAYS);
+            let last_fed = match api_client.get_pet_report(token, pet, from, now).await {
+                Ok(report) => report
+                    .feeding
+                    .and_then(|f| f.datapoints.iter().map(|e| e.from).max()),
+                Err(e) => {
+                    error!("failed to fetch history for {}: {}", pet.name, e);
+                    summary::failed();
+                    partial = true;
+                    None
+                }
+            };
+            pet_output.enrich(pet, last_fed, now);
+        }
+        output.push(pet_output);
+    }
+
+    let list = PetList {
+        pets: output,
+        stale: data_as_of.is_some(),
+        data_as_of,
+        meta: meta.then(|| Meta::new(api_client, partial)),
+    };
+    println!("{}", serde_json::to_string_pretty(&list)?);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn preview_export(
+    api_client: &impl SurePetApi,
+    token: &str,
+    formats: &[ExportFormat],
+    range: TimeRange,
+    rows: usize,
+    rounding: Rounding,
+    units: &Units,
+    query: Option<&Filter>,
+) -> std::io::Result<()> {
+    let (days, sample_days) = (range.days(), range.days().min(export::PREVIEW_DAYS));
+    let sample = TimeRange {
+        from: range
+            .from
+            .max(range.to - chrono::Duration::days(sample_days)),
+        to: range.to,
+    };
+    let mut data = export::fetch(
+        api_client,
+        token,
+        sample,
+        None,
+        api_client.cfg().export.concurrency,
+        query,
+    )
+    .await?;
+    let mut in_units = data.in_units(units);
+    data.round(rounding);
+    in_units.round(rounding);
+
+    for format in formats {
+        let data = if format.in_units() { &in_units } else { &data };
+        print!("{}", export::preview(data, *format, rows)?);
+    }
+
+    // scale the sample up to the full period, assuming activity is steady
+    let estimate = |sampled: usize| sampled as i64 * days / sample_days.max(1);
+    println!(
+        "Estimated {} feeding and {} drinking records over {} days",
+        estimate(data.feeding.len()),
+        estimate(data.drinking.len()),
+        days
+    );
+    if data.partial {
+        println!("Some pets' history could not be fetched, so the estimate is low");
+    }
+
+    Ok(())
+}
+
+async fn daemon(
+    api_client: &impl SurePetApi,
+    token: &str,
+    interval: Option<u64>,
+    rules_file: Option<&Path>,
+) -> std::io::Result<()> {
+    let rules = Rules::load(rules_file)?;
+    let interval =
+        Duration::from_secs(interval.unwrap_or(api_client.cfg().daemon.poll_interval_secs));
+    status!(
+        "Evaluating {} rule(s) and {} schedule(s) every {}s",
+        rules.rules.len(),
+        rules.schedules.len(),
+        interval.as_secs()
+    );
+
+    // rules act on the current state, never a cached one
+    api_client.set_cache_mode(CacheMode::Off);
+
+    let mut engine = RuleEngine::default();
+    let mut scheduler = Scheduler::default();
+    let mut tracker = StateTracker::default();
+    let mut recorded = HashMap::new();
+    let sync_interval = Duration::from_secs(api_client.cfg().daemon.sync_interval_secs);
+    let mut last_sync: Option<std::time::Instant> = None;
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let polled = async {
+            let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+            let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+            Ok::<_, Error>((pets, devices))
+        };
+        match polled.await {
+            Ok((pets, devices)) => {
+                if let Some(db) = &api_client.cfg().daemon.status_db {
+                    record_states(db, &devices, &mut recorded);
+                }
+                if let Some(db) = &api_client.cfg().daemon.history_db {
+                    if last_sync.is_none_or(|t| t.elapsed() >= sync_interval) {
+                        match sync(api_client, token, db, SYNC_DAYS).await {
+                            Ok(()) => last_sync = Some(std::time::Instant::now()),
+                            Err(e) => error!("Failed to sync history: {}", e),
+                        }
+                    }
+                }
+                notifications::dispatch(api_client, token, &mut tracker, &pets, &devices).await;
+                engine
+                    .evaluate(&rules, api_client, token, &pets, &devices)
+                    .await;
+                scheduler
+                    .run(&rules.schedules, api_client, token, &devices)
+                    .await;
+                // the API is back, so make anything queued while it wasn't
+                if let Err(e) = replay_pending(api_client, token).await {
+                    error!("Failed to replay queued changes: {}", e);
+                }
+                if let Err(e) = restore_snoozes(api_client, token).await {
+                    error!("Failed to restore snoozed flaps: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to poll SurePet data: {}", e),
+        }
+        views::print_planned(&api_client.take_planned(), false);
+    }
+}
+
+/// Fetch the events newer than those in `db` and add them to it. Pets with
+/// nothing stored yet get the last `days` of history.
+async fn sync(
+    api_client: &impl SurePetApi,
+    token: &str,
+    db: &Path,
+    days: i64,
+) -> std::io::Result<()> {
+    let watermarks = sqlite::watermarks(db).map_err(Error::other)?;
+    let data = export::fetch(
+        api_client,
+        token,
+        TimeRange::last_days(days, Utc::now()),
+        Some(&watermarks),
+        api_client.cfg().export.concurrency,
+        None,
+    )
+    .await?;
+    let added = sqlite::append(&data, db).map_err(Error::other)?;
+    if added > 0 {
+        summary::changed();
+    }
+    status!("Synced {} new events into {}", added, db.display());
+    Ok(())
+}
+
+/// Store each device's connection, battery and lock state in `db` when any
+/// of them changes, and on the first poll so every period starts from a
+/// known state.
+fn record_states(db: &Path, devices: &[Device], last: &mut HashMap<u32, DeviceRecord>) {
+    let now = Utc::now();
+    for device in devices {
+        let record = DeviceRecord::from(device);
+        let unchanged = last.get(&device.id).is_some_and(|l| {
+            (l.online, l.battery, &l.lock) == (record.online, record.battery, &record.lock)
+        });
+        if unchanged {
+            continue;
+        }
+        match sqlite::record_state(db, &record, now) {
+            Ok(()) => {
+                last.insert(device.id, record);
+            }
+            Err(e) => {
+                error!("Failed to record state of {}: {}", device.name, e);
+                summary::failed();
+            }
+        }
+    }
+}
+
+/// Add the state of devices just fetched to [daemon] status_db where it
+/// changed, so battery history builds up without the daemon running.
+fn record_fetched(api_client: &impl SurePetApi, devices: &[Device]) {
+    let Some(db) = &api_client.cfg().daemon.status_db else {
+        return;
+    };
+    let records: Vec<DeviceRecord> = devices.iter().map(DeviceRecord::from).collect();
+    if let Err(e) = sqlite::record_changes(db, &records, Utc::now()) {
+        error!("Failed to record device status in {}: {}", db.display(), e);
+        summary::failed();
+    }
+}
+
+async fn diff(
+    api_client: &impl SurePetApi,
+    token: &str,
+    since: chrono::Duration,
+    db: &Path,
+) -> std::io::Result<()> {
+    if !db.exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{} not found, create it with export --format sqlite",
+                db.display()
+            ),
+        ));
+    }
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+
+    let since = Utc::now() - since;
+    let changes = diff::changes(db, since, &pets, &devices).map_err(Error::other)?;
+    let at = api_client.cfg().units.time(&since);
+    if changes.is_empty() {
+        println!("Nothing changed since {}", at);
+    } else {
+        println!("Since {}:", at);
+    }
+    for change in &changes {
+        println!("  {}", change);
+    }
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+async fn search(
+    api_client: &impl SurePetApi,
+    token: &str,
+    patterns: &[String],
+    mode: MatchMode,
+    filter: Option<Filter>,
+) -> std::io::Result<()> {
+    let wanted = search::describe(patterns, filter.as_ref());
+    let query = search::Query::new(patterns, mode)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?
+        .filtered(filter);
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+
+    let hits = search::search_state(&pets, &devices, &query);
+    views::print_search_hits(&hits, &wanted);
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+async fn run_saved_search(
+    api_client: &impl SurePetApi,
+    token: &str,
+    name: &str,
+) -> std::io::Result<()> {
+    let mut saved = SavedSearches::load()?;
+    let (patterns, mode, filter) = match saved.take(name) {
+        Some(search) => (search.patterns.clone(), search.mode, search.query.clone()),
+        None => {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no saved search called {}, see search saved list", name),
+            ))
+        }
+    };
+    saved.save()?;
+    search(api_client, token, &patterns, mode, filter).await
+}
+
+/// Which pets and devices `search-events` looks at, and how far back.
+struct EventQuery<'a> {
+    pet: Option<&'a str>,
+    device: Option<&'a str>,
+    range: TimeRange,
+    page: PageArgs,
+}
+
+async fn search_events(
+    api_client: &impl SurePetApi,
+    token: &str,
+    mut criteria: EventCriteria,
+    query: EventQuery<'_>,
+    json: bool,
+    meta: bool,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let pets = match query.pet {
+        Some(name) => vec![find_pet(&pets, name)?],
+        None => pets.iter().collect(),
+    };
+    if let Some(name) = query.device {
+        criteria.device_id = Some(find_device(&devices, name)?.id);
+    }
+    let device_names: HashMap<u32, String> =
+        devices.iter().map(|d| (d.id, d.name.clone())).collect();
+
+    let TimeRange { from, to } = query.range;
+    let mut events = Vec::new();
+    let mut partial = false;
+    for pet in pets {
+        match api_client.get_pet_report(token, pet, from, to).await {
+            Ok(report) => events.extend(criteria.matching(pet, report, &device_names, to)),
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                partial = true;
+            }
+        }
+    }
+    events.sort_by_key(|e| e.from);
+    query.page.apply(&mut events);
+
+    if json {
+        let list = EventList {
+            events,
+            partial,
+            meta: meta.then(|| Meta::new(api_client, partial)),
+        };
+        println!("{}", serde_json::to_string_pretty(&list)?);
+        return Ok(());
+    }
+    views::print_event_hits(&events, &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+fn list_saved_searches(units: &Units) -> std::io::Result<()> {
+    let saved = SavedSearches::load()?;
+    if saved.searches.is_empty() {
+        println!("No saved searches, save one with search --save NAME");
+    }
+    for search in saved.by_use() {
+        let last_used = search
+            .last_used
+            .map_or("never".to_string(), |at| units.time(&at));
+        println!(
+            "{}: {} (used {} time(s), last {})",
+            search.name,
+            search::describe(&search.patterns, search.query.as_ref()),
+            search.uses,
+            last_used
+        );
+    }
+
+    Ok(())
+}
+
+fn delete_saved_search(name: &str, assume_yes: bool) -> std::io::Result<()> {
+    let mut saved = SavedSearches::load()?;
+    if !saved.remove(name) {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no saved search called {}", name),
+        ));
+    }
+    if !confirm(format!("Delete the saved search {}?", name), assume_yes)? {
+        return Ok(());
+    }
+    saved.save()?;
+    status!("Deleted saved search {}", name);
+    summary::changed();
+
+    Ok(())
+}
+
+fn search_history(
+    patterns: &[String],
+    args: &HistorySearchArgs,
+    db: &Path,
+    units: &Units,
+) -> std::io::Result<()> {
+    if !db.exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{} not found, create it with export --format sqlite or sync",
+                db.display()
+            ),
+        ));
+    }
+
+    let query = sqlite::HistoryQuery {
+        patterns,
+        // whole days in the [units] timezone
+        since: args.since.map(|date| units.start_of_date(date)),
+        until: args
+            .until
+            .and_then(|date| date.succ_opt())
+            .map(|date| units.start_of_date(date)),
+        limit: args.limit,
+        page: args.page,
+    };
+    let (rows, total) = sqlite::search_history(db, &query).map_err(Error::other)?;
+
+    for row in &rows {
+        let pet = search::highlight(&row.pet, &search::find_matches(&row.pet, patterns));
+        let device = row
+            .device
+            .as_deref()
+            .map(|d| search::highlight(d, &search::find_matches(d, patterns)))
+            .unwrap_or_default();
+        let detail = match (row.kind.as_str(), row.amount, row.back) {
+            ("feeding", Some(grams), _) => units.weight(grams, 1),
+            ("drinking", Some(ml), _) => units.volume(ml, 1),
+            (_, _, Some(back)) => format!("until {}", units.time(&back)),
+            _ => "still out".to_string(),
+        };
+        println!(
+            "{}  {:<8}  {}  {}  {}",
+            units.time(&row.timestamp),
+            row.kind,
+            pet,
+            detail,
+            device
+        );
+    }
+
+    let first = query.offset();
+    let last = first.saturating_add(rows.len() as u32);
+    if total == 0 {
+        println!("No stored events matched");
+    } else if last < total {
+        println!(
+            "Events {}-{} of {}, see more with --page {}",
+            first + 1,
+            last,
+            total,
+            args.page.saturating_add(1)
+        );
+    }
+
+    Ok(())
+}
+
+fn offline_history(
+    device_name: Option<&str>,
+    days: i64,
+    db: &Path,
+    units: &Units,
+) -> std::io::Result<()> {
+    if !db.exists() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{} not found, set [daemon] status_db to record device status",
+                db.display()
+            ),
+        ));
+    }
+    let names = sqlite::device_names(db).map_err(Error::other)?;
+    let mut ids: Vec<u32> = match device_name {
+        Some(name) => {
+            let id = names
+                .iter()
+                .find(|(id, n)| id.to_string() == name || n.eq_ignore_ascii_case(name))
+                .map(|(id, _)| *id)
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::NotFound, format!("no device called {}", name))
+                })?;
+            vec![id]
+        }
+        None => names.keys().copied().collect(),
+    };
+    ids.sort_by_key(|id| &names[id]);
+
+    let now = Utc::now();
+    let from = now - chrono::Duration::days(days);
+    let states = sqlite::device_states(db, now).map_err(Error::other)?;
+    for id in ids {
+        let history: Vec<_> = states.iter().filter(|s| s.device_id == id).collect();
+        if history.is_empty() {
+            println!("{}: no status recorded", names[&id]);
+            continue;
+        }
+
+        let outages = stats::outages(&history, from);
+        let total = outages
+            .iter()
+            .fold(chrono::Duration::zero(), |total, o| total + o.duration(now));
+        println!(
+            "{}: {} outage(s), {} offline in the last {} days",
+            names[&id],
+            outages.len(),
+            stats::format_duration(total),
+            days
+        );
+        for outage in &outages {
+            let end = match outage.end {
+                Some(end) => units.time(&end),
+                None => "still offline".to_string(),
+            };
+            println!(
+                "  {} - {:<16}  {}",
+                units.time(&outage.start),
+                end,
+                stats::format_duration(outage.duration(now))
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Whose history `history` and `activity-history` show, from when and
+/// where.
+struct HistoryQuery<'a> {
+    pet: &'a str,
+    range: TimeRange,
+    /// A database kept by `sync`, instead of the API
+    db: Option<&'a Path>,
+    page: PageArgs,
+}
+
+async fn history(
+    api_client: &impl SurePetApi,
+    token: &str,
+    query: HistoryQuery<'_>,
+    event_type: EventType,
+    group_by: Option<GroupBy>,
+    output: OutputMode,
+    template: Option<TemplateFormatter>,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, query.pet)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device_names: HashMap<u32, String> = devices.into_iter().map(|d| (d.id, d.name)).collect();
+
+    let TimeRange { from, to } = query.range;
+    let report = match query.db {
+        Some(db) => sqlite::pet_report(db, pet.id, from, to).map_err(Error::other)?,
+        None => api_client
+            .get_pet_report(token, pet, from, to)
+            .await
+            .map_err(Error::other)?,
+    };
+
+    let cfg = api_client.cfg();
+    let (units, compact) = (&cfg.units, cfg.headless.compact);
+    let page = query.page.or_limit(cfg.headless.max_history_items);
+    let markdown = MarkdownFormatter { units, compact };
+    // the template or Markdown, when either was asked for
+    let formatted =
+        |title: &str, render: &dyn Fn(&dyn OutputFormatter) -> String| match (&template, output) {
+            (Some(template), _) => Some(render(template)),
+            (None, OutputMode::Markdown) => Some(format!(
+                "## {} for {}\n\n{}",
+                title,
+                pet.name,
+                render(&markdown)
+            )),
+            _ => None,
+        };
+    let device_name = |id: u32| device_names.get(&id).cloned().unwrap_or_default();
+
+    if let Some(by) = group_by {
+        let amounts: Vec<_> = match event_type {
+            EventType::Feeding => report
+                .feeding
+                .map(|f| f.datapoints)
+                .unwrap_or_default()
+                .iter()
+                .map(|e| (e.from, e.grams_eaten()))
+                .collect(),
+            EventType::Drinking => report
+                .drinking
+                .map(|d| d.datapoints)
+                .unwrap_or_default()
+                .iter()
+                .map(|e| (e.from, e.ml_drunk()))
+                .collect(),
+            EventType::Activity => {
+                let movement = report.movement.map(|m| m.datapoints).unwrap_or_default();
+                outings::outings(&movement, from, to, &device_names)
+                    .0
+                    .iter()
+                    .map(|o| (o.from, o.minutes as f64))
+                    .collect()
+            }
+        };
+        let all = history::group(amounts.into_iter(), from, to, by, units);
+        let periods = page.slice(&all);
+        let title = match by {
+            GroupBy::Day => format!("{} by day", history::title(event_type)),
+            GroupBy::Week => format!("{} by week", history::title(event_type)),
+        };
+        if let Some(text) = formatted(&title, &|f| {
+            f.history_periods(periods, &all, by, event_type)
+        }) {
+            println!("{}", text);
+            return Ok(());
+        }
+        if output != OutputMode::Text {
+            return output::print_records(periods, output);
+        }
+        views::print_history_periods(&title, pet, periods, &all, by, event_type, units);
+        views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+        return Ok(());
+    }
+
+    match event_type {
+        EventType::Feeding => {
+            let all = report.feeding.map(|f| f.datapoints).unwrap_or_default();
+            let events = page.slice(&all);
+            if let Some(text) = formatted(history::title(event_type), &|f| {
+                f.feeding_history(events, &all, &device_names)
+            }) {
+                println!("{}", text);
+                return Ok(());
+            }
+            if output != OutputMode::Text {
+                let records: Vec<_> = events
+                    .iter()
+                    .map(|event| FeedingRecord {
+                        pet_id: pet.id,
+                        pet: pet.name.clone(),
+                        device_id: event.device_id,
+                        device: device_name(event.device_id),
+                        timestamp: event.from,
+                        amount: event.grams_eaten(),
+                        weight: Weight::Grams,
+                        offset: None,
+                    })
+                    .collect();
+                return output::print_records(&records, output);
+            }
+            views::print_feeding_history_page(pet, events, &all, &device_names, units, compact);
+            views::print_shown(events.len(), all.len());
+        }
+        EventType::Drinking => {
+            let all = report.drinking.map(|d| d.datapoints).unwrap_or_default();
+            let events = page.slice(&all);
+            if let Some(text) = formatted(history::title(event_type), &|f| {
+                f.drinking_history(events, &all, &device_names)
+            }) {
+                println!("{}", text);
+                return Ok(());
+            }
+            if output != OutputMode::Text {
+                let records: Vec<_> = events
+                    .iter()
+                    .map(|event| DrinkingRecord {
+                        pet_id: pet.id,
+                        pet: pet.name.clone(),
+                        device_id: event.device_id,
+                        device: device_name(event.device_id),
+                        timestamp: event.from,
+                        amount: event.ml_drunk(),
+                        volume: Volume::Ml,
+                        offset: None,
+                    })
+                    .collect();
+                return output::print_records(&records, output);
+            }
+            views::print_drinking_history(pet, events, &all, &device_names, units, compact);
+            views::print_shown(events.len(), all.len());
+        }
+        EventType::Activity => {
+            let movement = report.movement.map(|m| m.datapoints).unwrap_or_default();
+            let (all, unpaired) = outings::outings(&movement, from, to, &device_names);
+            let trips = page.slice(&all);
+            if let Some(text) = formatted(history::title(event_type), &|f| {
+                f.activity_history(trips, &all, unpaired)
+            }) {
+                println!("{}", text);
+                return Ok(());
+            }
+            if output != OutputMode::Text {
+                return output::print_records(trips, output);
+            }
+            views::print_activity_history(pet, trips, &all, unpaired, units, compact);
+            views::print_shown(trips.len(), all.len());
+        }
+    }
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+/// What `activity-history` shows.
+enum ActivityView {
+    Outings,
+    Stats,
+    Heatmap,
+}
+
+async fn activity_history(
+    api_client: &impl SurePetApi,
+    token: &str,
+    query: HistoryQuery<'_>,
+    view: ActivityView,
+    json: bool,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, query.pet)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device_names: HashMap<u32, String> = devices.into_iter().map(|d| (d.id, d.name)).collect();
+
+    let units = &api_client.cfg().units;
+    let (to, days) = (query.range.to, query.range.days());
+    // a day more, so trips reaching into the first day count towards it
+    let from = units.start_of_day(&to) - chrono::Duration::days(days);
+    let report = match query.db {
+        Some(db) => sqlite::pet_report(db, pet.id, from, to).map_err(Error::other)?,
+        None => api_client
+            .get_pet_report(token, pet, from, to)
+            .await
+            .map_err(Error::other)?,
+    };
+    let movement = report.movement.map(|m| m.datapoints).unwrap_or_default();
+
+    let since = query.range.from;
+    match view {
+        ActivityView::Outings => {
+            let (all, unpaired) = outings::outings(&movement, since, to, &device_names);
+            let headless = &api_client.cfg().headless;
+            let trips = query.page.or_limit(headless.max_history_items).slice(&all);
+            if json {
+                println!("{}", serde_json::to_string_pretty(trips)?);
+                return Ok(());
+            }
+            views::print_activity_history(pet, trips, &all, unpaired, units, headless.compact);
+            views::print_shown(trips.len(), all.len());
+        }
+        ActivityView::Stats => {
+            let stats = outings::outing_stats(&movement, days, to, &device_names, units);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+                return Ok(());
+            }
+            views::print_outing_stats(pet, &stats, units);
+        }
+        ActivityView::Heatmap => {
+            let heatmap = outings::heatmap(&movement, since, units);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&heatmap)?);
+                return Ok(());
+            }
+            views::print_heatmap(pet, &heatmap, days, units);
+        }
+    }
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+async fn compare_pets(
+    api_client: &impl SurePetApi,
+    token: &str,
+    names: &[String],
+    days: i64,
+    json: bool,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let compared = names
+        .iter()
+        .map(|name| find_pet(&pets, name))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    let comparison = compare::compare(api_client, token, &compared, days).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&comparison)?);
+        return Ok(());
+    }
+    views::print_comparison(&comparison, &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+async fn watch(
+    api_client: &impl SurePetApi,
+    token: &str,
+    interval: Option<u64>,
+    output: OutputMode,
+) -> std::io::Result<()> {
+    let cfg = &api_client.cfg().notifications;
+    let units = &api_client.cfg().units;
+    let interval = Duration::from_secs(interval.unwrap_or(cfg.watch_interval_secs));
+    // kept off stdout, which is only for notifications when streaming
+    if output::verbosity() > Verbosity::Quiet {
+        eprintln!(
+            "Watching for changes every {}s, notifying {} webhook(s)",
+            interval.as_secs(),
+            cfg.webhooks.len()
+        );
+    }
+
+    // each poll has to see fresh data to notice changes
+    api_client.set_cache_mode(CacheMode::Off);
+
+    let mut tracker = StateTracker::default();
+    let mut csv = csv::Writer::from_writer(std::io::stdout());
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+
+        let polled = async {
+            let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+            let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+            Ok::<_, Error>((pets, devices))
+        };
+        match polled.await {
+            Ok((pets, devices)) => {
+                for notification in
+                    notifications::dispatch(api_client, token, &mut tracker, &pets, &devices).await
+                {
+                    match output {
+                        OutputMode::Text => {
+                            let time = units.time(&notification.timestamp);
+                            println!("{} {}", time, notification.message)
+                        }
+                        OutputMode::Markdown => {
+                            let time = units.time(&notification.timestamp);
+                            println!("- {} {}", time, notification.message)
+                        }
+                        OutputMode::Ndjson => output::print_ndjson(&notification)?,
+                        OutputMode::Csv => {
+                            csv.serialize(&notification)?;
+                            csv.flush()?;
+                        }
+                    }
+                }
+            }
+            Err(e) => error!("Failed to poll SurePet data: {}", e),
+        }
+    }
+}
+
+async fn subscribe(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet_name: &str,
+    events: &[PetEvent],
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, pet_name)?;
+
+    let mut subscriptions = Subscriptions::load()?;
+    subscriptions.subscribe(pet.id, &pet.name, events);
+    subscriptions.save()?;
+    status!("Subscribed to {:?} for {}", events, pet.name);
+    summary::changed();
+
+    Ok(())
+}
+
+fn unsubscribe(pet_name: &str, events: &[PetEvent], assume_yes: bool) -> std::io::Result<()> {
+    let mut subscriptions = Subscriptions::load()?;
+    if !subscriptions.unsubscribe(pet_name, events) {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("no matching subscriptions for {}", pet_name),
+        ));
+    }
+    let prompt = if events.is_empty() {
+        format!("Remove every subscription for {}?", pet_name)
+    } else {
+        format!("Remove {:?} subscriptions for {}?", events, pet_name)
+    };
+    if !confirm(prompt, assume_yes)? {
+        return Ok(());
+    }
+    subscriptions.save()?;
+    status!("Unsubscribed {}", pet_name);
+    summary::changed();
+
+    Ok(())
+}
+
+fn list_subscriptions() -> std::io::Result<()> {
+    let subscriptions = Subscriptions::load()?;
+    if subscriptions.rules.is_empty() {
+        println!("No subscriptions, notifications are sent for every pet");
+    }
+    for rule in &subscriptions.rules {
+        println!("{}: {:?}", rule.pet, rule.events);
+    }
+
+    Ok(())
+}
+
+async fn anomalies(
+    api_client: &impl SurePetApi,
+    token: &str,
+    json: bool,
+    meta: bool,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+
+    let mut found = Vec::new();
+    let mut partial = false;
+    for pet in &pets {
+        let info = match pet_info::gather(api_client, token, pet, &devices).await {
+            Ok(info) => info,
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                partial = true;
+                continue;
+            }
+        };
+        found.push(PetAnomalies {
+            id: pet.id,
+            name: pet.name.clone(),
+            meal_windows: info.meal_windows,
+            anomalies: info.alerts,
+        });
+    }
+
+    if json {
+        let list = AnomalyList {
+            pets: found,
+            partial,
+            meta: meta.then(|| Meta::new(api_client, partial)),
+        };
+        println!("{}", serde_json::to_string_pretty(&list)?);
+        return Ok(());
+    }
+    views::print_anomalies(&found, partial, &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+async fn check_health(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet_name: &str,
+) -> std::io::Result<()> {
+    debug!("Checking health of {}", pet_name);
+
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, pet_name)?;
+
+    let cfg = &api_client.cfg().health;
+    let now = Utc::now();
+    let report = api_client
+        .get_pet_report(token, pet, now - health::drinking_lookback(cfg), now)
+        .await
+        .map_err(Error::other)?;
+    let events = report.drinking.map(|d| d.datapoints).unwrap_or_default();
+
+    health::print_drinking_trend(
+        pet,
+        &health::drinking_trend(&events, now, cfg),
+        cfg,
+        &api_client.cfg().units,
+    );
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+
+    Ok(())
+}
+
+async fn set_profile(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet_name: &str,
+    device_name: Option<&str>,
+    profile: PetProfile,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    debug!("Setting {} to {:?}", pet_name, profile);
+
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, pet_name)?;
+    let tag_id = pet.tag_id.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("{} has no microchip or tag registered", pet.name),
+        )
+    })?;
+
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let flaps: Vec<&Device> = match device_name {
+        Some(name) => vec![find_flap(&devices, name)?],
+        None => devices.iter().filter(|d| d.is_flap()).collect(),
+    };
+    if flaps.is_empty() {
+        return Err(Error::new(ErrorKind::NotFound, "no flaps found"));
+    }
+    // changing every flap at once is a bulk change, a named flap isn't
+    if device_name.is_none() && !api_client.dry_run() {
+        let prompt = format!("Change {} on all {} flaps?", pet.name, flaps.len());
+        if !confirm(prompt, assume_yes)? {
+            return Ok(());
+        }
+    }
+
+    // every flap is changed even if one fails, reporting each as it finishes
+    let mut results = stream::iter(flaps)
+        .map(|flap| async move {
+            let operation = PendingOperation::profile(pet, tag_id, flap, profile);
+            let result = operation.apply(api_client, token).await;
+            (flap, operation, result)
+        })
+        .buffer_unordered(api_client.cfg().api.command_concurrency.max(1));
+    let mut failure = None;
+    while let Some((flap, operation, result)) = results.next().await {
+        match result {
+            // the planned PUT is all there is to say
+            Ok(()) if api_client.dry_run() => continue,
+            Ok(()) => {}
+            Err(e) if client::is_unreachable(&e) => {
+                status!(
+                    "The API can't be reached, queued: {}. Run `pending replay` once it's back",
+                    operation.describe()
+                );
+                Pending::add(operation)?;
+                continue;
+            }
+            Err(e) => {
+                summary::failed();
+                match failure {
+                    // the first failure is the command's error, the rest are logged
+                    Some(_) => error!("Failed to change {} on {}: {}", pet.name, flap.name, e),
+                    None => failure = Some(e),
+                }
+                continue;
+            }
+        }
+        match profile {
+            PetProfile::IndoorOnly => status!("{} is kept indoors by {}", pet.name, flap.name),
+            PetProfile::Outdoor => status!("{} may go outdoors through {}", pet.name, flap.name),
+        }
+        summary::changed();
+    }
+
+    match failure {
+        Some(e) => Err(Error::other(e)),
+        None => Ok(()),
+    }
+}
+
+async fn download_photo(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet_name: &str,
+    output: Option<PathBuf>,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, pet_name)?;
+    let photo = pet.photo.as_ref().ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("{} has no profile picture", pet.name),
+        )
+    })?;
+    let output = output.unwrap_or_else(|| {
+        let extension = Path::new(photo.location.split('?').next().unwrap_or_default())
+            .extension()
+            .and_then(|e| e.to_str())
+            .filter(|e| e.chars().all(|c| c.is_ascii_alphanumeric()))
+            .unwrap_or("jpg");
+        PathBuf::from(format!("{}.{}", output::file_stem(&pet.name), extension))
+    });
+
+    debug!("Downloading {}", photo.location);
+    let image = api_client
+        .http()
+        .get(&photo.location)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(Error::other)?
+        .bytes()
+        .await
+        .map_err(Error::other)?;
+    fs::write(&output, &image)?;
+    status!("Wrote {}", output.display());
+    Ok(())
+}
+
+async fn upload_photo(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet_name: &str,
+    file: &Path,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    let image = fs::read(file)
+        .map_err(|e| Error::new(e.kind(), format!("reading {}: {}", file.display(), e)))?;
+    // by what's in the file rather than its name, which may not say
+    let content_type = if image.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if image.starts_with(&[0xff, 0xd8, 0xff]) {
+        "image/jpeg"
+    } else {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} isn't a JPEG or PNG picture", file.display()),
+        ));
+    };
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, pet_name)?;
+    if pet.photo.is_some()
+        && !confirm(
+            format!("Replace {}'s profile picture?", pet.name),
+            assume_yes,
+        )?
+    {
+        return Ok(());
+    }
+
+    api_client
+        .set_pet_photo(token, pet.id, image, content_type)
+        .await
+        .map_err(Error::other)?;
+    if !api_client.dry_run() {
+        status!("Set {}'s profile picture to {}", pet.name, file.display());
+        summary::changed();
+    }
+    Ok(())
+}
+
+async fn pet_info(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet_name: &str,
+    json: bool,
+    meta: bool,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let pet = find_pet(&pets, pet_name)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let mut info = pet_info::gather(api_client, token, pet, &devices).await?;
+    if json {
+        info.meta = meta.then(|| Meta::new(api_client, false));
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+    views::print_pet_info(&info, &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+    Ok(())
+}
+
+async fn device_info(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device_name: &str,
+) -> std::io::Result<()> {
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device = find_device(&devices, device_name)?;
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let info = device_info::gather(api_client, token, device, &pets).await?;
+    views::print_device_info(&info, &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+    Ok(())
+}
+
+/// Set a flap's curfew after showing when it will lock, or with neither time
+/// given turn its curfew off.
+async fn set_curfew(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device_name: &str,
+    lock: Option<NaiveTime>,
+    unlock: Option<NaiveTime>,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device = find_flap(&devices, device_name)?;
+
+    let (curfew, prompt) = match (lock, unlock) {
+        (Some(lock), Some(unlock)) => {
+            let curfew = curfew::curfew(lock, unlock)?;
+            let preview = curfew::preview(
+                &device.name,
+                lock,
+                unlock,
+                Utc::now(),
+                &api_client.cfg().units,
+            );
+            views::print_curfew_preview(&preview);
+            (curfew, format!("Set this curfew on {}?", device.name))
+        }
+        _ => {
+            let current = device.control.iter().flat_map(|c| &c.curfew).next();
+            let Some(current) = current.filter(|c| c.enabled) else {
+                status!("{} has no curfew to turn off", device.name);
+                return Ok(());
+            };
+            let curfew = Curfew {
+                enabled: false,
+                lock_time: current.lock_time.clone(),
+                unlock_time: current.unlock_time.clone(),
+            };
+            (curfew, format!("Turn off the curfew on {}?", device.name))
+        }
+    };
+    if !api_client.dry_run() && !confirm(prompt, assume_yes)? {
+        return Ok(());
+    }
+
+    api_client
+        .set_curfew(token, device, std::slice::from_ref(&curfew))
+        .await
+        .map_err(Error::other)?;
+    if !api_client.dry_run() {
+        // a snooze ending would put back the curfew this replaces
+        let mut snoozes = Snoozes::load()?;
+        if snoozes.take(device.id).is_some() {
+            snoozes.save()?;
+            status!("Ended the snooze on {}", device.name);
+        }
+        if curfew.enabled {
+            status!(
+                "{} locks at {} and unlocks at {}",
+                device.name,
+                curfew.lock_time,
+                curfew.unlock_time
+            );
+        } else {
+            status!("Turned off the curfew on {}", device.name);
+        }
+        summary::changed();
+    }
+    Ok(())
+}
+
+/// Unlock a flap and turn its curfews off for `length`, saving what to put
+/// back in snoozes.toml. Snoozing a snoozed flap only moves the end, so the
+/// state from before the first snooze is still what comes back.
+async fn snooze_curfew(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device_name: &str,
+    length: chrono::Duration,
+    wait: bool,
+) -> std::io::Result<()> {
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device = find_flap(&devices, device_name)?;
+    let units = &api_client.cfg().units;
+
+    let until = Utc::now()
+        .checked_add_signed(length)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "--for is too long"))?;
+    let mut snoozes = Snoozes::load()?;
+    let (snooze, fresh) = match snoozes.take(device.id) {
+        Some(snooze) => (Snooze { until, ..snooze }, false),
+        None => {
+            let curfews: Vec<Curfew> = device
+                .control
+                .iter()
+                .flat_map(|c| &c.curfew)
+                .cloned()
+                .collect();
+            let snooze = Snooze {
+                device_id: device.id,
+                device: device.name.clone(),
+                until,
+                // curfew locking isn't a mode to put back, turning the curfew
+                // back on does that
+                lock_mode: device
+                    .lock_mode()
+                    .filter(|m| m.code().is_some() && *m != LockMode::Unlocked),
+                curfews: if curfews.iter().any(|c| c.enabled) {
+                    curfews
+                } else {
+                    Vec::new()
+                },
+            };
+            if snooze.lock_mode.is_none() && snooze.curfews.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{} is unlocked with no curfew, so there's nothing to snooze",
+                        device.name
+                    ),
+                ));
+            }
+            (snooze, true)
+        }
+    };
+    let dry_run = api_client.dry_run();
+    // saved before anything changes, so what to put back is never lost
+    if !dry_run {
+        snoozes.snoozes.push(snooze.clone());
+        snoozes.save()?;
+    }
+    if fresh {
+        match snooze::apply(api_client, token, device, &snooze).await {
+            Ok(Sent::Applied) => {}
+            Ok(Sent::Queued) => status!(
+                "The API can't be reached, queued: unlock {}. Run `pending replay` once it's back",
+                device.name
+            ),
+            Err(e) if dry_run => return Err(e),
+            Err(e) => {
+                // undo whatever did change; if that fails too the daemon
+                // still puts it back when the snooze is over
+                match snooze::restore(api_client, token, device, &snooze).await {
+                    Ok(_) => {
+                        snoozes.take(device.id);
+                        snoozes.save()?;
+                    }
+                    Err(restore_error) => error!(
+                        "Failed to put {} back, it goes back to {} at {}: {}",
+                        device.name,
+                        snooze.describe(),
+                        units.time(&until),
+                        restore_error
+                    ),
+                }
+                return Err(e);
+            }
+        }
+    }
+    if dry_run {
+        return Ok(());
+    }
+    status!(
+        "{} is unlocked until {}, then back to {}",
+        device.name,
+        units.time(&until),
+        snooze.describe()
+    );
+    summary::changed();
+
+    if !wait {
+        status!("The daemon puts it back, so keep `rusty_pet daemon` running or pass --wait");
+        return Ok(());
+    }
+    tokio::time::sleep(length.to_std().unwrap_or_default()).await;
+    restore_snoozes(api_client, token).await
+}
+
+/// Put back every flap whose snooze is over, against the devices as they
+/// are now.
+async fn restore_snoozes(api_client: &impl SurePetApi, token: &str) -> std::io::Result<()> {
+    let mut snoozes = Snoozes::load()?;
+    if snoozes.snoozes.is_empty() {
+        return Ok(());
+    }
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    if snoozes
+        .restore_due(api_client, token, &devices, Utc::now())
+        .await
+        && !api_client.dry_run()
+    {
+        snoozes.save()?;
+    }
+    Ok(())
+}
+
+/// Poll the devices' signal every `interval` seconds, redrawing a plot of it
+/// on a terminal and printing each poll's readings otherwise.
+async fn watch_signal(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device_name: Option<&str>,
+    interval: u64,
+    count: Option<u64>,
+) -> std::io::Result<()> {
+    // every poll has to be a fresh reading
+    api_client.set_cache_mode(CacheMode::Off);
+    let units = &api_client.cfg().units;
+    let term = Term::stdout();
+    // room for the labels and the reading beside the plot
+    let mut history = SignalHistory::new(
+        (usize::from(term.size().1))
+            .saturating_sub(50)
+            .clamp(10, 120),
+    );
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    let mut polls = 0;
+    while count.is_none_or(|count| polls < count) {
+        ticker.tick().await;
+        polls += 1;
+        let devices = match api_client.get_devices(token).await {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("Failed to poll devices: {}", e);
+                summary::failed();
+                continue;
+            }
+        };
+        let shown: Vec<&Device> = match device_name {
+            Some(name) => vec![find_device(&devices, name)?],
+            None => devices.iter().collect(),
+        };
+        history.record(&shown);
+        if term.is_term() {
+            term.clear_screen()?;
+            views::print_signal(&history, interval, units);
+        } else {
+            for line in history.latest() {
+                println!("{} {}", units.time(&Utc::now()), line);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn battery_history(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device_name: &str,
+    days: Option<i64>,
+) -> std::io::Result<()> {
+    let cfg = api_client.cfg();
+    let Some(db) = cfg.daemon.status_db.as_deref() else {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "set [daemon] status_db to record battery levels, which status and the daemon \
+             then add to",
+        ));
+    };
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device = find_device(&devices, device_name)?;
+    if device.battery_percent().is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} doesn't report a battery level", device.name),
+        ));
+    }
+    record_fetched(api_client, &devices);
+
+    let now = Utc::now();
+    let from = now - chrono::Duration::days(days.unwrap_or(cfg.forecast.battery_days));
+    let states = sqlite::device_states(db, now).map_err(Error::other)?;
+    if let Some(outlook) = battery::outlook(device, &states, from, now, &cfg.notifications) {
+        views::print_battery(&outlook, from, now, &cfg.units);
+    }
+    views::print_cache_age(api_client.take_cache_age(), REFRESH_HINT);
+    Ok(())
+}
+
+/// Look up a flap by id or case-insensitive name, refusing other devices.
+fn find_flap<'a>(devices: &'a [Device], name: &str) -> std::io::Result<&'a Device> {
+    let device = find_device(devices, name)?;
+    if !device.is_flap() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} isn't a flap, only flaps have a curfew", device.name),
+        ));
+    }
+    Ok(device)
+}
+
+/// Look up a pet by id or case-insensitive name.
+pub fn find_pet<'a>(pets: &'a [Pet], name: &str) -> std::io::Result<&'a Pet> {
+    pets.iter()
+        .find(|p| p.id.to_string() == name || p.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no pet called {}", name)))
+}
+
+/// Look up a device by id or case-insensitive name.
+pub fn find_device<'a>(devices: &'a [Device], name: &str) -> std::io::Result<&'a Device> {
+    devices
+        .iter()
+        .find(|d| d.id.to_string() == name || d.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no device called {}", name)))
+}
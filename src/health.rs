@@ -0,0 +1,418 @@
+use crate::api::client::{DrinkingEvent, FeedingEvent, Pet};
+use crate::config;
+use crate::units::Units;
+use chrono::{DateTime, Duration, Utc};
+use console::style;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Fewest days with meals that usual meal times are learned from.
+const MIN_MEAL_DAYS: usize = 3;
+/// Hours either side of which meals count towards the same usual time.
+const MEAL_SPAN_HOURS: u32 = 2;
+
+/// Drinking behaviour over a period of time.
+#[derive(Debug, Default)]
+pub struct DrinkingWindow {
+    pub visits_per_day: f64,
+    pub avg_ml: f64,
+}
+
+/// The most recent drinking window compared against the baseline before it.
+#[derive(Debug)]
+pub struct DrinkingTrend {
+    pub current: DrinkingWindow,
+    pub baseline: DrinkingWindow,
+    pub frequency_alert: bool,
+    pub volume_alert: bool,
+}
+
+/// How far back drinking history is needed to evaluate a trend.
+pub fn drinking_lookback(cfg: &config::Health) -> Duration {
+    Duration::days(
+        cfg.drinking_window_days + cfg.drinking_baseline_days + cfg.drinking_sustained_days,
+    )
+}
+
+/// Compare rolling drinking windows against their baselines. An alert is only
+/// raised when the increase holds for every window ending in the last
+/// `drinking_sustained_days` days, so a single hot afternoon doesn't trigger it.
+pub fn drinking_trend(
+    events: &[DrinkingEvent],
+    now: DateTime<Utc>,
+    cfg: &config::Health,
+) -> DrinkingTrend {
+    let window = Duration::days(cfg.drinking_window_days);
+    let baseline = Duration::days(cfg.drinking_baseline_days);
+
+    let mut frequency_alert = true;
+    let mut volume_alert = true;
+    for offset in (0..cfg.drinking_sustained_days.max(1)).rev() {
+        let end = now - Duration::days(offset);
+        let current = window_stats(events, end - window, end);
+        let before = window_stats(events, end - window - baseline, end - window);
+
+        frequency_alert &= before.visits_per_day > 0.0
+            && current.visits_per_day > before.visits_per_day * cfg.drinking_sensitivity;
+        volume_alert &=
+            before.avg_ml > 0.0 && current.avg_ml > before.avg_ml * cfg.drinking_sensitivity;
+    }
+
+    DrinkingTrend {
+        current: window_stats(events, now - window, now),
+        baseline: window_stats(events, now - window - baseline, now - window),
+        frequency_alert,
+        volume_alert,
+    }
+}
+
+fn window_stats(
+    events: &[DrinkingEvent],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> DrinkingWindow {
+    let in_window: Vec<&DrinkingEvent> = events
+        .iter()
+        .filter(|e| e.from >= start && e.from < end)
+        .collect();
+    if in_window.is_empty() {
+        return DrinkingWindow::default();
+    }
+
+    let days = (end - start).num_hours() as f64 / 24.0;
+    let total_ml: f64 = in_window.iter().map(|e| e.ml_drunk()).sum();
+
+    DrinkingWindow {
+        visits_per_day: in_window.len() as f64 / days,
+        avg_ml: total_ml / in_window.len() as f64,
+    }
+}
+
+pub fn print_drinking_trend(pet: &Pet, trend: &DrinkingTrend, cfg: &config::Health, units: &Units) {
+    println!(
+        "{}",
+        style(format!(
+            "Drinking trend for {} ({} day window vs previous {} days)",
+            pet.name, cfg.drinking_window_days, cfg.drinking_baseline_days
+        ))
+        .bold()
+    );
+    println!(
+        "  Visits per day: {:.1} (baseline {:.1})",
+        trend.current.visits_per_day, trend.baseline.visits_per_day
+    );
+    println!(
+        "  Average per visit: {} (baseline {})",
+        units.volume(trend.current.avg_ml, 1),
+        units.volume(trend.baseline.avg_ml, 1)
+    );
+
+    if trend.frequency_alert {
+        println!(
+            "  {}",
+            style("Sustained increase in drinking frequency, consider a vet check").yellow()
+        );
+    }
+    if trend.volume_alert {
+        println!(
+            "  {}",
+            style("Sustained increase in volume per visit, consider a vet check").yellow()
+        );
+    }
+    if !trend.frequency_alert && !trend.volume_alert {
+        println!("  {}", style("No sustained change in drinking").green());
+    }
+}
+
+/// A time of day a pet usually eats, learned from its feeding history.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct MealWindow {
+    /// Hour of the day, in the [units] timezone, the window starts at
+    pub start_hour: u32,
+    /// Hour it ends at, 24 for a window running to midnight
+    pub end_hour: u32,
+    /// Share of the learned days with a meal in the window
+    pub regularity: f64,
+}
+
+impl MealWindow {
+    /// Such as "07:00 to 09:00".
+    pub fn label(&self, units: &Units) -> String {
+        format!(
+            "{} to {}",
+            units.hour(self.start_hour),
+            units.hour(self.end_hour % 24)
+        )
+    }
+}
+
+/// How far back feeding history is needed to learn usual meal times.
+pub fn meal_lookback(cfg: &config::Health) -> Duration {
+    Duration::days(cfg.meal_learning_days + 1)
+}
+
+/// The pet's usual meal times, from the `meal_learning_days` before today.
+/// None are learned from fewer than a few days with meals.
+pub fn meal_windows(
+    events: &[FeedingEvent],
+    now: DateTime<Utc>,
+    cfg: &config::Health,
+    units: &Units,
+) -> Vec<MealWindow> {
+    let midnight = units.start_of_day(&now);
+    let learned_from = midnight - Duration::days(cfg.meal_learning_days);
+    // (days before today, hour) of every meal
+    let meals: HashSet<(i64, u32)> = events
+        .iter()
+        .filter(|e| e.from >= learned_from && e.from < midnight && e.grams_eaten() > 0.0)
+        .map(|e| ((midnight - e.from).num_days(), units.hour_of(&e.from)))
+        .collect();
+    let days: HashSet<i64> = meals.iter().map(|(day, _)| *day).collect();
+    if days.len() < MIN_MEAL_DAYS {
+        return Vec::new();
+    }
+
+    let share = |start: u32, end: u32| {
+        let eaten = days
+            .iter()
+            .filter(|day| (start..end).any(|hour| meals.contains(&(**day, hour))))
+            .count();
+        eaten as f64 / days.len() as f64
+    };
+    let mut windows: Vec<MealWindow> = Vec::new();
+    for hour in 0..24 {
+        if share(hour, hour + MEAL_SPAN_HOURS) < cfg.meal_regularity {
+            continue;
+        }
+        let end_hour = (hour + MEAL_SPAN_HOURS).min(24);
+        match windows.last_mut() {
+            // overlapping windows are one usual time
+            Some(last) if last.end_hour >= hour => last.end_hour = end_hour,
+            _ => windows.push(MealWindow {
+                start_hour: hour,
+                end_hour,
+                regularity: 0.0,
+            }),
+        }
+    }
+    for window in &mut windows {
+        window.regularity = share(window.start_hour, window.end_hour);
+    }
+    windows
+}
+
+/// The usual meal times today that have passed, grace period included,
+/// without the pet eating.
+pub fn missed_meals(
+    events: &[FeedingEvent],
+    now: DateTime<Utc>,
+    cfg: &config::Health,
+    units: &Units,
+) -> Vec<MealWindow> {
+    if cfg.meal_learning_days <= 0 {
+        return Vec::new();
+    }
+    let midnight = units.start_of_day(&now);
+    let grace = Duration::minutes(cfg.meal_grace_minutes);
+    meal_windows(events, now, cfg, units)
+        .into_iter()
+        .filter(|window| {
+            let start = midnight + Duration::hours(window.start_hour.into());
+            let due = midnight + Duration::hours(window.end_hour.into()) + grace;
+            now >= due
+                && !events
+                    .iter()
+                    .any(|e| e.from >= start && e.from < due && e.grams_eaten() > 0.0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::client::BowlWeight;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn utc() -> Units {
+        let mut units = config::built_in().units;
+        units.timezone = Some(chrono_tz::UTC);
+        units
+    }
+
+    /// A 7 day window against the 14 days before it, sustained for 3 days.
+    fn health() -> config::Health {
+        let mut cfg = config::built_in().health;
+        cfg.drinking_window_days = 7;
+        cfg.drinking_baseline_days = 14;
+        cfg.drinking_sustained_days = 3;
+        cfg.drinking_sensitivity = 1.3;
+        cfg
+    }
+
+    /// `visits` a day of `ml` each, on each of the `days` days before `end`.
+    fn drinking(end: DateTime<Utc>, days: i64, visits: i64, ml: f64) -> Vec<DrinkingEvent> {
+        (1..=days)
+            .flat_map(|day| {
+                (0..visits).map(move |visit| DrinkingEvent {
+                    from: end - Duration::days(day) + Duration::hours(visit + 1),
+                    device_id: 1,
+                    weights: vec![BowlWeight { change: -ml }],
+                })
+            })
+            .collect()
+    }
+
+    fn meal(from: DateTime<Utc>, change: f64) -> FeedingEvent {
+        FeedingEvent {
+            from,
+            device_id: 1,
+            weights: vec![BowlWeight { change }],
+        }
+    }
+
+    #[test]
+    fn steady_drinking_raises_no_alert() {
+        let now = at("2024-06-30T00:00:00Z");
+        let trend = drinking_trend(&drinking(now, 30, 2, 20.0), now, &health());
+        assert!((trend.current.visits_per_day - 2.0).abs() < 1e-9);
+        assert!((trend.baseline.visits_per_day - 2.0).abs() < 1e-9);
+        assert!((trend.current.avg_ml - 20.0).abs() < 1e-9);
+        assert!(!trend.frequency_alert && !trend.volume_alert);
+    }
+
+    #[test]
+    fn a_sustained_increase_raises_its_alert() {
+        let now = at("2024-06-30T00:00:00Z");
+        // twice a day for a month, then twice more for the last ten days
+        let mut events = drinking(now, 30, 2, 20.0);
+        events.extend(drinking(now, 10, 2, 20.0));
+        let trend = drinking_trend(&events, now, &health());
+        assert!(trend.frequency_alert);
+        assert!(!trend.volume_alert);
+
+        let mut events = drinking(now, 30, 2, 20.0);
+        events.retain(|e| e.from < now - Duration::days(10));
+        events.extend(drinking(now, 10, 2, 40.0));
+        let trend = drinking_trend(&events, now, &health());
+        assert!(!trend.frequency_alert);
+        assert!(trend.volume_alert);
+        assert!((trend.current.avg_ml - 40.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn a_single_day_spike_is_not_sustained() {
+        let now = at("2024-06-30T00:00:00Z");
+        let mut events = drinking(now, 30, 2, 20.0);
+        events.extend(drinking(now, 1, 20, 20.0));
+        let trend = drinking_trend(&events, now, &health());
+        // the last window alone is well over the baseline
+        assert!(trend.current.visits_per_day > trend.baseline.visits_per_day * 2.0);
+        assert!(!trend.frequency_alert);
+    }
+
+    #[test]
+    fn no_baseline_means_no_alert() {
+        let now = at("2024-06-30T00:00:00Z");
+        let trend = drinking_trend(&drinking(now, 7, 5, 30.0), now, &health());
+        assert_eq!(trend.baseline.visits_per_day, 0.0);
+        assert!(!trend.frequency_alert && !trend.volume_alert);
+        let trend = drinking_trend(&[], now, &health());
+        assert_eq!(trend.current.avg_ml, 0.0);
+        assert!(!trend.frequency_alert && !trend.volume_alert);
+    }
+
+    #[test]
+    fn a_window_includes_its_start_but_not_its_end() {
+        let start = at("2024-06-01T00:00:00Z");
+        let end = start + Duration::days(2);
+        let events: Vec<DrinkingEvent> = [start, end - Duration::seconds(1), end]
+            .into_iter()
+            .map(|from| DrinkingEvent {
+                from,
+                device_id: 1,
+                weights: vec![BowlWeight { change: -10.0 }],
+            })
+            .collect();
+        let window = window_stats(&events, start, end);
+        assert!((window.visits_per_day - 1.0).abs() < 1e-9);
+        assert!((window.avg_ml - 10.0).abs() < 1e-9);
+    }
+
+    /// Breakfast at 07:30 every day for a week and dinner at 18:15 on all
+    /// but one, with a refill at noon that isn't a meal.
+    fn week_of_meals(midnight: DateTime<Utc>) -> Vec<FeedingEvent> {
+        let mut events = Vec::new();
+        for day in 1..=7 {
+            let date = midnight - Duration::days(day);
+            events.push(meal(date + Duration::minutes(7 * 60 + 30), -30.0));
+            if day != 3 {
+                events.push(meal(date + Duration::minutes(18 * 60 + 15), -25.0));
+            }
+            events.push(meal(date + Duration::hours(12), 80.0));
+        }
+        events
+    }
+
+    fn meal_health() -> config::Health {
+        let mut cfg = health();
+        cfg.meal_learning_days = 7;
+        cfg.meal_regularity = 0.7;
+        cfg.meal_grace_minutes = 90;
+        cfg
+    }
+
+    #[test]
+    fn usual_meal_times_are_learned_from_regular_meals() {
+        let midnight = at("2024-06-30T00:00:00Z");
+        let now = midnight + Duration::hours(12);
+        let windows = meal_windows(&week_of_meals(midnight), now, &meal_health(), &utc());
+        let hours: Vec<(u32, u32)> = windows.iter().map(|w| (w.start_hour, w.end_hour)).collect();
+        // each meal falls in both two hour windows starting the hour before
+        // and the hour of it, which merge into one
+        assert_eq!(hours, [(6, 9), (17, 20)]);
+        assert!((windows[0].regularity - 1.0).abs() < 1e-9);
+        assert!((windows[1].regularity - 6.0 / 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn too_few_days_teach_nothing() {
+        let midnight = at("2024-06-30T00:00:00Z");
+        let mut events = week_of_meals(midnight);
+        events.retain(|e| e.from >= midnight - Duration::days(2));
+        let windows = meal_windows(&events, midnight, &meal_health(), &utc());
+        assert!(windows.is_empty());
+    }
+
+    #[test]
+    fn a_meal_is_missed_once_its_time_and_grace_have_passed() {
+        let midnight = at("2024-06-30T00:00:00Z");
+        let cfg = meal_health();
+        let units = utc();
+        let events = week_of_meals(midnight);
+
+        // breakfast ends at 09:00, so it's missed from 10:30
+        let missed = |events: &[FeedingEvent], now| {
+            missed_meals(events, now, &cfg, &units)
+                .iter()
+                .map(|w| w.start_hour)
+                .collect::<Vec<_>>()
+        };
+        assert!(missed(&events, midnight + Duration::minutes(10 * 60 + 29)).is_empty());
+        assert_eq!(
+            missed(&events, midnight + Duration::minutes(10 * 60 + 30)),
+            [6]
+        );
+        assert_eq!(missed(&events, midnight + Duration::hours(23)), [6, 17]);
+
+        let mut fed = week_of_meals(midnight);
+        fed.push(meal(midnight + Duration::hours(8), -30.0));
+        assert_eq!(missed(&fed, midnight + Duration::hours(23)), [17]);
+
+        let mut off = meal_health();
+        off.meal_learning_days = 0;
+        assert!(missed_meals(&events, midnight + Duration::hours(23), &off, &units).is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::api::client::{DrinkingEvent, FeedingEvent};
+use crate::events::EventType;
+use crate::stats::format_duration;
+use crate::units::Units;
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// The periods `history --group-by` adds events up over.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupBy {
+    Day,
+    /// Monday to Sunday
+    Week,
+}
+
+/// Events in one day or week, in the [units] timezone.
+#[derive(Serialize, Debug)]
+pub struct Period {
+    pub start: DateTime<Utc>,
+    pub count: usize,
+    /// Grams eaten, millilitres drunk, or minutes outside
+    pub amount: f64,
+}
+
+/// Add `events`, each a time and an amount, up a period at a time from the
+/// one `from` falls in to the one `to` does, quiet periods included.
+pub fn group(
+    events: impl Iterator<Item = (DateTime<Utc>, f64)>,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    by: GroupBy,
+    units: &Units,
+) -> Vec<Period> {
+    let start_of = |time: &DateTime<Utc>| match by {
+        GroupBy::Day => units.start_of_day(time),
+        GroupBy::Week => {
+            let monday =
+                *time - Duration::days(units.weekday_of(time).num_days_from_monday().into());
+            units.start_of_day(&monday)
+        }
+    };
+    let mut periods = Vec::new();
+    let mut start = start_of(&from);
+    while start <= to {
+        periods.push(Period {
+            start,
+            count: 0,
+            amount: 0.0,
+        });
+        // a day and a bit on, so a daylight saving change can't repeat a day
+        start = start_of(
+            &(start
+                + Duration::hours(match by {
+                    GroupBy::Day => 25,
+                    GroupBy::Week => 24 * 7 + 1,
+                })),
+        );
+    }
+    for (time, amount) in events.filter(|(time, _)| (from..=to).contains(time)) {
+        if let Some(period) = periods.iter_mut().rev().find(|p| p.start <= time) {
+            period.count += 1;
+            period.amount += amount;
+        }
+    }
+    periods
+}
+
+/// What a history of a kind of event is called.
+pub fn title(event_type: EventType) -> &'static str {
+    match event_type {
+        EventType::Feeding => "Feeding history",
+        EventType::Drinking => "Drinking history",
+        EventType::Activity => "Trips outside",
+    }
+}
+
+/// Headings for the count and amount of a kind of event.
+pub fn columns(event_type: EventType) -> [&'static str; 2] {
+    match event_type {
+        EventType::Feeding => ["Meals", "Eaten"],
+        EventType::Drinking => ["Drinks", "Drunk"],
+        EventType::Activity => ["Trips", "Outside"],
+    }
+}
+
+/// An amount of a kind of event, in the preferred units.
+pub fn amount_text(event_type: EventType, amount: f64, units: &Units) -> String {
+    match event_type {
+        EventType::Feeding => units.weight(amount, 1),
+        EventType::Drinking => units.volume(amount, 0),
+        EventType::Activity if amount == 0.0 => "-".to_string(),
+        EventType::Activity => format_duration(Duration::minutes(amount as i64)),
+    }
+}
+
+/// A period's first day, or "week of" it.
+pub fn period_text(period: &Period, by: GroupBy, units: &Units) -> String {
+    match by {
+        GroupBy::Day => units.date(&period.start),
+        GroupBy::Week => format!("Week of {}", units.date(&period.start)),
+    }
+}
+
+/// Totals for a single device (bowl or fountain) over a set of events.
+#[derive(Debug, Default)]
+pub struct DeviceTotal {
+    pub device_id: u32,
+    pub events: u32,
+    /// Grams eaten or millilitres drunk
+    pub amount: f64,
+}
+
+/// Break a pet's feeding events down by the device they were recorded on,
+/// so meals taken from another pet's bowl stand out.
+pub fn feeding_by_device<'a>(
+    events: impl IntoIterator<Item = &'a FeedingEvent>,
+) -> Vec<DeviceTotal> {
+    by_device(events.into_iter().map(|e| (e.device_id, e.grams_eaten())))
+}
+
+/// The same for drinking, a fountain at a time.
+pub fn drinking_by_device(events: &[DrinkingEvent]) -> Vec<DeviceTotal> {
+    by_device(events.iter().map(|e| (e.device_id, e.ml_drunk())))
+}
+
+fn by_device(events: impl Iterator<Item = (u32, f64)>) -> Vec<DeviceTotal> {
+    let mut totals: BTreeMap<u32, DeviceTotal> = BTreeMap::new();
+
+    for (device_id, amount) in events {
+        let entry = totals.entry(device_id).or_insert(DeviceTotal {
+            device_id,
+            ..Default::default()
+        });
+        entry.events += 1;
+        entry.amount += amount;
+    }
+
+    totals.into_values().collect()
+}
+
+/// The footer under a history: how much altogether and on average.
+pub struct Summary {
+    pub count: usize,
+    pub total: f64,
+}
+
+impl Summary {
+    pub fn new(amounts: impl Iterator<Item = f64>) -> Self {
+        amounts.fold(
+            Summary {
+                count: 0,
+                total: 0.0,
+            },
+            |s, amount| Summary {
+                count: s.count + 1,
+                total: s.total + amount,
+            },
+        )
+    }
+
+    /// "3 meals", given "meal"
+    pub fn label(&self, noun: &str) -> String {
+        match self.count {
+            1 => format!("1 {}", noun),
+            count => format!("{} {}s", count, noun),
+        }
+    }
+
+    /// Missing for an empty history
+    pub fn average(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total / self.count as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn london() -> Units {
+        let mut units = config::built_in().units;
+        units.timezone = Some(chrono_tz::Europe::London);
+        units
+    }
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn days(periods: &[Period], units: &Units) -> Vec<String> {
+        periods
+            .iter()
+            .map(|p| units.day_of(&p.start).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn days_follow_the_clocks_going_back() {
+        let units = london();
+        let events = [
+            // 00:30 on the 27th, still summer time
+            (at("2024-10-26T23:30:00Z"), 10.0),
+            // 23:30 on the 27th, after the clocks went back
+            (at("2024-10-27T23:30:00Z"), 5.0),
+            (at("2024-10-28T00:30:00Z"), 1.0),
+        ];
+        let from = at("2024-10-26T12:00:00Z");
+        let to = at("2024-10-28T12:00:00Z");
+        let periods = group(events.into_iter(), from, to, GroupBy::Day, &units);
+        assert_eq!(
+            days(&periods, &units),
+            ["2024-10-26", "2024-10-27", "2024-10-28"]
+        );
+        assert_eq!(periods[1].start, at("2024-10-26T23:00:00Z"));
+        assert_eq!(periods[1].count, 2);
+        assert_eq!(periods[1].amount, 15.0);
+        assert_eq!(periods[2].start, at("2024-10-28T00:00:00Z"));
+        assert_eq!(periods[2].count, 1);
+    }
+
+    #[test]
+    fn days_follow_the_clocks_going_forward() {
+        let units = london();
+        let events = [(at("2024-03-31T22:30:00Z"), 3.0)];
+        // 23:00 on the 31st, after the clocks went forward
+        let from = at("2024-03-31T22:00:00Z");
+        let to = at("2024-04-01T12:00:00Z");
+        let periods = group(events.into_iter(), from, to, GroupBy::Day, &units);
+        assert_eq!(days(&periods, &units), ["2024-03-31", "2024-04-01"]);
+        assert_eq!(periods[0].start, at("2024-03-31T00:00:00Z"));
+        assert_eq!(periods[0].count, 1);
+        assert_eq!(periods[1].start, at("2024-03-31T23:00:00Z"));
+    }
+
+    #[test]
+    fn weeks_start_on_monday_either_side_of_a_clock_change() {
+        let units = london();
+        let from = at("2024-10-23T12:00:00Z");
+        let to = at("2024-11-05T12:00:00Z");
+        let periods = group(std::iter::empty(), from, to, GroupBy::Week, &units);
+        assert_eq!(
+            days(&periods, &units),
+            ["2024-10-21", "2024-10-28", "2024-11-04"]
+        );
+        assert_eq!(periods[0].start, at("2024-10-20T23:00:00Z"));
+        assert_eq!(periods[1].start, at("2024-10-28T00:00:00Z"));
+    }
+}
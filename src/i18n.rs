@@ -0,0 +1,329 @@
+use clap::{Arg, ArgAction, Command, ValueEnum};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::sync::{OnceLock, RwLock};
+
+/// Languages with a catalog in `assets/locales`.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Lang {
+    #[default]
+    En,
+    De,
+}
+
+impl Lang {
+    /// The value of `--lang` in `args`, read before clap parses them so the
+    /// help clap prints is already translated.
+    pub fn from_args(args: impl IntoIterator<Item = OsString>) -> Option<Lang> {
+        let mut args = args.into_iter().map(|a| a.to_string_lossy().into_owned());
+        while let Some(arg) = args.next() {
+            let value = match arg.strip_prefix("--lang") {
+                Some("") => args.next()?,
+                Some(value) if value.starts_with('=') => value[1..].to_string(),
+                _ => continue,
+            };
+            return Lang::from_str(&value, true).ok();
+        }
+        None
+    }
+
+    /// The language of the locale in LC_ALL, LC_MESSAGES or LANG, such as
+    /// `de_DE.UTF-8`. Locales without a catalog fall back to English.
+    pub fn from_env() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| Lang::from_locale(&locale))
+            .unwrap_or_default()
+    }
+
+    fn from_locale(locale: &str) -> Option<Lang> {
+        let language = locale.split(['_', '.', '@']).next()?;
+        Lang::from_str(language, true).ok()
+    }
+
+    fn catalog(self) -> &'static Catalog {
+        static EN: OnceLock<Catalog> = OnceLock::new();
+        static DE: OnceLock<Catalog> = OnceLock::new();
+        let (cell, source) = match self {
+            Lang::En => (&EN, include_str!("./assets/locales/en.toml")),
+            Lang::De => (&DE, include_str!("./assets/locales/de.toml")),
+        };
+        cell.get_or_init(|| toml::from_str(source).expect("invalid locale catalog"))
+    }
+}
+
+/// Translated text for one language. English help lives in the doc comments
+/// on the clap types, so a catalog only holds what it translates and anything
+/// missing falls back to English.
+#[derive(Deserialize, Debug, Default)]
+struct Catalog {
+    #[serde(default)]
+    headings: Headings,
+    /// Help for the top level and its global flags
+    #[serde(default)]
+    cli: CommandText,
+    /// Help for each subcommand, keyed by its path such as `pet restrict`
+    #[serde(default)]
+    commands: HashMap<String, CommandText>,
+    #[serde(default)]
+    messages: HashMap<String, String>,
+}
+
+/// Headings in the help output, and the help of the flags clap adds itself.
+#[derive(Deserialize, Debug, Default)]
+struct Headings {
+    usage: Option<String>,
+    commands: Option<String>,
+    arguments: Option<String>,
+    options: Option<String>,
+    examples: Option<String>,
+    help: Option<String>,
+    version: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct CommandText {
+    about: Option<String>,
+    /// Help for each argument, keyed by its id, the field name in cli.rs
+    #[serde(default)]
+    args: HashMap<String, String>,
+    #[serde(default)]
+    examples: Vec<Example>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Example {
+    command: String,
+    description: String,
+}
+
+static LANG: RwLock<Lang> = RwLock::new(Lang::En);
+
+/// Choose the language of [`message`].
+pub fn set_lang(lang: Lang) {
+    *LANG.write().unwrap() = lang;
+}
+
+/// The message `key` in the chosen language, or English if it has no
+/// translation, with each `{name}` replaced by its value.
+pub fn message(key: &str, values: &[(&str, &str)]) -> String {
+    let lang = *LANG.read().unwrap();
+    let template = lang
+        .catalog()
+        .messages
+        .get(key)
+        .or_else(|| Lang::En.catalog().messages.get(key))
+        .map_or(key, String::as_str);
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// Translate the help of `cmd` and every subcommand, and add their usage
+/// examples.
+pub fn localize(cmd: Command, lang: Lang) -> Command {
+    let catalog = lang.catalog();
+    let cmd = localize_command(cmd, catalog, &catalog.cli, "");
+    if lang == Lang::En {
+        return cmd;
+    }
+    let version = catalog.headings.version.clone();
+    let cmd = cmd.disable_version_flag(true).arg(
+        Arg::new("version")
+            .short('V')
+            .long("version")
+            .action(ArgAction::Version)
+            .help(version.unwrap_or_else(|| "Print version".to_string())),
+    );
+    translate_headings(cmd, &catalog.headings)
+}
+
+fn localize_command(
+    mut cmd: Command,
+    catalog: &Catalog,
+    text: &CommandText,
+    path: &str,
+) -> Command {
+    let english = Lang::En.catalog();
+    let english = if path.is_empty() {
+        Some(&english.cli)
+    } else {
+        english.commands.get(path)
+    };
+    let examples = if text.examples.is_empty() {
+        english.map_or(&[][..], |t| &t.examples[..])
+    } else {
+        &text.examples[..]
+    };
+    if let Some(about) = &text.about {
+        cmd = cmd.about(about).long_about(None);
+    }
+    // mut_arg would move the argument to the end, reordering positionals
+    cmd = cmd.mut_args(|arg| match text.args.get(arg.get_id().as_str()) {
+        Some(help) => arg.help(help).long_help(None),
+        None => arg,
+    });
+    if !examples.is_empty() {
+        let heading = catalog.headings.examples.as_deref().unwrap_or("Examples");
+        cmd = cmd.after_help(format_examples(heading, examples));
+    }
+
+    let names: Vec<String> = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        let path = if path.is_empty() {
+            name.clone()
+        } else {
+            format!("{} {}", path, name)
+        };
+        let text = catalog.commands.get(&path);
+        let default = CommandText::default();
+        cmd = cmd.mut_subcommand(&name, |sub| {
+            localize_command(sub, catalog, text.unwrap_or(&default), &path)
+        });
+    }
+    cmd
+}
+
+fn format_examples(heading: &str, examples: &[Example]) -> String {
+    let mut text = format!("{}:", heading);
+    for example in examples {
+        text.push_str(&format!(
+            "\n  {}\n      {}",
+            example.command, example.description
+        ));
+    }
+    text
+}
+
+/// clap's own headings and `--help` are English, so in another language
+/// every command gets a template and help flag with the catalog's words.
+fn translate_headings(mut cmd: Command, headings: &'static Headings) -> Command {
+    let usage = headings.usage.as_deref().unwrap_or("Usage");
+    cmd = cmd
+        .help_template(format!(
+            "{{before-help}}{{about-with-newline}}\n{}: {{usage}}\n\n{{all-args}}{{after-help}}",
+            usage
+        ))
+        .disable_help_flag(true)
+        .arg(
+            Arg::new("help")
+                .short('h')
+                .long("help")
+                .action(ArgAction::Help)
+                .help(headings.help.as_deref().unwrap_or("Print help")),
+        );
+    if let Some(commands) = &headings.commands {
+        cmd = cmd.subcommand_help_heading(commands.as_str());
+    }
+    cmd = cmd.mut_args(|arg| {
+        let heading = if arg.is_positional() {
+            headings.arguments.as_deref()
+        } else {
+            headings.options.as_deref()
+        };
+        match (arg.get_help_heading(), heading) {
+            (None, Some(heading)) => arg.help_heading(heading),
+            _ => arg,
+        }
+    });
+
+    let names: Vec<String> = cmd
+        .get_subcommands()
+        .map(|sub| sub.get_name().to_string())
+        .collect();
+    for name in names {
+        cmd = cmd.mut_subcommand(&name, |sub| translate_headings(sub, headings));
+    }
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    fn ids(cmd: &Command) -> Vec<String> {
+        cmd.get_arguments()
+            .map(|a| a.get_id().to_string())
+            .collect()
+    }
+
+    fn find<'a>(cmd: &'a Command, path: &str) -> Option<&'a Command> {
+        path.split(' ')
+            .try_fold(cmd, |cmd, name| cmd.find_subcommand(name))
+    }
+
+    #[test]
+    fn every_catalog_names_real_commands_and_arguments() {
+        let cli = Cli::command();
+        for lang in Lang::value_variants() {
+            let catalog = lang.catalog();
+            for id in catalog.cli.args.keys() {
+                assert!(ids(&cli).contains(id), "{:?}: no global --{}", lang, id);
+            }
+            for (path, text) in &catalog.commands {
+                let cmd =
+                    find(&cli, path).unwrap_or_else(|| panic!("{:?}: no command {:?}", lang, path));
+                for id in text.args.keys() {
+                    assert!(ids(cmd).contains(id), "{:?}: {} has no {}", lang, path, id);
+                }
+            }
+            for key in catalog.messages.keys() {
+                assert!(
+                    Lang::En.catalog().messages.contains_key(key),
+                    "{:?}: message {} isn't in English",
+                    lang,
+                    key
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn localized_commands_still_build() {
+        for lang in Lang::value_variants() {
+            localize(Cli::command(), *lang).debug_assert();
+        }
+    }
+
+    #[test]
+    fn lang_comes_from_the_flag_in_either_form() {
+        let args = |a: &[&str]| a.iter().map(OsString::from).collect::<Vec<_>>();
+        assert_eq!(
+            Lang::from_args(args(&["rp", "--lang", "de", "status"])),
+            Some(Lang::De)
+        );
+        assert_eq!(
+            Lang::from_args(args(&["rp", "status", "--lang=DE"])),
+            Some(Lang::De)
+        );
+        assert_eq!(Lang::from_args(args(&["rp", "--lang"])), None);
+        assert_eq!(Lang::from_args(args(&["rp", "--lang", "xx"])), None);
+        assert_eq!(Lang::from_args(args(&["rp", "--language", "de"])), None);
+    }
+
+    #[test]
+    fn lang_comes_from_the_locale_language() {
+        assert_eq!(Lang::from_locale("de_AT.UTF-8"), Some(Lang::De));
+        assert_eq!(Lang::from_locale("de"), Some(Lang::De));
+        assert_eq!(Lang::from_locale("en_GB.UTF-8"), Some(Lang::En));
+        assert_eq!(Lang::from_locale("C.UTF-8"), None);
+        assert_eq!(Lang::from_locale("fr_FR"), None);
+    }
+
+    #[test]
+    fn messages_fill_in_values() {
+        let text = message("age_unit", &[("unit", "\"w\"")]);
+        assert!(text.contains("\"w\"") && !text.contains('{'), "{}", text);
+        assert_eq!(message("no such message", &[]), "no such message");
+    }
+}
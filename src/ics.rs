@@ -0,0 +1,102 @@
+use crate::export::{CurfewRecord, MovementRecord};
+use chrono::{DateTime, Duration, Local, NaiveDateTime, Utc};
+
+const PRODID: &str = "-//rusty_pet//SurePet export//EN";
+/// Longest line, in bytes, before it is folded onto a continuation line
+const LINE_LIMIT: usize = 75;
+
+/// An iCalendar document with each curfew as a daily recurring event and
+/// each trip outside as a timed event.
+pub fn calendar(curfews: &[CurfewRecord], movements: &[MovementRecord]) -> String {
+    let now = Utc::now();
+    let today = Local::now().date_naive();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", PRODID),
+    ];
+
+    for curfew in curfews {
+        let start = today.and_time(curfew.lock_time);
+        let mut end = today.and_time(curfew.unlock_time);
+        // curfews usually run overnight, unlocking the next morning
+        if end <= start {
+            end += Duration::days(1);
+        }
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!(
+                "UID:curfew-{}-{}@rusty_pet",
+                curfew.device_id,
+                curfew.lock_time.format("%H%M")
+            ),
+            format!("DTSTAMP:{}", utc(now)),
+            // floating times, so the curfew follows the calendar's time zone
+            format!("DTSTART:{}", floating(start)),
+            format!("DTEND:{}", floating(end)),
+            "RRULE:FREQ=DAILY".to_string(),
+            format!("SUMMARY:{}", escape(&format!("{} curfew", curfew.device))),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+
+    for movement in movements {
+        let summary = match movement.outside_to {
+            Some(_) => format!("{} outside", movement.pet),
+            None => format!("{} outside (still out)", movement.pet),
+        };
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!(
+                "UID:movement-{}-{}@rusty_pet",
+                movement.pet_id,
+                movement.outside_from.timestamp()
+            ),
+            format!("DTSTAMP:{}", utc(now)),
+            format!("DTSTART:{}", utc(movement.outside_from)),
+        ]);
+        if let Some(to) = movement.outside_to {
+            lines.push(format!("DTEND:{}", utc(to)));
+        }
+        lines.extend([
+            format!("SUMMARY:{}", escape(&summary)),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|l| fold(l)).collect()
+}
+
+fn utc(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn floating(time: NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// Escape a TEXT value, such as a pet or device name.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Terminate a content line with CRLF, folding it so no line is longer
+/// than the limit and no character is split.
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > LINE_LIMIT {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
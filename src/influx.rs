@@ -0,0 +1,120 @@
+use crate::config;
+use crate::export::ExportData;
+use chrono::{DateTime, Utc};
+use std::env;
+use std::io::{Error, ErrorKind};
+
+/// Read from the environment rather than the config, so the token isn't left
+/// in a file that may be shared.
+const TOKEN_ENV: &str = "INFLUXDB_TOKEN";
+
+/// The measurements written, for `export schema --format influx`.
+pub const SCHEMA: &str = "export.lp, timestamps in seconds
+  feeding   tags pet, device   fields grams
+  drinking  tags pet, device   fields ml
+  activity  tags pet           fields outside_secs (integer, once back in), still_out
+  device    tags device        fields battery (integer), online, at the time of export
+";
+
+/// The export as InfluxDB line protocol, one point per event, plus each
+/// device's current battery and connection state stamped with `now`.
+pub fn lines(data: &ExportData, now: DateTime<Utc>) -> String {
+    let mut out = String::new();
+
+    for record in &data.feeding {
+        out.push_str(&format!(
+            "feeding,pet={},device={} grams={} {}\n",
+            tag(&record.pet),
+            tag(&record.device),
+            record.amount,
+            record.timestamp.timestamp()
+        ));
+    }
+    for record in &data.drinking {
+        out.push_str(&format!(
+            "drinking,pet={},device={} ml={} {}\n",
+            tag(&record.pet),
+            tag(&record.device),
+            record.amount,
+            record.timestamp.timestamp()
+        ));
+    }
+    for record in &data.movements {
+        let fields = match record.outside_to {
+            Some(to) => format!(
+                "outside_secs={}i,still_out=false",
+                (to - record.outside_from).num_seconds()
+            ),
+            None => "still_out=true".to_string(),
+        };
+        out.push_str(&format!(
+            "activity,pet={} {} {}\n",
+            tag(&record.pet),
+            fields,
+            record.outside_from.timestamp()
+        ));
+    }
+    for device in &data.devices {
+        let mut fields = Vec::new();
+        if let Some(battery) = device.battery {
+            fields.push(format!("battery={}i", battery));
+        }
+        if let Some(online) = device.online {
+            fields.push(format!("online={}", online));
+        }
+        // a point needs at least one field
+        if fields.is_empty() {
+            continue;
+        }
+        out.push_str(&format!(
+            "device,device={} {} {}\n",
+            tag(&device.name),
+            fields.join(","),
+            now.timestamp()
+        ));
+    }
+
+    out
+}
+
+/// Write line protocol to the InfluxDB v2 server in the [influxdb] config.
+pub async fn push(
+    http: &reqwest::Client,
+    cfg: Option<&config::InfluxDb>,
+    body: String,
+) -> std::io::Result<()> {
+    let cfg =
+        cfg.ok_or_else(|| Error::new(ErrorKind::NotFound, "no [influxdb] section in config.toml"))?;
+    let token = env::var(TOKEN_ENV)
+        .map_err(|_| Error::new(ErrorKind::NotFound, format!("{} is not set", TOKEN_ENV)))?;
+
+    http.post(format!("{}/api/v2/write", cfg.url.trim_end_matches('/')))
+        .query(&[
+            ("org", cfg.org.as_str()),
+            ("bucket", cfg.bucket.as_str()),
+            ("precision", "s"),
+        ])
+        .header("Authorization", format!("Token {}", token))
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body(body)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| Error::other(e.without_url()))?;
+    Ok(())
+}
+
+/// Escape a tag value; commas, equals signs and spaces would end it.
+fn tag(value: &str) -> String {
+    let value = value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ");
+    // empty tag values aren't allowed
+    if value.is_empty() {
+        "unknown".to_string()
+    } else {
+        value
+    }
+}
@@ -1,47 +1,241 @@
 mod api;
+mod auth;
+mod battery;
+mod capabilities;
+mod cli;
+mod compare;
+mod compress;
 mod config;
+mod confirm;
+mod curfew;
+mod dashboard;
+mod device_info;
+mod diff;
+mod doctor;
+mod error;
+mod events;
+mod exit;
+mod export;
+mod filter;
+mod forecast;
+mod headless;
+mod health;
+mod history;
+mod i18n;
+mod ics;
+mod influx;
+mod markdown;
+mod mqtt;
+mod notifications;
+mod outings;
+mod output;
+mod overview;
+mod pager;
+mod pending;
+mod pet_info;
+mod preview;
+mod range;
+mod report;
+mod rules;
+mod schedule;
+mod search;
+mod searches;
+mod signal;
+mod snooze;
+mod sqlite;
+mod stats;
+mod subscriptions;
+mod summary;
+mod table;
+mod template;
+mod tui;
+mod units;
+mod vet_report;
+mod views;
+mod watermarks;
+mod weather;
 
+use crate::api::cache::CacheMode;
 use crate::api::client::Client;
-use console::style;
+use crate::api::client::{Pet, PetProfile};
+use crate::api::mock::MockClient;
+use crate::api::SurePetApi;
+use crate::capabilities::Capability;
+use crate::cli::{Cli, Command};
+use crate::error::RustyPetError;
+use crate::exit::{ErrorEnvelope, Exit};
+use crate::pending::{Pending, PendingOperation, Sent};
+use crate::search::MatchMode;
+use crate::searches::SavedSearches;
+use chrono::{Duration, Utc};
+use console::{style, Key, Term};
 use env_logger::{Builder, Target};
-use log::{debug, error};
+use log::{debug, error, LevelFilter};
+use output::Verbosity;
+use std::collections::HashMap;
 use std::env;
-
-const TOKEN_ENV: &str = "SUREPY_TOKEN";
+use std::process::ExitCode;
+use std::time::Instant;
+use tokio::sync::mpsc;
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> ExitCode {
+    let cli = Cli::parse_localized();
+    let verbosity = cli.global.verbosity();
+    output::set_verbosity(verbosity);
+
     let mut builder = Builder::from_default_env();
-    builder.target(Target::Stdout);
+    builder.target(Target::Stderr);
+    // RUST_LOG still wins when it's set
+    if env::var_os("RUST_LOG").is_none() {
+        match verbosity {
+            Verbosity::Quiet => builder.filter_level(LevelFilter::Error),
+            Verbosity::Normal => &mut builder,
+            Verbosity::Verbose => builder.filter_module("rusty_pet", LevelFilter::Debug),
+        };
+    }
     builder.init();
 
-    let cfg: config::Config = config::read_config();
+    let json_errors = cli.command.as_ref().is_some_and(Command::writes_json);
+    let cfg = match config::read_config() {
+        Ok(cfg) => cfg,
+        Err(e) => return unusable(&cli, e, json_errors),
+    };
+    match cli
+        .global
+        .replay
+        .clone()
+        .or_else(|| cfg.api.mock_dir.clone())
+    {
+        Some(dir) => run(cli, MockClient::new(cfg, dir)).await,
+        None => {
+            let mut api_client = match Client::new(cfg) {
+                Ok(api_client) => api_client,
+                Err(e) => return unusable(&cli, e, json_errors),
+            };
+            if let Some(dir) = cli.global.record.clone() {
+                api_client.record_to(dir);
+            }
+            api_client.set_dry_run(cli.global.dry_run);
+            run(cli, api_client).await
+        }
+    }
+}
+
+/// Report a config or client that can't be used. `doctor` still runs, to
+/// say what's wrong alongside anything else it can check without them.
+fn unusable(cli: &Cli, e: std::io::Error, json_errors: bool) -> ExitCode {
+    if matches!(cli.command, Some(Command::Doctor)) {
+        doctor::report_unusable(&e);
+        return Exit::from_result(&Ok(())).into();
+    }
+    report_error(&e.into(), json_errors)
+}
+
+/// Run the command given, or the interactive menu when there isn't one.
+async fn run(cli: Cli, api_client: impl SurePetApi) -> ExitCode {
+    if let Some(command) = cli.command {
+        let json_errors = command.writes_json();
+        let started = Instant::now();
+        api_client.set_cache_mode(cli.global.cache_mode());
+        let result = async {
+            let token = if command.needs_auth() {
+                auth::check_token(&api_client).await?
+            } else {
+                String::new()
+            };
+            headless::run(&api_client, &token, command, &cli.global).await?;
+            Ok::<_, RustyPetError>(())
+        }
+        .await;
+        views::print_planned(&api_client.take_planned(), json_errors);
+        output::print_request_stats(&api_client.stats());
+        if api_client.cfg().headless.summary_line {
+            summary::print(&result, started.elapsed());
+        }
+        return match &result {
+            Err(e) => report_error(e, json_errors),
+            Ok(()) => Exit::from_result(&result).into(),
+        };
+    }
+
+    let result = interactive(cli, &api_client).await;
+    views::print_planned(&api_client.take_planned(), false);
+    match result.map_err(RustyPetError::from) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit().into()
+        }
+    }
+}
+
+/// Print why a command failed, as a JSON envelope for commands writing
+/// JSON so the stream stays parseable to the end, and exit with its code.
+fn report_error(e: &RustyPetError, json_errors: bool) -> ExitCode {
+    if json_errors {
+        let _ = output::print_ndjson(&ErrorEnvelope::new(e));
+    } else {
+        eprintln!("Error: {}", e);
+    }
+    e.exit().into()
+}
 
-    ctrlc::set_handler(move || {}).expect("setting Ctrl-C handler");
+async fn interactive(cli: Cli, api_client: &impl SurePetApi) -> std::io::Result<()> {
+    ctrlc::set_handler(tui::interrupt).expect("setting Ctrl-C handler");
     cliclack::clear_screen()?;
 
     cliclack::intro(style(" RustyPet - Your SurePet CLI ").on_cyan().black())?;
 
-    let op = cliclack::select(format!("What would you like to do?"))
-        .initial_value("st")
-        .item("st", "Status", "")
-        .item("ls", "List Pets", "")
-        .interact()?;
+    let assume_yes = cli.global.assume_yes(api_client.cfg());
 
     // Sign in etc
-    let api_client = Client::new(cfg);
+    api_client.set_cache_mode(cli.global.cache_mode());
+
+    let token = auth::check_token(api_client).await;
+    if let Err(e) = &token {
+        error!("failed to authenticate to SurePy: {}", e)
+    }
+    let token = token?;
 
-    let token = check_token(&api_client).await;
-    if token.is_ok() == false {
-        error!(
-            "failed to authenticate to SurePy: {}",
-            &token.as_ref().err().unwrap()
-        )
+    if !cli.simple && Term::stdout().is_term() {
+        return tui::run(api_client, &token, assume_yes).await;
     }
 
+    // only offer what this household has the devices for
+    let capabilities = capabilities::probe(api_client, &token).await?;
+    let mut menu = cliclack::select("What would you like to do?")
+        .initial_value("st")
+        .item("st", "Status", "")
+        .item("ls", "List Pets", "")
+        .item("pi", "Pet Info", "")
+        .item("di", "Device Info", "")
+        .item("se", "Search", "");
+    if capabilities.has(Capability::Feeder) {
+        menu = menu.item("fh", "Feeding History", "");
+    }
+    if capabilities.has(Capability::Flap) {
+        menu = menu.item("pp", "Pet Permissions", "");
+    }
+    if capabilities.has(Capability::WaterStation) {
+        menu = menu.item("hc", "Health Check", "");
+    }
+    let pending = Pending::load()?;
+    if !pending.operations.is_empty() {
+        menu = menu.item("pe", "Pending Changes", "made while offline");
+    }
+    let op = menu.interact()?;
+
     match op {
-        "st" => do_status(&api_client, &token.unwrap()).await,
-        "ls" => do_list(&api_client, &token.unwrap()).await,
+        "st" => do_status(api_client, &token).await?,
+        "ls" => do_list(api_client, &token).await?,
+        "fh" => do_feeding_history(api_client, &token, None).await?,
+        "pp" => do_pet_permissions(api_client, &token, None, assume_yes).await?,
+        "hc" => do_health_check(api_client, &token, None).await?,
+        "pi" => do_pet_info(api_client, &token, None).await?,
+        "di" => do_device_info(api_client, &token, None).await?,
+        "se" => do_search(api_client, &token).await?,
+        "pe" => do_pending(api_client, &token, pending, assume_yes).await?,
         _ => {
             println!("This is an invalid operation");
             error!("Invalid operation")
@@ -51,38 +245,478 @@ async fn main() -> std::io::Result<()> {
     Ok(())
 }
 
-async fn do_list(api_client: &Client, token: &String) {
+/// Views that stay on screen and can be refreshed in place.
+#[derive(Clone, Copy)]
+enum View {
+    Pets,
+    Devices,
+}
+
+async fn do_list(api_client: &impl SurePetApi, token: &str) -> std::io::Result<()> {
     debug!("Performing list operation");
+    live_view(api_client, token, View::Pets).await
 }
 
-async fn do_status(api_client: &Client, token: &String) {
+async fn do_status(api_client: &impl SurePetApi, token: &str) -> std::io::Result<()> {
     debug!("Performing status operation");
+    live_view(api_client, token, View::Devices).await
 }
 
-async fn check_token(api_client: &Client) -> std::io::Result<String> {
-    // check if authentication token has been set in environment
-    if env::var(TOKEN_ENV).is_ok() {
-        debug!("{} found", TOKEN_ENV);
-        println!("using token {}", env::var(TOKEN_ENV).unwrap());
-        return Ok(env::var(TOKEN_ENV).unwrap());
-    } else {
-        // if no token, sign in with username and password then return the token
-        debug!("{} not found", TOKEN_ENV);
-        let username: String = cliclack::input("Provide your username").interact()?;
+async fn render_view(api_client: &impl SurePetApi, token: &str, view: View) -> std::io::Result<()> {
+    // only views fall back to cached data, not the menu items that act on it
+    api_client.set_offline_fallback(true);
+    let shown = async {
+        match view {
+            View::Pets => {
+                let pets = api_client
+                    .get_pets(token)
+                    .await
+                    .map_err(std::io::Error::other)?;
+                views::print_pets(&pets, &api_client.cfg().units);
+            }
+            View::Devices => {
+                let devices = api_client
+                    .get_devices(token)
+                    .await
+                    .map_err(std::io::Error::other)?;
+                views::print_devices(&devices, &api_client.cfg().units);
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    }
+    .await;
+    api_client.set_offline_fallback(false);
+    shown?;
+    views::print_offline(api_client.take_offline_as_of(), &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), "press r");
+
+    Ok(())
+}
+
+/// Show a view until the user quits, refreshing it on `r` or, once toggled
+/// with `a`, every `auto_refresh_interval` seconds. Refreshes closer together
+/// than `min_refresh_interval` are ignored so the API isn't hammered.
+async fn live_view(api_client: &impl SurePetApi, token: &str, view: View) -> std::io::Result<()> {
+    let cfg = &api_client.cfg().interactive;
+    let auto_interval = std::time::Duration::from_secs(cfg.auto_refresh_interval);
+    let min_interval = std::time::Duration::from_secs(cfg.min_refresh_interval);
+
+    let term = Term::stdout();
+    let mut keys = read_keys();
+    let mut auto_refresh = false;
+
+    loop {
+        term.clear_screen()?;
+        render_view(api_client, token, view).await?;
+        let refreshed = Instant::now();
+        // the first render may come from the cache, refreshes never do
+        if api_client.cache_mode() == CacheMode::Use {
+            api_client.set_cache_mode(CacheMode::Refresh);
+        }
+        println!(
+            "\n{}",
+            style(format!(
+                "r refresh · a auto-refresh ({}) · q quit",
+                if auto_refresh { "on" } else { "off" }
+            ))
+            .dim()
+        );
+
+        loop {
+            let key = if auto_refresh {
+                let wait = auto_interval.saturating_sub(refreshed.elapsed());
+                match tokio::time::timeout(wait, keys.recv()).await {
+                    Ok(key) => key,
+                    Err(_) => break,
+                }
+            } else {
+                keys.recv().await
+            };
+
+            match key {
+                Some(Key::Char('r')) if refreshed.elapsed() >= min_interval => break,
+                Some(Key::Char('r')) => println!(
+                    "Refreshed {}s ago, try again shortly",
+                    refreshed.elapsed().as_secs()
+                ),
+                Some(Key::Char('a')) if auto_interval.is_zero() => {
+                    println!("Auto-refresh is disabled in the config")
+                }
+                Some(Key::Char('a')) => {
+                    auto_refresh = !auto_refresh;
+                    println!(
+                        "Auto-refresh every {}s {}",
+                        auto_interval.as_secs(),
+                        if auto_refresh { "on" } else { "off" }
+                    );
+                }
+                Some(Key::Char('q')) | Some(Key::Escape) | Some(Key::CtrlC) | None => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Read keys on a separate thread so a view can wait for a key press and
+/// its auto-refresh timer at the same time.
+fn read_keys() -> mpsc::UnboundedReceiver<Key> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let term = Term::stdout();
+        while let Ok(key) = term.read_key() {
+            if tx.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+async fn do_feeding_history(
+    api_client: &impl SurePetApi,
+    token: &str,
+    chosen: Option<u32>,
+) -> std::io::Result<()> {
+    debug!("Performing feeding history operation");
+
+    let pets = api_client
+        .get_pets(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let devices = api_client
+        .get_devices(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let device_names: HashMap<u32, String> = devices.into_iter().map(|d| (d.id, d.name)).collect();
+
+    let pet = select_pet(&pets, chosen)?;
+
+    let days: i64 = cliclack::select("Over what period?")
+        .initial_value(7)
+        .item(1, "Last 24 hours", "")
+        .item(7, "Last 7 days", "")
+        .item(30, "Last 30 days", "")
+        .interact()?;
+
+    let to = Utc::now();
+    let report = api_client
+        .get_pet_report(token, pet, to - Duration::days(days), to)
+        .await
+        .map_err(std::io::Error::other)?;
+    let events = report.feeding.map(|f| f.datapoints).unwrap_or_default();
+
+    let cfg = api_client.cfg();
+    views::print_feeding_history(
+        pet,
+        &events,
+        &device_names,
+        &cfg.units,
+        cfg.headless.compact,
+    );
+    views::print_cache_age(api_client.take_cache_age(), "run with --refresh");
+
+    Ok(())
+}
+
+async fn do_pet_permissions(
+    api_client: &impl SurePetApi,
+    token: &str,
+    chosen: Option<u32>,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    debug!("Performing pet permissions operation");
+
+    let pets = api_client
+        .get_pets(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let devices = api_client
+        .get_devices(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let flaps: Vec<_> = devices.iter().filter(|d| d.is_flap()).collect();
+    if flaps.is_empty() {
+        println!("No flaps found in your household");
+        return Ok(());
+    }
+
+    let pet = select_pet(&pets, chosen)?;
+    let Some(tag_id) = pet.tag_id else {
+        println!("{} has no microchip or tag registered", pet.name);
+        return Ok(());
+    };
+
+    let mut flap_select = cliclack::select("Which flap?");
+    for (idx, flap) in flaps.iter().enumerate() {
+        flap_select = flap_select.item(idx, &flap.name, "");
+    }
+    let flap = flaps[flap_select.interact()?];
+
+    let profile = cliclack::select(format!("What may {} do?", pet.name))
+        .initial_value(PetProfile::Outdoor)
+        .item(PetProfile::Outdoor, "Go outdoors", "")
+        .item(PetProfile::IndoorOnly, "Stay indoors only", "")
+        .interact()?;
+
+    let prompt = format!("Change {} on {}?", pet.name, flap.name);
+    if !confirm::confirm(prompt, assume_yes)? {
+        return Ok(());
+    }
+
+    let operation = PendingOperation::profile(pet, tag_id, flap, profile);
+    let describe = operation.describe();
+    match pending::apply_or_queue(api_client, token, operation).await? {
+        Sent::Applied if api_client.dry_run() => {}
+        Sent::Applied => println!("Updated {} on {}", pet.name, flap.name),
+        Sent::Queued => println!(
+            "The API can't be reached, so this will be done once it's back: {}",
+            describe
+        ),
+    }
+
+    Ok(())
+}
+
+async fn do_pending(
+    api_client: &impl SurePetApi,
+    token: &str,
+    mut pending: Pending,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    debug!("Performing pending changes operation");
+
+    views::print_pending(&pending, &api_client.cfg().units);
+    if !confirm::confirm("Make these changes now?", assume_yes)? {
+        return Ok(());
+    }
+    let outcomes = pending.replay(api_client, token).await?;
+    if !api_client.dry_run() {
+        pending.save()?;
+    }
+    views::print_replay(&outcomes);
+
+    Ok(())
+}
 
-        let password = cliclack::password("Provide your password")
-            .mask('▪')
-            .interact()?;
+async fn do_health_check(
+    api_client: &impl SurePetApi,
+    token: &str,
+    chosen: Option<u32>,
+) -> std::io::Result<()> {
+    debug!("Performing health check operation");
 
-        let resp = api_client
-            .login(&username, &password)
-            .await
-            .expect("Failed to log in");
+    let pets = api_client
+        .get_pets(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let pet = select_pet(&pets, chosen)?;
 
-        // Set the token in the environment for use in same session
-        env::set_var(TOKEN_ENV, &resp.data.token);
-        debug!("Token ENV set");
+    let cfg = &api_client.cfg().health;
+    let now = Utc::now();
+    let report = api_client
+        .get_pet_report(token, pet, now - health::drinking_lookback(cfg), now)
+        .await
+        .map_err(std::io::Error::other)?;
+    let events = report.drinking.map(|d| d.datapoints).unwrap_or_default();
+
+    health::print_drinking_trend(
+        pet,
+        &health::drinking_trend(&events, now, cfg),
+        cfg,
+        &api_client.cfg().units,
+    );
+    views::print_cache_age(api_client.take_cache_age(), "run with --refresh");
+
+    Ok(())
+}
+
+async fn do_pet_info(
+    api_client: &impl SurePetApi,
+    token: &str,
+    chosen: Option<u32>,
+) -> std::io::Result<()> {
+    debug!("Performing pet info operation");
+
+    let pets = api_client
+        .get_pets(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let pet = select_pet(&pets, chosen)?;
+    let devices = api_client
+        .get_devices(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let info = pet_info::gather(api_client, token, pet, &devices).await?;
+    if let (Some(photo), Some(protocol)) = (&pet.photo, preview::protocol()) {
+        // best effort, the info is what was asked for
+        if let Err(e) = show_photo(api_client, &photo.location, protocol).await {
+            debug!("Not previewing {}'s picture: {}", pet.name, e);
+        }
+    }
+    views::print_pet_info(&info, &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), "run with --refresh");
 
-        return Ok(resp.data.token);
+    Ok(())
+}
+
+async fn show_photo(
+    api_client: &impl SurePetApi,
+    location: &str,
+    protocol: preview::Protocol,
+) -> std::io::Result<()> {
+    let image = api_client
+        .http()
+        .get(location)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(std::io::Error::other)?
+        .bytes()
+        .await
+        .map_err(std::io::Error::other)?;
+    if !preview::show(&image, protocol)? {
+        debug!("{:?} can't show this format", protocol);
+    }
+    Ok(())
+}
+
+async fn do_device_info(
+    api_client: &impl SurePetApi,
+    token: &str,
+    chosen: Option<u32>,
+) -> std::io::Result<()> {
+    debug!("Performing device info operation");
+
+    let devices = api_client
+        .get_devices(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let device = match devices.iter().find(|d| Some(d.id) == chosen) {
+        Some(device) => device,
+        None => {
+            let mut device_select = cliclack::select("Which device?");
+            for (idx, device) in devices.iter().enumerate() {
+                device_select = device_select.item(idx, &device.name, "");
+            }
+            &devices[device_select.interact()?]
+        }
+    };
+
+    let pets = api_client
+        .get_pets(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let info = device_info::gather(api_client, token, device, &pets).await?;
+    views::print_device_info(&info, &api_client.cfg().units);
+    views::print_cache_age(api_client.take_cache_age(), "run with --refresh");
+
+    Ok(())
+}
+
+/// What to do from the search menu.
+#[derive(Clone, Default, PartialEq, Eq)]
+enum SearchChoice {
+    #[default]
+    New,
+    Saved(String),
+    Delete,
+}
+
+async fn do_search(api_client: &impl SurePetApi, token: &str) -> std::io::Result<()> {
+    debug!("Performing search operation");
+
+    let mut saved = SavedSearches::load()?;
+    let mut choose = cliclack::select("Search for what?").item(SearchChoice::New, "New search", "");
+    for search in saved.by_use() {
+        let hint = search::describe(&search.patterns, search.query.as_ref());
+        choose = choose.item(SearchChoice::Saved(search.name.clone()), &search.name, hint);
+    }
+    if !saved.searches.is_empty() {
+        choose = choose.item(SearchChoice::Delete, "Delete a saved search", "");
+    }
+
+    let choice = choose.interact()?;
+    // a saved search is already saved, query and all
+    let from_saved = matches!(choice, SearchChoice::Saved(_));
+    let (patterns, mode, filter) = match choice {
+        SearchChoice::Delete => {
+            let mut which = cliclack::select("Delete which search?");
+            for search in saved.by_use() {
+                which = which.item(search.name.clone(), &search.name, "");
+            }
+            let name: String = which.interact()?;
+            if !confirm::confirm(format!("Delete {}?", name), false)? {
+                return Ok(());
+            }
+            saved.remove(&name);
+            saved.save()?;
+            println!("Deleted {}", name);
+            return Ok(());
+        }
+        SearchChoice::Saved(name) => {
+            let search = saved
+                .take(&name)
+                .map(|s| (s.patterns.clone(), s.mode, s.query.clone()));
+            saved.save()?;
+            search.unwrap_or_default()
+        }
+        SearchChoice::New => {
+            let text: String = cliclack::input("Patterns, separated by spaces")
+                .placeholder("offline battery<20")
+                .interact()?;
+            let mode = cliclack::select("Match how?")
+                .item(MatchMode::Substring, "Text", "anywhere, ignoring case")
+                .item(MatchMode::Regex, "Regular expression", "")
+                .item(MatchMode::Fuzzy, "Fuzzy", "letters in order, best first")
+                .interact()?;
+            let patterns = text.split_whitespace().map(str::to_string).collect();
+            (patterns, mode, None)
+        }
+    };
+    let wanted = search::describe(&patterns, filter.as_ref());
+    let query = search::Query::new(&patterns, mode)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?
+        .filtered(filter);
+
+    let pets = api_client
+        .get_pets(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let devices = api_client
+        .get_devices(token)
+        .await
+        .map_err(std::io::Error::other)?;
+    let hits = search::search_state(&pets, &devices, &query);
+    views::print_search_hits(&hits, &wanted);
+    views::print_cache_age(api_client.take_cache_age(), "run with --refresh");
+
+    let is_saved = from_saved
+        || saved
+            .searches
+            .iter()
+            .any(|s| s.patterns == patterns && s.mode == mode && s.query.is_none());
+    if !is_saved && cliclack::confirm("Save this search?").interact()? {
+        let name: String = cliclack::input("Save it as").interact()?;
+        saved.add(&name, &patterns, mode, None);
+        saved.save()?;
+        println!(
+            "Saved {}, run it again with search saved run {}",
+            name, name
+        );
+    }
+
+    Ok(())
+}
+
+/// The pet with the id `chosen`, such as the one selected in the full-screen
+/// interface, or the one picked from a list.
+fn select_pet(pets: &[Pet], chosen: Option<u32>) -> std::io::Result<&Pet> {
+    if let Some(pet) = pets.iter().find(|p| Some(p.id) == chosen) {
+        return Ok(pet);
+    }
+    let mut pet_select = cliclack::select("Which pet?");
+    for (idx, pet) in pets.iter().enumerate() {
+        pet_select = pet_select.item(idx, &pet.name, "");
     }
+    Ok(&pets[pet_select.interact()?])
 }
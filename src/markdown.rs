@@ -0,0 +1,287 @@
+use crate::api::client::{Device, DrinkingEvent, FeedingEvent, Location, Pet};
+use crate::curfew;
+use crate::events::EventType;
+use crate::history::{self, DeviceTotal, GroupBy, Period, Summary};
+use crate::outings::Outing;
+use crate::output::OutputFormatter;
+use crate::stats::format_duration;
+use crate::table::device_name;
+use crate::units::Units;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Markdown tables, to paste into GitHub issues or notes.
+pub struct MarkdownFormatter<'a> {
+    pub units: &'a Units,
+    /// Histories without device columns or per-device totals, from
+    /// [headless] compact
+    pub compact: bool,
+}
+
+impl MarkdownFormatter<'_> {
+    /// Bold total and average rows, padded to the history's columns.
+    fn footer(
+        rows: &mut Vec<Vec<String>>,
+        columns: usize,
+        summary: &Summary,
+        noun: &str,
+        amount: impl Fn(f64) -> String,
+    ) {
+        let footer = [
+            (summary.label(noun), Some(summary.total)),
+            ("Average".to_string(), summary.average()),
+        ];
+        for (label, value) in footer {
+            let mut row = vec![
+                format!("**{}**", label),
+                format!("**{}**", value.map_or("-".to_string(), &amount)),
+            ];
+            row.resize(columns, String::new());
+            rows.push(row);
+        }
+    }
+
+    fn device_totals(
+        totals: Vec<DeviceTotal>,
+        header: &[&str],
+        device_name: impl Fn(u32) -> String,
+        amount: impl Fn(f64) -> String,
+    ) -> String {
+        let rows = totals.into_iter().map(|device| {
+            vec![
+                device_name(device.device_id),
+                device.events.to_string(),
+                amount(device.amount),
+            ]
+        });
+        table(header, rows)
+    }
+}
+
+impl OutputFormatter for MarkdownFormatter<'_> {
+    fn pets(&self, pets: &[Pet]) -> String {
+        let rows = pets.iter().map(|pet| match &pet.position {
+            Some(position) => vec![
+                pet.name.clone(),
+                format!(
+                    "{} {}",
+                    location_emoji(position.location),
+                    position.location
+                ),
+                self.units.time(&position.since),
+            ],
+            None => vec![pet.name.clone(), "❔ unknown".to_string(), String::new()],
+        });
+        table(&["Pet", "Location", "Since"], rows)
+    }
+
+    fn devices(&self, devices: &[Device]) -> String {
+        let rows = devices.iter().map(|device| {
+            vec![
+                device.name.clone(),
+                online(device.online()).to_string(),
+                device
+                    .battery_percent()
+                    .map(|b| format!("{}%", b))
+                    .unwrap_or_else(|| "-".to_string()),
+                device
+                    .lock_mode()
+                    .map(|m| m.to_string())
+                    .unwrap_or_default(),
+                device
+                    .control
+                    .as_ref()
+                    .and_then(|c| curfew::text(&c.curfew))
+                    .unwrap_or_default(),
+            ]
+        });
+        table(&["Device", "Connection", "Battery", "Lock", "Curfew"], rows)
+    }
+
+    fn feeding_history(
+        &self,
+        events: &[FeedingEvent],
+        all: &[FeedingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String {
+        let grams = |grams: f64| self.units.weight(grams, 1);
+        let header: &[&str] = match self.compact {
+            true => &["Time", "Eaten"],
+            false => &["Time", "Eaten", "Device"],
+        };
+        let mut meals: Vec<Vec<String>> = events
+            .iter()
+            .map(|event| {
+                let mut row = vec![self.units.time(&event.from), grams(event.grams_eaten())];
+                if !self.compact {
+                    row.push(device_name(device_names, event.device_id));
+                }
+                row
+            })
+            .collect();
+        let summary = Summary::new(all.iter().map(|e| e.grams_eaten()));
+        Self::footer(&mut meals, header.len(), &summary, "meal", grams);
+        let meals = table(header, meals.into_iter());
+        if self.compact || all.is_empty() {
+            return meals;
+        }
+
+        let totals = Self::device_totals(
+            history::feeding_by_device(all),
+            &["Device", "Meals", "Eaten"],
+            |id| device_name(device_names, id),
+            grams,
+        );
+        format!("### Meals\n\n{}\n### By device\n\n{}", meals, totals)
+    }
+
+    fn drinking_history(
+        &self,
+        events: &[DrinkingEvent],
+        all: &[DrinkingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String {
+        let ml = |ml: f64| self.units.volume(ml, 0);
+        let header: &[&str] = match self.compact {
+            true => &["Time", "Drunk"],
+            false => &["Time", "Drunk", "Device"],
+        };
+        let mut drinks: Vec<Vec<String>> = events
+            .iter()
+            .map(|event| {
+                let mut row = vec![self.units.time(&event.from), ml(event.ml_drunk())];
+                if !self.compact {
+                    row.push(device_name(device_names, event.device_id));
+                }
+                row
+            })
+            .collect();
+        let summary = Summary::new(all.iter().map(|e| e.ml_drunk()));
+        Self::footer(&mut drinks, header.len(), &summary, "drink", ml);
+        let drinks = table(header, drinks.into_iter());
+        if self.compact || all.is_empty() {
+            return drinks;
+        }
+
+        let totals = Self::device_totals(
+            history::drinking_by_device(all),
+            &["Device", "Drinks", "Drunk"],
+            |id| device_name(device_names, id),
+            ml,
+        );
+        format!("### Drinks\n\n{}\n### By device\n\n{}", drinks, totals)
+    }
+
+    fn activity_history(&self, outings: &[Outing], all: &[Outing], unpaired: usize) -> String {
+        let duration = |minutes: f64| format_duration(chrono::Duration::minutes(minutes as i64));
+        let header: &[&str] = match self.compact {
+            true => &["Went out", "Outside"],
+            false => &["Went out", "Outside", "Back in", "Flap"],
+        };
+        let mut trips: Vec<Vec<String>> = outings
+            .iter()
+            .map(|outing| {
+                let mut row = vec![
+                    self.units.time(&outing.from),
+                    duration(outing.minutes as f64),
+                ];
+                if !self.compact {
+                    row.push(match outing.to {
+                        Some(to) => self.units.time(&to),
+                        None => "still out".to_string(),
+                    });
+                    row.push(outing.device.clone().unwrap_or_default());
+                }
+                row
+            })
+            .collect();
+        let summary = Summary::new(all.iter().map(|o| o.minutes as f64));
+        Self::footer(&mut trips, header.len(), &summary, "trip", duration);
+
+        let mut md = table(header, trips.into_iter());
+        if unpaired > 0 {
+            let _ = write!(
+                md,
+                "\n_{} trip(s) left out, the return wasn't scanned_\n",
+                unpaired
+            );
+        }
+        md
+    }
+
+    fn history_periods(
+        &self,
+        periods: &[Period],
+        all: &[Period],
+        by: GroupBy,
+        event_type: EventType,
+    ) -> String {
+        let [count, amount] = history::columns(event_type);
+        let heading = match by {
+            GroupBy::Day => "Day",
+            GroupBy::Week => "Week",
+        };
+        let amount_text = |amount: f64| history::amount_text(event_type, amount, self.units);
+        let mut rows: Vec<Vec<String>> = periods
+            .iter()
+            .map(|period| {
+                vec![
+                    history::period_text(period, by, self.units),
+                    period.count.to_string(),
+                    amount_text(period.amount),
+                ]
+            })
+            .collect();
+        let counts = Summary::new(all.iter().map(|p| p.count as f64));
+        let amounts = Summary::new(all.iter().map(|p| p.amount));
+        let footer = [
+            ("Total", counts.total.to_string(), Some(amounts.total)),
+            (
+                "Average",
+                format!("{:.1}", counts.average().unwrap_or_default()),
+                amounts.average(),
+            ),
+        ];
+        for (label, count, amount) in footer {
+            rows.push(vec![
+                format!("**{}**", label),
+                format!("**{}**", count),
+                format!("**{}**", amount.map_or("-".to_string(), amount_text)),
+            ]);
+        }
+        table(&[heading, count, amount], rows.into_iter())
+    }
+}
+
+/// A Markdown table, one line per row.
+pub fn table(header: &[&str], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let mut table = String::new();
+    let _ = writeln!(table, "| {} |", header.join(" | "));
+    let _ = writeln!(table, "|{}", "---|".repeat(header.len()));
+    for row in rows {
+        let cells: Vec<_> = row.iter().map(|c| escape(c)).collect();
+        let _ = writeln!(table, "| {} |", cells.join(" | "));
+    }
+    table
+}
+
+pub fn online(online: Option<bool>) -> &'static str {
+    match online {
+        Some(true) => "🟢 online",
+        Some(false) => "🔴 offline",
+        None => "-",
+    }
+}
+
+fn location_emoji(location: Location) -> &'static str {
+    match location {
+        Location::Inside => "🏠",
+        Location::Outside => "🌳",
+        Location::Unknown(_) => "❔",
+    }
+}
+
+/// Keep a cell's text from breaking the table.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
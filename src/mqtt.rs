@@ -0,0 +1,443 @@
+use crate::api::cache::CacheMode;
+use crate::api::client::{Device, LockMode, Pet};
+use crate::api::SurePetApi;
+use crate::cli::MqttArgs;
+use crate::config;
+use crate::notifications::{self, StateTracker};
+use crate::output::status;
+use crate::pending::{self, PendingOperation, Sent};
+use crate::views;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use std::collections::HashMap;
+use std::env;
+use std::io::Error;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const PASSWORD_ENV: &str = "MQTT_PASSWORD";
+
+/// Publishes SurePet data under a topic prefix. State topics are retained
+/// and only republished when their value changes; events are not retained.
+struct Publisher {
+    client: AsyncClient,
+    prefix: String,
+    retained: HashMap<String, String>,
+    /// When the latest event published to each event topic happened, so a
+    /// poll retried after failing partway doesn't publish events twice
+    sent: HashMap<String, DateTime<Utc>>,
+}
+
+impl Publisher {
+    async fn state(&mut self, topic: &str, value: String) -> std::io::Result<()> {
+        let topic = format!("{}/{}", self.prefix, topic);
+        self.retain(topic, value).await
+    }
+
+    async fn retain(&mut self, topic: String, value: String) -> std::io::Result<()> {
+        if self.retained.get(&topic) == Some(&value) {
+            return Ok(());
+        }
+
+        debug!("Publishing {} = {}", topic, value);
+        self.client
+            .publish(&topic, QoS::AtLeastOnce, true, value.clone())
+            .await
+            .map_err(Error::other)?;
+        self.retained.insert(topic, value);
+        Ok(())
+    }
+
+    async fn event(
+        &mut self,
+        topic: &str,
+        at: DateTime<Utc>,
+        payload: serde_json::Value,
+    ) -> std::io::Result<()> {
+        let topic = format!("{}/{}", self.prefix, topic);
+        if self.sent.get(&topic).is_some_and(|sent| at <= *sent) {
+            return Ok(());
+        }
+
+        debug!("Publishing {} = {}", topic, payload);
+        self.client
+            .publish(&topic, QoS::AtLeastOnce, false, payload.to_string())
+            .await
+            .map_err(Error::other)?;
+        self.sent.insert(topic, at);
+        Ok(())
+    }
+}
+
+pub async fn serve(
+    api_client: &impl SurePetApi,
+    token: &str,
+    args: MqttArgs,
+) -> std::io::Result<()> {
+    let cfg = &api_client.cfg().mqtt;
+    let host = args.host.unwrap_or_else(|| cfg.host.clone());
+    let port = args.port.unwrap_or(cfg.port);
+    let interval = Duration::from_secs(args.interval.unwrap_or(cfg.poll_interval_secs));
+    let prefix = args
+        .topic_prefix
+        .unwrap_or_else(|| cfg.topic_prefix.clone());
+
+    let mut options = MqttOptions::new(&cfg.client_id, &host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = args.username {
+        options.set_credentials(username, env::var(PASSWORD_ENV).unwrap_or_default());
+    }
+
+    let (client, mut eventloop) = AsyncClient::new(options, 100);
+    let (commands_tx, mut commands) = mpsc::unbounded_channel();
+    let command_topic = format!("{}/devices/+/lock/set", prefix);
+    let subscriber = client.clone();
+
+    // the event loop owns the connection and reconnects on the next poll after an error
+    tokio::spawn(async move {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    // subscriptions don't survive a reconnect with a clean session
+                    if let Err(e) = subscriber.subscribe(&command_topic, QoS::AtLeastOnce).await {
+                        error!("failed to subscribe to {}: {}", command_topic, e);
+                    }
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let payload = String::from_utf8_lossy(&publish.payload).to_string();
+                    let _ = commands_tx.send((publish.topic, payload));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("MQTT connection error: {}", e);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+
+    let mut publisher = Publisher {
+        client,
+        prefix,
+        retained: HashMap::new(),
+        sent: HashMap::new(),
+    };
+
+    status!(
+        "Publishing to {}:{} every {}s",
+        host,
+        port,
+        interval.as_secs()
+    );
+
+    // each poll publishes what changed since the last, so never use cached data
+    api_client.set_cache_mode(CacheMode::Off);
+
+    let mut tracker = StateTracker::default();
+    let mut ticker = tokio::time::interval(interval);
+    let mut last_polled = Utc::now();
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let now = Utc::now();
+                let published =
+                    publish_all(api_client, token, &mut publisher, &mut tracker, last_polled, now);
+                match published.await {
+                    Ok(()) => last_polled = now,
+                    Err(e) => error!("Failed to publish SurePet data: {}", e),
+                }
+            }
+            Some((topic, payload)) = commands.recv() => {
+                let handled =
+                    handle_lock_command(api_client, token, &mut publisher, &topic, &payload).await;
+                if let Err(e) = handled {
+                    error!("Failed to handle {}: {}", topic, e);
+                }
+            }
+        }
+        views::print_planned(&api_client.take_planned(), false);
+    }
+}
+
+async fn publish_all(
+    api_client: &impl SurePetApi,
+    token: &str,
+    publisher: &mut Publisher,
+    tracker: &mut StateTracker,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> std::io::Result<()> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device_names: HashMap<u32, &str> =
+        devices.iter().map(|d| (d.id, d.name.as_str())).collect();
+
+    notifications::dispatch(api_client, token, tracker, &pets, &devices).await;
+
+    let cfg = &api_client.cfg().mqtt;
+    if cfg.discovery {
+        publish_discovery(publisher, cfg, &pets, &devices).await?;
+    }
+
+    for device in &devices {
+        let name = topic_name(&device.name);
+        if let Some(mode) = device.lock_mode() {
+            publisher
+                .state(&format!("devices/{}/lock", name), mode.to_string())
+                .await?;
+        }
+        if let Some(battery) = device.battery_percent() {
+            publisher
+                .state(&format!("devices/{}/battery", name), battery.to_string())
+                .await?;
+        }
+        if let Some(online) = device.online() {
+            publisher
+                .state(&format!("devices/{}/online", name), online.to_string())
+                .await?;
+        }
+    }
+
+    for pet in &pets {
+        let name = topic_name(&pet.name);
+        if let Some(position) = &pet.position {
+            publisher
+                .state(
+                    &format!("pets/{}/location", name),
+                    position.location.to_string(),
+                )
+                .await?;
+            publisher
+                .state(
+                    &format!("pets/{}/location_since", name),
+                    position.since.to_rfc3339(),
+                )
+                .await?;
+        }
+
+        let report = api_client
+            .get_pet_report(token, pet, since, now)
+            .await
+            .map_err(Error::other)?;
+        // oldest first, as each topic skips events older than its last
+        let mut feeding = report.feeding.map(|f| f.datapoints).unwrap_or_default();
+        feeding.sort_by_key(|e| e.from);
+        let mut drinking = report.drinking.map(|d| d.datapoints).unwrap_or_default();
+        drinking.sort_by_key(|e| e.from);
+        for event in feeding {
+            let payload = serde_json::json!({
+                "timestamp": event.from,
+                "device": device_names.get(&event.device_id),
+                "grams": event.grams_eaten(),
+            });
+            publisher
+                .event(&format!("pets/{}/feeding", name), event.from, payload)
+                .await?;
+        }
+        for event in drinking {
+            let payload = serde_json::json!({
+                "timestamp": event.from,
+                "device": device_names.get(&event.device_id),
+                "ml": event.ml_drunk(),
+            });
+            publisher
+                .event(&format!("pets/{}/drinking", name), event.from, payload)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Publish Home Assistant MQTT discovery config for every pet and device,
+/// pointing the entities at the state topics `publish_all` maintains.
+async fn publish_discovery(
+    publisher: &mut Publisher,
+    cfg: &config::Mqtt,
+    pets: &[Pet],
+    devices: &[Device],
+) -> std::io::Result<()> {
+    let node_id = topic_name(&cfg.client_id);
+    let prefix = publisher.prefix.clone();
+
+    for pet in pets {
+        let state = format!("{}/pets/{}", prefix, topic_name(&pet.name));
+        let device = serde_json::json!({
+            "identifiers": [format!("{}_pet_{}", node_id, pet.id)],
+            "name": cfg.discovery_device_name.replace("{name}", &pet.name),
+            "manufacturer": "Sure Petcare",
+            "model": "Pet",
+        });
+        let entities = [
+            (
+                "binary_sensor",
+                "inside",
+                serde_json::json!({
+                    "name": "Inside",
+                    "device_class": "presence",
+                    "state_topic": format!("{}/location", state),
+                    "payload_on": "inside",
+                    "payload_off": "outside",
+                }),
+            ),
+            (
+                "sensor",
+                "location_since",
+                serde_json::json!({
+                    "name": "Location since",
+                    "device_class": "timestamp",
+                    "state_topic": format!("{}/location_since", state),
+                }),
+            ),
+        ];
+        for (component, object_id, config) in entities {
+            let object_id = format!("pet_{}_{}", pet.id, object_id);
+            publish_entity(
+                publisher, cfg, &node_id, component, &object_id, config, &device,
+            )
+            .await?;
+        }
+    }
+
+    for device in devices {
+        let state = format!("{}/devices/{}", prefix, topic_name(&device.name));
+        let ha_device = serde_json::json!({
+            "identifiers": [format!("{}_device_{}", node_id, device.id)],
+            "name": cfg.discovery_device_name.replace("{name}", &device.name),
+            "manufacturer": "Sure Petcare",
+        });
+        let mut entities = vec![
+            (
+                "sensor",
+                "battery",
+                serde_json::json!({
+                    "name": "Battery",
+                    "device_class": "battery",
+                    "unit_of_measurement": "%",
+                    "state_class": "measurement",
+                    "state_topic": format!("{}/battery", state),
+                }),
+            ),
+            (
+                "binary_sensor",
+                "online",
+                serde_json::json!({
+                    "name": "Online",
+                    "device_class": "connectivity",
+                    "state_topic": format!("{}/online", state),
+                    "payload_on": "true",
+                    "payload_off": "false",
+                }),
+            ),
+        ];
+        if device.is_flap() {
+            entities.push((
+                "sensor",
+                "lock_mode",
+                serde_json::json!({
+                    "name": "Lock mode",
+                    "state_topic": format!("{}/lock", state),
+                }),
+            ));
+            entities.push((
+                "lock",
+                "lock",
+                serde_json::json!({
+                    "name": "Lock",
+                    "state_topic": format!("{}/lock", state),
+                    "command_topic": format!("{}/lock/set", state),
+                    "state_locked": LockMode::LockedAll.to_string(),
+                    "state_unlocked": LockMode::Unlocked.to_string(),
+                    "payload_lock": "LOCK",
+                    "payload_unlock": "UNLOCK",
+                }),
+            ));
+        }
+        for (component, object_id, config) in entities {
+            let object_id = format!("device_{}_{}", device.id, object_id);
+            publish_entity(
+                publisher, cfg, &node_id, component, &object_id, config, &ha_device,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn publish_entity(
+    publisher: &mut Publisher,
+    cfg: &config::Mqtt,
+    node_id: &str,
+    component: &str,
+    object_id: &str,
+    mut config: serde_json::Value,
+    device: &serde_json::Value,
+) -> std::io::Result<()> {
+    config["unique_id"] = serde_json::json!(format!("{}_{}", node_id, object_id));
+    config["device"] = device.clone();
+
+    let topic = format!(
+        "{}/{}/{}/{}/config",
+        cfg.discovery_prefix, component, node_id, object_id
+    );
+    publisher.retain(topic, config.to_string()).await
+}
+
+/// Lock or unlock a flap from a `<prefix>/devices/<name>/lock/set` message.
+async fn handle_lock_command(
+    api_client: &impl SurePetApi,
+    token: &str,
+    publisher: &mut Publisher,
+    topic: &str,
+    payload: &str,
+) -> std::io::Result<()> {
+    let mode = match payload {
+        "LOCK" => LockMode::LockedAll,
+        "UNLOCK" => LockMode::Unlocked,
+        _ => {
+            return Err(Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("unknown lock command {}", payload),
+            ))
+        }
+    };
+
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device = devices
+        .iter()
+        .find(|d| {
+            topic
+                == format!(
+                    "{}/devices/{}/lock/set",
+                    publisher.prefix,
+                    topic_name(&d.name)
+                )
+        })
+        .ok_or_else(|| Error::new(std::io::ErrorKind::NotFound, "no matching device"))?;
+
+    info!("Setting {} to {}", device.name, mode);
+    let operation = PendingOperation::lock_mode(device, mode);
+    let describe = operation.describe();
+    if pending::apply_or_queue(api_client, token, operation).await? == Sent::Queued {
+        // the state is published once a poll sees the change made
+        info!("The API can't be reached, queued: {}", describe);
+        return Ok(());
+    }
+
+    publisher
+        .state(
+            &format!("devices/{}/lock", topic_name(&device.name)),
+            mode.to_string(),
+        )
+        .await
+}
+
+/// Lowercase a pet or device name into a single MQTT topic level.
+fn topic_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,25 @@
+use super::Notification;
+use log::warn;
+
+/// Show a native desktop notification, through the notification daemon over
+/// D-Bus or Notification Center on macOS. Best effort: a desktop without one
+/// only logs why. Doesn't wait for it to be shown, so a slow notification
+/// daemon can't hold up polling.
+pub fn show(notification: &Notification) {
+    let mut desktop = notify_rust::Notification::new();
+    desktop
+        .appname("RustyPet")
+        .summary(&format!("RustyPet: {}", notification.subject))
+        .body(&notification.message);
+    #[cfg(all(unix, not(target_os = "macos")))]
+    desktop.urgency(match notification.event.severity() {
+        super::AlertSeverity::Low => notify_rust::Urgency::Low,
+        super::AlertSeverity::Normal => notify_rust::Urgency::Normal,
+        super::AlertSeverity::High => notify_rust::Urgency::Critical,
+    });
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = desktop.show() {
+            warn!("Desktop notification failed: {}", e);
+        }
+    });
+}
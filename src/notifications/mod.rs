@@ -0,0 +1,464 @@
+mod desktop;
+mod notifier;
+
+use crate::api::client::{Device, Location, Pet};
+use crate::api::SurePetApi;
+use crate::battery;
+use crate::config;
+use crate::health;
+use crate::sqlite;
+use crate::subscriptions::{PetEvent, Subscriptions};
+use chrono::{DateTime, Utc};
+use log::{debug, error};
+use notifier::Notifier;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// How often the daemon looks for missed meals. Each look fetches every
+/// pet's feeding history, too much to do on every poll.
+const MEAL_CHECK_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// How often the daemon works out how fast batteries are draining. Levels
+/// change a few times a day at most.
+const BATTERY_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    PetLocation,
+    PetOutsideLong,
+    /// The pet has stayed inside for [notifications] inactive_alert_hours
+    PetInactive,
+    DeviceOffline,
+    LowBattery,
+    /// A battery will drop below [notifications] battery_threshold within
+    /// battery_warning_days at the rate it's draining
+    BatteryDraining,
+    /// Sent by a `notify` action of a daemon rule
+    Rule,
+    /// The results of a scheduled saved search changed
+    SavedSearch,
+    /// A daily or weekly summary from `digest`
+    Digest,
+    /// A pet hasn't eaten at a time it usually does
+    MissedMeal,
+    /// A device has firmware waiting to install
+    FirmwareUpdate,
+}
+
+/// How urgently a notification should get the user's attention, for
+/// backends that support priorities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertSeverity {
+    Low,
+    Normal,
+    High,
+}
+
+impl NotificationKind {
+    pub fn severity(self) -> AlertSeverity {
+        match self {
+            NotificationKind::PetLocation
+            | NotificationKind::Digest
+            | NotificationKind::FirmwareUpdate => AlertSeverity::Low,
+            NotificationKind::LowBattery
+            | NotificationKind::BatteryDraining
+            | NotificationKind::Rule
+            | NotificationKind::SavedSearch
+            | NotificationKind::MissedMeal => AlertSeverity::Normal,
+            NotificationKind::PetOutsideLong
+            | NotificationKind::PetInactive
+            | NotificationKind::DeviceOffline => AlertSeverity::High,
+        }
+    }
+}
+
+/// A change worth telling the user about, found between two polls.
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    pub event: NotificationKind,
+    /// The pet or device the notification is about
+    pub subject: String,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Remembers what was seen on the previous poll so that only changes are
+/// notified. Nothing but a pet already outside or inside for too long, or a
+/// firmware update already waiting, is notified for the first poll.
+#[derive(Default)]
+pub struct StateTracker {
+    locations: HashMap<u32, Location>,
+    /// Pets already notified about for this stretch outside
+    outside_long: HashSet<u32>,
+    /// Pets already notified about for this stretch inside
+    inside_long: HashSet<u32>,
+    online: HashMap<u32, bool>,
+    low_battery: HashMap<u32, bool>,
+    /// Devices already notified about for the update waiting now
+    update_pending: HashSet<u32>,
+    meals: MealTracker,
+    batteries: BatteryTracker,
+}
+
+impl StateTracker {
+    pub fn update(
+        &mut self,
+        pets: &[Pet],
+        devices: &[Device],
+        cfg: &config::Notifications,
+        subscriptions: &Subscriptions,
+    ) -> Vec<Notification> {
+        let now = Utc::now();
+        let mut notifications = Vec::new();
+
+        for pet in pets {
+            let Some(position) = &pet.position else {
+                continue;
+            };
+            let previous = self.locations.insert(pet.id, position.location);
+            if previous.is_some_and(|p| p != position.location)
+                && subscriptions.wants(pet.id, PetEvent::Location)
+            {
+                notifications.push(Notification {
+                    event: NotificationKind::PetLocation,
+                    subject: pet.name.clone(),
+                    message: format!("{} is now {}", pet.name, position.location),
+                    timestamp: now,
+                });
+            }
+
+            if position.location != Location::Inside {
+                self.inside_long.remove(&pet.id);
+            } else {
+                let inside = now - position.since;
+                if cfg.inactive_alert_hours > 0
+                    && inside >= chrono::Duration::hours(cfg.inactive_alert_hours)
+                    && self.inside_long.insert(pet.id)
+                    && subscriptions.wants(pet.id, PetEvent::Inactivity)
+                {
+                    notifications.push(Notification {
+                        event: NotificationKind::PetInactive,
+                        subject: pet.name.clone(),
+                        message: format!(
+                            "{} has stayed inside for {} hours",
+                            pet.name,
+                            inside.num_hours()
+                        ),
+                        timestamp: now,
+                    });
+                }
+            }
+
+            if position.location != Location::Outside {
+                self.outside_long.remove(&pet.id);
+                continue;
+            }
+            let outside = now - position.since;
+            if cfg.outside_alert_hours > 0
+                && outside >= chrono::Duration::hours(cfg.outside_alert_hours)
+                && self.outside_long.insert(pet.id)
+                && subscriptions.wants(pet.id, PetEvent::Location)
+            {
+                notifications.push(Notification {
+                    event: NotificationKind::PetOutsideLong,
+                    subject: pet.name.clone(),
+                    message: format!(
+                        "{} has been outside for {} hours",
+                        pet.name,
+                        outside.num_hours()
+                    ),
+                    timestamp: now,
+                });
+            }
+        }
+
+        for device in devices {
+            if let Some(online) = device.online() {
+                let previous = self.online.insert(device.id, online);
+                if previous == Some(true) && !online {
+                    notifications.push(Notification {
+                        event: NotificationKind::DeviceOffline,
+                        subject: device.name.clone(),
+                        message: format!("{} has gone offline", device.name),
+                        timestamp: now,
+                    });
+                }
+            }
+
+            if let Some(battery) = device.battery_percent() {
+                let low = battery < cfg.battery_threshold;
+                let previous = self.low_battery.insert(device.id, low);
+                if previous == Some(false) && low {
+                    notifications.push(Notification {
+                        event: NotificationKind::LowBattery,
+                        subject: device.name.clone(),
+                        message: format!("{} battery is down to {}%", device.name, battery),
+                        timestamp: now,
+                    });
+                }
+            }
+
+            if !device.update_pending() {
+                self.update_pending.remove(&device.id);
+            } else if self.update_pending.insert(device.id) {
+                notifications.push(Notification {
+                    event: NotificationKind::FirmwareUpdate,
+                    subject: device.name.clone(),
+                    message: format!(
+                        "{} has a firmware update waiting to install{}",
+                        device.name,
+                        device
+                            .firmware()
+                            .map_or(String::new(), |f| format!(", it's on {}", f))
+                    ),
+                    timestamp: now,
+                });
+            }
+        }
+
+        notifications
+    }
+}
+
+/// Remembers which missed meals were notified so each is sent once.
+#[derive(Default)]
+struct MealTracker {
+    checked_at: Option<Instant>,
+    /// Pet, the midnight starting the day and the hour the meal was due from
+    notified: HashSet<(u32, DateTime<Utc>, u32)>,
+}
+
+impl MealTracker {
+    /// Notifications for usual meals pets have missed today, looking no
+    /// more often than every [MEAL_CHECK_INTERVAL].
+    async fn check(
+        &mut self,
+        api_client: &impl SurePetApi,
+        token: &str,
+        pets: &[Pet],
+        subscriptions: &Subscriptions,
+    ) -> Vec<Notification> {
+        let cfg = api_client.cfg();
+        let mut notifications = Vec::new();
+        if cfg.health.meal_learning_days <= 0
+            || self
+                .checked_at
+                .is_some_and(|t| t.elapsed() < MEAL_CHECK_INTERVAL)
+        {
+            return notifications;
+        }
+        self.checked_at = Some(Instant::now());
+
+        let now = Utc::now();
+        let midnight = cfg.units.start_of_day(&now);
+        self.notified.retain(|(_, day, _)| *day == midnight);
+        let from = now - health::meal_lookback(&cfg.health);
+        for pet in pets {
+            if !subscriptions.wants(pet.id, PetEvent::Feeding) {
+                continue;
+            }
+            let report = match api_client.get_pet_report(token, pet, from, now).await {
+                Ok(report) => report,
+                Err(e) => {
+                    error!("failed to fetch feeding history for {}: {}", pet.name, e);
+                    continue;
+                }
+            };
+            let feeding = report.feeding.map(|f| f.datapoints).unwrap_or_default();
+            for missed in health::missed_meals(&feeding, now, &cfg.health, &cfg.units) {
+                if !self.notified.insert((pet.id, midnight, missed.start_hour)) {
+                    continue;
+                }
+                notifications.push(Notification {
+                    event: NotificationKind::MissedMeal,
+                    subject: pet.name.clone(),
+                    message: format!(
+                        "{} hasn't eaten at its usual {} meal time",
+                        pet.name,
+                        missed.label(&cfg.units)
+                    ),
+                    timestamp: now,
+                });
+            }
+        }
+        notifications
+    }
+}
+
+/// Remembers which draining batteries were notified so each is sent once
+/// per set of batteries.
+#[derive(Default)]
+struct BatteryTracker {
+    checked_at: Option<Instant>,
+    /// Devices notified about, with their level at the time
+    notified: HashMap<u32, u8>,
+}
+
+impl BatteryTracker {
+    /// Notifications for batteries draining fast, worked out from the levels
+    /// in [daemon] status_db no more often than every
+    /// [BATTERY_CHECK_INTERVAL].
+    fn check(&mut self, cfg: &config::Config, devices: &[Device]) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+        let Some(db) = cfg.daemon.status_db.as_deref().filter(|db| db.exists()) else {
+            return notifications;
+        };
+        if cfg.notifications.battery_warning_days <= 0
+            || self
+                .checked_at
+                .is_some_and(|t| t.elapsed() < BATTERY_CHECK_INTERVAL)
+        {
+            return notifications;
+        }
+        self.checked_at = Some(Instant::now());
+
+        let now = Utc::now();
+        let states = match sqlite::device_states(db, now) {
+            Ok(states) => states,
+            Err(e) => {
+                error!("failed to read battery history: {}", e);
+                return notifications;
+            }
+        };
+        let from = now - chrono::Duration::days(cfg.forecast.battery_days);
+        for device in devices {
+            let Some(outlook) = battery::outlook(device, &states, from, now, &cfg.notifications)
+            else {
+                continue;
+            };
+            // a big rise means new batteries, which may drain differently
+            let replaced = self.notified.get(&device.id).is_some_and(|level| {
+                f64::from(outlook.battery) - f64::from(*level) >= battery::REPLACED_RISE
+            });
+            if replaced {
+                self.notified.remove(&device.id);
+            }
+            let (Some(rate), Some(low)) = (outlook.percent_per_day, outlook.low) else {
+                continue;
+            };
+            if !outlook.draining_fast || self.notified.contains_key(&device.id) {
+                continue;
+            }
+            self.notified.insert(device.id, outlook.battery);
+            notifications.push(Notification {
+                event: NotificationKind::BatteryDraining,
+                subject: device.name.clone(),
+                message: format!(
+                    "{} battery is draining fast, {:.1}% a day, and will be below {}% {}",
+                    device.name,
+                    -rate,
+                    outlook.threshold,
+                    battery::in_days(now, low)
+                ),
+                timestamp: now,
+            });
+        }
+        notifications
+    }
+}
+
+/// Work out what changed since the last poll, which usual meals were
+/// missed and which batteries are draining fast, and send it to every
+/// configured notifier.
+pub async fn dispatch(
+    api_client: &impl SurePetApi,
+    token: &str,
+    tracker: &mut StateTracker,
+    pets: &[Pet],
+    devices: &[Device],
+) -> Vec<Notification> {
+    let cfg = &api_client.cfg().notifications;
+    let subscriptions = Subscriptions::load().unwrap_or_else(|e| {
+        error!("failed to read notification subscriptions: {}", e);
+        Subscriptions::default()
+    });
+
+    let mut notifications = tracker.update(pets, devices, cfg, &subscriptions);
+    notifications.extend(
+        tracker
+            .meals
+            .check(api_client, token, pets, &subscriptions)
+            .await,
+    );
+    notifications.extend(tracker.batteries.check(api_client.cfg(), devices));
+    deliver(api_client, &notifications).await;
+    notifications
+}
+
+/// Whether any notifier is configured, the desktop included.
+pub fn has_backends(cfg: &config::Notifications) -> bool {
+    cfg.desktop || !notifier::from_config(cfg).is_empty()
+}
+
+/// Send notifications to every configured notifier. Returns how many
+/// deliveries failed, after retries.
+pub async fn deliver(api_client: &impl SurePetApi, notifications: &[Notification]) -> usize {
+    let mut failed = 0;
+    if notifications.is_empty() {
+        return failed;
+    }
+
+    let cfg = &api_client.cfg().notifications;
+    if cfg.desktop {
+        show_on_desktop(notifications);
+    }
+    let notifiers = notifier::from_config(cfg);
+    for notification in notifications {
+        for notifier in &notifiers {
+            let sent = send(api_client.http(), notifier.as_ref(), cfg, notification).await;
+            if let Err(e) = sent {
+                error!(
+                    "failed to deliver notification to {}: {}",
+                    notifier.name(),
+                    e
+                );
+                failed += 1;
+            }
+        }
+    }
+    failed
+}
+
+/// Show notifications as native desktop notifications, whether or not
+/// [notifications] desktop is set.
+pub fn show_on_desktop(notifications: &[Notification]) {
+    for notification in notifications {
+        desktop::show(notification);
+    }
+}
+
+/// Send a notification, retrying failures with exponential backoff.
+async fn send(
+    http: &reqwest::Client,
+    notifier: &dyn Notifier,
+    cfg: &config::Notifications,
+    notification: &Notification,
+) -> Result<(), reqwest::Error> {
+    let mut delay = Duration::from_secs(1);
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+        debug!("Notifying {} (attempt {})", notifier.name(), attempt);
+
+        let resp = notifier
+            .request(http, notification)
+            .header("User-Agent", "RustyPet")
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            // Telegram, Discord and Slack URLs carry their credentials
+            .map_err(|e| e.without_url());
+
+        match resp {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt > cfg.webhook_retries => return Err(e),
+            Err(e) => {
+                debug!("Notification failed, retrying in {:?}: {}", delay, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+        }
+    }
+}
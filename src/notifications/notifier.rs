@@ -0,0 +1,172 @@
+use super::{AlertSeverity, Notification};
+use crate::config;
+use reqwest::RequestBuilder;
+
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+/// Somewhere notifications can be delivered. A backend only builds the
+/// request; sending and retrying are shared by `dispatch`.
+pub trait Notifier {
+    /// Where the notification goes, for log messages. Must not include secrets.
+    fn name(&self) -> String;
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> RequestBuilder;
+}
+
+/// Every backend enabled in the [notifications] config.
+pub fn from_config(cfg: &config::Notifications) -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    for url in &cfg.webhooks {
+        notifiers.push(Box::new(Webhook {
+            url: url.clone(),
+            template: cfg.webhook_template.clone().filter(|t| !t.is_empty()),
+        }));
+    }
+    for url in &cfg.discord_webhooks {
+        notifiers.push(Box::new(Discord { url: url.clone() }));
+    }
+    for url in &cfg.slack_webhooks {
+        notifiers.push(Box::new(Slack { url: url.clone() }));
+    }
+    if let Some(telegram) = &cfg.telegram {
+        notifiers.push(Box::new(Telegram {
+            bot_token: telegram.bot_token.clone(),
+            chat_id: telegram.chat_id.clone(),
+        }));
+    }
+    if let Some(ntfy) = &cfg.ntfy {
+        notifiers.push(Box::new(Ntfy {
+            server: ntfy.server.clone(),
+            topic: ntfy.topic.clone(),
+            token: ntfy.token.clone().filter(|t| !t.is_empty()),
+        }));
+    }
+    notifiers
+}
+
+/// A generic webhook, sent the notification as JSON or a templated body.
+struct Webhook {
+    url: String,
+    template: Option<String>,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> String {
+        // just the host, as the path or query often carries a token
+        match reqwest::Url::parse(&self.url) {
+            Ok(url) => format!("webhook at {}", url.host_str().unwrap_or_default()),
+            Err(_) => "webhook".to_string(),
+        }
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> RequestBuilder {
+        let body = match &self.template {
+            Some(template) => render_template(template, notification),
+            None => serde_json::to_string(notification).unwrap(),
+        };
+        http.post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(body)
+    }
+}
+
+/// A Discord channel webhook.
+struct Discord {
+    url: String,
+}
+
+impl Notifier for Discord {
+    fn name(&self) -> String {
+        "Discord".to_string()
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> RequestBuilder {
+        http.post(&self.url)
+            .json(&serde_json::json!({ "content": notification.message }))
+    }
+}
+
+/// A Slack incoming webhook.
+struct Slack {
+    url: String,
+}
+
+impl Notifier for Slack {
+    fn name(&self) -> String {
+        "Slack".to_string()
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> RequestBuilder {
+        http.post(&self.url)
+            .json(&serde_json::json!({ "text": notification.message }))
+    }
+}
+
+/// A Telegram chat, messaged through the Bot API.
+struct Telegram {
+    bot_token: String,
+    chat_id: String,
+}
+
+impl Notifier for Telegram {
+    fn name(&self) -> String {
+        format!("Telegram chat {}", self.chat_id)
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> RequestBuilder {
+        http.post(format!(
+            "{}/bot{}/sendMessage",
+            TELEGRAM_API, self.bot_token
+        ))
+        .json(&serde_json::json!({
+            "chat_id": self.chat_id,
+            "text": notification.message,
+        }))
+    }
+}
+
+/// An ntfy topic, which pushes to the ntfy phone apps.
+struct Ntfy {
+    server: String,
+    topic: String,
+    token: Option<String>,
+}
+
+impl Notifier for Ntfy {
+    fn name(&self) -> String {
+        "ntfy".to_string()
+    }
+
+    fn request(&self, http: &reqwest::Client, notification: &Notification) -> RequestBuilder {
+        let priority = match notification.event.severity() {
+            AlertSeverity::Low => 2,
+            AlertSeverity::Normal => 3,
+            AlertSeverity::High => 4,
+        };
+        // JSON publishing, as headers can't carry non-ASCII pet names
+        let request = http.post(&self.server).json(&serde_json::json!({
+            "topic": self.topic,
+            "title": notification.subject,
+            "message": notification.message,
+            "priority": priority,
+        }));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+/// The template with its placeholders replaced by JSON-escaped values.
+fn render_template(template: &str, notification: &Notification) -> String {
+    let escape = |value: &str| {
+        let quoted = serde_json::to_string(value).unwrap();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let event = serde_json::to_value(notification.event).unwrap();
+
+    template
+        .replace("{event}", event.as_str().unwrap_or_default())
+        .replace("{subject}", &escape(&notification.subject))
+        .replace("{message}", &escape(&notification.message))
+        .replace("{timestamp}", &notification.timestamp.to_rfc3339())
+}
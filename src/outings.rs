@@ -0,0 +1,274 @@
+use crate::api::client::MovementEvent;
+use crate::units::Units;
+use chrono::{DateTime, Duration, Utc, Weekday};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// A trip outside, from going out through a flap to coming back in.
+#[derive(Serialize, Debug, Clone)]
+pub struct Outing {
+    pub from: DateTime<Utc>,
+    /// Missing while the pet is still outside
+    pub to: Option<DateTime<Utc>>,
+    pub minutes: i64,
+    /// The flap the pet went out through
+    pub device_id: Option<u32>,
+    pub device: Option<String>,
+}
+
+/// Time outside on one day in the [units] timezone, outings that cross
+/// midnight split between the days.
+#[derive(Serialize, Debug, Clone)]
+pub struct DayOutside {
+    pub start: DateTime<Utc>,
+    pub minutes: i64,
+    /// Outings that began on the day
+    pub outings: usize,
+}
+
+/// Where a pet spent its time over a range of days, for
+/// `activity-history --stats`, the pet detail and the report.
+#[derive(Serialize, Debug, Clone)]
+pub struct OutingStats {
+    /// Oldest first, ending with today
+    pub days: Vec<DayOutside>,
+    pub total_minutes: i64,
+    pub outings: usize,
+    /// Over the outings that have ended, missing without any
+    pub average_minutes: Option<i64>,
+    pub longest: Option<Outing>,
+    /// Trips whose return wasn't scanned, left out of every figure
+    pub unpaired: usize,
+}
+
+/// The pet's outings that began at or after `from`, oldest first, and how
+/// many trips had no usable return. A trip without a return is only still
+/// going on if it is the latest; before that the flap missed the pet coming
+/// back in, as it did when a return is later than the next trip out.
+pub fn outings(
+    movement: &[MovementEvent],
+    from: DateTime<Utc>,
+    now: DateTime<Utc>,
+    device_names: &HashMap<u32, String>,
+) -> (Vec<Outing>, usize) {
+    let mut trips: Vec<&MovementEvent> = movement.iter().collect();
+    trips.sort_by_key(|t| t.from);
+
+    let mut outings = Vec::new();
+    let mut unpaired = 0;
+    for (i, trip) in trips.iter().enumerate() {
+        let next = trips.get(i + 1).map(|t| t.from);
+        let paired = match (trip.to, next) {
+            (Some(to), next) => to >= trip.from && next.is_none_or(|n| to <= n),
+            (None, next) => next.is_none(),
+        };
+        if trip.from < from {
+            continue;
+        }
+        if !paired {
+            unpaired += 1;
+            continue;
+        }
+        outings.push(Outing {
+            from: trip.from,
+            to: trip.to,
+            minutes: (trip.to.unwrap_or(now) - trip.from).num_minutes(),
+            device_id: trip.device_id,
+            device: trip.device_id.and_then(|id| device_names.get(&id).cloned()),
+        });
+    }
+    (outings, unpaired)
+}
+
+/// Statistics over the last `days` days, today included.
+pub fn outing_stats(
+    movement: &[MovementEvent],
+    days: i64,
+    now: DateTime<Utc>,
+    device_names: &HashMap<u32, String>,
+    units: &Units,
+) -> OutingStats {
+    let first_day = units.start_of_day(&now) - Duration::days(days - 1);
+    // trips that began before the range may still reach into it
+    let (all, _) = outings(movement, DateTime::<Utc>::MIN_UTC, now, device_names);
+    let (counted, unpaired) = outings(movement, first_day, now, device_names);
+
+    let days: Vec<DayOutside> = (0..days)
+        .map(|day| {
+            let start = first_day + Duration::days(day);
+            let end = start + Duration::days(1);
+            let minutes = all
+                .iter()
+                .map(|o| {
+                    let overlap = o.to.unwrap_or(now).min(end) - o.from.max(start);
+                    overlap.num_minutes().max(0)
+                })
+                .sum();
+            DayOutside {
+                start,
+                minutes,
+                outings: counted
+                    .iter()
+                    .filter(|o| o.from >= start && o.from < end)
+                    .count(),
+            }
+        })
+        .collect();
+
+    let ended: Vec<&Outing> = counted.iter().filter(|o| o.to.is_some()).collect();
+    OutingStats {
+        total_minutes: days.iter().map(|d| d.minutes).sum(),
+        days,
+        outings: counted.len(),
+        average_minutes: (!ended.is_empty())
+            .then(|| ended.iter().map(|o| o.minutes).sum::<i64>() / ended.len() as i64),
+        longest: ended.iter().max_by_key(|o| o.minutes).map(|o| (*o).clone()),
+        unpaired,
+    }
+}
+
+/// Passages through the flaps by day of the week and hour, for
+/// `activity-history --heatmap`.
+#[derive(Serialize, Debug)]
+pub struct Heatmap {
+    /// Monday first
+    pub days: Vec<Weekday>,
+    /// Hours of the day, in the [units] timezone
+    pub hours: Vec<u32>,
+    /// A row per day, a count per hour
+    pub counts: Vec<Vec<usize>>,
+}
+
+/// Count every trip out and back in at or after `from`, returns included
+/// even when the trip out wasn't scanned.
+pub fn heatmap(movement: &[MovementEvent], from: DateTime<Utc>, units: &Units) -> Heatmap {
+    let mut counts = vec![vec![0; 24]; 7];
+    let passages = movement
+        .iter()
+        .flat_map(|trip| std::iter::once(trip.from).chain(trip.to))
+        .filter(|at| *at >= from);
+    for at in passages {
+        let day = units.weekday_of(&at).num_days_from_monday() as usize;
+        counts[day][units.hour_of(&at) as usize] += 1;
+    }
+    Heatmap {
+        days: std::iter::successors(Some(Weekday::Mon), |d| Some(d.succ()))
+            .take(7)
+            .collect(),
+        hours: (0..24).collect(),
+        counts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn utc() -> Units {
+        let mut units = config::built_in().units;
+        units.timezone = Some(chrono_tz::UTC);
+        units
+    }
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn trip(from: &str, to: Option<&str>) -> MovementEvent {
+        MovementEvent {
+            from: at(from),
+            device_id: Some(1),
+            to: to.map(at),
+        }
+    }
+
+    fn outings_of(movement: &[MovementEvent], now: &str) -> (Vec<Outing>, usize) {
+        let names = HashMap::from([(1, "Back door".to_string())]);
+        outings(movement, DateTime::<Utc>::MIN_UTC, at(now), &names)
+    }
+
+    #[test]
+    fn a_return_after_the_next_trip_out_is_unpaired() {
+        let movement = [
+            trip("2024-03-01T08:00:00Z", Some("2024-03-01T12:00:00Z")),
+            trip("2024-03-01T10:00:00Z", Some("2024-03-01T11:00:00Z")),
+        ];
+        let (outings, unpaired) = outings_of(&movement, "2024-03-01T13:00:00Z");
+        assert_eq!(unpaired, 1);
+        assert_eq!(outings.len(), 1);
+        assert_eq!(outings[0].from, at("2024-03-01T10:00:00Z"));
+        assert_eq!(outings[0].minutes, 60);
+        assert_eq!(outings[0].device.as_deref(), Some("Back door"));
+    }
+
+    #[test]
+    fn the_latest_trip_pairs_with_its_return() {
+        let movement = [trip("2024-03-01T08:00:00Z", Some("2024-03-01T09:30:00Z"))];
+        let (outings, unpaired) = outings_of(&movement, "2024-03-01T13:00:00Z");
+        assert_eq!(unpaired, 0);
+        assert_eq!(outings.len(), 1);
+        assert_eq!(outings[0].to, Some(at("2024-03-01T09:30:00Z")));
+        assert_eq!(outings[0].minutes, 90);
+    }
+
+    #[test]
+    fn only_the_latest_trip_can_still_be_going_on() {
+        let movement = [
+            trip("2024-03-01T12:00:00Z", None),
+            trip("2024-03-01T08:00:00Z", None),
+            trip("2024-03-01T10:00:00Z", Some("2024-03-01T11:00:00Z")),
+        ];
+        let (outings, unpaired) = outings_of(&movement, "2024-03-01T13:00:00Z");
+        assert_eq!(unpaired, 1);
+        let from: Vec<_> = outings.iter().map(|o| o.from).collect();
+        assert_eq!(
+            from,
+            [at("2024-03-01T10:00:00Z"), at("2024-03-01T12:00:00Z")]
+        );
+        // still outside, so timed until now
+        assert_eq!(outings[1].to, None);
+        assert_eq!(outings[1].minutes, 60);
+    }
+
+    #[test]
+    fn an_outing_over_midnight_is_split_between_the_days() {
+        let movement = [
+            // began before the range, so only its time in the range counts
+            trip("2024-02-29T23:30:00Z", Some("2024-03-01T00:30:00Z")),
+            trip("2024-03-01T23:00:00Z", Some("2024-03-02T01:00:00Z")),
+        ];
+        let stats = outing_stats(
+            &movement,
+            2,
+            at("2024-03-02T12:00:00Z"),
+            &HashMap::new(),
+            &utc(),
+        );
+        let days: Vec<_> = stats.days.iter().map(|d| (d.minutes, d.outings)).collect();
+        assert_eq!(days, [(90, 1), (60, 0)]);
+        assert_eq!(stats.total_minutes, 150);
+        assert_eq!(stats.outings, 1);
+        assert_eq!(stats.average_minutes, Some(120));
+        assert_eq!(stats.longest.unwrap().minutes, 120);
+        assert_eq!(stats.unpaired, 0);
+    }
+
+    #[test]
+    fn unpaired_trips_are_left_out_of_the_stats() {
+        let movement = [
+            trip("2024-03-02T08:00:00Z", None),
+            trip("2024-03-02T09:00:00Z", Some("2024-03-02T09:20:00Z")),
+        ];
+        let stats = outing_stats(
+            &movement,
+            1,
+            at("2024-03-02T12:00:00Z"),
+            &HashMap::new(),
+            &utc(),
+        );
+        assert_eq!(stats.unpaired, 1);
+        assert_eq!(stats.outings, 1);
+        assert_eq!(stats.total_minutes, 20);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::api::client::{Device, DrinkingEvent, FeedingEvent, Pet, RequestStats};
+use crate::api::SurePetApi;
+use crate::events::{EventHit, EventType};
+use crate::export;
+use crate::health::MealWindow;
+use crate::history::{GroupBy, Period};
+use crate::markdown;
+use crate::outings::Outing;
+use crate::table;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much is printed besides a command's results.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the data asked for, for cron
+    Quiet,
+    #[default]
+    Normal,
+    /// Also API timing, cache hits and debug logging
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Set once from `--quiet` or `--verbose`, before anything is printed.
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Print what a command did, such as a file written or a setting changed,
+/// on stderr unless `--quiet`. Results themselves are always printed, on
+/// stdout, so JSON and CSV output stays clean.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() > $crate::output::Verbosity::Quiet {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub(crate) use status;
+
+/// With `--verbose`, the requests made and how long they took, on stderr.
+pub fn print_request_stats(stats: &RequestStats) {
+    if verbosity() < Verbosity::Verbose {
+        return;
+    }
+    eprintln!(
+        "{} request(s), {} failed, {}ms in total; {} served from the cache{}",
+        stats.requests,
+        stats.failures,
+        stats.latency_ms,
+        stats.cache_hits,
+        stats
+            .cache_age_secs
+            .map(|age| format!(", the oldest {}s old", age))
+            .unwrap_or_default()
+    );
+}
+
+/// How a command prints its results.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Tables and lines for people
+    #[default]
+    Text,
+    /// One JSON object per line, written as each is ready
+    Ndjson,
+    /// CSV with a header row, for spreadsheets
+    Csv,
+    /// Markdown tables, to paste into GitHub issues or notes
+    Markdown,
+}
+
+/// Print `records` in `mode`, one of the record-per-line modes.
+pub fn print_records<T: Serialize>(records: &[T], mode: OutputMode) -> std::io::Result<()> {
+    match mode {
+        OutputMode::Text | OutputMode::Markdown => {
+            unreachable!("tables are formatted by each command")
+        }
+        OutputMode::Ndjson => records.iter().try_for_each(print_ndjson),
+        OutputMode::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout().lock());
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()
+        }
+    }
+}
+
+/// Print only the `columns` of each record, in the order given. Unknown
+/// column names are an error listing the valid ones.
+pub fn print_columns<T: Serialize + Default>(
+    records: &[T],
+    columns: &[String],
+    mode: OutputMode,
+) -> std::io::Result<()> {
+    let valid: Vec<_> = export::record_fields::<T>()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    if let Some(unknown) = columns.iter().find(|c| !valid.contains(c)) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "unknown column {}, valid columns are {}",
+                unknown,
+                valid.join(", ")
+            ),
+        ));
+    }
+
+    let mut rows = Vec::new();
+    for record in records {
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(record)? else {
+            unreachable!("records are structs");
+        };
+        rows.push(
+            columns
+                .iter()
+                .map(|c| (c.clone(), fields.remove(c).unwrap_or_default()))
+                .collect::<Vec<_>>(),
+        );
+    }
+    let cells = |row: &[(String, serde_json::Value)]| -> Vec<String> {
+        row.iter()
+            .map(|(_, value)| match value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Null => String::new(),
+                value => value.to_string(),
+            })
+            .collect()
+    };
+    let header: Vec<_> = columns.iter().map(String::as_str).collect();
+
+    match mode {
+        OutputMode::Text => println!("{}", table::table(&header, rows.iter().map(|r| cells(r)))),
+        OutputMode::Markdown => {
+            println!(
+                "{}",
+                markdown::table(&header, rows.iter().map(|r| cells(r)))
+            )
+        }
+        OutputMode::Ndjson => {
+            for row in rows {
+                print_ndjson(&row.into_iter().collect::<serde_json::Map<_, _>>())?;
+            }
+        }
+        OutputMode::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout().lock());
+            writer.write_record(&header)?;
+            for row in &rows {
+                writer.write_record(cells(row))?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// A pet as one flat row, for CSV and NDJSON output.
+#[derive(Serialize, Debug, Default)]
+pub struct PetRow {
+    pub id: u32,
+    pub name: String,
+    pub location: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl PetRow {
+    pub fn new(pet: &Pet) -> Self {
+        PetRow {
+            id: pet.id,
+            name: pet.name.clone(),
+            location: pet.position.as_ref().map(|p| p.location.to_string()),
+            since: pet.position.as_ref().map(|p| p.since),
+        }
+    }
+}
+
+/// Write `value` as a single line of JSON and flush it, so a consumer
+/// reading the stream sees it straight away.
+pub fn print_ndjson<T: Serialize>(value: &T) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    writeln!(stdout)?;
+    stdout.flush()
+}
+
+/// `name`, such as a pet's, made safe to write as a file in the current
+/// directory: every run of characters other than letters, digits and `_`
+/// becomes one `-`, so no separator or `..` survives.
+pub fn file_stem(name: &str) -> String {
+    let mut stem = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            stem.push(c);
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    let stem = stem.trim_end_matches('-');
+    if stem.is_empty() {
+        "unnamed".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+/// Renders pets, devices and histories for display. Histories are given the
+/// rows shown and `all` of them, so a page's totals cover the whole range.
+pub trait OutputFormatter {
+    fn pets(&self, pets: &[Pet]) -> String;
+    fn devices(&self, devices: &[Device]) -> String;
+    /// Each meal, then totals for each device eaten from
+    fn feeding_history(
+        &self,
+        events: &[FeedingEvent],
+        all: &[FeedingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String;
+    /// Each drink, then totals for each fountain drunk from
+    fn drinking_history(
+        &self,
+        events: &[DrinkingEvent],
+        all: &[DrinkingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String;
+    /// Each trip outside, given how many were left out because the return
+    /// wasn't scanned
+    fn activity_history(&self, outings: &[Outing], all: &[Outing], unpaired: usize) -> String;
+    /// A row per day or week of a history, with totals and averages
+    fn history_periods(
+        &self,
+        periods: &[Period],
+        all: &[Period],
+        by: GroupBy,
+        event_type: EventType,
+    ) -> String;
+}
+
+/// The `_meta` block added to JSON output with `--meta`, so automation can
+/// tell how the data was obtained.
+#[derive(Serialize, Debug)]
+pub struct Meta {
+    pub generated_at: DateTime<Utc>,
+    pub api: RequestStats,
+    /// Some of the requested data could not be fetched
+    pub partial: bool,
+}
+
+impl Meta {
+    pub fn new(api_client: &impl SurePetApi, partial: bool) -> Self {
+        Meta {
+            generated_at: Utc::now(),
+            api: api_client.stats(),
+            partial,
+        }
+    }
+}
+
+/// `list --json` output.
+#[derive(Serialize, Debug)]
+pub struct PetList {
+    pub pets: Vec<PetOutput>,
+    /// The pets came from the cache because the API couldn't be reached
+    pub stale: bool,
+    /// When the cached pets were fetched, if stale
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_as_of: Option<DateTime<Utc>>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+/// `search-events --json` output.
+#[derive(Serialize, Debug)]
+pub struct EventList {
+    pub events: Vec<EventHit>,
+    /// A pet's history could not be fetched and its events are missing
+    pub partial: bool,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+/// `anomalies --json` output.
+#[derive(Serialize, Debug)]
+pub struct AnomalyList {
+    pub pets: Vec<PetAnomalies>,
+    /// A pet's history could not be fetched and it is missing
+    pub partial: bool,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PetAnomalies {
+    pub id: u32,
+    pub name: String,
+    /// When the pet usually eats, which missed meals are judged against
+    pub meal_windows: Vec<MealWindow>,
+    pub anomalies: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PetOutput {
+    pub id: u32,
+    pub name: String,
+    pub household_id: u32,
+    pub tag_id: Option<u32>,
+    /// The API's location code, 1 inside and 2 outside
+    pub location: Option<i32>,
+    pub since: Option<DateTime<Utc>>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub enriched: Option<PetEnrichment>,
+}
+
+/// Fields worked out from the raw data with `--enrich`.
+#[derive(Serialize, Debug)]
+pub struct PetEnrichment {
+    pub location_name: Option<String>,
+    pub minutes_in_current_location: Option<i64>,
+    pub last_fed_minutes_ago: Option<i64>,
+    /// Share of what a pet can report that is known, from 0 to 1: a
+    /// registered tag, a location, and a meal within the enrichment window
+    pub completeness_score: f64,
+}
+
+impl PetOutput {
+    pub fn new(pet: &Pet) -> Self {
+        PetOutput {
+            id: pet.id,
+            name: pet.name.clone(),
+            household_id: pet.household_id,
+            tag_id: pet.tag_id,
+            location: pet.position.as_ref().map(|p| p.location.code()),
+            since: pet.position.as_ref().map(|p| p.since),
+            enriched: None,
+        }
+    }
+
+    /// Add the enriched fields, given when the pet last ate, if recently.
+    pub fn enrich(&mut self, pet: &Pet, last_fed: Option<DateTime<Utc>>, now: DateTime<Utc>) {
+        let known = [
+            pet.tag_id.is_some(),
+            pet.position.is_some(),
+            last_fed.is_some(),
+        ];
+        self.enriched = Some(PetEnrichment {
+            location_name: pet.position.as_ref().map(|p| p.location.to_string()),
+            minutes_in_current_location: pet
+                .position
+                .as_ref()
+                .map(|p| (now - p.since).num_minutes()),
+            last_fed_minutes_ago: last_fed.map(|t| (now - t).num_minutes()),
+            completeness_score: known.iter().filter(|k| **k).count() as f64 / known.len() as f64,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::file_stem;
+
+    #[test]
+    fn file_stems_stay_in_the_directory() {
+        assert_eq!(file_stem("Tom"), "Tom");
+        assert_eq!(file_stem("Mr. Whiskers"), "Mr-Whiskers");
+        assert_eq!(file_stem("../../etc/passwd"), "etc-passwd");
+        assert_eq!(file_stem("C:\\pets\\Zoë"), "C-pets-Zoë");
+        assert_eq!(file_stem("..."), "unnamed");
+    }
+}
@@ -0,0 +1,141 @@
+use crate::api::client::Location;
+use crate::api::SurePetApi;
+use crate::curfew;
+use crate::summary;
+use chrono::{DateTime, NaiveTime, Utc};
+use log::error;
+use serde::Serialize;
+use std::io::Error;
+
+/// The household right now, what `summary` shows: where the pets are, what
+/// they've had today, and how the devices are doing.
+#[derive(Serialize, Debug)]
+pub struct Overview {
+    pub generated_at: DateTime<Utc>,
+    pub inside: usize,
+    pub outside: usize,
+    /// Pets whose position SurePet doesn't know
+    pub unknown: usize,
+    pub pets: Vec<PetToday>,
+    pub devices: DeviceHealth,
+    /// Enabled curfews, whether or not they are locking now
+    pub curfews: Vec<ActiveCurfew>,
+    /// A pet's history could not be fetched and its totals are missing
+    pub partial: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PetToday {
+    pub name: String,
+    pub location: Option<String>,
+    /// Since midnight in the [units] timezone, missing if the history
+    /// couldn't be fetched
+    pub grams: Option<f64>,
+    pub ml: Option<f64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeviceHealth {
+    pub total: usize,
+    pub online: usize,
+    pub offline: Vec<String>,
+    /// Below [notifications] battery_threshold
+    pub low_battery: Vec<String>,
+    /// With firmware waiting to install
+    pub update_pending: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ActiveCurfew {
+    pub device: String,
+    /// HH:MM, local to the household
+    pub lock_time: String,
+    pub unlock_time: String,
+    pub locked_now: bool,
+}
+
+pub async fn gather(api_client: &impl SurePetApi, token: &str) -> std::io::Result<Overview> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let units = &api_client.cfg().units;
+    let now = Utc::now();
+    let midnight = units.start_of_day(&now);
+
+    let location = |l: Location| {
+        pets.iter()
+            .filter(|p| p.position.as_ref().map(|p| p.location) == Some(l))
+            .count()
+    };
+    let mut overview = Overview {
+        generated_at: now,
+        inside: location(Location::Inside),
+        outside: location(Location::Outside),
+        unknown: pets.iter().filter(|p| p.position.is_none()).count(),
+        pets: Vec::new(),
+        devices: DeviceHealth {
+            total: devices.len(),
+            online: devices.iter().filter(|d| d.online() == Some(true)).count(),
+            offline: Vec::new(),
+            low_battery: Vec::new(),
+            update_pending: Vec::new(),
+        },
+        curfews: Vec::new(),
+        partial: false,
+    };
+
+    for pet in &pets {
+        let mut today = PetToday {
+            name: pet.name.clone(),
+            location: pet.position.as_ref().map(|p| p.location.to_string()),
+            grams: None,
+            ml: None,
+        };
+        match api_client.get_pet_report(token, pet, midnight, now).await {
+            Ok(report) => {
+                let feeding = report.feeding.map(|f| f.datapoints).unwrap_or_default();
+                let drinking = report.drinking.map(|d| d.datapoints).unwrap_or_default();
+                today.grams = Some(feeding.iter().fold(0.0, |sum, e| sum + e.grams_eaten()));
+                today.ml = Some(drinking.iter().fold(0.0, |sum, e| sum + e.ml_drunk()));
+            }
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                overview.partial = true;
+            }
+        }
+        overview.pets.push(today);
+    }
+
+    let threshold = api_client.cfg().notifications.battery_threshold;
+    let time_now = units.time_of_day(&now);
+    for device in &devices {
+        if device.online() == Some(false) {
+            overview.devices.offline.push(device.name.clone());
+        }
+        if device.battery_percent().is_some_and(|b| b < threshold) {
+            overview.devices.low_battery.push(device.name.clone());
+        }
+        if device.update_pending() {
+            overview.devices.update_pending.push(device.name.clone());
+        }
+        let curfews = device.control.as_ref().map(|c| c.curfew.as_slice());
+        for curfew in curfews.unwrap_or_default().iter().filter(|c| c.enabled) {
+            let time = |t: &str| NaiveTime::parse_from_str(t, "%H:%M");
+            let (Ok(lock), Ok(unlock)) = (time(&curfew.lock_time), time(&curfew.unlock_time))
+            else {
+                error!("skipping unreadable curfew on {}", device.name);
+                summary::failed();
+                continue;
+            };
+            let locked_now = curfew::locks_at(lock, unlock, time_now);
+            overview.curfews.push(ActiveCurfew {
+                device: device.name.clone(),
+                lock_time: curfew.lock_time.clone(),
+                unlock_time: curfew.unlock_time.clone(),
+                locked_now,
+            });
+        }
+    }
+
+    Ok(overview)
+}
@@ -0,0 +1,39 @@
+use console::{style, Key, Term};
+
+/// Print `text`, a screen at a time when it is longer than the terminal:
+/// space shows the next screen, enter the next line and q stops. Printed
+/// as is when stdout isn't a terminal, so pipes and files get everything.
+pub fn page(text: &str) {
+    let term = Term::stdout();
+    let height = term.size().0 as usize;
+    let lines: Vec<&str> = text.lines().collect();
+    if !term.is_term() || lines.len() < height {
+        println!("{}", text);
+        return;
+    }
+
+    // a line is kept free for the prompt, though a screen always moves on
+    // by one at least
+    let screen = height.saturating_sub(1).max(1);
+    let mut shown = screen.min(lines.len());
+    for line in &lines[..shown] {
+        println!("{}", line);
+    }
+    while shown < lines.len() {
+        let prompt = format!("-- {}/{} space, enter or q --", shown, lines.len());
+        let _ = term.write_str(&style(prompt).reverse().to_string());
+        let key = term.read_key();
+        let _ = term.clear_line();
+        let more = match key {
+            Ok(Key::Char(' ')) => screen,
+            Ok(Key::Enter) => 1,
+            Ok(Key::Char('q') | Key::Escape) | Err(_) => return,
+            Ok(_) => continue,
+        };
+        let next = (shown + more).min(lines.len());
+        for line in &lines[shown..next] {
+            println!("{}", line);
+        }
+        shown = next;
+    }
+}
@@ -0,0 +1,381 @@
+use crate::api::cache::CacheMode;
+use crate::api::client::{is_unreachable, Device, Location, LockMode, Pet, PetProfile};
+use crate::api::SurePetApi;
+use crate::config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+
+const PENDING_FILE: &str = "pending.toml";
+
+/// A change made while the API couldn't be reached, to be made again once
+/// it can.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PendingOperation {
+    /// Whether a pet may go out through a flap
+    Profile {
+        queued_at: DateTime<Utc>,
+        pet_id: u32,
+        pet: String,
+        /// The pet's tag when queued; if it has changed the change is dropped
+        tag_id: u32,
+        device_id: u32,
+        device: String,
+        profile: PetProfile,
+    },
+    /// A flap's lock mode
+    LockMode {
+        queued_at: DateTime<Utc>,
+        device_id: u32,
+        device: String,
+        mode: LockMode,
+    },
+    /// Where a pet is, set by hand
+    Position {
+        queued_at: DateTime<Utc>,
+        pet_id: u32,
+        pet: String,
+        location: Location,
+    },
+}
+
+impl PendingOperation {
+    pub fn profile(pet: &Pet, tag_id: u32, device: &Device, profile: PetProfile) -> Self {
+        PendingOperation::Profile {
+            queued_at: Utc::now(),
+            pet_id: pet.id,
+            pet: pet.name.clone(),
+            tag_id,
+            device_id: device.id,
+            device: device.name.clone(),
+            profile,
+        }
+    }
+
+    pub fn lock_mode(device: &Device, mode: LockMode) -> Self {
+        PendingOperation::LockMode {
+            queued_at: Utc::now(),
+            device_id: device.id,
+            device: device.name.clone(),
+            mode,
+        }
+    }
+
+    pub fn position(pet: &Pet, location: Location) -> Self {
+        PendingOperation::Position {
+            queued_at: Utc::now(),
+            pet_id: pet.id,
+            pet: pet.name.clone(),
+            location,
+        }
+    }
+
+    pub fn queued_at(&self) -> DateTime<Utc> {
+        match self {
+            PendingOperation::Profile { queued_at, .. }
+            | PendingOperation::LockMode { queued_at, .. }
+            | PendingOperation::Position { queued_at, .. } => *queued_at,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            PendingOperation::Profile {
+                pet,
+                device,
+                profile,
+                ..
+            } => {
+                let change = match profile {
+                    PetProfile::IndoorOnly => "keep indoors",
+                    PetProfile::Outdoor => "let outdoors",
+                };
+                format!("{} {} through {}", change, pet, device)
+            }
+            PendingOperation::LockMode { device, mode, .. } => {
+                format!("set {} to {}", device, mode)
+            }
+            PendingOperation::Position { pet, location, .. } => {
+                format!("mark {} as {}", pet, location)
+            }
+        }
+    }
+
+    /// Whether this changes the same thing as `other`, so making both would
+    /// only leave this one's change.
+    fn overrides(&self, other: &PendingOperation) -> bool {
+        use PendingOperation::*;
+        match (self, other) {
+            (
+                Profile {
+                    pet_id, device_id, ..
+                },
+                Profile {
+                    pet_id: other_pet,
+                    device_id: other_device,
+                    ..
+                },
+            ) => (pet_id, device_id) == (other_pet, other_device),
+            (
+                LockMode { device_id, .. },
+                LockMode {
+                    device_id: other_device,
+                    ..
+                },
+            ) => device_id == other_device,
+            (
+                Position { pet_id, .. },
+                Position {
+                    pet_id: other_pet, ..
+                },
+            ) => pet_id == other_pet,
+            _ => false,
+        }
+    }
+
+    /// Why this can no longer be made in the household as it is now.
+    fn conflict(&self, pets: &[Pet], devices: &[Device]) -> Option<String> {
+        let gone = |name: &str| Some(format!("{} is no longer in the household", name));
+        let has_pet = |id: u32| pets.iter().find(|p| p.id == id);
+        let has_device = |id: u32| devices.iter().any(|d| d.id == id);
+        match self {
+            PendingOperation::Profile {
+                pet_id,
+                pet,
+                tag_id,
+                device_id,
+                device,
+                ..
+            } => match has_pet(*pet_id) {
+                None => gone(pet),
+                Some(p) if p.tag_id != Some(*tag_id) => Some(format!("{}'s tag has changed", pet)),
+                Some(_) if !has_device(*device_id) => gone(device),
+                Some(_) => None,
+            },
+            PendingOperation::LockMode {
+                device_id, device, ..
+            } if !has_device(*device_id) => gone(device),
+            PendingOperation::Position { pet_id, pet, .. } if has_pet(*pet_id).is_none() => {
+                gone(pet)
+            }
+            _ => None,
+        }
+    }
+
+    /// Make the change now.
+    pub async fn apply(
+        &self,
+        api_client: &impl SurePetApi,
+        token: &str,
+    ) -> Result<(), reqwest::Error> {
+        match self {
+            PendingOperation::Profile {
+                tag_id,
+                device_id,
+                profile,
+                ..
+            } => {
+                api_client
+                    .set_pet_profile(token, *device_id, *tag_id, *profile)
+                    .await
+            }
+            PendingOperation::LockMode {
+                device_id, mode, ..
+            } => api_client.set_lock_mode(token, *device_id, *mode).await,
+            PendingOperation::Position {
+                pet_id, location, ..
+            } => api_client.set_pet_position(token, *pet_id, *location).await,
+        }
+    }
+}
+
+/// Whether [`apply_or_queue`] made a change or left it for later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sent {
+    Applied,
+    /// The API couldn't be reached, so it waits in the queue
+    Queued,
+}
+
+/// Make `operation` now, or queue it if the API can't be reached so
+/// `pending replay` or the daemon makes it once it's back.
+pub async fn apply_or_queue(
+    api_client: &impl SurePetApi,
+    token: &str,
+    operation: PendingOperation,
+) -> std::io::Result<Sent> {
+    match operation.apply(api_client, token).await {
+        Ok(()) => Ok(Sent::Applied),
+        Err(e) if is_unreachable(&e) => {
+            Pending::add(operation)?;
+            Ok(Sent::Queued)
+        }
+        Err(e) => Err(Error::other(e)),
+    }
+}
+
+/// The queue of changes waiting for the API to come back.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Pending {
+    #[serde(default, rename = "operation")]
+    pub operations: Vec<PendingOperation>,
+}
+
+/// What happened to a queued change when it was replayed.
+#[derive(Debug)]
+pub enum Outcome {
+    Applied,
+    /// Not made because the household changed since it was queued
+    Conflict(String),
+    Failed(String),
+    /// The API still can't be reached, so it stays queued
+    Unreachable,
+}
+
+impl Pending {
+    pub fn load() -> std::io::Result<Self> {
+        let path = pending_path()?;
+        if !path.exists() {
+            return Ok(Pending::default());
+        }
+        toml::from_str(&fs::read_to_string(path)?).map_err(Error::other)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = toml::to_string_pretty(self).map_err(Error::other)?;
+        fs::write(pending_path()?, contents)
+    }
+
+    /// Queue `operation`, replacing any earlier change it overrides.
+    pub fn push(&mut self, operation: PendingOperation) {
+        self.operations.retain(|o| !operation.overrides(o));
+        self.operations.push(operation);
+    }
+
+    /// Queue `operation` in the saved queue.
+    pub fn add(operation: PendingOperation) -> std::io::Result<()> {
+        let mut pending = Pending::load()?;
+        pending.push(operation);
+        pending.save()
+    }
+
+    /// Make each queued change, oldest first, against the household as it is
+    /// now. Changes that were made, conflicted or failed leave the queue;
+    /// the rest stay for next time.
+    pub async fn replay(
+        &mut self,
+        api_client: &impl SurePetApi,
+        token: &str,
+    ) -> std::io::Result<Vec<(PendingOperation, Outcome)>> {
+        // conflicts have to be checked against the current state
+        let mode = api_client.cache_mode();
+        api_client.set_cache_mode(CacheMode::Refresh);
+        let household = async {
+            let pets = api_client.get_pets(token).await?;
+            let devices = api_client.get_devices(token).await?;
+            Ok::<_, reqwest::Error>((pets, devices))
+        }
+        .await;
+        api_client.set_cache_mode(mode);
+        let (pets, devices) = match household {
+            Ok(household) => household,
+            Err(e) if is_unreachable(&e) => {
+                return Ok(self
+                    .operations
+                    .iter()
+                    .map(|o| (o.clone(), Outcome::Unreachable))
+                    .collect())
+            }
+            Err(e) => return Err(Error::other(e)),
+        };
+
+        let mut outcomes = Vec::new();
+        let mut remaining = Vec::new();
+        for operation in self.operations.drain(..) {
+            let outcome = match operation.conflict(&pets, &devices) {
+                Some(reason) => Outcome::Conflict(reason),
+                None => match operation.apply(api_client, token).await {
+                    Ok(()) => Outcome::Applied,
+                    Err(e) if is_unreachable(&e) => Outcome::Unreachable,
+                    Err(e) => Outcome::Failed(e.to_string()),
+                },
+            };
+            if matches!(outcome, Outcome::Unreachable) {
+                remaining.push(operation.clone());
+            }
+            outcomes.push((operation, outcome));
+        }
+        self.operations = remaining;
+        Ok(outcomes)
+    }
+}
+
+fn pending_path() -> std::io::Result<PathBuf> {
+    Ok(config::config_dir()?.join(PENDING_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(device_id: u32, mode: LockMode) -> PendingOperation {
+        PendingOperation::LockMode {
+            queued_at: Utc::now(),
+            device_id,
+            device: format!("flap {}", device_id),
+            mode,
+        }
+    }
+
+    fn position(pet_id: u32, location: Location) -> PendingOperation {
+        PendingOperation::Position {
+            queued_at: Utc::now(),
+            pet_id,
+            pet: format!("pet {}", pet_id),
+            location,
+        }
+    }
+
+    #[test]
+    fn a_later_change_replaces_only_the_same_kind_on_the_same_target() {
+        let mut pending = Pending::default();
+        pending.push(lock(1, LockMode::LockedAll));
+        pending.push(lock(2, LockMode::LockedAll));
+        pending.push(position(1, Location::Inside));
+        pending.push(lock(1, LockMode::Unlocked));
+        let described: Vec<String> = pending.operations.iter().map(|o| o.describe()).collect();
+        assert_eq!(
+            described,
+            [
+                "set flap 2 to locked_all",
+                "mark pet 1 as inside",
+                "set flap 1 to unlocked"
+            ]
+        );
+    }
+
+    #[test]
+    fn every_kind_of_change_survives_the_queue_file() {
+        let mut pending = Pending::default();
+        pending.push(PendingOperation::Profile {
+            queued_at: Utc::now(),
+            pet_id: 1,
+            pet: "Tom".to_string(),
+            tag_id: 7,
+            device_id: 2,
+            device: "Back door".to_string(),
+            profile: PetProfile::IndoorOnly,
+        });
+        pending.push(lock(2, LockMode::LockedIn));
+        pending.push(position(1, Location::Outside));
+        let saved = toml::to_string_pretty(&pending).unwrap();
+        let loaded: Pending = toml::from_str(&saved).unwrap();
+        let describe =
+            |p: &Pending| -> Vec<String> { p.operations.iter().map(|o| o.describe()).collect() };
+        assert_eq!(describe(&loaded), describe(&pending));
+        assert!(toml::from_str::<Pending>("[[operation]]\nkind = \"teleport\"\n").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::api::client::{Device, Location, Pet, PetProfile};
+use crate::api::SurePetApi;
+use crate::device_info::Activity;
+use crate::health::{self, MealWindow};
+use crate::outings::{self, OutingStats};
+use crate::output::{Meta, PetOutput};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::Error;
+
+const RECENT_EVENTS: usize = 10;
+/// Days of totals kept for the trend, including today
+const TREND_DAYS: i64 = 7;
+
+/// Everything known about one pet, for `pet info` and its interactive
+/// screen. Serialized as is for `pet info --json`.
+#[derive(Serialize, Debug)]
+pub struct PetInfo {
+    #[serde(flatten)]
+    pub pet: PetOutput,
+    pub minutes_in_location: Option<i64>,
+    /// What the pet may do at each flap that knows its tag
+    pub access: Vec<FlapAccess>,
+    pub today: Totals,
+    /// Each of the last week's days, oldest first and ending with today
+    pub days: Vec<Totals>,
+    /// Newest first
+    pub events: Vec<PetEvent>,
+    /// Time outside over the same days
+    pub outside: OutingStats,
+    /// When the pet usually eats, learned from its history
+    pub meal_windows: Vec<MealWindow>,
+    /// Why someone might want to check on the pet
+    pub alerts: Vec<String>,
+    #[serde(rename = "_meta", skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct FlapAccess {
+    pub device_id: u32,
+    pub device: String,
+    pub profile: Option<PetProfile>,
+}
+
+/// For a day, or since midnight for today, in the [units] timezone.
+#[derive(Serialize, Debug, Clone)]
+pub struct Totals {
+    pub eaten_g: f64,
+    pub drunk_ml: f64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PetEvent {
+    pub at: DateTime<Utc>,
+    pub device: Option<String>,
+    #[serde(flatten)]
+    pub activity: Activity,
+}
+
+/// Fetch the pet's access on each flap and enough history for today's
+/// totals, its recent events and its drinking trend.
+pub async fn gather(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet: &Pet,
+    devices: &[Device],
+) -> std::io::Result<PetInfo> {
+    let cfg = api_client.cfg();
+    let now = Utc::now();
+
+    let mut access = Vec::new();
+    if let Some(tag_id) = pet.tag_id {
+        for flap in devices.iter().filter(|d| d.is_flap()) {
+            let tags = api_client
+                .get_device_tags(token, flap.id)
+                .await
+                .map_err(Error::other)?;
+            if let Some(tag) = tags.iter().find(|t| t.id == tag_id) {
+                access.push(FlapAccess {
+                    device_id: flap.id,
+                    device: flap.name.clone(),
+                    profile: tag.pet_profile(),
+                });
+            }
+        }
+    }
+
+    let midnight = cfg.units.start_of_day(&now);
+    let first_day = midnight - Duration::days(TREND_DAYS - 1);
+    let from = first_day
+        .min(now - health::drinking_lookback(&cfg.health))
+        .min(now - health::meal_lookback(&cfg.health));
+    let report = api_client
+        .get_pet_report(token, pet, from, now)
+        .await
+        .map_err(Error::other)?;
+    let feeding = report.feeding.map(|s| s.datapoints).unwrap_or_default();
+    let drinking = report.drinking.map(|s| s.datapoints).unwrap_or_default();
+    let movement = report.movement.map(|s| s.datapoints).unwrap_or_default();
+
+    // folded from 0.0, as an empty f64 sum is -0.0
+    let totals = |start: DateTime<Utc>| {
+        let on_day = |at: DateTime<Utc>| at >= start && at < start + Duration::days(1);
+        Totals {
+            eaten_g: feeding
+                .iter()
+                .filter(|e| on_day(e.from))
+                .fold(0.0, |total, e| total + e.grams_eaten()),
+            drunk_ml: drinking
+                .iter()
+                .filter(|e| on_day(e.from))
+                .fold(0.0, |total, e| total + e.ml_drunk()),
+        }
+    };
+    let days: Vec<Totals> = (0..TREND_DAYS)
+        .map(|day| totals(first_day + Duration::days(day)))
+        .collect();
+
+    let device_names: HashMap<u32, String> =
+        devices.iter().map(|d| (d.id, d.name.clone())).collect();
+    let device_name = |id: Option<u32>| device_names.get(&id?).cloned();
+    let mut events: Vec<PetEvent> = feeding
+        .iter()
+        .map(|e| (e.from, Some(e.device_id), Activity::Ate(e.grams_eaten())))
+        .chain(
+            drinking
+                .iter()
+                .map(|e| (e.from, Some(e.device_id), Activity::Drank(e.ml_drunk()))),
+        )
+        .chain(
+            movement
+                .iter()
+                .map(|e| (e.from, e.device_id, Activity::Outside(e.to))),
+        )
+        .map(|(at, device_id, activity)| PetEvent {
+            at,
+            device: device_name(device_id),
+            activity,
+        })
+        .collect();
+    events.sort_by_key(|e| Reverse(e.at));
+    events.truncate(RECENT_EVENTS);
+
+    let mut alerts = Vec::new();
+    if let Some(position) = &pet.position {
+        let hours = (now - position.since).num_hours();
+        let limit = match position.location {
+            Location::Outside => cfg.notifications.outside_alert_hours,
+            Location::Inside => cfg.notifications.inactive_alert_hours,
+            Location::Unknown(_) => 0,
+        };
+        if limit > 0 && hours >= limit {
+            alerts.push(format!("{} for {} hours", position.location, hours));
+        }
+    }
+    for missed in health::missed_meals(&feeding, now, &cfg.health, &cfg.units) {
+        alerts.push(format!(
+            "overdue for its usual {} meal",
+            missed.label(&cfg.units)
+        ));
+    }
+    if cfg.health.low_intake_percent > 0.0 {
+        alerts.extend(low_intake(&days, cfg.health.low_intake_percent));
+    }
+    if !drinking.is_empty() {
+        let trend = health::drinking_trend(&drinking, now, &cfg.health);
+        if trend.frequency_alert {
+            alerts.push("drinking more often than usual".to_string());
+        }
+        if trend.volume_alert {
+            alerts.push("drinking more per visit than usual".to_string());
+        }
+    }
+
+    Ok(PetInfo {
+        pet: PetOutput::new(pet),
+        minutes_in_location: pet.position.as_ref().map(|p| (now - p.since).num_minutes()),
+        access,
+        today: totals(midnight),
+        days,
+        events,
+        outside: outings::outing_stats(&movement, TREND_DAYS, now, &device_names, &cfg.units),
+        meal_windows: health::meal_windows(&feeding, now, &cfg.health, &cfg.units),
+        alerts,
+        meta: None,
+    })
+}
+
+/// Alerts for yesterday's food or water falling below `percent` of the
+/// average of the days before it. Days with none of it don't count towards
+/// the average, as the bowl most likely wasn't in use.
+fn low_intake(days: &[Totals], percent: f64) -> Vec<String> {
+    let [before @ .., yesterday, _today] = days else {
+        return Vec::new();
+    };
+    let share_of_usual = |amount: fn(&Totals) -> f64| {
+        let used: Vec<f64> = before.iter().map(amount).filter(|a| *a > 0.0).collect();
+        let usual = used.iter().fold(0.0, |sum, a| sum + a) / used.len() as f64;
+        (!used.is_empty()).then(|| amount(yesterday) / usual * 100.0)
+    };
+    let mut alerts = Vec::new();
+    let eaten = share_of_usual(|t| t.eaten_g);
+    let drunk = share_of_usual(|t| t.drunk_ml);
+    for (doing, what, share) in [("eating", "food", eaten), ("drinking", "water", drunk)] {
+        if let Some(share) = share.filter(|s| *s < percent) {
+            alerts.push(format!(
+                "{} less, {:.0}% of its usual {} yesterday",
+                doing, share, what
+            ));
+        }
+    }
+    alerts
+}
@@ -0,0 +1,72 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::env;
+use std::io::{self, Write};
+
+/// Columns a preview takes up, so a large picture doesn't fill the screen.
+const PREVIEW_COLUMNS: u32 = 30;
+/// The most base64 the kitty protocol takes in one escape sequence.
+const KITTY_CHUNK: usize = 4096;
+
+/// How the terminal can be sent a picture to show inline. Sixel would
+/// need the picture decoded to pixels first, so isn't offered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// kitty's graphics protocol, also spoken by Ghostty and WezTerm. Takes
+    /// PNG only
+    Kitty,
+    /// iTerm2's inline images, also spoken by WezTerm. Takes any format
+    /// the terminal can decode
+    Iterm,
+}
+
+/// The protocol the terminal speaks, going by the variables it sets.
+pub fn protocol() -> Option<Protocol> {
+    let var = |name| env::var(name).unwrap_or_default();
+    if env::var_os("KITTY_WINDOW_ID").is_some()
+        || matches!(var("TERM").as_str(), "xterm-kitty" | "xterm-ghostty")
+    {
+        return Some(Protocol::Kitty);
+    }
+    if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm")
+        || var("LC_TERMINAL") == "iTerm2"
+    {
+        return Some(Protocol::Iterm);
+    }
+    None
+}
+
+/// Show `image` inline on stdout. Returns whether it could, as kitty only
+/// takes PNG.
+pub fn show(image: &[u8], protocol: Protocol) -> io::Result<bool> {
+    let encoded = STANDARD.encode(image);
+    let mut stdout = io::stdout().lock();
+    match protocol {
+        Protocol::Kitty => {
+            if !image.starts_with(b"\x89PNG\r\n\x1a\n") {
+                return Ok(false);
+            }
+            let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(i + 1 < chunks.len());
+                if i == 0 {
+                    write!(stdout, "\x1b_Ga=T,f=100,c={},m={};", PREVIEW_COLUMNS, more)?;
+                } else {
+                    write!(stdout, "\x1b_Gm={};", more)?;
+                }
+                stdout.write_all(chunk)?;
+                write!(stdout, "\x1b\\")?;
+            }
+        }
+        Protocol::Iterm => write!(
+            stdout,
+            "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07",
+            image.len(),
+            PREVIEW_COLUMNS,
+            encoded
+        )?,
+    }
+    writeln!(stdout)?;
+    stdout.flush()?;
+    Ok(true)
+}
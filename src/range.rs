@@ -0,0 +1,320 @@
+use crate::i18n;
+use crate::units::Units;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use std::io::{Error, ErrorKind};
+
+/// The longest span any option takes, so arithmetic on times can't
+/// overflow: a century.
+pub const MAX_DAYS: i64 = 36500;
+
+/// A stretch of history a command covers.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeRange {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+impl TimeRange {
+    /// The `days` days up to `now`, at most [MAX_DAYS].
+    pub fn last_days(days: i64, now: DateTime<Utc>) -> Self {
+        TimeRange {
+            from: now - Duration::days(days.clamp(0, MAX_DAYS)),
+            to: now,
+        }
+    }
+
+    /// Whole days covered, part of a day counting as one.
+    pub fn days(&self) -> i64 {
+        let seconds = (self.to - self.from).num_seconds();
+        ((seconds + 86399) / 86400).max(1)
+    }
+}
+
+/// A fractional number of days after `at`, to the minute. Missing when it
+/// isn't a number or is more than [MAX_DAYS] away, as a projection from a
+/// rate near zero can be.
+pub fn days_after(at: DateTime<Utc>, days: f64) -> Option<DateTime<Utc>> {
+    if !days.is_finite() || days.abs() > MAX_DAYS as f64 {
+        return None;
+    }
+    at.checked_add_signed(Duration::try_minutes((days * 24.0 * 60.0) as i64)?)
+}
+
+/// A point or period given to `--from` or `--to`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum When {
+    /// A whole day in the [units] timezone
+    Date(NaiveDate),
+    Time(DateTime<Utc>),
+    /// This long before now
+    Ago(Duration),
+    Today,
+    Yesterday,
+    /// Since Monday
+    ThisWeek,
+    LastWeek,
+    ThisMonth,
+    LastMonth,
+}
+
+/// A time span such as `90s`, `30m`, `1h` or `2d`, up to [MAX_DAYS].
+pub fn parse_age(age: &str) -> Result<Duration, String> {
+    let split = age.len() - age.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (number, unit) = age.split_at(split);
+    if number.is_empty() {
+        return Err(i18n::message("age_not_number", &[("age", age)]));
+    }
+    let max = MAX_DAYS.to_string();
+    let too_long = || i18n::message("age_too_long", &[("age", age), ("max", &max)]);
+    let number: i64 = number.parse().map_err(|_| too_long())?;
+    let span = match unit {
+        "s" => Duration::try_seconds(number),
+        "m" => Duration::try_minutes(number),
+        "h" => Duration::try_hours(number),
+        "d" => Duration::try_days(number),
+        _ => {
+            return Err(i18n::message(
+                "age_unit",
+                &[("unit", &format!("{:?}", unit))],
+            ))
+        }
+    };
+    span.filter(|span| *span <= Duration::days(MAX_DAYS))
+        .ok_or_else(too_long)
+}
+
+/// A number of days from 1 to [MAX_DAYS].
+pub fn parse_days(days: &str) -> Result<i64, String> {
+    match days.parse() {
+        Ok(days) if (1..=MAX_DAYS).contains(&days) => Ok(days),
+        _ => Err(i18n::message(
+            "days_out_of_range",
+            &[("max", &MAX_DAYS.to_string())],
+        )),
+    }
+}
+
+/// A date such as 2024-01-31, an RFC 3339 time, a span ago such as 3d or
+/// 12h, or today, yesterday, this-week, last-week, this-month or last-month.
+pub fn parse_when(when: &str) -> Result<When, String> {
+    match when.to_ascii_lowercase().as_str() {
+        "today" => return Ok(When::Today),
+        "yesterday" => return Ok(When::Yesterday),
+        "this-week" => return Ok(When::ThisWeek),
+        "last-week" => return Ok(When::LastWeek),
+        "this-month" => return Ok(When::ThisMonth),
+        "last-month" => return Ok(When::LastMonth),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(when, "%Y-%m-%d") {
+        return Ok(When::Date(date));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(when) {
+        return Ok(When::Time(time.to_utc()));
+    }
+    let span = when.trim_end_matches(['s', 'm', 'h', 'd']);
+    if !span.is_empty() && span.len() + 1 == when.len() && span.chars().all(|c| c.is_ascii_digit())
+    {
+        return parse_age(when).map(When::Ago);
+    }
+    parse_age(when).map(When::Ago).map_err(|_| {
+        format!(
+            "expected a date such as 2024-01-31, a span such as 3d or 12h, \
+             or a period such as last-month, not {}",
+            when
+        )
+    })
+}
+
+impl When {
+    /// When it begins and ends, the same moment for a point in time.
+    /// Missing for a time too far off to work with.
+    fn bounds(self, now: DateTime<Utc>, units: &Units) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let today = units.start_of_day(&now);
+        let this_week = week_start(now, units);
+        let this_month = month_start(now, units);
+        Some(match self {
+            When::Date(date) => (
+                units.start_of_date(date),
+                units.start_of_date(date.succ_opt()?),
+            ),
+            When::Time(time) => (time, time),
+            When::Ago(span) => {
+                let ago = now.checked_sub_signed(span)?;
+                (ago, ago)
+            }
+            When::Today => (today, now),
+            When::Yesterday => (units.start_of_day(&(today - Duration::hours(1))), today),
+            When::ThisWeek => (this_week, now),
+            When::LastWeek => (week_start(this_week - Duration::hours(1), units), this_week),
+            When::ThisMonth => (this_month, now),
+            When::LastMonth => (
+                month_start(this_month - Duration::hours(1), units),
+                this_month,
+            ),
+        })
+    }
+}
+
+fn week_start(time: DateTime<Utc>, units: &Units) -> DateTime<Utc> {
+    let monday = time - Duration::days(units.weekday_of(&time).num_days_from_monday().into());
+    units.start_of_day(&monday)
+}
+
+fn month_start(time: DateTime<Utc>, units: &Units) -> DateTime<Utc> {
+    units.start_of_date(units.day_of(&time).with_day(1).unwrap_or_default())
+}
+
+/// The range `--from` and `--to` ask for. Without `--from` it is `default`
+/// long, ending at `--to` or now. A period given only to `--from` ends with
+/// it, so `--from last-month` is just last month, and a date given to
+/// `--to` is included.
+pub fn resolve(
+    from: Option<When>,
+    to: Option<When>,
+    default: Duration,
+    units: &Units,
+) -> std::io::Result<TimeRange> {
+    let now = Utc::now();
+    let out_of_range = |option: &str| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is too far from today", option),
+        )
+    };
+    let to = match to {
+        Some(to) => Some(to.bounds(now, units).ok_or_else(|| out_of_range("--to"))?.1),
+        None => None,
+    };
+    let Some(from) = from else {
+        let to = to.unwrap_or(now).min(now);
+        return Ok(TimeRange {
+            from: to
+                .checked_sub_signed(default)
+                .ok_or_else(|| out_of_range("--to"))?,
+            to,
+        });
+    };
+    let (start, end) = from
+        .bounds(now, units)
+        .ok_or_else(|| out_of_range("--from"))?;
+    let to = match to {
+        Some(to) => to,
+        None if matches!(from, When::Date(_) | When::Time(_) | When::Ago(_)) => now,
+        None => end,
+    }
+    .min(now);
+    if start >= to {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "--from {} isn't before --to {}",
+                units.time(&start),
+                units.time(&to)
+            ),
+        ));
+    }
+    Ok(TimeRange { from: start, to })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn utc() -> Units {
+        let mut units = config::built_in().units;
+        units.timezone = Some(chrono_tz::UTC);
+        units
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn parses_dates_spans_and_periods() {
+        assert_eq!(parse_when("2024-01-31"), Ok(When::Date(date("2024-01-31"))));
+        assert_eq!(parse_when("3d"), Ok(When::Ago(Duration::days(3))));
+        assert_eq!(parse_when("12h"), Ok(When::Ago(Duration::hours(12))));
+        assert_eq!(parse_when("Last-Month"), Ok(When::LastMonth));
+        let time = DateTime::parse_from_rfc3339("2024-01-31T12:00:00+01:00").unwrap();
+        assert_eq!(
+            parse_when("2024-01-31T12:00:00+01:00"),
+            Ok(When::Time(time.to_utc()))
+        );
+        assert!(parse_when("soon").is_err());
+        assert!(parse_when("3w").is_err());
+        assert!(parse_when("2024-02-30").is_err());
+    }
+
+    #[test]
+    fn rejects_spans_too_long_to_work_with() {
+        assert!(parse_when("36501d").is_err());
+        assert!(parse_when("99999999999999999999d").is_err());
+        assert!(parse_age("9223372036854775807s").is_err());
+        assert_eq!(
+            parse_age(&format!("{}d", MAX_DAYS)),
+            Ok(Duration::days(MAX_DAYS))
+        );
+        assert!(parse_days("0").is_err());
+        assert!(parse_days("36501").is_err());
+    }
+
+    #[test]
+    fn a_date_given_to_to_is_included() {
+        let from = Some(When::Date(date("2024-01-01")));
+        let to = Some(When::Date(date("2024-01-31")));
+        let range = resolve(from, to, Duration::days(7), &utc()).unwrap();
+        assert_eq!(
+            range.from,
+            date("2024-01-01").and_hms_opt(0, 0, 0).unwrap().and_utc()
+        );
+        assert_eq!(
+            range.to,
+            date("2024-02-01").and_hms_opt(0, 0, 0).unwrap().and_utc()
+        );
+        assert_eq!(range.days(), 31);
+    }
+
+    #[test]
+    fn a_period_given_to_from_ends_with_it() {
+        let range = resolve(Some(When::Yesterday), None, Duration::days(7), &utc()).unwrap();
+        assert_eq!(range.to - range.from, Duration::days(1));
+        assert_eq!(range.to, utc().start_of_day(&Utc::now()));
+    }
+
+    #[test]
+    fn without_from_the_range_is_the_default_length() {
+        let range = resolve(None, None, Duration::days(7), &utc()).unwrap();
+        assert_eq!(range.to - range.from, Duration::days(7));
+    }
+
+    #[test]
+    fn from_must_be_before_to() {
+        let from = Some(When::Date(date("2024-02-01")));
+        let to = Some(When::Date(date("2024-01-01")));
+        let e = resolve(from, to, Duration::days(7), &utc()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn dates_at_the_ends_of_the_calendar_are_errors() {
+        let last = Some(When::Date(NaiveDate::MAX));
+        let e = resolve(last, None, Duration::days(7), &utc()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        let first = Some(When::Date(NaiveDate::MIN));
+        let e = resolve(None, first, Duration::days(7), &utc()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn days_after_gives_up_past_max_days() {
+        let at = Utc::now();
+        assert_eq!(days_after(at, 1.5), Some(at + Duration::hours(36)));
+        assert_eq!(days_after(at, -1.0), Some(at - Duration::days(1)));
+        assert_eq!(days_after(at, f64::INFINITY), None);
+        assert_eq!(days_after(at, f64::NAN), None);
+        assert_eq!(days_after(at, 1e12), None);
+    }
+}
@@ -0,0 +1,625 @@
+use crate::api::client::MovementEvent;
+use crate::api::SurePetApi;
+use crate::forecast;
+use crate::health::{self, DrinkingTrend};
+use crate::history::{self, DeviceTotal};
+use crate::markdown;
+use crate::outings::{self, OutingStats};
+use crate::range::TimeRange;
+use crate::sqlite;
+use crate::stats::format_duration;
+use crate::summary;
+use crate::units::Units;
+use crate::weather;
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use log::error;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::process::Command;
+
+/// Converts the HTML report to PDF when `--pdf` is given.
+const PDF_CONVERTER: &str = "wkhtmltopdf";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// A standalone page, which can also be converted to PDF
+    Html,
+    /// For pasting into GitHub issues or notes
+    Markdown,
+}
+
+impl ReportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Html => "html",
+            ReportFormat::Markdown => "md",
+        }
+    }
+}
+
+/// Inline styles shared by the HTML reports, which are meant to be printed.
+pub const STYLE: &str = "
+body { font-family: sans-serif; max-width: 56em; margin: 2em auto; color: #222; }
+h1, h2 { color: #06788f; }
+table { border-collapse: collapse; margin: 0.5em 0 1em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: left; }
+th { background: #eef6f8; }
+.alert { color: #a33; }
+.ok { color: #283; }
+section { page-break-inside: avoid; }
+";
+
+/// A household summary over a range of days, one section per pet.
+pub struct Report {
+    pub generated_at: DateTime<Utc>,
+    pub range: TimeRange,
+    /// Whole days in the range, which per day figures are averaged over
+    pub days: i64,
+    pub pets: Vec<PetSection>,
+    pub devices: Vec<DeviceRow>,
+    /// Problems with the household as a whole, such as flat batteries
+    pub alerts: Vec<String>,
+    pub recommendations: Vec<String>,
+    /// When food and batteries are likely to run out
+    pub forecast: Vec<String>,
+}
+
+pub struct PetSection {
+    pub name: String,
+    pub location: String,
+    pub meals: usize,
+    pub grams: f64,
+    /// Meals and food eaten at each feeder, by name, so food taken from
+    /// another pet's bowl stands out
+    pub feeders: Vec<(String, DeviceTotal)>,
+    pub drinks: usize,
+    pub ml: f64,
+    pub trips_outside: usize,
+    pub outside: OutingStats,
+    pub trend: DrinkingTrend,
+    /// When the pet is usually about, and how that goes with the weather
+    pub trends: Vec<String>,
+    pub alerts: Vec<String>,
+    pub recommendations: Vec<String>,
+}
+
+pub struct DeviceRow {
+    pub name: String,
+    pub online: Option<bool>,
+    pub battery: Option<u8>,
+    pub lock: Option<String>,
+}
+
+/// Generate the report from the API, or with `db` from the history kept by
+/// `sync`, which may go back further than the API does.
+pub async fn generate(
+    api_client: &impl SurePetApi,
+    token: &str,
+    range: TimeRange,
+    db: Option<&Path>,
+) -> std::io::Result<Report> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+    let device_names: HashMap<u32, String> =
+        devices.iter().map(|d| (d.id, d.name.clone())).collect();
+
+    let TimeRange { from, to } = range;
+    let days = range.days();
+    // the drinking trend needs its baseline even for a short report
+    let lookback = from.min(to - health::drinking_lookback(&api_client.cfg().health));
+
+    let mut report = Report {
+        generated_at: Utc::now(),
+        range,
+        days,
+        pets: Vec::new(),
+        devices: Vec::new(),
+        alerts: Vec::new(),
+        recommendations: Vec::new(),
+        forecast: Vec::new(),
+    };
+
+    let units = &api_client.cfg().units;
+    // Open-Meteo counts back from today, which the range may end before
+    let past_days = (Utc::now() - from).num_days() + 1;
+    let weather = match &api_client.cfg().weather {
+        Some(cfg) => match weather::daily(api_client.http(), cfg, past_days, units).await {
+            Ok(weather) => weather,
+            Err(e) => {
+                error!("failed to fetch the weather: {}", e);
+                summary::failed();
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+
+    for pet in &pets {
+        let history = match db {
+            Some(db) => sqlite::pet_report(db, pet.id, lookback, to).map_err(|e| e.to_string()),
+            None => api_client
+                .get_pet_report(token, pet, lookback, to)
+                .await
+                .map_err(|e| e.to_string()),
+        };
+        let history = match history {
+            Ok(history) => history,
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                report
+                    .alerts
+                    .push(format!("{}'s history could not be fetched", pet.name));
+                continue;
+            }
+        };
+        let feeding: Vec<_> = history.feeding.map(|f| f.datapoints).unwrap_or_default();
+        let drinking: Vec<_> = history.drinking.map(|d| d.datapoints).unwrap_or_default();
+        let movement: Vec<_> = history.movement.map(|m| m.datapoints).unwrap_or_default();
+
+        let meals: Vec<_> = feeding.iter().filter(|e| e.from >= from).collect();
+        let drinks: Vec<_> = drinking.iter().filter(|e| e.from >= from).collect();
+        let trend = health::drinking_trend(&drinking, to, &api_client.cfg().health);
+
+        let mut section = PetSection {
+            name: pet.name.clone(),
+            location: match &pet.position {
+                Some(p) => format!("{} since {}", p.location, units.time(&p.since)),
+                None => "unknown".to_string(),
+            },
+            meals: meals.len(),
+            grams: meals.iter().fold(0.0, |sum, e| sum + e.grams_eaten()),
+            feeders: history::feeding_by_device(meals.iter().copied())
+                .into_iter()
+                .map(|total| {
+                    let name = device_names.get(&total.device_id).cloned();
+                    (
+                        name.unwrap_or_else(|| format!("device {}", total.device_id)),
+                        total,
+                    )
+                })
+                .collect(),
+            drinks: drinks.len(),
+            ml: drinks.iter().fold(0.0, |sum, e| sum + e.ml_drunk()),
+            trips_outside: movement.iter().filter(|m| m.from >= from).count(),
+            outside: outings::outing_stats(&movement, days, to, &device_names, units),
+            alerts: Vec::new(),
+            recommendations: Vec::new(),
+            trends: busiest_hour(&pet.name, &movement, from, units)
+                .into_iter()
+                .collect(),
+            trend,
+        };
+        section.trends.extend(weather::trends(
+            &pet.name,
+            &section.outside.days,
+            &weather,
+            units,
+        ));
+        if section.trend.frequency_alert || section.trend.volume_alert {
+            section
+                .alerts
+                .push("Sustained increase in drinking".to_string());
+            section
+                .recommendations
+                .push("Consider a vet check for increased thirst".to_string());
+        }
+        if !feeding.is_empty() && meals.is_empty() {
+            let period = match days {
+                1 => "day".to_string(),
+                days => format!("{} days", days),
+            };
+            section
+                .alerts
+                .push(format!("No meals recorded in the last {}", period));
+        }
+        report.pets.push(section);
+    }
+
+    let threshold = api_client.cfg().notifications.battery_threshold;
+    for device in &devices {
+        let battery = device.battery_percent();
+        if battery.is_some_and(|b| b < threshold) {
+            report
+                .alerts
+                .push(format!("{} battery is low", device.name));
+            report
+                .recommendations
+                .push(format!("Replace the batteries in {}", device.name));
+        }
+        if device.online() == Some(false) {
+            report.alerts.push(format!("{} is offline", device.name));
+        }
+        report.devices.push(DeviceRow {
+            name: device.name.clone(),
+            online: device.online(),
+            battery,
+            lock: device.lock_mode().map(|m| m.to_string()),
+        });
+    }
+
+    let status_db = api_client.cfg().daemon.status_db.as_deref();
+    match forecast::gather(api_client, token, status_db).await {
+        Ok(forecast) => {
+            report.forecast = forecast::describe(&forecast, units);
+        }
+        Err(e) => {
+            error!("failed to forecast: {}", e);
+            summary::failed();
+        }
+    }
+
+    Ok(report)
+}
+
+/// The hour the pet most often goes through a flap, from the heatmap.
+fn busiest_hour(
+    pet: &str,
+    movement: &[MovementEvent],
+    from: DateTime<Utc>,
+    units: &Units,
+) -> Option<String> {
+    let heatmap = outings::heatmap(movement, from, units);
+    let (hour, passages) = heatmap
+        .hours
+        .iter()
+        .enumerate()
+        .map(|(i, hour)| {
+            (
+                *hour,
+                heatmap.counts.iter().map(|day| day[i]).sum::<usize>(),
+            )
+        })
+        .max_by_key(|(_, passages)| *passages)?;
+    (passages > 0).then(|| {
+        format!(
+            "{} is most often through a flap around {}",
+            pet,
+            units.hour(hour)
+        )
+    })
+}
+
+/// A standalone HTML page with inline styles, so it can be mailed or printed.
+pub fn render_html(report: &Report, units: &Units) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>
+<html><head><meta charset=\"utf-8\"><title>RustyPet report</title>
+<style>{}</style></head><body>
+<h1>RustyPet report</h1>
+<p>{}, generated {}</p>
+",
+        STYLE,
+        escape(&period(report, units)),
+        units.time(&report.generated_at)
+    );
+
+    html.push_str("<h2>Household</h2>\n");
+    push_list(&mut html, "alert", &report.alerts);
+    push_list(&mut html, "", &report.recommendations);
+    html.push_str("<table><tr><th>Device</th><th>Online</th><th>Battery</th><th>Lock</th></tr>\n");
+    for device in &report.devices {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&device.name),
+            match device.online {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "-",
+            },
+            device
+                .battery
+                .map(|b| format!("{}%", b))
+                .unwrap_or_else(|| "-".to_string()),
+            escape(device.lock.as_deref().unwrap_or("-"))
+        );
+    }
+    html.push_str("</table>\n");
+    if !report.forecast.is_empty() {
+        html.push_str("<h2>Forecast</h2>\n");
+        push_list(&mut html, "", &report.forecast);
+    }
+
+    for pet in &report.pets {
+        let _ = write!(
+            html,
+            "<section><h2>{}</h2>
+<p>Currently {}</p>
+<table>
+<tr><th></th><th>Total</th><th>Per day</th></tr>
+<tr><td>Meals</td><td>{}</td><td>{:.1}</td></tr>
+<tr><td>Food</td><td>{}</td><td>{}</td></tr>
+<tr><td>Drinks</td><td>{}</td><td>{:.1}</td></tr>
+<tr><td>Water</td><td>{}</td><td>{}</td></tr>
+<tr><td>Trips outside</td><td>{}</td><td>{:.1}</td></tr>
+<tr><td>Time outside</td><td>{}</td><td>{}</td></tr>
+</table>
+<p>{}</p>
+<p>Drinking {:.1} times a day, {} a visit (baseline {:.1} times, {})</p>
+",
+            escape(&pet.name),
+            escape(&pet.location),
+            pet.meals,
+            per_day(pet.meals as f64, report.days),
+            units.weight(pet.grams, 0),
+            units.weight(per_day(pet.grams, report.days), 1),
+            pet.drinks,
+            per_day(pet.drinks as f64, report.days),
+            units.volume(pet.ml, 0),
+            units.volume(per_day(pet.ml, report.days), 1),
+            pet.trips_outside,
+            per_day(pet.trips_outside as f64, report.days),
+            minutes(pet.outside.total_minutes),
+            minutes(pet.outside.total_minutes / report.days.max(1)),
+            escape(&outings_text(&pet.outside)),
+            pet.trend.current.visits_per_day,
+            units.volume(pet.trend.current.avg_ml, 1),
+            pet.trend.baseline.visits_per_day,
+            units.volume(pet.trend.baseline.avg_ml, 1),
+        );
+        if !pet.feeders.is_empty() {
+            html.push_str("<table><tr><th>Feeder</th><th>Meals</th><th>Food</th></tr>\n");
+            for (name, total) in &pet.feeders {
+                let _ = writeln!(
+                    html,
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape(name),
+                    total.events,
+                    units.weight(total.amount, 0)
+                );
+            }
+            html.push_str("</table>\n");
+        }
+        if pet.alerts.is_empty() {
+            html.push_str("<p class=\"ok\">Nothing unusual</p>\n");
+        }
+        push_list(&mut html, "alert", &pet.alerts);
+        push_list(&mut html, "", &pet.recommendations);
+        if !pet.trends.is_empty() {
+            html.push_str("<h3>Trends</h3>\n");
+            push_list(&mut html, "", &pet.trends);
+        }
+        html.push_str("</section>\n");
+    }
+
+    html.push_str("</body></html>\n");
+    html
+}
+
+/// The same report as Markdown, to paste into an issue or a note.
+pub fn render_markdown(report: &Report, units: &Units) -> String {
+    let mut md = String::new();
+    let _ = write!(
+        md,
+        "# RustyPet report\n\n{}, generated {}\n\n## Household\n\n",
+        period(report, units),
+        units.time(&report.generated_at)
+    );
+    push_markdown_list(&mut md, "⚠️ ", &report.alerts);
+    push_markdown_list(&mut md, "", &report.recommendations);
+    let devices = report.devices.iter().map(|device| {
+        vec![
+            device.name.clone(),
+            markdown::online(device.online).to_string(),
+            device
+                .battery
+                .map(|b| format!("{}%", b))
+                .unwrap_or_else(|| "-".to_string()),
+            device.lock.clone().unwrap_or_else(|| "-".to_string()),
+        ]
+    });
+    md.push_str(&markdown::table(
+        &["Device", "Connection", "Battery", "Lock"],
+        devices,
+    ));
+    if !report.forecast.is_empty() {
+        md.push_str("\n## Forecast\n\n");
+        push_markdown_list(&mut md, "", &report.forecast);
+    }
+
+    for pet in &report.pets {
+        let _ = write!(md, "\n## {}\n\nCurrently {}\n\n", pet.name, pet.location);
+        let count = |n: usize| format!("{:.1}", per_day(n as f64, report.days));
+        let rows = [
+            ["Meals".to_string(), pet.meals.to_string(), count(pet.meals)],
+            [
+                "Food".to_string(),
+                units.weight(pet.grams, 0),
+                units.weight(per_day(pet.grams, report.days), 1),
+            ],
+            [
+                "Drinks".to_string(),
+                pet.drinks.to_string(),
+                count(pet.drinks),
+            ],
+            [
+                "Water".to_string(),
+                units.volume(pet.ml, 0),
+                units.volume(per_day(pet.ml, report.days), 1),
+            ],
+            [
+                "Trips outside".to_string(),
+                pet.trips_outside.to_string(),
+                count(pet.trips_outside),
+            ],
+            [
+                "Time outside".to_string(),
+                minutes(pet.outside.total_minutes),
+                minutes(pet.outside.total_minutes / report.days.max(1)),
+            ],
+        ]
+        .into_iter()
+        .map(Vec::from);
+        md.push_str(&markdown::table(&["", "Total", "Per day"], rows));
+        let _ = write!(
+            md,
+            "\n{}\n\nDrinking {:.1} times a day, {} a visit (baseline {:.1} times, {})\n\n",
+            outings_text(&pet.outside),
+            pet.trend.current.visits_per_day,
+            units.volume(pet.trend.current.avg_ml, 1),
+            pet.trend.baseline.visits_per_day,
+            units.volume(pet.trend.baseline.avg_ml, 1),
+        );
+        if !pet.feeders.is_empty() {
+            let feeders = pet.feeders.iter().map(|(name, total)| {
+                vec![
+                    name.clone(),
+                    total.events.to_string(),
+                    units.weight(total.amount, 0),
+                ]
+            });
+            md.push_str(&markdown::table(&["Feeder", "Meals", "Food"], feeders));
+            md.push('\n');
+        }
+        if pet.alerts.is_empty() {
+            md.push_str("✅ Nothing unusual\n");
+        }
+        push_markdown_list(&mut md, "⚠️ ", &pet.alerts);
+        push_markdown_list(&mut md, "", &pet.recommendations);
+        if !pet.trends.is_empty() {
+            md.push_str("\n### Trends\n\n");
+            push_markdown_list(&mut md, "", &pet.trends);
+        }
+    }
+    md
+}
+
+/// A few plain lines per pet, short enough for a chat message or a
+/// notification, for `digest`.
+pub fn render_digest(report: &Report, title: &str, units: &Units) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "{} to {}", title, units.time(&report.generated_at));
+    let daily = |amount: String, per_day: String| match report.days {
+        1 => amount,
+        _ => format!("{}, {} a day", amount, per_day),
+    };
+    for pet in &report.pets {
+        let _ = writeln!(
+            text,
+            "{}: {} meals ({}), {} drinks ({}), {} trips outside. Now {}",
+            pet.name,
+            pet.meals,
+            daily(
+                units.weight(pet.grams, 0),
+                units.weight(per_day(pet.grams, report.days), 0)
+            ),
+            pet.drinks,
+            daily(
+                units.volume(pet.ml, 0),
+                units.volume(per_day(pet.ml, report.days), 0)
+            ),
+            pet.trips_outside,
+            pet.location
+        );
+        for alert in &pet.alerts {
+            let _ = writeln!(text, "  ! {}", alert);
+        }
+    }
+    for alert in &report.alerts {
+        let _ = writeln!(text, "! {}", alert);
+    }
+    let unusual = !report.alerts.is_empty() || report.pets.iter().any(|p| !p.alerts.is_empty());
+    if !unusual {
+        text.push_str("Nothing unusual\n");
+    }
+    text
+}
+
+/// Convert the HTML report at `html` to a PDF next to it.
+pub fn convert_to_pdf(html: &Path, pdf: &Path) -> std::io::Result<()> {
+    let status = Command::new(PDF_CONVERTER)
+        .arg("--quiet")
+        .arg(html)
+        .arg(pdf)
+        .status()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::new(
+                ErrorKind::NotFound,
+                format!("{} is needed for PDF reports", PDF_CONVERTER),
+            ),
+            _ => e,
+        })?;
+    if !status.success() {
+        return Err(Error::other(format!(
+            "{} failed: {}",
+            PDF_CONVERTER, status
+        )));
+    }
+    Ok(())
+}
+
+fn minutes(minutes: i64) -> String {
+    format_duration(Duration::minutes(minutes))
+}
+
+/// Such as "Outings last 40m on average, the longest 2h 5m", noting trips
+/// left out because the return wasn't scanned.
+fn outings_text(outside: &OutingStats) -> String {
+    let mut text = match (outside.average_minutes, &outside.longest) {
+        (Some(average), Some(longest)) => format!(
+            "Outings last {} on average, the longest {}",
+            minutes(average),
+            minutes(longest.minutes)
+        ),
+        _ => "No finished outings".to_string(),
+    };
+    if outside.unpaired > 0 {
+        text.push_str(&format!(
+            ", {} left out as the return wasn't scanned",
+            outside.unpaired
+        ));
+    }
+    text
+}
+
+pub fn push_list(html: &mut String, class: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    let _ = writeln!(html, "<ul class=\"{}\">", class);
+    for item in items {
+        let _ = writeln!(html, "<li>{}</li>", escape(item));
+    }
+    html.push_str("</ul>\n");
+}
+
+fn push_markdown_list(md: &mut String, prefix: &str, items: &[String]) {
+    if items.is_empty() {
+        return;
+    }
+    for item in items {
+        let _ = writeln!(md, "- {}{}", prefix, item);
+    }
+    md.push('\n');
+}
+
+/// "Last 30 days", or the dates of a range that ended before the report.
+fn period(report: &Report, units: &Units) -> String {
+    if report.generated_at - report.range.to < Duration::minutes(1) {
+        return format!("Last {} days", report.days);
+    }
+    // a range ending at midnight ends with the day before
+    format!(
+        "{} to {}, {} days",
+        units.date(&report.range.from),
+        units.date(&(report.range.to - Duration::seconds(1))),
+        report.days
+    )
+}
+
+fn per_day(total: f64, days: i64) -> f64 {
+    total / days.max(1) as f64
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
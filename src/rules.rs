@@ -0,0 +1,608 @@
+use crate::api::client::{is_unreachable, Device, Location, LockMode, Pet};
+use crate::api::SurePetApi;
+use crate::config;
+use crate::headless::{find_device, find_pet};
+use crate::notifications::{self, Notification, NotificationKind};
+use crate::pending::{Pending, PendingOperation};
+use crate::schedule::Schedule;
+use crate::search::{self, MatchMode};
+use chrono::{DateTime, Local, NaiveTime, Utc};
+use futures::stream::{self, StreamExt};
+use log::{debug, error, info};
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const RULES_FILE: &str = "rules.toml";
+
+/// Automations for the daemon, read from `rules.toml` in the config directory:
+///
+/// ```toml
+/// [[rule]]
+/// name = "Lock up for the night"
+/// when = { inside = ["Tom", "Luna"], after = "19:00" }
+/// then = [
+///     { action = "lock", device = "Back door" },
+///     { action = "notify", message = "Both cats are in, back door locked" },
+/// ]
+///
+/// [[schedule]]
+/// name = "Weeknight lock"
+/// at = "21:30"
+/// days = "weekdays"
+/// then = [{ action = "lock", device = "Back door" }]
+///
+/// [[search]]
+/// name = "Needs attention"
+/// patterns = ["battery<20", "offline"]
+/// every_mins = 60
+///
+/// [[search]]
+/// name = "Doors"
+/// patterns = ["door|flap"]
+/// mode = "regex"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+pub struct Rules {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+    #[serde(default, rename = "search")]
+    pub searches: Vec<SavedSearch>,
+    #[serde(default, rename = "schedule")]
+    pub schedules: Vec<Schedule>,
+}
+
+/// A search run on a schedule, notifying whenever its results change.
+#[derive(Deserialize, Debug)]
+pub struct SavedSearch {
+    pub name: String,
+    /// Patterns as given to the search command, any of which may match
+    pub patterns: Vec<String>,
+    /// How text patterns match, as with --regex or --fuzzy
+    #[serde(default)]
+    pub mode: MatchMode,
+    /// Minutes between runs, rounded up to the next poll
+    #[serde(default)]
+    pub every_mins: u64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default)]
+    pub when: Conditions,
+    pub then: Vec<Action>,
+}
+
+/// Everything given must hold for a rule to match.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+    /// Pets, by name or id, that must all be inside
+    #[serde(default)]
+    pub inside: Vec<String>,
+    /// Pets, by name or id, that must all be outside
+    #[serde(default)]
+    pub outside: Vec<String>,
+    /// Local time of day from which the rule may match, as HH:MM
+    #[serde(default, deserialize_with = "time_of_day")]
+    pub after: Option<NaiveTime>,
+    /// Local time of day until which the rule may match, as HH:MM. Before
+    /// `after` means the window runs past midnight.
+    #[serde(default, deserialize_with = "time_of_day")]
+    pub before: Option<NaiveTime>,
+    /// Any device's battery is below this percentage
+    pub battery_below: Option<u8>,
+    /// Any pet hasn't gone in or out for this many hours
+    pub inactive_hours: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Lock a flap, or every flap, both ways
+    Lock { device: Option<String> },
+    /// Unlock a flap, or every flap
+    Unlock { device: Option<String> },
+    /// Send a notification to every configured notifier
+    Notify { message: String },
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Lock {
+                device: Some(device),
+            } => write!(f, "lock {}", device),
+            Action::Lock { device: None } => write!(f, "lock every flap"),
+            Action::Unlock {
+                device: Some(device),
+            } => write!(f, "unlock {}", device),
+            Action::Unlock { device: None } => write!(f, "unlock every flap"),
+            Action::Notify { message } => write!(f, "notify {:?}", message),
+        }
+    }
+}
+
+impl Rules {
+    /// Read `path`, or rules.toml in the config directory, which needn't
+    /// exist.
+    pub fn load(path: Option<&Path>) -> std::io::Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let path = rules_path()?;
+                if !path.exists() {
+                    return Ok(Rules::default());
+                }
+                path
+            }
+        };
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| Error::new(e.kind(), format!("reading {}: {}", path.display(), e)))?;
+        toml::from_str(&contents).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+/// Names of what a saved search found, keyed by kind and id
+type SearchResults = BTreeMap<(&'static str, u32), String>;
+
+/// Remembers which rules matched on the previous poll. A rule's actions run
+/// once when it starts matching, not on every poll while it keeps matching.
+#[derive(Default)]
+pub struct RuleEngine {
+    matching: HashSet<String>,
+    /// When each saved search last ran and what it found
+    searched: HashMap<String, (Instant, SearchResults)>,
+}
+
+impl RuleEngine {
+    /// Evaluate every rule against the latest poll and run the actions of
+    /// those that have just started to match.
+    pub async fn evaluate(
+        &mut self,
+        rules: &Rules,
+        api_client: &impl SurePetApi,
+        token: &str,
+        pets: &[Pet],
+        devices: &[Device],
+    ) {
+        let now = Utc::now();
+        for rule in &rules.rules {
+            let matches = rule.when.matches(pets, devices, now, Local::now().time());
+            if !matches {
+                self.matching.remove(&rule.name);
+                continue;
+            }
+            if !self.matching.insert(rule.name.clone()) {
+                continue;
+            }
+
+            info!("Rule {} matched", rule.name);
+            for action in &rule.then {
+                if let Err(e) = run_action(action, api_client, token, devices, &rule.name).await {
+                    error!("rule {} failed: {}", rule.name, e);
+                }
+            }
+        }
+
+        let mut changes = Vec::new();
+        for search in &rules.searches {
+            if let Some(change) = self.run_search(search, pets, devices) {
+                changes.push(change);
+            }
+        }
+        notifications::deliver(api_client, &changes).await;
+    }
+
+    /// Run a saved search if it is due, returning a notification when its
+    /// results differ from the last run. The first run only notifies when
+    /// something matches.
+    fn run_search(
+        &mut self,
+        search: &SavedSearch,
+        pets: &[Pet],
+        devices: &[Device],
+    ) -> Option<Notification> {
+        let every = Duration::from_secs(search.every_mins * 60);
+        let previous = self.searched.get(&search.name);
+        if previous.is_some_and(|(ran, _)| ran.elapsed() < every) {
+            return None;
+        }
+
+        let query = match search::Query::new(&search.patterns, search.mode) {
+            Ok(query) => query,
+            Err(e) => {
+                error!("saved search {} is invalid: {}", search.name, e);
+                return None;
+            }
+        };
+        let found: SearchResults = search::search_state(pets, devices, &query)
+            .into_iter()
+            .map(|hit| ((hit.kind, hit.id), hit.name))
+            .collect();
+        let before = previous.map(|(_, found)| found.clone()).unwrap_or_default();
+        self.searched
+            .insert(search.name.clone(), (Instant::now(), found.clone()));
+
+        let added: Vec<&str> = found
+            .iter()
+            .filter(|(key, _)| !before.contains_key(key))
+            .map(|(_, name)| name.as_str())
+            .collect();
+        let removed: Vec<&str> = before
+            .iter()
+            .filter(|(key, _)| !found.contains_key(key))
+            .map(|(_, name)| name.as_str())
+            .collect();
+        if added.is_empty() && removed.is_empty() {
+            return None;
+        }
+
+        let mut message = search.name.clone();
+        if !added.is_empty() {
+            message.push_str(&format!(" now matches {}", added.join(", ")));
+        }
+        if !removed.is_empty() {
+            if !added.is_empty() {
+                message.push(';');
+            }
+            message.push_str(&format!(" no longer matches {}", removed.join(", ")));
+        }
+        info!("{}", message);
+
+        Some(Notification {
+            event: NotificationKind::SavedSearch,
+            subject: search.name.clone(),
+            message,
+            timestamp: Utc::now(),
+        })
+    }
+}
+
+impl Conditions {
+    fn matches(
+        &self,
+        pets: &[Pet],
+        devices: &[Device],
+        now: DateTime<Utc>,
+        time: NaiveTime,
+    ) -> bool {
+        let at = |names: &[String], location: Location| {
+            names.iter().all(|name| {
+                find_pet(pets, name)
+                    .ok()
+                    .and_then(|p| p.position.as_ref())
+                    .is_some_and(|p| p.location == location)
+            })
+        };
+        if !at(&self.inside, Location::Inside) || !at(&self.outside, Location::Outside) {
+            return false;
+        }
+
+        let in_window = match (self.after, self.before) {
+            (Some(after), Some(before)) if after <= before => after <= time && time < before,
+            (Some(after), Some(before)) => time >= after || time < before,
+            (Some(after), None) => time >= after,
+            (None, Some(before)) => time < before,
+            (None, None) => true,
+        };
+        if !in_window {
+            return false;
+        }
+
+        if let Some(threshold) = self.battery_below {
+            let low = devices
+                .iter()
+                .filter_map(|d| d.battery_percent())
+                .any(|b| b < threshold);
+            if !low {
+                return false;
+            }
+        }
+
+        if let Some(hours) = self.inactive_hours {
+            let inactive = pets
+                .iter()
+                .filter_map(|p| p.position.as_ref())
+                .any(|p| now - p.since >= chrono::Duration::hours(hours));
+            if !inactive {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Carry out one of a rule's or schedule's actions, named `rule` in any
+/// notification it sends.
+pub async fn run_action(
+    action: &Action,
+    api_client: &impl SurePetApi,
+    token: &str,
+    devices: &[Device],
+    rule: &str,
+) -> std::io::Result<()> {
+    let (device, mode) = match action {
+        Action::Lock { device } => (device, LockMode::LockedAll),
+        Action::Unlock { device } => (device, LockMode::Unlocked),
+        Action::Notify { message } => {
+            let notification = Notification {
+                event: NotificationKind::Rule,
+                subject: rule.to_string(),
+                message: message.clone(),
+                timestamp: Utc::now(),
+            };
+            notifications::deliver(api_client, &[notification]).await;
+            return Ok(());
+        }
+    };
+
+    let flaps: Vec<&Device> = match device {
+        Some(name) => vec![find_device(devices, name)?],
+        None => devices.iter().filter(|d| d.is_flap()).collect(),
+    };
+    let flaps = flaps.into_iter().filter(|flap| {
+        let unchanged = flap.lock_mode() == Some(mode);
+        if unchanged {
+            debug!("{} is already {}", flap.name, mode);
+        }
+        !unchanged
+    });
+    let mut results = stream::iter(flaps)
+        .map(|flap| async move {
+            info!("Setting {} to {}", flap.name, mode);
+            let operation = PendingOperation::lock_mode(flap, mode);
+            let result = operation.apply(api_client, token).await;
+            (flap, operation, result)
+        })
+        .buffer_unordered(api_client.cfg().api.command_concurrency.max(1));
+    let mut failure = None;
+    while let Some((flap, operation, result)) = results.next().await {
+        match result {
+            Ok(()) => {}
+            // queued one at a time, as the queue is a single file
+            Err(e) if is_unreachable(&e) => {
+                info!("The API can't be reached, queued: {}", operation.describe());
+                Pending::add(operation)?;
+            }
+            Err(e) => match failure {
+                Some(_) => error!("Failed to set {} to {}: {}", flap.name, mode, e),
+                None => failure = Some(e),
+            },
+        }
+    }
+
+    match failure {
+        Some(e) => Err(Error::other(e)),
+        None => Ok(()),
+    }
+}
+
+fn time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+    let time = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&time, "%H:%M")
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn rules_path() -> std::io::Result<PathBuf> {
+    Ok(config::config_dir()?.join(RULES_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockClient;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn time(text: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(text, "%H:%M").unwrap()
+    }
+
+    /// Tom came in at 08:00 and Luna went out at 06:00.
+    fn pets() -> Vec<Pet> {
+        serde_json::from_str(
+            r#"[{"id": 1, "name": "Tom", "household_id": 9,
+                    "position": {"where": 1, "since": "2024-06-01T08:00:00Z"}},
+                {"id": 2, "name": "Luna", "household_id": 9,
+                    "position": {"where": 2, "since": "2024-06-01T06:00:00Z"}}]"#,
+        )
+        .unwrap()
+    }
+
+    fn devices(battery: f64) -> Vec<Device> {
+        serde_json::from_str(&format!(
+            r#"[{{"id": 3, "name": "Back door", "product_id": 6,
+                    "status": {{"online": true, "battery": {}}}}}]"#,
+            battery
+        ))
+        .unwrap()
+    }
+
+    fn rules(text: &str) -> Result<Rules, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    fn when(text: &str) -> Conditions {
+        toml::from_str(text).unwrap()
+    }
+
+    fn matches(conditions: &Conditions, now: &str, clock: &str) -> bool {
+        conditions.matches(&pets(), &devices(6.4), at(now), time(clock))
+    }
+
+    const NOON: &str = "2024-06-01T12:00:00Z";
+
+    #[test]
+    fn every_pet_named_must_be_where_the_rule_says() {
+        assert!(matches(&when(r#"inside = ["Tom"]"#), NOON, "12:00"));
+        assert!(matches(
+            &when(
+                r#"inside = ["tom"]
+                     outside = ["2"]"#
+            ),
+            NOON,
+            "12:00"
+        ));
+        assert!(!matches(
+            &when(r#"inside = ["Tom", "Luna"]"#),
+            NOON,
+            "12:00"
+        ));
+        // a pet that isn't in the household is nowhere
+        assert!(!matches(&when(r#"outside = ["Felix"]"#), NOON, "12:00"));
+        assert!(matches(&Conditions::default(), NOON, "12:00"));
+    }
+
+    #[test]
+    fn time_windows_include_their_start_and_may_run_past_midnight() {
+        let evening = when(
+            r#"after = "19:00"
+                              before = "23:00""#,
+        );
+        assert!(matches(&evening, NOON, "19:00"));
+        assert!(!matches(&evening, NOON, "23:00"));
+        assert!(!matches(&evening, NOON, "12:00"));
+
+        let night = when(
+            r#"after = "22:00"
+                            before = "06:00""#,
+        );
+        assert!(matches(&night, NOON, "23:30"));
+        assert!(matches(&night, NOON, "05:59"));
+        assert!(!matches(&night, NOON, "06:00"));
+        assert!(!matches(&night, NOON, "12:00"));
+
+        assert!(matches(&when(r#"before = "09:00""#), NOON, "08:59"));
+        assert!(!matches(&when(r#"after = "09:00""#), NOON, "08:59"));
+    }
+
+    #[test]
+    fn thresholds_need_a_device_or_pet_past_them() {
+        let low = when("battery_below = 20");
+        assert!(low.matches(&pets(), &devices(4.8), at(NOON), time("12:00")));
+        assert!(!low.matches(&pets(), &devices(6.4), at(NOON), time("12:00")));
+        // without a battery reading nothing is low
+        assert!(!low.matches(&pets(), &[], at(NOON), time("12:00")));
+
+        // Luna has been out since 06:00
+        let idle = when("inactive_hours = 6");
+        assert!(matches(&idle, NOON, "12:00"));
+        assert!(!matches(&idle, "2024-06-01T11:59:00Z", "11:59"));
+    }
+
+    #[test]
+    fn malformed_rules_are_refused() {
+        assert!(rules(
+            r#"[[rule]]
+               name = "Night"
+               when = { inside = ["Tom"], after = "19:00" }
+               then = [{ action = "lock", device = "Back door" }, { action = "unlock" }]"#
+        )
+        .is_ok());
+        let refused = [
+            // a misspelt condition would otherwise always hold
+            r#"[[rule]]
+               name = "Night"
+               when = { insde = ["Tom"] }
+               then = []"#,
+            r#"[[rule]]
+               name = "Night"
+               when = { after = "25:00" }
+               then = []"#,
+            r#"[[rule]]
+               name = "Night"
+               when = { after = "7pm" }
+               then = []"#,
+            r#"[[rule]]
+               name = "Night"
+               then = [{ action = "explode" }]"#,
+            r#"[[rule]]
+               name = "Night"
+               then = [{ action = "notify" }]"#,
+            r#"[[rule]]
+               name = "Night""#,
+        ];
+        for text in refused {
+            assert!(rules(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn actions_describe_themselves() {
+        let rules = rules(
+            r#"[[rule]]
+               name = "All"
+               then = [
+                   { action = "lock" },
+                   { action = "unlock", device = "Back door" },
+                   { action = "notify", message = "In" },
+               ]"#,
+        )
+        .unwrap();
+        let described: Vec<String> = rules.rules[0].then.iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            described,
+            ["lock every flap", "unlock Back door", "notify \"In\""]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_rule_is_remembered_as_matching_until_it_stops() {
+        let api_client = MockClient::new(config::built_in(), PathBuf::from("/nonexistent"));
+        let rules = rules(
+            r#"[[rule]]
+               name = "Tom is in"
+               when = { inside = ["Tom"] }
+               then = []"#,
+        )
+        .unwrap();
+        let mut engine = RuleEngine::default();
+        let mut inside = pets();
+        engine.evaluate(&rules, &api_client, "", &inside, &[]).await;
+        assert!(engine.matching.contains("Tom is in"));
+
+        inside[0] = serde_json::from_str(
+            r#"{"id": 1, "name": "Tom", "household_id": 9,
+                "position": {"where": 2, "since": "2024-06-01T13:00:00Z"}}"#,
+        )
+        .unwrap();
+        engine.evaluate(&rules, &api_client, "", &inside, &[]).await;
+        assert!(!engine.matching.contains("Tom is in"));
+    }
+
+    #[test]
+    fn a_saved_search_notifies_when_its_results_change() {
+        let search = SavedSearch {
+            name: "Low".to_string(),
+            patterns: vec!["battery<20".to_string()],
+            mode: MatchMode::Substring,
+            every_mins: 0,
+        };
+        let mut engine = RuleEngine::default();
+        // nothing matching at first isn't news
+        assert!(engine.run_search(&search, &[], &devices(6.4)).is_none());
+        let found = engine.run_search(&search, &[], &devices(4.8)).unwrap();
+        assert_eq!(found.message, "Low now matches Back door");
+        assert!(engine.run_search(&search, &[], &devices(4.8)).is_none());
+        let cleared = engine.run_search(&search, &[], &devices(6.4)).unwrap();
+        assert_eq!(cleared.message, "Low no longer matches Back door");
+
+        let broken = SavedSearch {
+            name: "Broken".to_string(),
+            patterns: vec!["(".to_string()],
+            mode: MatchMode::Regex,
+            every_mins: 0,
+        };
+        assert!(engine.run_search(&broken, &[], &devices(4.8)).is_none());
+    }
+}
@@ -0,0 +1,260 @@
+use crate::api::client::Device;
+use crate::api::SurePetApi;
+use crate::rules::{self, Action};
+use crate::units::Units;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc, Weekday};
+use log::{error, info};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+const WEEKDAYS: [Weekday; 5] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+];
+const WEEKEND: [Weekday; 2] = [Weekday::Sat, Weekday::Sun];
+
+/// Actions the daemon takes at a time of day, for flaps without enough
+/// curfews of their own. Read from `[[schedule]]` tables in rules.toml.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub name: String,
+    /// Time of day in the [units] timezone, as HH:MM
+    #[serde(deserialize_with = "time_of_day")]
+    pub at: NaiveTime,
+    /// "daily", "weekdays", "weekends", or a list such as ["sat", "sun"].
+    /// Every day when left out.
+    #[serde(default, deserialize_with = "days")]
+    pub days: Vec<Weekday>,
+    pub then: Vec<Action>,
+}
+
+impl Schedule {
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        self.days.is_empty() || self.days.contains(&date.weekday())
+    }
+
+    /// When the schedule runs on `date`, an hour late where the clocks
+    /// going forward skip its time.
+    fn on(&self, date: NaiveDate, units: &Units) -> Option<DateTime<Utc>> {
+        if !self.runs_on(date) {
+            return None;
+        }
+        units.at(date, self.at)
+    }
+
+    /// The first time the schedule runs after `time`, within a week.
+    pub fn next_after(&self, time: DateTime<Utc>, units: &Units) -> Option<DateTime<Utc>> {
+        let today = units.day_of(&time);
+        (0..=7)
+            .filter_map(|days| self.on(today + Duration::days(days), units))
+            .find(|at| *at > time)
+    }
+
+    /// Its days as written in rules.toml.
+    pub fn days_text(&self) -> String {
+        match self.days.as_slice() {
+            [] => "daily".to_string(),
+            days if days == WEEKDAYS => "weekdays".to_string(),
+            days if days == WEEKEND => "weekends".to_string(),
+            days => days
+                .iter()
+                .map(|d| d.to_string().to_lowercase())
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+}
+
+/// Runs each schedule once when its time comes round between one poll and
+/// the next. A time missed while the API couldn't be polled is caught up
+/// on the next successful poll, once however many were missed.
+pub struct Scheduler {
+    last_run: DateTime<Utc>,
+}
+
+impl Default for Scheduler {
+    /// Starting now, so times already passed today don't run at startup
+    fn default() -> Self {
+        Scheduler {
+            last_run: Utc::now(),
+        }
+    }
+}
+
+impl Scheduler {
+    pub async fn run(
+        &mut self,
+        schedules: &[Schedule],
+        api_client: &impl SurePetApi,
+        token: &str,
+        devices: &[Device],
+    ) {
+        let units = &api_client.cfg().units;
+        let now = Utc::now();
+        let mut due: Vec<(DateTime<Utc>, &Schedule)> = schedules
+            .iter()
+            .filter_map(|schedule| {
+                // the latest time missed, so a lock and unlock both missed
+                // still run in order and leave the flap as the later one says
+                let mut date = units.day_of(&now);
+                while date >= units.day_of(&self.last_run) {
+                    let at = schedule.on(date, units);
+                    if let Some(at) = at.filter(|at| *at > self.last_run && *at <= now) {
+                        return Some((at, schedule));
+                    }
+                    date -= Duration::days(1);
+                }
+                None
+            })
+            .collect();
+        due.sort_by_key(|(at, _)| *at);
+        self.last_run = now;
+
+        for (_, schedule) in due {
+            info!("Schedule {} is due", schedule.name);
+            for action in &schedule.then {
+                if let Err(e) =
+                    rules::run_action(action, api_client, token, devices, &schedule.name).await
+                {
+                    error!("schedule {} failed: {}", schedule.name, e);
+                }
+            }
+        }
+    }
+}
+
+fn time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let time = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&time, "%H:%M").map_err(D::Error::custom)
+}
+
+fn days<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Weekday>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Days {
+        Named(String),
+        List(Vec<String>),
+    }
+
+    let names = match Days::deserialize(deserializer)? {
+        Days::Named(name) => match name.to_ascii_lowercase().as_str() {
+            "daily" => return Ok(Vec::new()),
+            "weekdays" => return Ok(WEEKDAYS.to_vec()),
+            "weekends" => return Ok(WEEKEND.to_vec()),
+            _ => vec![name],
+        },
+        Days::List(names) => names,
+    };
+    names
+        .iter()
+        .map(|name| {
+            name.parse().map_err(|_| {
+                D::Error::custom(format!(
+                    "expected daily, weekdays, weekends or days such as mon, not {}",
+                    name
+                ))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn london() -> Units {
+        let mut units = config::built_in().units;
+        units.timezone = Some(chrono_tz::Europe::London);
+        units
+    }
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn schedule(toml: &str) -> Schedule {
+        let rules: rules::Rules = toml::from_str(toml).unwrap();
+        rules.schedules.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn days_take_names_and_lists() {
+        let days = |days: &str| {
+            schedule(&format!(
+                "[[schedule]]\nname = \"s\"\nat = \"22:00\"\ndays = {}\nthen = []",
+                days
+            ))
+            .days
+        };
+        assert_eq!(days("\"daily\""), []);
+        assert_eq!(days("\"Weekdays\""), WEEKDAYS);
+        assert_eq!(days("\"weekends\""), WEEKEND);
+        assert_eq!(days("[\"sat\", \"Sun\"]"), WEEKEND);
+        assert_eq!(days("\"mon\""), [Weekday::Mon]);
+        let bad = "[[schedule]]\nname = \"s\"\nat = \"22:00\"\ndays = \"someday\"\nthen = []";
+        assert!(toml::from_str::<rules::Rules>(bad).is_err());
+    }
+
+    #[test]
+    fn days_text_gives_them_back_as_written() {
+        let with_days = |days: Vec<Weekday>| Schedule {
+            name: "s".to_string(),
+            at: NaiveTime::MIN,
+            days,
+            then: Vec::new(),
+        };
+        assert_eq!(with_days(Vec::new()).days_text(), "daily");
+        assert_eq!(with_days(WEEKDAYS.to_vec()).days_text(), "weekdays");
+        assert_eq!(with_days(WEEKEND.to_vec()).days_text(), "weekends");
+        let days = vec![Weekday::Mon, Weekday::Wed];
+        assert_eq!(with_days(days).days_text(), "mon, wed");
+    }
+
+    #[test]
+    fn next_after_is_in_the_units_timezone() {
+        let units = london();
+        let nightly = schedule("[[schedule]]\nname = \"s\"\nat = \"22:00\"\nthen = []");
+        // 22:00 summer time
+        let monday = at("2024-06-10T12:00:00Z");
+        assert_eq!(
+            nightly.next_after(monday, &units),
+            Some(at("2024-06-10T21:00:00Z"))
+        );
+        // exactly at the time, so the next is tomorrow's
+        let due = at("2024-06-10T21:00:00Z");
+        assert_eq!(
+            nightly.next_after(due, &units),
+            Some(at("2024-06-11T21:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn next_after_skips_days_it_doesnt_run() {
+        let units = london();
+        let weekend =
+            schedule("[[schedule]]\nname = \"s\"\nat = \"08:00\"\ndays = \"weekends\"\nthen = []");
+        // a Monday, so Saturday morning
+        let monday = at("2024-06-10T12:00:00Z");
+        assert_eq!(
+            weekend.next_after(monday, &units),
+            Some(at("2024-06-15T07:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn a_time_the_clocks_skip_runs_an_hour_late() {
+        let units = london();
+        let early = schedule("[[schedule]]\nname = \"s\"\nat = \"01:30\"\nthen = []");
+        // there's no 01:30 on the 31st of March 2024, so 02:30 summer time
+        let before = at("2024-03-30T12:00:00Z");
+        assert_eq!(
+            early.next_after(before, &units),
+            Some(at("2024-03-31T01:30:00Z"))
+        );
+    }
+}
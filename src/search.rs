@@ -0,0 +1,483 @@
+use crate::api::client::{Device, Pet};
+use crate::filter::Filter;
+use clap::ValueEnum;
+use console::style;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::ops::Range;
+
+/// How text patterns are matched against names and states.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    /// The pattern appears as is, ignoring case
+    #[default]
+    Substring,
+    /// The pattern is a regular expression, ignoring case
+    Regex,
+    /// The pattern's letters appear in order, best matches first
+    Fuzzy,
+}
+
+/// What devices can be ordered by.
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceSort {
+    Name,
+    /// Battery percentage, lowest first
+    Battery,
+    /// Offline devices first
+    Online,
+    /// Device signal strength, weakest first
+    Signal,
+}
+
+/// A pet or device that matched a search.
+pub struct SearchHit {
+    pub kind: &'static str,
+    pub id: u32,
+    pub name: String,
+    /// The matched fields with the matching parts highlighted
+    pub line: String,
+}
+
+/// Search patterns ready to match, any of which may. A pattern is text to
+/// find in a name or state, or a battery comparison such as `battery<20`.
+/// Without patterns everything the filter allows matches.
+pub struct Query {
+    mode: MatchMode,
+    text: Vec<String>,
+    regexes: Vec<Regex>,
+    comparisons: Vec<BatteryTerm>,
+    filter: Option<Filter>,
+}
+
+impl Query {
+    /// Fails when a pattern isn't a valid regular expression in
+    /// [`MatchMode::Regex`].
+    pub fn new(patterns: &[String], mode: MatchMode) -> Result<Self, regex::Error> {
+        let mut text = Vec::new();
+        let mut comparisons = Vec::new();
+        for pattern in patterns {
+            match BatteryTerm::parse(pattern) {
+                Some(term) => comparisons.push(term),
+                None => text.push(pattern.clone()),
+            }
+        }
+        let regexes = match mode {
+            MatchMode::Regex => text
+                .iter()
+                .map(|p| RegexBuilder::new(p).case_insensitive(true).build())
+                .collect::<Result<_, _>>()?,
+            _ => Vec::new(),
+        };
+        Ok(Query {
+            mode,
+            text,
+            regexes,
+            comparisons,
+            filter: None,
+        })
+    }
+
+    /// Only match pets and devices that `filter` allows as well.
+    pub fn filtered(mut self, filter: Option<Filter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.comparisons.is_empty()
+    }
+
+    /// Where the patterns match `text`, and how well for fuzzy matching.
+    fn find(&self, text: &str) -> (Vec<Range<usize>>, u32) {
+        match self.mode {
+            MatchMode::Substring => (find_matches(text, &self.text), 0),
+            MatchMode::Regex => {
+                let ranges = self
+                    .regexes
+                    .iter()
+                    .flat_map(|r| r.find_iter(text))
+                    .filter(|m| !m.is_empty())
+                    .map(|m| m.range())
+                    .collect();
+                (merge(ranges), 0)
+            }
+            MatchMode::Fuzzy => {
+                let (mut ranges, mut score) = (Vec::new(), 0);
+                for pattern in &self.text {
+                    if let Some((found, points)) = fuzzy_match(text, pattern) {
+                        ranges.extend(found);
+                        score = score.max(points);
+                    }
+                }
+                (merge(ranges), score)
+            }
+        }
+    }
+}
+
+/// Patterns and filter as one line, such as `cat or dog where kind==pet`.
+pub fn describe(patterns: &[String], filter: Option<&Filter>) -> String {
+    match (patterns.is_empty(), filter) {
+        (_, None) => patterns.join(" or "),
+        (true, Some(filter)) => filter.to_string(),
+        (false, Some(filter)) => format!("{} where {}", patterns.join(" or "), filter),
+    }
+}
+
+/// Match pets and devices against the query. Fuzzy matches come best first,
+/// everything else pets first in the API's order.
+pub fn search_state(pets: &[Pet], devices: &[Device], query: &Query) -> Vec<SearchHit> {
+    // name and state are matched separately so highlighting stays within each
+    let found = |fields: &[String], compared: Option<usize>| {
+        let (mut matches, scores): (Vec<_>, Vec<_>) = fields.iter().map(|f| query.find(f)).unzip();
+        if let Some(field) = compared {
+            // a comparison matches the whole field
+            matches[field] = std::iter::once(0..fields[field].len()).collect();
+        }
+        let any = query.is_empty() || matches.iter().any(|m| !m.is_empty());
+        any.then(|| {
+            let line = fields
+                .iter()
+                .zip(&matches)
+                .map(|(f, m)| highlight(f, m))
+                .collect::<Vec<_>>()
+                .join("  ");
+            (line, scores.into_iter().max().unwrap_or_default())
+        })
+    };
+
+    let mut hits = Vec::new();
+    let filter = query.filter.as_ref();
+    for pet in pets
+        .iter()
+        .filter(|p| filter.is_none_or(|f| f.matches_pet(p)))
+    {
+        let location = pet
+            .position
+            .as_ref()
+            .map(|p| p.location.to_string())
+            .unwrap_or_default();
+        if let Some((line, score)) = found(&[pet.name.clone(), location], None) {
+            let hit = SearchHit {
+                kind: "pet",
+                id: pet.id,
+                name: pet.name.clone(),
+                line,
+            };
+            hits.push((score, hit));
+        }
+    }
+    for device in devices
+        .iter()
+        .filter(|d| filter.is_none_or(|f| f.matches_device(d)))
+    {
+        let online = match device.online() {
+            Some(true) => "online".to_string(),
+            Some(false) => "offline".to_string(),
+            None => String::new(),
+        };
+        let lock = device
+            .lock_mode()
+            .map(|m| m.to_string())
+            .unwrap_or_default();
+        let level = device.battery_percent();
+        let battery = level.map(|b| format!("{}%", b)).unwrap_or_default();
+        let compared = level.is_some_and(|b| query.comparisons.iter().any(|t| t.matches(b)));
+        let fields = [device.name.clone(), online, lock, battery];
+        if let Some((line, score)) = found(&fields, compared.then_some(3)) {
+            let hit = SearchHit {
+                kind: "device",
+                id: device.id,
+                name: device.name.clone(),
+                line,
+            };
+            hits.push((score, hit));
+        }
+    }
+    // stable, so equal scores and non-fuzzy matches keep their order
+    hits.sort_by_key(|(score, _)| Reverse(*score));
+    hits.into_iter().map(|(_, hit)| hit).collect()
+}
+
+/// A comparison against a device's battery percentage.
+struct BatteryTerm {
+    below: bool,
+    percent: u8,
+}
+
+impl BatteryTerm {
+    fn parse(pattern: &str) -> Option<Self> {
+        let rest = pattern.strip_prefix("battery")?;
+        let (below, value) = match rest.split_at_checked(1)? {
+            ("<", value) => (true, value),
+            (">", value) => (false, value),
+            _ => return None,
+        };
+        Some(BatteryTerm {
+            below,
+            percent: value.trim().parse().ok()?,
+        })
+    }
+
+    fn matches(&self, level: u8) -> bool {
+        if self.below {
+            level < self.percent
+        } else {
+            level > self.percent
+        }
+    }
+}
+
+/// Where any of `patterns` occur in `text`, ignoring ASCII case, merged
+/// into non-overlapping ranges in order.
+pub fn find_matches(text: &str, patterns: &[String]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for pattern in patterns.iter().filter(|p| !p.is_empty()) {
+        for (start, _) in text.char_indices() {
+            let end = start + pattern.len();
+            if text.is_char_boundary(end) && text[start..end].eq_ignore_ascii_case(pattern) {
+                ranges.push(start..end);
+            }
+        }
+    }
+
+    merge(ranges)
+}
+
+/// `ranges` in order, with those that overlap or touch joined.
+fn merge(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Where `pattern`'s characters appear in order in `text`, ignoring case,
+/// and a score in the style of skim: points for each character, more for
+/// runs and word starts, fewer for gaps. The best of the alignments that
+/// start at each place the first character appears is kept.
+fn fuzzy_match(text: &str, pattern: &str) -> Option<(Vec<Range<usize>>, u32)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let wanted: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let first = *wanted.first()?;
+    let same = |c: char, w: char| c.to_lowercase().eq(std::iter::once(w));
+
+    let mut best: Option<(Vec<Range<usize>>, u32)> = None;
+    for start in (0..chars.len()).filter(|&i| same(chars[i].1, first)) {
+        let mut ranges = Vec::new();
+        let mut score: i64 = 0;
+        let mut previous: Option<usize> = None;
+        let mut next = wanted.iter();
+        let mut want = next.next();
+        for (i, &(at, c)) in chars.iter().enumerate().skip(start) {
+            let Some(&w) = want else {
+                break;
+            };
+            if !same(c, w) {
+                continue;
+            }
+            score += 16;
+            let word_start = i == 0 || !chars[i - 1].1.is_alphanumeric();
+            if word_start {
+                score += 8;
+            }
+            match previous {
+                Some(p) if p + 1 == i => score += 12,
+                Some(p) => score -= (i - p - 1) as i64,
+                None => {}
+            }
+            ranges.push(at..at + c.len_utf8());
+            previous = Some(i);
+            want = next.next();
+        }
+        if want.is_some() {
+            // later starts can only have fewer characters left to match in
+            break;
+        }
+        let score = score.max(1) as u32;
+        if best.as_ref().is_none_or(|(_, b)| score > *b) {
+            best = Some((ranges, score));
+        }
+    }
+    best
+}
+
+/// `text` with the matched ranges underlined and bold, so it is obvious
+/// why a result matched. Styling is dropped when output isn't a terminal.
+pub fn highlight(text: &str, matches: &[Range<usize>]) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    for range in matches {
+        out.push_str(&text[pos..range.start]);
+        out.push_str(&style(&text[range.clone()]).bold().underlined().to_string());
+        pos = range.end;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+/// Order devices by `by`, ties by name. Devices without the value, such as a
+/// feeder's signal when it hasn't reported one, go last either way.
+pub fn sort_devices(devices: &mut [Device], by: DeviceSort, descending: bool) {
+    let value = |device: &Device| match by {
+        DeviceSort::Name => None,
+        DeviceSort::Battery => device.battery_percent().map(f64::from),
+        DeviceSort::Online => device.online().map(|o| if o { 1.0 } else { 0.0 }),
+        DeviceSort::Signal => device.signal().and_then(|s| s.device_rssi),
+    };
+    let name = |device: &Device| device.name.to_lowercase();
+    devices.sort_by(|a, b| {
+        let order = match by {
+            DeviceSort::Name => name(a).cmp(&name(b)),
+            _ => match (value(a), value(b)) {
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+        let order = if descending { order.reverse() } else { order };
+        order.then_with(|| name(a).cmp(&name(b)))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pets() -> Vec<Pet> {
+        serde_json::from_str(
+            r#"[{"id": 1, "name": "Tom", "household_id": 9,
+                    "position": {"where": 2, "since": "2026-10-16T08:00:00Z"}},
+                {"id": 2, "name": "Bottom", "household_id": 9}]"#,
+        )
+        .unwrap()
+    }
+
+    /// A hub, which has no battery, and two flaps with low and full ones.
+    fn devices() -> Vec<Device> {
+        serde_json::from_str(
+            r#"[{"id": 3, "name": "Hub", "product_id": 1, "status": {"online": false}},
+                {"id": 4, "name": "Back door", "product_id": 6,
+                    "status": {"online": true, "battery": 4.8}},
+                {"id": 5, "name": "Attic door", "product_id": 6,
+                    "status": {"online": true, "battery": 6.4}}]"#,
+        )
+        .unwrap()
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn names(pets: &[Pet], devices: &[Device], query: &Query) -> Vec<String> {
+        search_state(pets, devices, query)
+            .into_iter()
+            .map(|hit| hit.name)
+            .collect()
+    }
+
+    #[test]
+    fn text_matches_ignore_case_and_merge() {
+        let found = |text, wanted: &[&str]| -> Vec<(usize, usize)> {
+            find_matches(text, &patterns(wanted))
+                .into_iter()
+                .map(|r| (r.start, r.end))
+                .collect()
+        };
+        assert_eq!(found("Back door", &["DOOR"]), [(5, 9)]);
+        assert_eq!(found("aaaa", &["aa"]), [(0, 4)]);
+        assert_eq!(found("door", &["do", "or"]), [(0, 4)]);
+        assert!(found("Hub", &[""]).is_empty());
+        // a match never splits a character
+        assert_eq!(found("Küche", &["che"]), [(3, 6)]);
+    }
+
+    #[test]
+    fn fuzzy_matches_take_letters_in_order_and_rank_word_starts() {
+        let (ranges, _) = fuzzy_match("Back door", "bdr").unwrap();
+        assert_eq!(ranges, [0..1, 5..6, 8..9]);
+        assert!(fuzzy_match("Back door", "rdb").is_none());
+        assert!(fuzzy_match("Back door", "").is_none());
+        let score = |text| fuzzy_match(text, "tom").unwrap().1;
+        assert!(score("Tom") > score("Bottom"));
+
+        let query = Query::new(&patterns(&["tom"]), MatchMode::Fuzzy).unwrap();
+        assert_eq!(names(&pets(), &[], &query), ["Tom", "Bottom"]);
+    }
+
+    #[test]
+    fn a_bad_regex_is_refused_only_in_regex_mode() {
+        let broken = patterns(&["(door"]);
+        assert!(Query::new(&broken, MatchMode::Regex).is_err());
+        assert!(Query::new(&broken, MatchMode::Substring).is_ok());
+        let query = Query::new(&patterns(&["^b.*r$"]), MatchMode::Regex).unwrap();
+        assert_eq!(names(&pets(), &devices(), &query), ["Back door"]);
+    }
+
+    #[test]
+    fn battery_comparisons_only_match_devices_with_a_battery() {
+        let query = Query::new(&patterns(&["battery<50"]), MatchMode::Substring).unwrap();
+        assert_eq!(names(&pets(), &devices(), &query), ["Back door"]);
+        let query = Query::new(&patterns(&["battery>50"]), MatchMode::Substring).unwrap();
+        assert_eq!(names(&pets(), &devices(), &query), ["Attic door"]);
+        // anything else that starts with battery is searched for as text
+        for text in ["battery<", "battery=20", "battery<low", "battery<300"] {
+            assert!(BatteryTerm::parse(text).is_none(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn no_patterns_match_whatever_the_filter_allows() {
+        let query = Query::new(&[], MatchMode::Substring).unwrap();
+        assert_eq!(names(&pets(), &devices(), &query).len(), 5);
+        let filter = Filter::parse("kind==device and online==false").unwrap();
+        let query = query.filtered(Some(filter));
+        assert_eq!(names(&pets(), &devices(), &query), ["Hub"]);
+    }
+
+    #[test]
+    fn devices_without_the_value_sort_last_either_way() {
+        let order = |by, descending| {
+            let mut devices = devices();
+            sort_devices(&mut devices, by, descending);
+            devices.into_iter().map(|d| d.name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            order(DeviceSort::Battery, false),
+            ["Back door", "Attic door", "Hub"]
+        );
+        assert_eq!(
+            order(DeviceSort::Battery, true),
+            ["Attic door", "Back door", "Hub"]
+        );
+        assert_eq!(
+            order(DeviceSort::Name, false),
+            ["Attic door", "Back door", "Hub"]
+        );
+        assert_eq!(
+            order(DeviceSort::Online, false),
+            ["Hub", "Attic door", "Back door"]
+        );
+    }
+
+    #[test]
+    fn describe_joins_patterns_and_filter() {
+        let filter = Filter::parse("kind==pet").unwrap();
+        assert_eq!(describe(&patterns(&["cat", "dog"]), None), "cat or dog");
+        assert_eq!(describe(&[], Some(&filter)), "kind==pet");
+        assert_eq!(
+            describe(&patterns(&["cat"]), Some(&filter)),
+            "cat where kind==pet"
+        );
+    }
+}
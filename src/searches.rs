@@ -0,0 +1,98 @@
+use crate::config;
+use crate::filter::Filter;
+use crate::search::MatchMode;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+
+const SEARCHES_FILE: &str = "searches.toml";
+
+/// Search patterns saved under a name to run again later.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedSearch {
+    pub name: String,
+    /// Patterns as given to the search command, any of which may match
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub mode: MatchMode,
+    /// Expression that pets and devices must also match, from `--query`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<Filter>,
+    #[serde(default)]
+    pub uses: u32,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+/// Searches saved with `search --save` or from the interactive search, kept
+/// apart from the scheduled searches in rules.toml.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SavedSearches {
+    #[serde(default, rename = "search")]
+    pub searches: Vec<SavedSearch>,
+}
+
+impl SavedSearches {
+    pub fn load() -> std::io::Result<Self> {
+        let path = searches_path()?;
+        if !path.exists() {
+            return Ok(SavedSearches::default());
+        }
+        toml::from_str(&fs::read_to_string(path)?).map_err(Error::other)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = toml::to_string_pretty(self).map_err(Error::other)?;
+        fs::write(searches_path()?, contents)
+    }
+
+    /// Save `patterns` as `name`, replacing any search already called that.
+    pub fn add(
+        &mut self,
+        name: &str,
+        patterns: &[String],
+        mode: MatchMode,
+        query: Option<&Filter>,
+    ) {
+        self.remove(name);
+        self.searches.push(SavedSearch {
+            name: name.to_string(),
+            patterns: patterns.to_vec(),
+            mode,
+            query: query.cloned(),
+            uses: 0,
+            last_used: None,
+        });
+    }
+
+    /// Returns whether there was a search called `name`.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.searches.len();
+        self.searches.retain(|s| !s.name.eq_ignore_ascii_case(name));
+        self.searches.len() != before
+    }
+
+    /// The search called `name`, counted as used now.
+    pub fn take(&mut self, name: &str) -> Option<&SavedSearch> {
+        let search = self
+            .searches
+            .iter_mut()
+            .find(|s| s.name.eq_ignore_ascii_case(name))?;
+        search.uses += 1;
+        search.last_used = Some(Utc::now());
+        Some(search)
+    }
+
+    /// Most used first, then most recently used.
+    pub fn by_use(&self) -> Vec<&SavedSearch> {
+        let mut searches: Vec<_> = self.searches.iter().collect();
+        searches.sort_by_key(|s| Reverse((s.uses, s.last_used)));
+        searches
+    }
+}
+
+fn searches_path() -> std::io::Result<PathBuf> {
+    Ok(config::config_dir()?.join(SEARCHES_FILE))
+}
@@ -0,0 +1,143 @@
+use crate::api::client::Device;
+use console::style;
+use std::collections::VecDeque;
+
+/// The weakest and strongest signal a plot spans, in dBm. Fixed rather than
+/// scaled to what was seen, so moving the hub shows as bars rising.
+const PLOT_FLOOR: f64 = -100.0;
+const PLOT_CEILING: f64 = -40.0;
+/// The least signal each quality needs, in dBm, strongest first.
+const QUALITIES: [(f64, &str); 3] = [(-65.0, "strong"), (-80.0, "good"), (-90.0, "weak")];
+
+/// One poll of a device's signal, each side missing when not reported.
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub device_rssi: Option<f64>,
+    pub hub_rssi: Option<f64>,
+}
+
+/// The signal devices reported on recent polls, newest last.
+pub struct SignalHistory {
+    devices: Vec<(u32, String, VecDeque<Sample>)>,
+    /// Samples kept per device, the width of the plot
+    keep: usize,
+}
+
+impl SignalHistory {
+    pub fn new(keep: usize) -> Self {
+        SignalHistory {
+            devices: Vec::new(),
+            keep: keep.max(1),
+        }
+    }
+
+    /// Add a poll's signal for each of `devices` that reports one.
+    pub fn record(&mut self, devices: &[&Device]) {
+        for device in devices {
+            let Some(signal) = device.signal() else {
+                continue;
+            };
+            let sample = Sample {
+                device_rssi: signal.device_rssi,
+                hub_rssi: signal.hub_rssi,
+            };
+            let index = match self.devices.iter().position(|(id, ..)| *id == device.id) {
+                Some(index) => index,
+                None => {
+                    self.devices
+                        .push((device.id, device.name.clone(), VecDeque::new()));
+                    self.devices.len() - 1
+                }
+            };
+            let samples = &mut self.devices[index].2;
+            samples.push_back(sample);
+            if samples.len() > self.keep {
+                samples.pop_front();
+            }
+        }
+    }
+
+    /// A plot for each device of how well it hears the hub and the hub hears
+    /// it, with the latest reading and the range seen.
+    pub fn render(&self) -> String {
+        if self.devices.is_empty() {
+            return "No devices have reported their signal yet".to_string();
+        }
+        let mut out = String::new();
+        for (_, name, samples) in &self.devices {
+            out.push_str(&format!("{}\n", style(name).bold()));
+            let device: Vec<Option<f64>> = samples.iter().map(|s| s.device_rssi).collect();
+            let hub: Vec<Option<f64>> = samples.iter().map(|s| s.hub_rssi).collect();
+            for (label, values) in [("Device", device), ("Hub", hub)] {
+                out.push_str(&format!(
+                    "  {:<7} {:<width$} {}\n",
+                    label,
+                    plot(&values),
+                    reading(&values),
+                    width = self.keep
+                ));
+            }
+        }
+        out
+    }
+
+    /// The latest readings on one line, for output that isn't a terminal.
+    pub fn latest(&self) -> Vec<String> {
+        self.devices
+            .iter()
+            .filter_map(|(_, name, samples)| {
+                let sample = samples.back()?;
+                Some(format!(
+                    "{}: device {}, hub {}",
+                    name,
+                    dbm(sample.device_rssi),
+                    dbm(sample.hub_rssi)
+                ))
+            })
+            .collect()
+    }
+}
+
+/// How usable a signal is, from strong to poor.
+pub fn quality(rssi: f64) -> &'static str {
+    QUALITIES
+        .iter()
+        .find(|(least, _)| rssi >= *least)
+        .map_or("poor", |(_, quality)| quality)
+}
+
+/// One block per sample between [PLOT_FLOOR] and [PLOT_CEILING], blank
+/// where nothing was reported.
+fn plot(values: &[Option<f64>]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    values
+        .iter()
+        .map(|value| match value {
+            Some(rssi) => {
+                let height = (rssi - PLOT_FLOOR) / (PLOT_CEILING - PLOT_FLOOR) * 8.0;
+                BLOCKS[(height.ceil() as usize).clamp(1, 8) - 1]
+            }
+            None => ' ',
+        })
+        .collect()
+}
+
+fn reading(values: &[Option<f64>]) -> String {
+    let seen: Vec<f64> = values.iter().flatten().copied().collect();
+    let Some(latest) = values.last().copied().flatten() else {
+        return "-".to_string();
+    };
+    let min = seen.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = seen.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    format!(
+        "{:.0} dBm {} (range {:.0} to {:.0})",
+        latest,
+        quality(latest),
+        min,
+        max
+    )
+}
+
+fn dbm(rssi: Option<f64>) -> String {
+    rssi.map_or("-".to_string(), |r| format!("{:.0} dBm", r))
+}
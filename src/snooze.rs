@@ -0,0 +1,169 @@
+use crate::api::client::{is_unreachable_io, Curfew, Device, LockMode};
+use crate::api::SurePetApi;
+use crate::config;
+use crate::pending::{self, PendingOperation, Sent};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+
+const SNOOZES_FILE: &str = "snoozes.toml";
+
+/// A flap unlocked for a while, and what to put back when the time is up.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snooze {
+    pub device_id: u32,
+    pub device: String,
+    pub until: DateTime<Utc>,
+    /// The lock mode to go back to, missing when the flap was unlocked
+    pub lock_mode: Option<LockMode>,
+    /// Curfews turned off meanwhile, to turn back on
+    #[serde(default, rename = "curfew")]
+    pub curfews: Vec<Curfew>,
+}
+
+impl Snooze {
+    /// What the flap goes back to.
+    pub fn describe(&self) -> String {
+        let mut restores = Vec::new();
+        if let Some(mode) = self.lock_mode {
+            restores.push(mode.to_string());
+        }
+        for curfew in &self.curfews {
+            restores.push(format!(
+                "curfew {} to {}",
+                curfew.lock_time, curfew.unlock_time
+            ));
+        }
+        restores.join(", ")
+    }
+}
+
+/// Snoozed flaps, kept in `snoozes.toml` in the config directory so the
+/// daemon can restore them.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Snoozes {
+    #[serde(default, rename = "snooze")]
+    pub snoozes: Vec<Snooze>,
+}
+
+impl Snoozes {
+    pub fn load() -> std::io::Result<Self> {
+        let path = snoozes_path()?;
+        if !path.exists() {
+            return Ok(Snoozes::default());
+        }
+        toml::from_str(&fs::read_to_string(path)?).map_err(Error::other)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = toml::to_string_pretty(self).map_err(Error::other)?;
+        fs::write(snoozes_path()?, contents)
+    }
+
+    /// The snooze already running on a device, whose saved state is what
+    /// a longer snooze must still restore.
+    pub fn take(&mut self, device_id: u32) -> Option<Snooze> {
+        let index = self.snoozes.iter().position(|s| s.device_id == device_id)?;
+        Some(self.snoozes.remove(index))
+    }
+
+    /// Put back each flap whose snooze is over, leaving those the API
+    /// can't be reached for until next time. Returns whether any changed.
+    pub async fn restore_due(
+        &mut self,
+        api_client: &impl SurePetApi,
+        token: &str,
+        devices: &[Device],
+        now: DateTime<Utc>,
+    ) -> bool {
+        let mut changed = false;
+        let mut remaining = Vec::new();
+        for snooze in self.snoozes.drain(..) {
+            if snooze.until > now {
+                remaining.push(snooze);
+                continue;
+            }
+            changed = true;
+            let Some(device) = devices.iter().find(|d| d.id == snooze.device_id) else {
+                error!(
+                    "{} is no longer in the household, dropping its snooze",
+                    snooze.device
+                );
+                continue;
+            };
+            match restore(api_client, token, device, &snooze).await {
+                Ok(Sent::Queued) => info!(
+                    "Snooze on {} is over, {} once the API is back",
+                    device.name,
+                    snooze.describe()
+                ),
+                Ok(Sent::Applied) => info!(
+                    "Snooze on {} is over, back to {}",
+                    device.name,
+                    snooze.describe()
+                ),
+                Err(e) if is_unreachable_io(&e) => remaining.push(snooze),
+                Err(e) => error!("Failed to restore {} after its snooze: {}", device.name, e),
+            }
+        }
+        self.snoozes = remaining;
+        changed
+    }
+}
+
+/// Turn off the curfews a snooze saved and unlock the flap, queueing the
+/// unlock if the API can't be reached by then.
+pub async fn apply(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device: &Device,
+    snooze: &Snooze,
+) -> std::io::Result<Sent> {
+    if !snooze.curfews.is_empty() {
+        let off: Vec<Curfew> = snooze
+            .curfews
+            .iter()
+            .map(|c| Curfew {
+                enabled: false,
+                ..c.clone()
+            })
+            .collect();
+        api_client
+            .set_curfew(token, device, &off)
+            .await
+            .map_err(Error::other)?;
+    }
+    if snooze.lock_mode.is_some() {
+        let operation = PendingOperation::lock_mode(device, LockMode::Unlocked);
+        return pending::apply_or_queue(api_client, token, operation).await;
+    }
+    Ok(Sent::Applied)
+}
+
+/// Put back what a snooze saved, queueing the lock mode if the API can't be
+/// reached by then.
+pub async fn restore(
+    api_client: &impl SurePetApi,
+    token: &str,
+    device: &Device,
+    snooze: &Snooze,
+) -> std::io::Result<Sent> {
+    if !snooze.curfews.is_empty() {
+        api_client
+            .set_curfew(token, device, &snooze.curfews)
+            .await
+            .map_err(Error::other)?;
+    }
+    if let Some(mode) = snooze.lock_mode {
+        let operation = PendingOperation::lock_mode(device, mode);
+        return pending::apply_or_queue(api_client, token, operation).await;
+    }
+    Ok(Sent::Applied)
+}
+
+fn snoozes_path() -> std::io::Result<PathBuf> {
+    Ok(config::config_dir()?.join(SNOOZES_FILE))
+}
@@ -0,0 +1,544 @@
+use crate::api::client::{
+    BowlWeight, DrinkingEvent, FeedingEvent, Location, MovementEvent, PetReport, ReportSection,
+};
+use crate::export::{DeviceRecord, ExportData};
+use crate::watermarks::{PetWatermarks, Watermarks};
+use chrono::{DateTime, Utc};
+use rusqlite::types::{Type, Value};
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension, Row};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Tables of an `export.db`. Events are keyed on what identifies them in the
+/// API, so exporting overlapping periods again only adds what is new.
+pub const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS pets (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS devices (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    product_id INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS feeding_events (
+    pet_id INTEGER NOT NULL REFERENCES pets(id),
+    device_id INTEGER NOT NULL REFERENCES devices(id),
+    timestamp TEXT NOT NULL,
+    grams REAL NOT NULL,
+    PRIMARY KEY (pet_id, device_id, timestamp)
+);
+CREATE TABLE IF NOT EXISTS drinking_events (
+    pet_id INTEGER NOT NULL REFERENCES pets(id),
+    device_id INTEGER NOT NULL REFERENCES devices(id),
+    timestamp TEXT NOT NULL,
+    ml REAL NOT NULL,
+    PRIMARY KEY (pet_id, device_id, timestamp)
+);
+CREATE TABLE IF NOT EXISTS activity_events (
+    pet_id INTEGER NOT NULL REFERENCES pets(id),
+    outside_from TEXT NOT NULL,
+    outside_to TEXT,
+    PRIMARY KEY (pet_id, outside_from)
+);
+CREATE TABLE IF NOT EXISTS device_status_events (
+    device_id INTEGER NOT NULL REFERENCES devices(id),
+    timestamp TEXT NOT NULL,
+    online INTEGER,
+    battery INTEGER,
+    lock TEXT,
+    PRIMARY KEY (device_id, timestamp)
+);
+CREATE INDEX IF NOT EXISTS feeding_events_timestamp ON feeding_events (timestamp);
+CREATE INDEX IF NOT EXISTS drinking_events_timestamp ON drinking_events (timestamp);
+CREATE INDEX IF NOT EXISTS activity_events_outside_from ON activity_events (outside_from);
+";
+
+/// Sets aside a `device_status_events` table from before it held battery
+/// and lock state. Online may now be missing, which a column can't be
+/// altered to allow, so [SCHEMA] creates the table again and
+/// [MIGRATE_DEVICE_STATES_COPY] moves the rows over.
+const MIGRATE_DEVICE_STATES: &str =
+    "ALTER TABLE device_status_events RENAME TO device_status_events_v1;";
+const MIGRATE_DEVICE_STATES_COPY: &str =
+    "INSERT INTO device_status_events (device_id, timestamp, online)
+    SELECT device_id, timestamp, online FROM device_status_events_v1;
+DROP TABLE device_status_events_v1;";
+
+/// Every kind of stored event as one set of rows, newest first once sorted.
+const EVENTS_VIEW: &str = "WITH events (kind, pet, device, timestamp, amount, back) AS (
+    SELECT 'feeding', p.name, d.name, f.timestamp, f.grams, NULL
+    FROM feeding_events f JOIN pets p ON p.id = f.pet_id LEFT JOIN devices d ON d.id = f.device_id
+    UNION ALL
+    SELECT 'drinking', p.name, d.name, e.timestamp, e.ml, NULL
+    FROM drinking_events e JOIN pets p ON p.id = e.pet_id LEFT JOIN devices d ON d.id = e.device_id
+    UNION ALL
+    SELECT 'outside', p.name, NULL, a.outside_from, NULL, a.outside_to
+    FROM activity_events a JOIN pets p ON p.id = a.pet_id
+)";
+
+/// A device's connection, battery and lock state, as recorded by the daemon
+/// and `status` whenever any of them changes.
+pub struct DeviceState {
+    pub device_id: u32,
+    pub timestamp: DateTime<Utc>,
+    pub online: Option<bool>,
+    pub battery: Option<u8>,
+    pub lock: Option<String>,
+}
+
+/// A filter over stored events, from `since` and before `until`. Patterns
+/// match pet or device names.
+pub struct HistoryQuery<'a> {
+    pub patterns: &'a [String],
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: u32,
+    pub page: u32,
+}
+
+impl HistoryQuery<'_> {
+    /// Events on the pages before this one, however large `page` is.
+    pub fn offset(&self) -> u32 {
+        self.page.saturating_sub(1).saturating_mul(self.limit)
+    }
+}
+
+pub struct HistoryRow {
+    pub kind: String,
+    pub pet: String,
+    pub device: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    /// Grams eaten or ml drunk
+    pub amount: Option<f64>,
+    /// When a pet outside came back in, if it has
+    pub back: Option<DateTime<Utc>>,
+}
+
+/// Open the database at `path` to write to, creating it or bringing it up
+/// to [SCHEMA] as needed.
+fn open(path: &Path) -> rusqlite::Result<Connection> {
+    let mut conn = Connection::open(path)?;
+    migrate(&mut conn)?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+/// Open an existing database to read. One written by an older version is
+/// brought up to [SCHEMA] first, which needs it opened for writing.
+fn open_existing(path: &Path) -> rusqlite::Result<Connection> {
+    let mut conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    if !outdated(&conn)? {
+        return Ok(conn);
+    }
+    drop(conn);
+    conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    migrate(&mut conn)?;
+    Ok(conn)
+}
+
+/// Whether `device_status_events` is there but without the battery column.
+fn outdated(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT count(*) > 0 AND sum(name = 'battery') = 0
+        FROM pragma_table_info('device_status_events')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    if !outdated(conn)? {
+        return Ok(());
+    }
+    let tx = conn.transaction()?;
+    tx.execute_batch(MIGRATE_DEVICE_STATES)?;
+    tx.execute_batch(SCHEMA)?;
+    tx.execute_batch(MIGRATE_DEVICE_STATES_COPY)?;
+    tx.commit()
+}
+
+/// One page of the events matching `query`, and how many match in total.
+/// Timestamps are stored as RFC 3339 in UTC, so the bounds compare as text
+/// and the timestamp indexes serve them.
+pub fn search_history(
+    path: &Path,
+    query: &HistoryQuery,
+) -> rusqlite::Result<(Vec<HistoryRow>, u32)> {
+    let conn = open_existing(path)?;
+
+    let mut filters = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    if let Some(since) = query.since {
+        filters.push("timestamp >= ?".to_string());
+        values.push(Value::Text(since.to_rfc3339()));
+    }
+    if let Some(until) = query.until {
+        filters.push("timestamp < ?".to_string());
+        values.push(Value::Text(until.to_rfc3339()));
+    }
+    if !query.patterns.is_empty() {
+        let names: Vec<&str> = query
+            .patterns
+            .iter()
+            .map(|_| r"pet LIKE ? ESCAPE '\' OR device LIKE ? ESCAPE '\'")
+            .collect();
+        filters.push(format!("({})", names.join(" OR ")));
+        for pattern in query.patterns {
+            // a name is matched as written, % and _ in it included
+            let escaped = pattern
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            let like = Value::Text(format!("%{}%", escaped));
+            values.push(like.clone());
+            values.push(like);
+        }
+    }
+    let filter = if filters.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", filters.join(" AND "))
+    };
+
+    let total: u32 = conn.query_row(
+        &format!("{} SELECT count(*) FROM events {}", EVENTS_VIEW, filter),
+        params_from_iter(&values),
+        |row| row.get(0),
+    )?;
+
+    values.push(Value::Integer(query.limit.into()));
+    values.push(Value::Integer(query.offset().into()));
+    let mut statement = conn.prepare(&format!(
+        "{} SELECT kind, pet, device, timestamp, amount, back FROM events {}
+         ORDER BY timestamp DESC LIMIT ? OFFSET ?",
+        EVENTS_VIEW, filter
+    ))?;
+    let rows = statement
+        .query_map(params_from_iter(&values), |row| {
+            Ok(HistoryRow {
+                kind: row.get(0)?,
+                pet: row.get(1)?,
+                device: row.get(2)?,
+                timestamp: timestamp(row, 3)?,
+                amount: row.get(4)?,
+                back: optional_timestamp(row, 5)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok((rows, total))
+}
+
+/// Add an export to the database at `path`, creating it if needed. Returns
+/// how many events were new.
+pub fn append(data: &ExportData, path: &Path) -> rusqlite::Result<usize> {
+    let mut conn = open(path)?;
+
+    let tx = conn.transaction()?;
+    let mut added = 0;
+    {
+        let mut pet = tx.prepare(
+            "INSERT INTO pets (id, name) VALUES (?1, ?2)
+             ON CONFLICT (id) DO UPDATE SET name = excluded.name",
+        )?;
+        for record in &data.pets {
+            pet.execute(params![record.id, record.name])?;
+        }
+
+        let mut device = tx.prepare(
+            "INSERT INTO devices (id, name, product_id) VALUES (?1, ?2, ?3)
+             ON CONFLICT (id)
+             DO UPDATE SET name = excluded.name, product_id = excluded.product_id",
+        )?;
+        for record in &data.devices {
+            device.execute(params![record.id, record.name, record.product_id])?;
+        }
+
+        let mut feeding = tx.prepare(
+            "INSERT OR IGNORE INTO feeding_events (pet_id, device_id, timestamp, grams)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for record in &data.feeding {
+            added += feeding.execute(params![
+                record.pet_id,
+                record.device_id,
+                record.timestamp.to_rfc3339(),
+                record.amount
+            ])?;
+        }
+
+        let mut drinking = tx.prepare(
+            "INSERT OR IGNORE INTO drinking_events (pet_id, device_id, timestamp, ml)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for record in &data.drinking {
+            added += drinking.execute(params![
+                record.pet_id,
+                record.device_id,
+                record.timestamp.to_rfc3339(),
+                record.amount
+            ])?;
+        }
+
+        // a trip still under way last time has since gained its return time
+        let mut activity = tx.prepare(
+            "INSERT INTO activity_events (pet_id, outside_from, outside_to) VALUES (?1, ?2, ?3)
+             ON CONFLICT (pet_id, outside_from)
+             DO UPDATE SET outside_to = excluded.outside_to WHERE outside_to IS NULL",
+        )?;
+        for record in &data.movements {
+            let existed: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM activity_events
+                 WHERE pet_id = ?1 AND outside_from = ?2)",
+                params![record.pet_id, record.outside_from.to_rfc3339()],
+                |row| row.get(0),
+            )?;
+            activity.execute(params![
+                record.pet_id,
+                record.outside_from.to_rfc3339(),
+                record.outside_to.map(|t| t.to_rfc3339())
+            ])?;
+            if !existed {
+                added += 1;
+            }
+        }
+    }
+    tx.commit()?;
+
+    Ok(added)
+}
+
+/// Record the state `device` was seen in at `timestamp`, creating the
+/// database if needed.
+pub fn record_state(
+    path: &Path,
+    device: &DeviceRecord,
+    timestamp: DateTime<Utc>,
+) -> rusqlite::Result<()> {
+    let conn = open(path)?;
+    insert_state(&conn, device, timestamp)
+}
+
+/// Record the state of each device whose connection, battery or lock
+/// differs from the last one stored for it, so commands other than the
+/// daemon can add to the history without repeating it. Returns how many
+/// were recorded.
+pub fn record_changes(
+    path: &Path,
+    devices: &[DeviceRecord],
+    timestamp: DateTime<Utc>,
+) -> rusqlite::Result<usize> {
+    let conn = open(path)?;
+    let mut recorded = 0;
+    for device in devices {
+        let last: Option<(Option<bool>, Option<u8>, Option<String>)> = conn
+            .query_row(
+                "SELECT online, battery, lock FROM device_status_events
+                 WHERE device_id = ?1 ORDER BY timestamp DESC LIMIT 1",
+                params![device.id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let unchanged = last.is_some_and(|(online, battery, lock)| {
+            (online, battery, lock.as_ref())
+                == (device.online, device.battery, device.lock.as_ref())
+        });
+        if unchanged {
+            continue;
+        }
+        insert_state(&conn, device, timestamp)?;
+        recorded += 1;
+    }
+    Ok(recorded)
+}
+
+fn insert_state(
+    conn: &Connection,
+    device: &DeviceRecord,
+    timestamp: DateTime<Utc>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO devices (id, name, product_id) VALUES (?1, ?2, ?3)
+         ON CONFLICT (id)
+         DO UPDATE SET name = excluded.name, product_id = excluded.product_id",
+        params![device.id, device.name, device.product_id],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO device_status_events (device_id, timestamp, online, battery, lock)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            device.id,
+            timestamp.to_rfc3339(),
+            device.online,
+            device.battery,
+            device.lock
+        ],
+    )?;
+    Ok(())
+}
+
+/// Every recorded device state before `until`, oldest first, so the state at
+/// any time is the last one before it.
+pub fn device_states(path: &Path, until: DateTime<Utc>) -> rusqlite::Result<Vec<DeviceState>> {
+    let conn = open_existing(path)?;
+    let mut statement = conn.prepare(
+        "SELECT device_id, timestamp, online, battery, lock FROM device_status_events
+         WHERE timestamp < ?1 ORDER BY timestamp",
+    )?;
+    let rows = statement.query_map(params![until.to_rfc3339()], |row| {
+        Ok(DeviceState {
+            device_id: row.get(0)?,
+            timestamp: timestamp(row, 1)?,
+            online: row.get(2)?,
+            battery: row.get(3)?,
+            lock: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Names of the devices in the database, by id.
+pub fn device_names(path: &Path) -> rusqlite::Result<HashMap<u32, String>> {
+    let conn = open_existing(path)?;
+    let mut statement = conn.prepare("SELECT id, name FROM devices")?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Where each pet with a recorded trip starting before `at` was at that time.
+pub fn locations_at(path: &Path, at: DateTime<Utc>) -> rusqlite::Result<HashMap<u32, Location>> {
+    let conn = open_existing(path)?;
+    let mut statement = conn.prepare(
+        "SELECT pet_id, outside_to FROM activity_events a
+         WHERE outside_from = (SELECT max(outside_from) FROM activity_events
+                               WHERE pet_id = a.pet_id AND outside_from <= ?1)",
+    )?;
+    let rows = statement.query_map(params![at.to_rfc3339()], |row| {
+        let location = match optional_timestamp(row, 1)? {
+            Some(back) if back <= at => Location::Inside,
+            _ => Location::Outside,
+        };
+        Ok((row.get(0)?, location))
+    })?;
+    rows.collect()
+}
+
+/// How far the events in the database at `path` go for each pet, so `sync`
+/// only fetches what is newer. Empty when there is no database yet.
+pub fn watermarks(path: &Path) -> rusqlite::Result<Watermarks> {
+    if !path.exists() {
+        return Ok(Watermarks::default());
+    }
+    let conn = open_existing(path)?;
+    // a trip still under way holds the movement mark back, so its return is
+    // fetched once it ends
+    let mut statement = conn.prepare(
+        "SELECT p.id, p.name,
+            (SELECT max(timestamp) FROM feeding_events WHERE pet_id = p.id),
+            (SELECT max(timestamp) FROM drinking_events WHERE pet_id = p.id),
+            (SELECT max(outside_from) FROM activity_events a
+             WHERE pet_id = p.id AND NOT EXISTS (
+                SELECT 1 FROM activity_events o WHERE o.pet_id = p.id
+                AND o.outside_to IS NULL AND o.outside_from <= a.outside_from))
+         FROM pets p",
+    )?;
+    let pets = statement.query_map([], |row| {
+        Ok(PetWatermarks {
+            pet_id: row.get(0)?,
+            pet: row.get(1)?,
+            feeding: optional_timestamp(row, 2)?,
+            drinking: optional_timestamp(row, 3)?,
+            movement: optional_timestamp(row, 4)?,
+        })
+    })?;
+    Ok(Watermarks {
+        pets: pets.collect::<rusqlite::Result<_>>()?,
+    })
+}
+
+/// A pet's stored events between `from` and `to`, in the shape the API
+/// reports them, so views of the API's history also work on the database.
+pub fn pet_report(
+    path: &Path,
+    pet_id: u32,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> rusqlite::Result<PetReport> {
+    let conn = open_existing(path)?;
+    let range = params![pet_id, from.to_rfc3339(), to.to_rfc3339()];
+
+    // one bowl with the whole amount eaten or drunk gives back the same totals
+    let mut statement = conn.prepare(
+        "SELECT timestamp, device_id, grams FROM feeding_events
+         WHERE pet_id = ?1 AND timestamp >= ?2 AND timestamp <= ?3 ORDER BY timestamp",
+    )?;
+    let feeding = statement
+        .query_map(range, |row| {
+            Ok(FeedingEvent {
+                from: timestamp(row, 0)?,
+                device_id: row.get(1)?,
+                weights: vec![BowlWeight {
+                    change: -row.get::<_, f64>(2)?,
+                }],
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut statement = conn.prepare(
+        "SELECT timestamp, device_id, ml FROM drinking_events
+         WHERE pet_id = ?1 AND timestamp >= ?2 AND timestamp <= ?3 ORDER BY timestamp",
+    )?;
+    let drinking = statement
+        .query_map(range, |row| {
+            Ok(DrinkingEvent {
+                from: timestamp(row, 0)?,
+                device_id: row.get(1)?,
+                weights: vec![BowlWeight {
+                    change: -row.get::<_, f64>(2)?,
+                }],
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    let mut statement = conn.prepare(
+        "SELECT outside_from, outside_to FROM activity_events
+         WHERE pet_id = ?1 AND outside_from >= ?2 AND outside_from <= ?3 ORDER BY outside_from",
+    )?;
+    let movement = statement
+        .query_map(range, |row| {
+            Ok(MovementEvent {
+                from: timestamp(row, 0)?,
+                device_id: None,
+                to: optional_timestamp(row, 1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(PetReport {
+        feeding: Some(ReportSection {
+            datapoints: feeding,
+        }),
+        drinking: Some(ReportSection {
+            datapoints: drinking,
+        }),
+        movement: Some(ReportSection {
+            datapoints: movement,
+        }),
+    })
+}
+
+/// Read a timestamp column, stored as RFC 3339 text.
+fn timestamp(row: &Row, column: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let text: String = row.get(column)?;
+    DateTime::parse_from_rfc3339(&text)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(column, Type::Text, Box::new(e)))
+}
+
+/// Read a timestamp column that may be NULL.
+fn optional_timestamp(row: &Row, column: usize) -> rusqlite::Result<Option<DateTime<Utc>>> {
+    match row.get::<_, Option<String>>(column)? {
+        Some(_) => timestamp(row, column).map(Some),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,451 @@
+use crate::api::SurePetApi;
+use crate::sqlite::{self, DeviceState};
+use crate::summary;
+use crate::units::Units;
+use chrono::{DateTime, Duration, Utc};
+use clap::ValueEnum;
+use console::style;
+use log::error;
+use std::collections::HashMap;
+use std::io::Error;
+use std::path::Path;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum StatsRange {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl StatsRange {
+    pub fn days(self) -> i64 {
+        match self {
+            StatsRange::Day => 1,
+            StatsRange::Week => 7,
+            StatsRange::Month => 30,
+            StatsRange::Year => 365,
+        }
+    }
+}
+
+/// Totals across every pet and device over a range.
+pub struct HouseholdStats {
+    pub days: i64,
+    /// Trips out through a flap, and back in
+    pub exits: usize,
+    pub entries: usize,
+    /// Hour, in the preferred zone, with the most flap traffic, and how many passages it saw
+    pub busiest_hour: Option<(u32, usize)>,
+    pub meals: usize,
+    pub grams: f64,
+    pub drinks: usize,
+    pub ml: f64,
+    pub pets: Vec<PetTotals>,
+    pub devices: Vec<DeviceUptime>,
+    /// A pet's history could not be fetched and is missing from the totals
+    pub partial: bool,
+}
+
+pub struct PetTotals {
+    pub name: String,
+    pub trips: usize,
+    pub meals: usize,
+    pub grams: f64,
+    pub drinks: usize,
+    pub ml: f64,
+}
+
+pub struct DeviceUptime {
+    pub name: String,
+    /// Percentage of the range the device was online, while that is known
+    pub uptime: Option<f64>,
+}
+
+/// Gather household totals for the last `range`. Device uptime comes from the
+/// status changes the daemon records in `db`, as the API keeps no history.
+pub async fn generate(
+    api_client: &impl SurePetApi,
+    token: &str,
+    range: StatsRange,
+    db: &Path,
+) -> std::io::Result<HouseholdStats> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+
+    let to = Utc::now();
+    let from = to - Duration::days(range.days());
+
+    let mut stats = HouseholdStats {
+        days: range.days(),
+        exits: 0,
+        entries: 0,
+        busiest_hour: None,
+        meals: 0,
+        grams: 0.0,
+        drinks: 0,
+        ml: 0.0,
+        pets: Vec::new(),
+        devices: Vec::new(),
+        partial: false,
+    };
+    let mut by_hour: HashMap<u32, usize> = HashMap::new();
+
+    for pet in &pets {
+        let report = match api_client.get_pet_report(token, pet, from, to).await {
+            Ok(report) => report,
+            Err(e) => {
+                error!("failed to fetch history for {}: {}", pet.name, e);
+                summary::failed();
+                stats.partial = true;
+                continue;
+            }
+        };
+        let feeding = report.feeding.map(|f| f.datapoints).unwrap_or_default();
+        let drinking = report.drinking.map(|d| d.datapoints).unwrap_or_default();
+        let movement = report.movement.map(|m| m.datapoints).unwrap_or_default();
+
+        for trip in &movement {
+            *by_hour
+                .entry(api_client.cfg().units.hour_of(&trip.from))
+                .or_default() += 1;
+            if let Some(back) = trip.to {
+                *by_hour
+                    .entry(api_client.cfg().units.hour_of(&back))
+                    .or_default() += 1;
+                stats.entries += 1;
+            }
+        }
+        stats.exits += movement.len();
+
+        let totals = PetTotals {
+            name: pet.name.clone(),
+            trips: movement.len(),
+            meals: feeding.len(),
+            grams: feeding.iter().map(|e| e.grams_eaten()).sum(),
+            drinks: drinking.len(),
+            ml: drinking.iter().map(|e| e.ml_drunk()).sum(),
+        };
+        stats.meals += totals.meals;
+        stats.grams += totals.grams;
+        stats.drinks += totals.drinks;
+        stats.ml += totals.ml;
+        stats.pets.push(totals);
+    }
+    // ties go to the earlier hour
+    stats.busiest_hour = by_hour
+        .into_iter()
+        .max_by_key(|&(hour, count)| (count, std::cmp::Reverse(hour)));
+
+    let states: Vec<DeviceState> = if db.exists() {
+        sqlite::device_states(db, to).map_err(Error::other)?
+    } else {
+        Vec::new()
+    };
+    for device in &devices {
+        let history: Vec<(DateTime<Utc>, bool)> = states
+            .iter()
+            .filter(|c| c.device_id == device.id)
+            .filter_map(|c| Some((c.timestamp, c.online?)))
+            .collect();
+        stats.devices.push(DeviceUptime {
+            name: device.name.clone(),
+            uptime: uptime(&history, from, to),
+        });
+    }
+
+    Ok(stats)
+}
+
+/// Percentage of `from..to` spent online, measured from the first recorded
+/// state; None when nothing was recorded before `to`.
+fn uptime(
+    changes: &[(DateTime<Utc>, bool)],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Option<f64> {
+    let &(first, mut online) = changes.first()?;
+    let mut since = first.max(from);
+    let mut up = Duration::zero();
+    for &(timestamp, now_online) in &changes[1..] {
+        if timestamp > since {
+            if online {
+                up += timestamp - since;
+            }
+            since = timestamp;
+        }
+        online = now_online;
+    }
+    if online {
+        up += to - since;
+    }
+
+    let known = to - first.max(from);
+    if known <= Duration::zero() {
+        return None;
+    }
+    Some(up.num_seconds() as f64 * 100.0 / known.num_seconds() as f64)
+}
+
+/// `part` as a percentage of `total`, None when there is no total.
+fn share(part: f64, total: f64) -> Option<f64> {
+    (total > 0.0).then(|| part * 100.0 / total)
+}
+
+/// A period a device was offline, clipped to the range asked about.
+pub struct Outage {
+    pub start: DateTime<Utc>,
+    /// None while the device is still offline
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl Outage {
+    pub fn duration(&self, now: DateTime<Utc>) -> Duration {
+        self.end.unwrap_or(now) - self.start
+    }
+}
+
+/// The outages overlapping `from..` in a device's recorded states.
+pub fn outages(states: &[&DeviceState], from: DateTime<Utc>) -> Vec<Outage> {
+    let mut outages: Vec<Outage> = Vec::new();
+    let mut offline_since = None;
+    for state in states {
+        match (state.online, offline_since) {
+            (Some(false), None) => offline_since = Some(state.timestamp),
+            (Some(true), Some(start)) => {
+                offline_since = None;
+                if state.timestamp > from {
+                    outages.push(Outage {
+                        start: start.max(from),
+                        end: Some(state.timestamp),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = offline_since {
+        outages.push(Outage {
+            start: start.max(from),
+            end: None,
+        });
+    }
+    outages
+}
+
+/// A duration to the minute, such as `2d 3h`, `3h 12m` or `45m`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    match minutes {
+        ..=0 => "<1m".to_string(),
+        1..=59 => format!("{}m", minutes),
+        60..=1439 => format!("{}h {}m", minutes / 60, minutes % 60),
+        _ => format!("{}d {}h", minutes / 1440, minutes % 1440 / 60),
+    }
+}
+
+pub fn print_stats(stats: &HouseholdStats, units: &Units) {
+    println!(
+        "{}",
+        style(format!("Household over the last {} days", stats.days)).bold()
+    );
+    println!("  Door traffic   {} out, {} in", stats.exits, stats.entries);
+    match stats.busiest_hour {
+        Some((hour, count)) => println!(
+            "  Busiest hour   {}-{}, {} passages",
+            units.hour(hour),
+            units.hour((hour + 1) % 24),
+            count
+        ),
+        None => println!("  Busiest hour   -"),
+    }
+    println!(
+        "  Food           {} in {} meals",
+        units.weight(stats.grams, 0),
+        stats.meals
+    );
+    println!(
+        "  Water          {} in {} drinks",
+        units.volume(stats.ml, 0),
+        stats.drinks
+    );
+
+    println!("{}", style("Pets").bold());
+    let share = |part: f64, total: f64| match share(part, total) {
+        Some(share) => format!("{:>3.0}%", share),
+        None => "   -".to_string(),
+    };
+    println!("  {:<16} {:>5} {:>5} {:>5}", "", "trips", "food", "water");
+    for pet in &stats.pets {
+        println!(
+            "  {:<16} {:>5} {:>5} {:>5}",
+            pet.name,
+            share(pet.trips as f64, stats.exits as f64),
+            share(pet.grams, stats.grams),
+            share(pet.ml, stats.ml)
+        );
+    }
+
+    println!("{}", style("Device uptime").bold());
+    for device in &stats.devices {
+        match device.uptime {
+            Some(uptime) => println!("  {:<16} {:.1}%", device.name, uptime),
+            None => println!("  {:<16} -", device.name),
+        }
+    }
+    if !stats.devices.is_empty() && stats.devices.iter().all(|d| d.uptime.is_none()) {
+        println!(
+            "{}",
+            style("Uptime is recorded by the daemon when [daemon] status_db is set").dim()
+        );
+    }
+    if stats.partial {
+        println!("Some pets' history could not be fetched, so totals are low");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockClient;
+    use crate::config;
+    use crate::export::DeviceRecord;
+    use std::fs;
+
+    const PETS: &str = r#"[{"id": 1, "name": "Tom", "household_id": 9},
+        {"id": 2, "name": "Kit", "household_id": 9}]"#;
+    const DEVICES: &str = r#"[{"id": 2, "name": "Back door", "product_id": 6},
+        {"id": 3, "name": "Feeder", "product_id": 4}]"#;
+    const TOM: &str = r#"{
+        "movement": {"datapoints": [
+            {"from": "2024-03-01T08:10:00Z", "to": "2024-03-01T08:40:00Z", "device_id": 2},
+            {"from": "2024-03-01T17:00:00Z", "device_id": 2}
+        ]},
+        "feeding": {"datapoints": [
+            {"from": "2024-03-01T07:00:00Z", "device_id": 3, "weights": [{"change": -30.0}]}
+        ]}
+    }"#;
+    const KIT: &str = r#"{
+        "movement": {"datapoints": [
+            {"from": "2024-03-01T08:20:00Z", "to": "2024-03-01T09:05:00Z", "device_id": 2}
+        ]},
+        "feeding": {"datapoints": [
+            {"from": "2024-03-01T07:30:00Z", "device_id": 3, "weights": [{"change": -10.0}]}
+        ]},
+        "drinking": {"datapoints": [
+            {"from": "2024-03-01T10:00:00Z", "device_id": 3, "weights": [{"change": -50.0}]}
+        ]}
+    }"#;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    fn flap(online: bool) -> DeviceRecord {
+        DeviceRecord {
+            id: 2,
+            name: "Back door".to_string(),
+            product_id: 6,
+            battery: None,
+            online: Some(online),
+            lock: None,
+            curfew: None,
+        }
+    }
+
+    async fn household_stats() -> HouseholdStats {
+        let dir = std::env::temp_dir().join(format!("rusty_pet_{}_stats", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in [
+            ("pets.json", PETS),
+            ("devices.json", DEVICES),
+            ("report_1.json", TOM),
+            ("report_2.json", KIT),
+        ] {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        // the flap was offline for a quarter of the week, the feeder has
+        // no recorded states
+        let db = dir.join("status.db");
+        let now = Utc::now();
+        sqlite::record_state(&db, &flap(true), now - Duration::days(10)).unwrap();
+        sqlite::record_state(&db, &flap(false), now - Duration::hours(84)).unwrap();
+        sqlite::record_state(&db, &flap(true), now - Duration::hours(42)).unwrap();
+
+        let mut cfg = config::built_in();
+        cfg.units.timezone = Some(chrono_tz::UTC);
+        let mock = MockClient::new(cfg, dir.clone());
+        let stats = generate(&mock, "mock", StatsRange::Week, &db)
+            .await
+            .unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        stats
+    }
+
+    #[tokio::test]
+    async fn household_totals_over_a_range() {
+        let stats = household_stats().await;
+        assert_eq!((stats.exits, stats.entries), (3, 2));
+        // 08:10 and 08:20 out, 08:40 back in
+        assert_eq!(stats.busiest_hour, Some((8, 3)));
+        assert_eq!((stats.meals, stats.grams), (2, 40.0));
+        assert_eq!((stats.drinks, stats.ml), (1, 50.0));
+        assert!(!stats.partial);
+
+        let shares: Vec<_> = stats
+            .pets
+            .iter()
+            .map(|p| {
+                (
+                    p.name.as_str(),
+                    share(p.grams, stats.grams),
+                    share(p.ml, stats.ml),
+                )
+            })
+            .collect();
+        assert_eq!(
+            shares,
+            [
+                ("Tom", Some(75.0), Some(0.0)),
+                ("Kit", Some(25.0), Some(100.0))
+            ]
+        );
+        let trips = share(stats.pets[0].trips as f64, stats.exits as f64).unwrap();
+        assert!((trips - 200.0 / 3.0).abs() < 1e-9);
+
+        let uptimes: Vec<_> = stats
+            .devices
+            .iter()
+            .map(|d| (d.name.as_str(), d.uptime))
+            .collect();
+        assert_eq!(uptimes[0].0, "Back door");
+        assert!((uptimes[0].1.unwrap() - 75.0).abs() < 0.01);
+        assert_eq!(uptimes[1], ("Feeder", None));
+    }
+
+    #[test]
+    fn no_share_of_nothing() {
+        assert_eq!(share(0.0, 0.0), None);
+        assert_eq!(share(1.0, 4.0), Some(25.0));
+    }
+
+    #[test]
+    fn uptime_is_measured_from_the_first_recorded_state() {
+        let (from, to) = (at("2024-03-01T00:00:00Z"), at("2024-03-02T00:00:00Z"));
+        // nothing known until noon, then online for 6 of the 12 hours left
+        let changes = [
+            (at("2024-03-01T12:00:00Z"), true),
+            (at("2024-03-01T18:00:00Z"), false),
+        ];
+        assert_eq!(uptime(&changes, from, to), Some(50.0));
+        // online since before the range, offline for its last 6 hours
+        let changes = [
+            (at("2024-02-20T00:00:00Z"), true),
+            (at("2024-03-01T18:00:00Z"), false),
+        ];
+        assert_eq!(uptime(&changes, from, to), Some(75.0));
+        assert_eq!(uptime(&[], from, to), None);
+        assert_eq!(uptime(&[(to, true)], from, to), None);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::config;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+
+const SUBSCRIPTIONS_FILE: &str = "notifications.toml";
+
+/// Pet events that can be subscribed to.
+#[derive(ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum PetEvent {
+    /// The pet went in or out
+    Location,
+    /// The pet ate
+    Feeding,
+    /// The pet drank
+    Drinking,
+    /// The pet hasn't been seen moving for a while
+    Inactivity,
+    /// A health trend such as increased drinking
+    Health,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Subscription {
+    pub pet_id: u32,
+    pub pet: String,
+    pub events: Vec<PetEvent>,
+}
+
+/// Which pets notifications are sent about. With no subscriptions at all
+/// every pet notifies; once any exist only subscribed pet events do.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Subscriptions {
+    #[serde(default, rename = "subscription")]
+    pub rules: Vec<Subscription>,
+}
+
+impl Subscriptions {
+    pub fn load() -> std::io::Result<Self> {
+        let path = subscriptions_path()?;
+        if !path.exists() {
+            return Ok(Subscriptions::default());
+        }
+        toml::from_str(&fs::read_to_string(path)?).map_err(Error::other)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let contents = toml::to_string_pretty(self).map_err(Error::other)?;
+        fs::write(subscriptions_path()?, contents)
+    }
+
+    pub fn subscribe(&mut self, pet_id: u32, pet: &str, events: &[PetEvent]) {
+        match self.rules.iter_mut().find(|r| r.pet_id == pet_id) {
+            Some(rule) => {
+                rule.pet = pet.to_string();
+                rule.events.extend_from_slice(events);
+                rule.events.sort();
+                rule.events.dedup();
+            }
+            None => {
+                let mut events = events.to_vec();
+                events.sort();
+                events.dedup();
+                self.rules.push(Subscription {
+                    pet_id,
+                    pet: pet.to_string(),
+                    events,
+                });
+            }
+        }
+    }
+
+    /// Remove `events` for a pet, or every event when `events` is empty.
+    /// Returns whether anything was removed.
+    pub fn unsubscribe(&mut self, pet: &str, events: &[PetEvent]) -> bool {
+        let before: usize = self.rules.iter().map(|r| r.events.len()).sum();
+        for rule in self.rules.iter_mut().filter(|r| r.matches(pet)) {
+            if events.is_empty() {
+                rule.events.clear();
+            } else {
+                rule.events.retain(|e| !events.contains(e));
+            }
+        }
+        self.rules.retain(|r| !r.events.is_empty());
+        let after: usize = self.rules.iter().map(|r| r.events.len()).sum();
+        after < before
+    }
+
+    /// Whether a notification about `event` for the pet should be sent.
+    pub fn wants(&self, pet_id: u32, event: PetEvent) -> bool {
+        self.rules.is_empty()
+            || self
+                .rules
+                .iter()
+                .any(|r| r.pet_id == pet_id && r.events.contains(&event))
+    }
+}
+
+impl Subscription {
+    fn matches(&self, pet: &str) -> bool {
+        self.pet_id.to_string() == pet || self.pet.eq_ignore_ascii_case(pet)
+    }
+}
+
+fn subscriptions_path() -> std::io::Result<PathBuf> {
+    Ok(config::config_dir()?.join(SUBSCRIPTIONS_FILE))
+}
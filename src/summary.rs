@@ -0,0 +1,44 @@
+use crate::error::RustyPetError;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+static CHANGED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// Count something a command changed: a setting, or a file written.
+pub fn changed() {
+    CHANGED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a part of a command that failed without failing the command.
+pub fn failed() {
+    FAILED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How many parts of the command failed without failing it.
+pub fn failures() -> usize {
+    FAILED.load(Ordering::Relaxed)
+}
+
+/// The `[headless] summary_line`, one `key=value` line on stderr so cron
+/// logs have the same thing to grep for whatever the command was.
+pub fn print(result: &Result<(), RustyPetError>, duration: Duration) {
+    let changed = CHANGED.load(Ordering::Relaxed);
+    let failed = FAILED.load(Ordering::Relaxed);
+    match result {
+        Ok(()) => eprintln!(
+            "result=ok changed={} failed={} duration_ms={}",
+            changed,
+            failed,
+            duration.as_millis()
+        ),
+        // the error itself is printed on its own line when main returns it
+        Err(e) => eprintln!(
+            "result=error changed={} failed={} duration_ms={} error={}",
+            changed,
+            failed + 1,
+            duration.as_millis(),
+            e.code()
+        ),
+    }
+}
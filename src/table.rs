@@ -0,0 +1,278 @@
+use crate::api::client::{Device, DrinkingEvent, FeedingEvent, Pet};
+use crate::curfew;
+use crate::events::EventType;
+use crate::history::{self, DeviceTotal, GroupBy, Period, Summary};
+use crate::outings::Outing;
+use crate::output::OutputFormatter;
+use crate::stats::format_duration;
+use crate::units::Units;
+use comfy_table::presets::UTF8_FULL_CONDENSED;
+use comfy_table::{Attribute, Cell, CellAlignment, Color, ContentArrangement, Table};
+use std::collections::HashMap;
+
+/// Aligned tables for people, wrapped to fit the terminal when there is one.
+pub struct TableFormatter<'a> {
+    pub units: &'a Units,
+    /// Histories without device columns or per-device totals, from
+    /// [headless] compact
+    pub compact: bool,
+}
+
+impl TableFormatter<'_> {
+    fn table(header: &[&str]) -> Table {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL_CONDENSED)
+            .set_content_arrangement(ContentArrangement::Dynamic)
+            .set_header(header.to_vec());
+        table
+    }
+
+    /// Right aligned, for amounts.
+    fn amount(text: String) -> Cell {
+        Cell::new(text).set_alignment(CellAlignment::Right)
+    }
+
+    /// Bold total and average rows under a history whose amounts are in
+    /// the second column.
+    fn footer(table: &mut Table, summary: &Summary, noun: &str, amount: impl Fn(f64) -> String) {
+        let rows = [
+            (summary.label(noun), Some(summary.total)),
+            ("Average".to_string(), summary.average()),
+        ];
+        for (label, value) in rows {
+            table.add_row(vec![
+                Cell::new(label).add_attribute(Attribute::Bold),
+                Self::amount(value.map_or("-".to_string(), &amount)).add_attribute(Attribute::Bold),
+            ]);
+        }
+    }
+
+    fn device_totals(
+        totals: Vec<DeviceTotal>,
+        header: &[&str],
+        device_name: impl Fn(u32) -> String,
+        amount: impl Fn(f64) -> String,
+    ) -> Table {
+        let mut table = Self::table(header);
+        for device in totals {
+            table.add_row(vec![
+                Cell::new(device_name(device.device_id)),
+                Self::amount(device.events.to_string()),
+                Self::amount(amount(device.amount)),
+            ]);
+        }
+        table
+    }
+}
+
+/// A table of plain text cells, for output whose columns are chosen at run time.
+pub fn table(header: &[&str], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let mut table = TableFormatter::table(header);
+    for row in rows {
+        table.add_row(row);
+    }
+    table.to_string()
+}
+
+impl OutputFormatter for TableFormatter<'_> {
+    fn pets(&self, pets: &[Pet]) -> String {
+        let mut table = Self::table(&["Pet", "Location", "Since"]);
+        for pet in pets {
+            match &pet.position {
+                Some(position) => table.add_row(vec![
+                    Cell::new(&pet.name),
+                    Cell::new(position.location),
+                    Cell::new(self.units.time(&position.since)),
+                ]),
+                None => table.add_row(vec![&pet.name, "unknown", ""]),
+            };
+        }
+        table.to_string()
+    }
+
+    fn devices(&self, devices: &[Device]) -> String {
+        let mut table = Self::table(&["Device", "Connection", "Battery", "Lock", "Curfew"]);
+        for device in devices {
+            let online = match device.online() {
+                Some(true) => Cell::new("online").fg(Color::Green),
+                Some(false) => Cell::new("offline").fg(Color::Red),
+                None => Cell::new("-"),
+            };
+            let battery = device
+                .battery_percent()
+                .map(|b| format!("{}%", b))
+                .unwrap_or_else(|| "-".to_string());
+            let lock = device
+                .lock_mode()
+                .map(|m| m.to_string())
+                .unwrap_or_default();
+            let curfew = device
+                .control
+                .as_ref()
+                .and_then(|c| curfew::text(&c.curfew));
+            table.add_row(vec![
+                Cell::new(&device.name),
+                online,
+                Cell::new(battery).set_alignment(CellAlignment::Right),
+                Cell::new(lock),
+                Cell::new(curfew.unwrap_or_default()),
+            ]);
+        }
+        table.to_string()
+    }
+
+    fn feeding_history(
+        &self,
+        events: &[FeedingEvent],
+        all: &[FeedingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String {
+        let grams = |grams: f64| self.units.weight(grams, 1);
+        let mut meals = match self.compact {
+            true => Self::table(&["Time", "Eaten"]),
+            false => Self::table(&["Time", "Eaten", "Device"]),
+        };
+        for event in events {
+            let mut row = vec![
+                Cell::new(self.units.time(&event.from)),
+                Self::amount(grams(event.grams_eaten())),
+            ];
+            if !self.compact {
+                row.push(Cell::new(device_name(device_names, event.device_id)));
+            }
+            meals.add_row(row);
+        }
+        let summary = Summary::new(all.iter().map(|e| e.grams_eaten()));
+        Self::footer(&mut meals, &summary, "meal", grams);
+        if self.compact || all.is_empty() {
+            return meals.to_string();
+        }
+
+        let totals = Self::device_totals(
+            history::feeding_by_device(all),
+            &["Device", "Meals", "Eaten"],
+            |id| device_name(device_names, id),
+            grams,
+        );
+        format!("{}\n{}", meals, totals)
+    }
+
+    fn drinking_history(
+        &self,
+        events: &[DrinkingEvent],
+        all: &[DrinkingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String {
+        let ml = |ml: f64| self.units.volume(ml, 0);
+        let mut drinks = match self.compact {
+            true => Self::table(&["Time", "Drunk"]),
+            false => Self::table(&["Time", "Drunk", "Device"]),
+        };
+        for event in events {
+            let mut row = vec![
+                Cell::new(self.units.time(&event.from)),
+                Self::amount(ml(event.ml_drunk())),
+            ];
+            if !self.compact {
+                row.push(Cell::new(device_name(device_names, event.device_id)));
+            }
+            drinks.add_row(row);
+        }
+        let summary = Summary::new(all.iter().map(|e| e.ml_drunk()));
+        Self::footer(&mut drinks, &summary, "drink", ml);
+        if self.compact || all.is_empty() {
+            return drinks.to_string();
+        }
+
+        let totals = Self::device_totals(
+            history::drinking_by_device(all),
+            &["Device", "Drinks", "Drunk"],
+            |id| device_name(device_names, id),
+            ml,
+        );
+        format!("{}\n{}", drinks, totals)
+    }
+
+    fn activity_history(&self, outings: &[Outing], all: &[Outing], unpaired: usize) -> String {
+        let duration = |minutes: f64| format_duration(chrono::Duration::minutes(minutes as i64));
+        let mut trips = match self.compact {
+            true => Self::table(&["Went out", "Outside"]),
+            false => Self::table(&["Went out", "Outside", "Back in", "Flap"]),
+        };
+        for outing in outings {
+            let mut row = vec![
+                Cell::new(self.units.time(&outing.from)),
+                Self::amount(duration(outing.minutes as f64)),
+            ];
+            if !self.compact {
+                row.push(Cell::new(match outing.to {
+                    Some(to) => self.units.time(&to),
+                    None => "still out".to_string(),
+                }));
+                row.push(Cell::new(outing.device.as_deref().unwrap_or_default()));
+            }
+            trips.add_row(row);
+        }
+        let summary = Summary::new(all.iter().map(|o| o.minutes as f64));
+        Self::footer(&mut trips, &summary, "trip", duration);
+
+        match unpaired {
+            0 => trips.to_string(),
+            unpaired => format!(
+                "{}\n{} trip(s) left out, the return wasn't scanned",
+                trips, unpaired
+            ),
+        }
+    }
+
+    fn history_periods(
+        &self,
+        periods: &[Period],
+        all: &[Period],
+        by: GroupBy,
+        event_type: EventType,
+    ) -> String {
+        let [count, amount] = history::columns(event_type);
+        let heading = match by {
+            GroupBy::Day => "Day",
+            GroupBy::Week => "Week",
+        };
+        let amount_text = |amount: f64| history::amount_text(event_type, amount, self.units);
+        let mut table = Self::table(&[heading, count, amount]);
+        for period in periods {
+            table.add_row(vec![
+                Cell::new(history::period_text(period, by, self.units)),
+                Self::amount(period.count.to_string()),
+                Self::amount(amount_text(period.amount)),
+            ]);
+        }
+        let counts = Summary::new(all.iter().map(|p| p.count as f64));
+        let amounts = Summary::new(all.iter().map(|p| p.amount));
+        let footer = [
+            ("Total", counts.total.to_string(), Some(amounts.total)),
+            (
+                "Average",
+                format!("{:.1}", counts.average().unwrap_or_default()),
+                amounts.average(),
+            ),
+        ];
+        for (label, count, amount) in footer {
+            table.add_row(vec![
+                Cell::new(label).add_attribute(Attribute::Bold),
+                Self::amount(count).add_attribute(Attribute::Bold),
+                Self::amount(amount.map_or("-".to_string(), amount_text))
+                    .add_attribute(Attribute::Bold),
+            ]);
+        }
+        table.to_string()
+    }
+}
+
+/// A device's name, or its id when it isn't in the household any more.
+pub fn device_name(device_names: &HashMap<u32, String>, id: u32) -> String {
+    device_names
+        .get(&id)
+        .cloned()
+        .unwrap_or_else(|| format!("device {}", id))
+}
@@ -0,0 +1,120 @@
+use crate::api::client::{Device, DrinkingEvent, FeedingEvent, Pet};
+use crate::events::EventType;
+use crate::export::DeviceRecord;
+use crate::history::{GroupBy, Period};
+use crate::outings::Outing;
+use crate::output::{OutputFormatter, PetRow};
+use handlebars::Handlebars;
+use log::warn;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+
+const TEMPLATE: &str = "output";
+
+/// Renders a user's Handlebars template once for each pet, device, meal,
+/// drink, trip outside or period of history, one per line, for status bars
+/// and scripts. Each is rendered with:
+///
+/// - pets: `pet` (id, name, location, since), `location` and `since`
+/// - devices: `device` (id, name, product_id, battery, online, lock, curfew)
+/// - meals: `meal` (time, grams, device_id, device)
+/// - drinks: `drink` (time, ml, device_id, device)
+/// - trips outside: `outing` (from, to, minutes, device_id, device)
+/// - days or weeks of history: `period` (start, count, amount)
+pub struct TemplateFormatter {
+    registry: Handlebars<'static>,
+}
+
+impl TemplateFormatter {
+    pub fn new(template: &str) -> std::io::Result<Self> {
+        let mut registry = Handlebars::new();
+        // the output is text, not HTML
+        registry.register_escape_fn(handlebars::no_escape);
+        registry
+            .register_template_string(TEMPLATE, template)
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("bad template: {}", e)))?;
+        Ok(TemplateFormatter { registry })
+    }
+
+    fn render_each(&self, contexts: impl Iterator<Item = serde_json::Value>) -> String {
+        contexts
+            .map(|context| {
+                self.registry
+                    .render(TEMPLATE, &context)
+                    .unwrap_or_else(|e| {
+                        warn!("template failed to render: {}", e);
+                        String::new()
+                    })
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl OutputFormatter for TemplateFormatter {
+    fn pets(&self, pets: &[Pet]) -> String {
+        self.render_each(pets.iter().map(|pet| {
+            let row = PetRow::new(pet);
+            json!({ "location": row.location, "since": row.since, "pet": row })
+        }))
+    }
+
+    fn devices(&self, devices: &[Device]) -> String {
+        self.render_each(
+            devices
+                .iter()
+                .map(|device| json!({ "device": DeviceRecord::from(device) })),
+        )
+    }
+
+    fn feeding_history(
+        &self,
+        events: &[FeedingEvent],
+        _all: &[FeedingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String {
+        self.render_each(events.iter().map(|event| {
+            json!({
+                "meal": {
+                    "time": event.from,
+                    "grams": event.grams_eaten(),
+                    "device_id": event.device_id,
+                    "device": device_names.get(&event.device_id),
+                }
+            })
+        }))
+    }
+
+    fn drinking_history(
+        &self,
+        events: &[DrinkingEvent],
+        _all: &[DrinkingEvent],
+        device_names: &HashMap<u32, String>,
+    ) -> String {
+        self.render_each(events.iter().map(|event| {
+            json!({
+                "drink": {
+                    "time": event.from,
+                    "ml": event.ml_drunk(),
+                    "device_id": event.device_id,
+                    "device": device_names.get(&event.device_id),
+                }
+            })
+        }))
+    }
+
+    fn activity_history(&self, outings: &[Outing], _all: &[Outing], _unpaired: usize) -> String {
+        self.render_each(outings.iter().map(|outing| json!({ "outing": outing })))
+    }
+
+    fn history_periods(
+        &self,
+        periods: &[Period],
+        _all: &[Period],
+        _by: GroupBy,
+        _kind: EventType,
+    ) -> String {
+        self.render_each(periods.iter().map(|period| json!({ "period": period })))
+    }
+}
@@ -0,0 +1,800 @@
+use crate::api::cache::CacheMode;
+use crate::api::client::{Device, Location, LockMode, Pet};
+use crate::api::SurePetApi;
+use crate::capabilities::{Capabilities, Capability};
+use crate::dashboard::{self, Panel};
+use crate::notifications::{self, StateTracker};
+use crate::pending::{self, Pending, PendingOperation, Sent};
+use crate::pet_info::{self, PetEvent, Totals};
+use crate::subscriptions::Subscriptions;
+use crate::units::Units;
+use crate::views;
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::widgets::{Block, List, ListState, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::{Error, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long to wait for a key before checking whether to auto-refresh.
+const TICK: Duration = Duration::from_millis(250);
+
+/// Set on SIGINT, which raw mode doesn't turn into a key, so the interface
+/// can put the terminal back before quitting.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The Ctrl-C handler for interactive mode.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Which side the arrow keys move in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Sidebar,
+    Pane,
+}
+
+/// A change made from the keyboard to what's selected.
+#[derive(Clone, Copy)]
+enum Action {
+    /// Lock or unlock the selected flap
+    Lock(LockMode),
+    /// Say where the selected pet is
+    Move(Location),
+}
+
+/// One of the menu's prompts, for what the panes don't show themselves.
+/// They run outside the full screen, for the pet or device selected.
+#[derive(Clone, Copy)]
+enum Prompt {
+    PetInfo,
+    DeviceInfo,
+    FeedingHistory,
+    /// Whether the selected pet may go out through a flap
+    Access,
+    HealthCheck,
+    Search,
+    /// Changes queued while the API couldn't be reached
+    Pending,
+}
+
+impl Prompt {
+    /// What the household needs for the prompt to be any use, as the menu
+    /// only offers what it has the devices for.
+    fn needs(self) -> Option<Capability> {
+        match self {
+            Prompt::FeedingHistory => Some(Capability::Feeder),
+            Prompt::Access => Some(Capability::Flap),
+            Prompt::HealthCheck => Some(Capability::WaterStation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    Warning,
+    /// Something has stopped working
+    Critical,
+}
+
+struct Alert {
+    severity: Severity,
+    text: String,
+    /// What identifies the alert between refreshes: what kind it is and
+    /// the id of the pet or device it's about
+    key: (String, u32),
+}
+
+impl Alert {
+    /// An alert from [pet_info], whose kind is its text without numbers,
+    /// so "outside for 6 hours" is the same alert an hour later.
+    fn pet(pet: &Pet, alert: &str) -> Self {
+        Alert {
+            severity: Severity::Warning,
+            text: format!("{} is {}", pet.name, alert),
+            key: (
+                alert.chars().filter(|c| !c.is_ascii_digit()).collect(),
+                pet.id,
+            ),
+        }
+    }
+}
+
+/// Everything shown, fetched in one go so every pane is from the same moment.
+struct Snapshot {
+    pets: Vec<Pet>,
+    devices: Vec<Device>,
+    /// Each pet's daily totals over the last week, by pet id
+    trends: HashMap<u32, Vec<Totals>>,
+    /// Every pet's recent events, newest first
+    history: Vec<(String, PetEvent)>,
+    /// Critical first
+    alerts: Vec<Alert>,
+    fetched_at: DateTime<Utc>,
+    cache_age: Option<Duration>,
+}
+
+async fn load(api_client: &impl SurePetApi, token: &str) -> std::io::Result<Snapshot> {
+    let pets = api_client.get_pets(token).await.map_err(Error::other)?;
+    let devices = api_client.get_devices(token).await.map_err(Error::other)?;
+
+    let mut trends = HashMap::new();
+    let mut history = Vec::new();
+    let mut alerts = Vec::new();
+    for pet in &pets {
+        let info = pet_info::gather(api_client, token, pet, &devices).await?;
+        trends.insert(pet.id, info.days);
+        alerts.extend(info.alerts.iter().map(|a| Alert::pet(pet, a)));
+        history.extend(info.events.into_iter().map(|e| (pet.name.clone(), e)));
+    }
+    history.sort_by_key(|(_, e)| Reverse(e.at));
+
+    let threshold = api_client.cfg().notifications.battery_threshold;
+    for device in &devices {
+        if device.online() == Some(false) {
+            alerts.push(Alert {
+                severity: Severity::Critical,
+                text: format!("{} is offline", device.name),
+                key: ("offline".to_string(), device.id),
+            });
+        }
+        if let Some(battery) = device.battery_percent().filter(|b| *b < threshold) {
+            alerts.push(Alert {
+                severity: if battery < api_client.cfg().notifications.battery_critical {
+                    Severity::Critical
+                } else {
+                    Severity::Warning
+                },
+                text: format!("{} battery is at {}%", device.name, battery),
+                key: ("battery".to_string(), device.id),
+            });
+        }
+    }
+    alerts.sort_by_key(|a| Reverse(a.severity));
+
+    Ok(Snapshot {
+        pets,
+        devices,
+        trends,
+        history,
+        alerts,
+        fetched_at: Utc::now(),
+        cache_age: api_client.take_cache_age(),
+    })
+}
+
+struct App<'a> {
+    units: &'a Units,
+    snapshot: Snapshot,
+    layout: dashboard::Layout,
+    section: usize,
+    focus: Focus,
+    rows: TableState,
+    auto_refresh: bool,
+    /// Why the last refresh failed, shown until one works
+    error: Option<String>,
+    /// What the last key did, shown until the next
+    notice: Option<String>,
+    /// A lock change waiting for y to confirm it
+    confirming: Option<Action>,
+    /// When each current alert, by key, was first seen
+    first_seen: HashMap<(String, u32), DateTime<Utc>>,
+}
+
+impl App<'_> {
+    fn section(&self) -> Panel {
+        self.layout.panels[self.section]
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [sidebar, pane] =
+            Layout::horizontal([Constraint::Length(16), Constraint::Min(0)]).areas(main);
+
+        let highlight = |focused: bool| {
+            if focused {
+                Style::new().reversed()
+            } else {
+                Style::new().bold()
+            }
+        };
+
+        let items = self.layout.panels.iter().map(|panel| match panel {
+            Panel::Alerts if !self.snapshot.alerts.is_empty() => {
+                format!("Alerts ({})", self.snapshot.alerts.len())
+            }
+            panel => panel.title().to_string(),
+        });
+        let sidebar_list = List::new(items)
+            .block(Block::bordered().title(" RustyPet "))
+            .highlight_style(highlight(self.focus == Focus::Sidebar));
+        let mut selected = ListState::default().with_selected(Some(self.section));
+        frame.render_stateful_widget(sidebar_list, sidebar, &mut selected);
+
+        let (header, rows) = self.table();
+        let widths = vec![Constraint::Fill(1); header.len()];
+        let rows = rows
+            .into_iter()
+            .enumerate()
+            .map(|(row, cells)| Row::new(cells).style(self.row_style(row)));
+        let table = Table::new(rows, widths)
+            .header(Row::new(header).bold())
+            .block(Block::bordered().title(format!(" {} ", self.section().title())))
+            .row_highlight_style(highlight(self.focus == Focus::Pane));
+        frame.render_stateful_widget(table, pane, &mut self.rows);
+
+        let state = match (&self.error, &self.notice) {
+            (Some(e), _) => format!("Refresh failed: {}", e),
+            (None, Some(notice)) => notice.clone(),
+            (None, None) => {
+                let cached = self
+                    .snapshot
+                    .cache_age
+                    .map(|age| format!(", cached {}s", age.as_secs()))
+                    .unwrap_or_default();
+                format!(
+                    "Updated {}{}",
+                    self.units.time(&self.snapshot.fetched_at),
+                    cached
+                )
+            }
+        };
+        let pause = if self.auto_refresh { "pause" } else { "resume" };
+        let status_line = format!(
+            " {} | tab pane  enter details  l/u lock  i/o in/out  a access  f food  \
+             h health  s search  c queued  r refresh  p {}  e layout  q quit",
+            state, pause
+        );
+        frame.render_widget(Paragraph::new(status_line).reversed(), status);
+    }
+
+    /// The selected panel's header and rows, cut down to its most useful
+    /// columns when the layout is compact.
+    fn table(&self) -> (Vec<&'static str>, Vec<Vec<String>>) {
+        let units = self.units;
+        let snapshot = &self.snapshot;
+        let (header, rows, compact): (Vec<&'static str>, Vec<Vec<String>>, &[usize]) =
+            match self.section() {
+                Panel::Pets => {
+                    let rows = snapshot.pets.iter().map(|pet| {
+                        let (location, since) = match &pet.position {
+                            Some(p) => (p.location.to_string(), units.time(&p.since)),
+                            None => ("unknown".to_string(), String::new()),
+                        };
+                        let days = snapshot.trends.get(&pet.id).map_or(&[][..], |d| d);
+                        let eaten: Vec<f64> = days.iter().map(|d| d.eaten_g).collect();
+                        let drunk: Vec<f64> = days.iter().map(|d| d.drunk_ml).collect();
+                        let today = days.last();
+                        vec![
+                            pet.name.clone(),
+                            location,
+                            since,
+                            format!(
+                                "{} {}",
+                                sparkline(&eaten),
+                                today.map_or(String::new(), |t| units.weight(t.eaten_g, 0))
+                            ),
+                            format!(
+                                "{} {}",
+                                sparkline(&drunk),
+                                today.map_or(String::new(), |t| units.volume(t.drunk_ml, 0))
+                            ),
+                        ]
+                    });
+                    let header = vec!["Pet", "Location", "Since", "Food, 7 days", "Water, 7 days"];
+                    (header, rows.collect(), &[0, 1])
+                }
+                Panel::Devices => {
+                    let rows = snapshot.devices.iter().map(|device| {
+                        let online = match device.online() {
+                            Some(true) => "online",
+                            Some(false) => "offline",
+                            None => "-",
+                        };
+                        let battery = device
+                            .battery_percent()
+                            .map_or("-".to_string(), |b| format!("{}%", b));
+                        let lock = device
+                            .lock_mode()
+                            .map(|m| m.to_string())
+                            .unwrap_or_default();
+                        vec![device.name.clone(), online.to_string(), battery, lock]
+                    });
+                    let header = vec!["Device", "Connection", "Battery", "Lock"];
+                    (header, rows.collect(), &[0, 2])
+                }
+                Panel::History => {
+                    let rows = snapshot.history.iter().map(|(pet, event)| {
+                        vec![
+                            units.time(&event.at),
+                            pet.clone(),
+                            event.device.clone().unwrap_or_else(|| "-".to_string()),
+                            views::activity_text(&event.activity, units),
+                        ]
+                    });
+                    (
+                        vec!["Time", "Pet", "Device", "Event"],
+                        rows.collect(),
+                        &[0, 1, 3],
+                    )
+                }
+                Panel::Alerts => {
+                    let mut rows: Vec<Vec<String>> = snapshot
+                        .alerts
+                        .iter()
+                        .map(|alert| {
+                            let severity = match alert.severity {
+                                Severity::Warning => "warning",
+                                Severity::Critical => "critical",
+                            };
+                            let first_seen = self
+                                .first_seen
+                                .get(&alert.key)
+                                .map(|at| units.time(at))
+                                .unwrap_or_default();
+                            vec![severity.to_string(), alert.text.clone(), first_seen]
+                        })
+                        .collect();
+                    if rows.is_empty() {
+                        let nothing = "Nothing needs attention".to_string();
+                        rows.push(vec![String::new(), nothing, String::new()]);
+                    }
+                    (vec!["Severity", "Alert", "First seen"], rows, &[1])
+                }
+                Panel::Intake => {
+                    let rows = snapshot.pets.iter().map(|pet| {
+                        let days = snapshot.trends.get(&pet.id).map_or(&[][..], |d| d);
+                        let today = days.last();
+                        // folded from 0.0, as an empty f64 sum is -0.0
+                        let average = |amount: fn(&Totals) -> f64| {
+                            days.iter().fold(0.0, |total, d| total + amount(d))
+                                / days.len().max(1) as f64
+                        };
+                        vec![
+                            pet.name.clone(),
+                            today.map_or("-".to_string(), |t| units.weight(t.eaten_g, 0)),
+                            today.map_or("-".to_string(), |t| units.volume(t.drunk_ml, 0)),
+                            units.weight(average(|d| d.eaten_g), 0),
+                            units.volume(average(|d| d.drunk_ml), 0),
+                        ]
+                    });
+                    let header = vec![
+                        "Pet",
+                        "Food today",
+                        "Water today",
+                        "Food, daily average",
+                        "Water, daily average",
+                    ];
+                    (header, rows.collect(), &[0, 1, 2])
+                }
+            };
+
+        if !self.layout.compact {
+            return (header, rows);
+        }
+        let rows = rows.into_iter().map(|row| columns(row, compact)).collect();
+        (columns(header, compact), rows)
+    }
+
+    /// Alerts are coloured by severity.
+    fn row_style(&self, row: usize) -> Style {
+        let alert = self.snapshot.alerts.get(row);
+        match alert.filter(|_| self.section() == Panel::Alerts) {
+            Some(a) if a.severity == Severity::Critical => Style::new().fg(Color::Red),
+            Some(_) => Style::new().fg(Color::Yellow),
+            None => Style::new(),
+        }
+    }
+
+    /// Remember when each alert was first seen, forgetting those that have
+    /// cleared, and return whether any are new.
+    fn track_alerts(&mut self) -> bool {
+        let mut first_seen = HashMap::new();
+        let mut new = false;
+        for alert in &self.snapshot.alerts {
+            let key = alert.key.clone();
+            let at = match self.first_seen.get(&key) {
+                Some(at) => *at,
+                None => {
+                    new = true;
+                    self.snapshot.fetched_at
+                }
+            };
+            first_seen.insert(key, at);
+        }
+        self.first_seen = first_seen;
+        new
+    }
+
+    /// The row picked in the pane, if the pane is showing `panel`.
+    fn selected(&self, panel: Panel) -> Option<usize> {
+        self.rows.selected().filter(|_| self.section() == panel)
+    }
+
+    fn selected_flap(&self) -> Option<&Device> {
+        self.selected(Panel::Devices)
+            .and_then(|row| self.snapshot.devices.get(row))
+            .filter(|d| d.is_flap())
+    }
+
+    /// Ask before changing the selected flap's lock, as it changes what the
+    /// pets can do straight away.
+    fn confirm_lock(&mut self, mode: LockMode) -> Option<Action> {
+        let Some(flap) = self.selected_flap() else {
+            // nothing to confirm, perform says what to select
+            return Some(Action::Lock(mode));
+        };
+        self.notice = Some(format!(
+            "Set {} to {}? y to confirm, any other key to cancel",
+            flap.name, mode
+        ));
+        self.confirming = Some(Action::Lock(mode));
+        None
+    }
+
+    /// The details of the selected pet or device.
+    fn details(&mut self) -> Option<Prompt> {
+        match self.section() {
+            Panel::Pets => Some(Prompt::PetInfo),
+            Panel::Devices => Some(Prompt::DeviceInfo),
+            _ => {
+                self.notice = Some("Select a pet or device for its details".to_string());
+                None
+            }
+        }
+    }
+
+    fn move_by(&mut self, down: bool) {
+        match self.focus {
+            Focus::Sidebar => {
+                self.section = if down {
+                    (self.section + 1).min(self.layout.panels.len() - 1)
+                } else {
+                    self.section.saturating_sub(1)
+                };
+                self.rows = TableState::default();
+            }
+            Focus::Pane if down => self.rows.select_next(),
+            Focus::Pane => self.rows.select_previous(),
+        }
+    }
+}
+
+/// The cells in `keep`, in order.
+fn columns<T>(cells: Vec<T>, keep: &[usize]) -> Vec<T> {
+    cells
+        .into_iter()
+        .enumerate()
+        .filter(|(column, _)| keep.contains(column))
+        .map(|(_, cell)| cell)
+        .collect()
+}
+
+/// One block character per value, as tall as the value is against the
+/// largest; days with nothing recorded are blank.
+fn sparkline(values: &[f64]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().copied().fold(0.0, f64::max);
+    values
+        .iter()
+        .map(|value| match *value {
+            v if v <= 0.0 => ' ',
+            v => BLOCKS[((v / max * 8.0).ceil() as usize).clamp(1, 8) - 1],
+        })
+        .collect()
+}
+
+/// Ring the terminal bell, as [interactive] alert_bell asks.
+fn ring_bell() {
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+}
+
+/// Make `action`'s change, or say why it wasn't, for the status bar.
+async fn perform(
+    app: &App<'_>,
+    api_client: &impl SurePetApi,
+    token: &str,
+    action: Action,
+) -> String {
+    let snapshot = &app.snapshot;
+    match action {
+        Action::Lock(mode) => {
+            let Some(flap) = app.selected_flap() else {
+                return "Select a flap under Devices first".to_string();
+            };
+            if api_client.dry_run() {
+                return format!("Dry run, {} not set to {}", flap.name, mode);
+            }
+            let operation = PendingOperation::lock_mode(flap, mode);
+            sent_status(
+                api_client,
+                token,
+                operation,
+                format!("{} set to {}", flap.name, mode),
+            )
+            .await
+        }
+        Action::Move(location) => {
+            let Some(pet) = app
+                .selected(Panel::Pets)
+                .and_then(|row| snapshot.pets.get(row))
+            else {
+                return "Select a pet under Pets first".to_string();
+            };
+            if api_client.dry_run() {
+                return format!("Dry run, {} not set to {}", pet.name, location);
+            }
+            let operation = PendingOperation::position(pet, location);
+            sent_status(
+                api_client,
+                token,
+                operation,
+                format!("{} set to {}", pet.name, location),
+            )
+            .await
+        }
+    }
+}
+
+/// Make `operation`, queueing it if the API can't be reached, and say how
+/// it went with `done` when it was made.
+async fn sent_status(
+    api_client: &impl SurePetApi,
+    token: &str,
+    operation: PendingOperation,
+    done: String,
+) -> String {
+    let describe = operation.describe();
+    match pending::apply_or_queue(api_client, token, operation).await {
+        Ok(Sent::Applied) => done,
+        Ok(Sent::Queued) => format!("The API can't be reached, queued: {}", describe),
+        Err(e) => format!("Couldn't {}: {}", describe, e),
+    }
+}
+
+/// The full-screen interface: a sidebar of sections, a scrollable pane for
+/// the one selected, and a status bar. Refreshes follow the same
+/// [interactive] intervals as the simple views, and the selected flap or pet
+/// can be changed without leaving it. The menu's other prompts are a key
+/// away, for the pet or device selected.
+pub async fn run(
+    api_client: &impl SurePetApi,
+    token: &str,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    let snapshot = load(api_client, token).await?;
+    INTERRUPTED.store(false, Ordering::Relaxed);
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, api_client, token, snapshot, assume_yes).await;
+    ratatui::restore();
+    result
+}
+
+/// Leave the full screen for the layout editor, saving what it's changed
+/// to, and come back.
+fn edit_layout(terminal: &mut DefaultTerminal, app: &mut App) -> std::io::Result<()> {
+    ratatui::restore();
+    let edited = dashboard::edit(&app.layout);
+    *terminal = ratatui::try_init()?;
+
+    match edited {
+        Ok(layout) => {
+            layout.save()?;
+            app.layout = layout;
+            app.section = 0;
+            app.rows = TableState::default();
+            app.notice = Some("Layout saved".to_string());
+        }
+        Err(_) => app.notice = Some("Layout unchanged".to_string()),
+    }
+    Ok(())
+}
+
+/// Leave the full screen to run `prompt` as the menu would, for the pet or
+/// device selected, and come back once a key is pressed.
+async fn run_prompt(
+    terminal: &mut DefaultTerminal,
+    app: &mut App<'_>,
+    api_client: &impl SurePetApi,
+    token: &str,
+    prompt: Prompt,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    if let Some(capability) = prompt.needs() {
+        let capabilities = Capabilities::from_devices(&app.snapshot.devices);
+        if let Err(e) = capabilities.require(capability) {
+            app.notice = Some(e.to_string());
+            return Ok(());
+        }
+    }
+    let pending = match prompt {
+        Prompt::Pending => Pending::load()?,
+        _ => Pending::default(),
+    };
+    if matches!(prompt, Prompt::Pending) && pending.operations.is_empty() {
+        app.notice = Some("No changes are waiting for the API".to_string());
+        return Ok(());
+    }
+    let pet = app
+        .selected(Panel::Pets)
+        .and_then(|row| app.snapshot.pets.get(row))
+        .map(|pet| pet.id);
+    let device = app
+        .selected(Panel::Devices)
+        .and_then(|row| app.snapshot.devices.get(row))
+        .map(|device| device.id);
+
+    ratatui::restore();
+    let result = match prompt {
+        Prompt::PetInfo => crate::do_pet_info(api_client, token, pet).await,
+        Prompt::DeviceInfo => crate::do_device_info(api_client, token, device).await,
+        Prompt::FeedingHistory => crate::do_feeding_history(api_client, token, pet).await,
+        Prompt::Access => crate::do_pet_permissions(api_client, token, pet, assume_yes).await,
+        Prompt::HealthCheck => crate::do_health_check(api_client, token, pet).await,
+        Prompt::Search => crate::do_search(api_client, token).await,
+        Prompt::Pending => crate::do_pending(api_client, token, pending, assume_yes).await,
+    };
+    // a prompt left with Esc has nothing to show
+    if result.is_ok() {
+        println!("\n{}", console::style("Press any key to go back").dim());
+        console::Term::stdout().read_key()?;
+    }
+    *terminal = ratatui::try_init()?;
+    if let Err(e) = result {
+        app.notice = Some(e.to_string());
+    }
+    Ok(())
+}
+
+async fn event_loop(
+    terminal: &mut DefaultTerminal,
+    api_client: &impl SurePetApi,
+    token: &str,
+    snapshot: Snapshot,
+    assume_yes: bool,
+) -> std::io::Result<()> {
+    let cfg = &api_client.cfg().interactive;
+    let notify = &api_client.cfg().notifications;
+    let auto_interval = Duration::from_secs(cfg.auto_refresh_interval);
+    let min_interval = Duration::from_secs(cfg.min_refresh_interval);
+
+    let mut app = App {
+        units: &api_client.cfg().units,
+        snapshot,
+        layout: dashboard::Layout::load()?,
+        section: 0,
+        focus: Focus::Sidebar,
+        rows: TableState::default(),
+        auto_refresh: !auto_interval.is_zero(),
+        error: None,
+        notice: None,
+        confirming: None,
+        first_seen: HashMap::new(),
+    };
+    // only alerts that appear while watching ring the bell
+    app.track_alerts();
+    // the same changes watch notifies, for the desktop
+    let subscriptions = Subscriptions::load()?;
+    let mut tracker = StateTracker::default();
+    let mut changes = |snapshot: &Snapshot| {
+        let found = tracker.update(&snapshot.pets, &snapshot.devices, notify, &subscriptions);
+        if notify.desktop {
+            notifications::show_on_desktop(&found);
+        }
+    };
+    changes(&app.snapshot);
+    let mut refreshed = Instant::now();
+
+    loop {
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        terminal.draw(|frame| app.draw(frame))?;
+
+        let mut refresh =
+            app.auto_refresh && !auto_interval.is_zero() && refreshed.elapsed() >= auto_interval;
+        // r skips the cache, auto-refresh goes by its TTLs like any command
+        let mut fresh = false;
+        let mut action = None;
+        let mut prompt = None;
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                app.notice = None;
+                if let Some(confirming) = app.confirming.take() {
+                    if key.code == KeyCode::Char('y') {
+                        action = Some(confirming);
+                    } else {
+                        app.notice = Some("Cancelled".to_string());
+                    }
+                } else {
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            return Ok(())
+                        }
+                        KeyCode::Tab => {
+                            app.focus = match app.focus {
+                                Focus::Sidebar => Focus::Pane,
+                                Focus::Pane => Focus::Sidebar,
+                            }
+                        }
+                        KeyCode::Enter if app.focus == Focus::Pane => prompt = app.details(),
+                        KeyCode::Right | KeyCode::Enter => app.focus = Focus::Pane,
+                        KeyCode::Left => app.focus = Focus::Sidebar,
+                        KeyCode::Down | KeyCode::Char('j') => app.move_by(true),
+                        KeyCode::Up | KeyCode::Char('k') => app.move_by(false),
+                        KeyCode::Char('l') => action = app.confirm_lock(LockMode::LockedAll),
+                        KeyCode::Char('u') => action = app.confirm_lock(LockMode::Unlocked),
+                        KeyCode::Char('i') => action = Some(Action::Move(Location::Inside)),
+                        KeyCode::Char('o') => action = Some(Action::Move(Location::Outside)),
+                        KeyCode::Char('a') => prompt = Some(Prompt::Access),
+                        KeyCode::Char('f') => prompt = Some(Prompt::FeedingHistory),
+                        KeyCode::Char('h') => prompt = Some(Prompt::HealthCheck),
+                        KeyCode::Char('s') => prompt = Some(Prompt::Search),
+                        KeyCode::Char('c') => prompt = Some(Prompt::Pending),
+                        KeyCode::Char('e') => edit_layout(terminal, &mut app)?,
+                        KeyCode::Char('p') if auto_interval.is_zero() => {
+                            app.notice = Some("Auto-refresh is disabled in the config".to_string())
+                        }
+                        KeyCode::Char('p') => app.auto_refresh = !app.auto_refresh,
+                        KeyCode::Char('r') if refreshed.elapsed() >= min_interval => {
+                            refresh = true;
+                            fresh = true;
+                        }
+                        KeyCode::Char('r') => {
+                            app.notice = Some(format!(
+                                "Refreshed {}s ago, try again shortly",
+                                refreshed.elapsed().as_secs()
+                            ))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if let Some(action) = action {
+            app.notice = Some(perform(&app, api_client, token, action).await);
+            // show the change straight away
+            refresh = true;
+        }
+        if let Some(prompt) = prompt {
+            run_prompt(terminal, &mut app, api_client, token, prompt, assume_yes).await?;
+            // prompts can change pets' access or make queued changes
+            refresh = true;
+        }
+
+        if refresh {
+            refreshed = Instant::now();
+            let cache_mode = api_client.cache_mode();
+            if fresh && cache_mode == CacheMode::Use {
+                api_client.set_cache_mode(CacheMode::Refresh);
+            }
+            let loaded = load(api_client, token).await;
+            api_client.set_cache_mode(cache_mode);
+            match loaded {
+                Ok(snapshot) => {
+                    app.snapshot = snapshot;
+                    app.error = None;
+                    changes(&app.snapshot);
+                    if app.track_alerts() && cfg.alert_bell {
+                        ring_bell();
+                    }
+                }
+                Err(e) => app.error = Some(e.to_string()),
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use chrono::{
+    DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone,
+    Timelike, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+const GRAMS_PER_OUNCE: f64 = 28.349523125;
+/// US fluid ounces
+const ML_PER_FL_OZ: f64 = 29.5735295625;
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Weight {
+    #[default]
+    Grams,
+    Ounces,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Volume {
+    #[default]
+    Ml,
+    FlOz,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+pub enum Clock {
+    #[default]
+    #[serde(rename = "24h")]
+    TwentyFourHour,
+    #[serde(rename = "12h")]
+    TwelveHour,
+}
+
+impl Weight {
+    /// `grams` in this unit.
+    pub fn amount(self, grams: f64) -> f64 {
+        match self {
+            Weight::Grams => grams,
+            Weight::Ounces => grams / GRAMS_PER_OUNCE,
+        }
+    }
+
+    /// The name of an exported column of weights in this unit.
+    pub fn column(self) -> &'static str {
+        match self {
+            Weight::Grams => "grams",
+            Weight::Ounces => "ounces",
+        }
+    }
+}
+
+impl Volume {
+    /// `ml` in this unit.
+    pub fn amount(self, ml: f64) -> f64 {
+        match self {
+            Volume::Ml => ml,
+            Volume::FlOz => ml / ML_PER_FL_OZ,
+        }
+    }
+
+    /// The name of an exported column of volumes in this unit.
+    pub fn column(self) -> &'static str {
+        match self {
+            Volume::Ml => "ml",
+            Volume::FlOz => "fl_oz",
+        }
+    }
+}
+
+/// The `[units]` section, how amounts and times are shown to people. CSV and
+/// JSON exports are written in these units and zone too, while the database
+/// and line protocol keep grams, millilitres and UTC.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Units {
+    pub(crate) weight: Weight,
+    pub(crate) volume: Volume,
+    pub(crate) clock: Clock,
+    /// strftime format for dates
+    pub(crate) date_format: String,
+    /// strftime format for times of day, instead of the clock's
+    pub(crate) time_format: Option<String>,
+    /// IANA zone times are shown in, the system's when unset
+    pub(crate) timezone: Option<Tz>,
+}
+
+impl Units {
+    /// `grams` in the preferred unit, with its symbol.
+    pub fn weight(&self, grams: f64, decimals: usize) -> String {
+        let amount = self.weight.amount(grams);
+        match self.weight {
+            Weight::Grams => format!("{:.*}g", decimals, amount),
+            Weight::Ounces => format!("{:.*}oz", decimals, amount),
+        }
+    }
+
+    /// `ml` in the preferred unit, with its symbol.
+    pub fn volume(&self, ml: f64, decimals: usize) -> String {
+        let amount = self.volume.amount(ml);
+        match self.volume {
+            Volume::Ml => format!("{:.*}ml", decimals, amount),
+            Volume::FlOz => format!("{:.*}fl oz", decimals, amount),
+        }
+    }
+
+    /// Date and time in the preferred zone and formats. Every time shown to
+    /// people goes through here.
+    pub fn time<Z: TimeZone>(&self, time: &DateTime<Z>) -> String {
+        let format = format!("{} {}", self.date_format, self.time_format());
+        let utc = time.with_timezone(&Utc);
+        match self.timezone {
+            Some(tz) => utc.with_timezone(&tz).format(&format).to_string(),
+            None => utc.with_timezone(&Local).format(&format).to_string(),
+        }
+    }
+
+    /// The preferred zone's offset from UTC at `time`, for timestamps that
+    /// have to stay exact.
+    pub fn offset_at(&self, time: &DateTime<Utc>) -> FixedOffset {
+        match self.timezone {
+            Some(tz) => time.with_timezone(&tz).offset().fix(),
+            None => time.with_timezone(&Local).offset().fix(),
+        }
+    }
+
+    /// The calendar day `time` falls on, in the preferred zone.
+    pub fn day_of(&self, time: &DateTime<Utc>) -> NaiveDate {
+        match self.timezone {
+            Some(tz) => time.with_timezone(&tz).date_naive(),
+            None => time.with_timezone(&Local).date_naive(),
+        }
+    }
+
+    /// Midnight at the start of `date` in the preferred zone, or 1am where
+    /// the clocks going forward skip midnight.
+    pub fn start_of_date(&self, date: NaiveDate) -> DateTime<Utc> {
+        self.at(date, NaiveTime::MIN)
+            .unwrap_or_else(|| date.and_time(NaiveTime::MIN).and_utc())
+    }
+
+    /// `time` of day on `date` in the preferred zone, an hour late where the
+    /// clocks going forward skip it.
+    pub fn at(&self, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+        let local = |at: NaiveDateTime| match self.timezone {
+            Some(tz) => tz.from_local_datetime(&at).earliest().map(|t| t.to_utc()),
+            None => Local
+                .from_local_datetime(&at)
+                .earliest()
+                .map(|t| t.to_utc()),
+        };
+        let at = date.and_time(time);
+        local(at).or_else(|| local(at + chrono::Duration::hours(1)))
+    }
+
+    /// Just the date `time` falls on, in the preferred zone and format.
+    pub fn date(&self, time: &DateTime<Utc>) -> String {
+        match self.timezone {
+            Some(tz) => time
+                .with_timezone(&tz)
+                .format(&self.date_format)
+                .to_string(),
+            None => time
+                .with_timezone(&Local)
+                .format(&self.date_format)
+                .to_string(),
+        }
+    }
+
+    /// The hour of the day `time` falls in, in the preferred zone.
+    pub fn hour_of(&self, time: &DateTime<Utc>) -> u32 {
+        match self.timezone {
+            Some(tz) => time.with_timezone(&tz).hour(),
+            None => time.with_timezone(&Local).hour(),
+        }
+    }
+
+    /// The day of the week `time` falls on, in the preferred zone.
+    pub fn weekday_of(&self, time: &DateTime<Utc>) -> Weekday {
+        match self.timezone {
+            Some(tz) => time.with_timezone(&tz).weekday(),
+            None => time.with_timezone(&Local).weekday(),
+        }
+    }
+
+    /// The time of day `time` falls at, in the preferred zone.
+    pub fn time_of_day(&self, time: &DateTime<Utc>) -> NaiveTime {
+        match self.timezone {
+            Some(tz) => time.with_timezone(&tz).time(),
+            None => time.with_timezone(&Local).time(),
+        }
+    }
+
+    /// Midnight at the start of the day `time` falls in, in the preferred zone.
+    pub fn start_of_day(&self, time: &DateTime<Utc>) -> DateTime<Utc> {
+        // from the date rather than the time of day, which is an hour out
+        // once the clocks have changed that day
+        self.start_of_date(self.day_of(time))
+    }
+
+    fn time_format(&self) -> &str {
+        match (&self.time_format, self.clock) {
+            (Some(format), _) => format,
+            (None, Clock::TwentyFourHour) => "%H:%M",
+            (None, Clock::TwelveHour) => "%I:%M %p",
+        }
+    }
+
+    /// The start of an hour of the day, such as 14:00 or 2pm.
+    pub fn hour(&self, hour: u32) -> String {
+        match self.clock {
+            Clock::TwentyFourHour => format!("{:02}:00", hour),
+            Clock::TwelveHour => format!(
+                "{}{}",
+                (hour + 11) % 12 + 1,
+                if hour < 12 { "am" } else { "pm" }
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    fn units(clock: Clock) -> Units {
+        let mut units = config::built_in().units;
+        units.clock = clock;
+        units.timezone = Some(chrono_tz::Europe::London);
+        units
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn weights_convert_to_ounces_and_back() {
+        for grams in [0.0, 1.0, 28.349523125, 85.5, 1000.0] {
+            assert!(close(Weight::Grams.amount(grams), grams));
+            assert!(close(Weight::Ounces.amount(grams) * GRAMS_PER_OUNCE, grams));
+        }
+        assert!(close(Weight::Ounces.amount(GRAMS_PER_OUNCE * 3.5), 3.5));
+    }
+
+    #[test]
+    fn volumes_convert_to_fluid_ounces_and_back() {
+        for ml in [0.0, 1.0, 29.5735295625, 250.0, 1000.0] {
+            assert!(close(Volume::Ml.amount(ml), ml));
+            assert!(close(Volume::FlOz.amount(ml) * ML_PER_FL_OZ, ml));
+        }
+        assert!(close(Volume::FlOz.amount(ML_PER_FL_OZ * 8.0), 8.0));
+    }
+
+    #[test]
+    fn amounts_are_shown_with_their_symbol() {
+        let mut units = units(Clock::TwentyFourHour);
+        assert_eq!(units.weight(12.345, 1), "12.3g");
+        assert_eq!(units.volume(250.0, 0), "250ml");
+        units.weight = Weight::Ounces;
+        units.volume = Volume::FlOz;
+        assert_eq!(units.weight(GRAMS_PER_OUNCE * 2.0, 2), "2.00oz");
+        assert_eq!(units.volume(ML_PER_FL_OZ / 2.0, 1), "0.5fl oz");
+    }
+
+    #[test]
+    fn times_follow_the_clock_in_the_preferred_zone() {
+        // 14:05 in London, an hour ahead of UTC in summer
+        let time: DateTime<Utc> = "2024-07-01T13:05:00Z".parse().unwrap();
+        assert_eq!(units(Clock::TwentyFourHour).time(&time), "2024-07-01 14:05");
+        assert_eq!(units(Clock::TwelveHour).time(&time), "2024-07-01 02:05 PM");
+
+        let mut custom = units(Clock::TwelveHour);
+        custom.time_format = Some("%H.%M".to_string());
+        assert_eq!(custom.time(&time), "2024-07-01 14.05");
+    }
+
+    #[test]
+    fn hours_follow_the_clock() {
+        let (h24, h12) = (units(Clock::TwentyFourHour), units(Clock::TwelveHour));
+        assert_eq!(h24.hour(0), "00:00");
+        assert_eq!(h24.hour(13), "13:00");
+        assert_eq!(h12.hour(0), "12am");
+        assert_eq!(h12.hour(11), "11am");
+        assert_eq!(h12.hour(12), "12pm");
+        assert_eq!(h12.hour(23), "11pm");
+    }
+}
@@ -0,0 +1,295 @@
+use crate::api::client::Pet;
+use crate::api::SurePetApi;
+use crate::config;
+use crate::health::{self, DrinkingTrend};
+use crate::outings;
+use crate::report::{escape, push_list, STYLE};
+use crate::sqlite;
+use crate::stats::format_duration;
+use crate::units::Units;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Error;
+use std::path::Path;
+
+/// Days at each end of the range compared to tell whether intake changed.
+const COMPARED_DAYS: usize = 7;
+/// Days without meals named one by one, beyond which they are only counted.
+const LISTED_DAYS: usize = 7;
+
+/// One day of a pet's intake and activity, in the [units] timezone.
+pub struct VetDay {
+    pub start: DateTime<Utc>,
+    pub meals: usize,
+    pub grams: f64,
+    pub drinks: usize,
+    pub ml: f64,
+    pub outings: usize,
+    pub minutes_outside: i64,
+}
+
+/// What a vet asks about one pet over a range: appetite, thirst, activity,
+/// and anything that stands out.
+pub struct VetReport {
+    pub pet: String,
+    pub location: String,
+    pub generated_at: DateTime<Utc>,
+    /// Oldest first, ending with today
+    pub days: Vec<VetDay>,
+    pub trend: DrinkingTrend,
+    pub anomalies: Vec<String>,
+}
+
+/// Fetch the pet's history from the API, or with `db` from the history kept
+/// by `sync`, and sum it up a day at a time.
+pub async fn generate(
+    api_client: &impl SurePetApi,
+    token: &str,
+    pet: &Pet,
+    range: Duration,
+    db: Option<&Path>,
+) -> std::io::Result<VetReport> {
+    let cfg = api_client.cfg();
+    let now = Utc::now();
+    let days = range.num_days().max(1);
+    let first_day = cfg.units.start_of_day(&now) - Duration::days(days - 1);
+    // the drinking trend needs its baseline even for a short range
+    let from = first_day.min(now - health::drinking_lookback(&cfg.health));
+    let history = match db {
+        Some(db) => sqlite::pet_report(db, pet.id, from, now).map_err(Error::other)?,
+        None => api_client
+            .get_pet_report(token, pet, from, now)
+            .await
+            .map_err(Error::other)?,
+    };
+    let feeding = history.feeding.map(|f| f.datapoints).unwrap_or_default();
+    let drinking = history.drinking.map(|d| d.datapoints).unwrap_or_default();
+    let movement = history.movement.map(|m| m.datapoints).unwrap_or_default();
+
+    let outside = outings::outing_stats(&movement, days, now, &HashMap::new(), &cfg.units);
+    let days: Vec<VetDay> = outside
+        .days
+        .iter()
+        .map(|day| {
+            let on_day = |at: DateTime<Utc>| at >= day.start && at < day.start + Duration::days(1);
+            let meals: Vec<_> = feeding.iter().filter(|e| on_day(e.from)).collect();
+            let drinks: Vec<_> = drinking.iter().filter(|e| on_day(e.from)).collect();
+            VetDay {
+                start: day.start,
+                meals: meals.len(),
+                grams: meals.iter().fold(0.0, |sum, e| sum + e.grams_eaten()),
+                drinks: drinks.len(),
+                ml: drinks.iter().fold(0.0, |sum, e| sum + e.ml_drunk()),
+                outings: day.outings,
+                minutes_outside: day.minutes,
+            }
+        })
+        .collect();
+
+    let mut report = VetReport {
+        pet: pet.name.clone(),
+        location: match &pet.position {
+            Some(p) => format!("{} since {}", p.location, cfg.units.time(&p.since)),
+            None => "unknown".to_string(),
+        },
+        generated_at: now,
+        trend: health::drinking_trend(&drinking, now, &cfg.health),
+        anomalies: Vec::new(),
+        days,
+    };
+    report.anomalies = anomalies(&report, outside.unpaired, &cfg.health, &cfg.units);
+    Ok(report)
+}
+
+fn anomalies(
+    report: &VetReport,
+    unpaired: usize,
+    cfg: &config::Health,
+    units: &Units,
+) -> Vec<String> {
+    let mut anomalies = Vec::new();
+    if report.trend.frequency_alert {
+        anomalies.push("Drinking more often than usual, sustained".to_string());
+    }
+    if report.trend.volume_alert {
+        anomalies.push("Drinking more per visit than usual, sustained".to_string());
+    }
+
+    // today is still going, so it isn't a day without meals yet, and days
+    // before the first meal recorded are before the feeder was set up
+    let past = &report.days[..report.days.len() - 1];
+    let fed = past.iter().position(|d| d.meals > 0).unwrap_or(past.len());
+    let fasting: Vec<String> = past[fed..]
+        .iter()
+        .filter(|d| d.meals == 0)
+        .map(|d| units.date(&d.start))
+        .collect();
+    match fasting.len() {
+        0 => {}
+        1..=LISTED_DAYS => anomalies.push(format!("No meals recorded on {}", fasting.join(", "))),
+        n => anomalies.push(format!("No meals recorded on {} days", n)),
+    }
+
+    if past.len() >= COMPARED_DAYS * 2 {
+        let (first, last) = (&past[..COMPARED_DAYS], &past[past.len() - COMPARED_DAYS..]);
+        let changes = [
+            (
+                "Food",
+                average(first, |d| d.grams),
+                average(last, |d| d.grams),
+            ),
+            ("Water", average(first, |d| d.ml), average(last, |d| d.ml)),
+            (
+                "Time outside",
+                average(first, |d| d.minutes_outside as f64),
+                average(last, |d| d.minutes_outside as f64),
+            ),
+        ];
+        for (name, before, after) in changes {
+            let change = (after - before) / before * 100.0;
+            if before > 0.0 && change.abs() >= cfg.intake_change_percent {
+                anomalies.push(format!(
+                    "{} {} by {:.0}% between the first and last week",
+                    name,
+                    if after > before { "up" } else { "down" },
+                    change.abs()
+                ));
+            }
+        }
+    }
+    if unpaired > 0 {
+        anomalies.push(format!(
+            "{} trip(s) outside left out, the flap missed the return",
+            unpaired
+        ));
+    }
+    anomalies
+}
+
+fn average(days: &[VetDay], value: impl Fn(&VetDay) -> f64) -> f64 {
+    days.iter().fold(0.0, |sum, d| sum + value(d)) / days.len().max(1) as f64
+}
+
+/// A printable page: averages, charts of each day, flagged anomalies, and
+/// every day in a table at the end.
+pub fn render_html(report: &VetReport, units: &Units) -> String {
+    let days = &report.days;
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>
+<html><head><meta charset=\"utf-8\"><title>Vet report for {name}</title>
+<style>{style}
+svg {{ display: block; margin: 0.3em 0 1em; }}
+</style></head><body>
+<h1>Vet report for {name}</h1>
+<p>{from} to {to}, {count} days. Currently {location}.</p>
+<p>Body weight isn't recorded by the devices, so isn't included.</p>
+<h2>Flagged</h2>
+",
+        name = escape(&report.pet),
+        style = STYLE,
+        from = units.date(&days[0].start),
+        to = units.date(&report.generated_at),
+        count = days.len(),
+        location = escape(&report.location),
+    );
+    if report.anomalies.is_empty() {
+        html.push_str("<p class=\"ok\">Nothing unusual</p>\n");
+    }
+    push_list(&mut html, "alert", &report.anomalies);
+
+    let _ = write!(
+        html,
+        "<h2>A day on average</h2>
+<table>
+<tr><td>Meals</td><td>{:.1}</td></tr>
+<tr><td>Food</td><td>{}</td></tr>
+<tr><td>Drinks</td><td>{:.1}</td></tr>
+<tr><td>Water</td><td>{}</td></tr>
+<tr><td>Trips outside</td><td>{:.1}</td></tr>
+<tr><td>Time outside</td><td>{}</td></tr>
+</table>
+<p>Drinking {:.1} times a day, {} a visit lately (baseline {:.1} times, {})</p>
+",
+        average(days, |d| d.meals as f64),
+        units.weight(average(days, |d| d.grams), 0),
+        average(days, |d| d.drinks as f64),
+        units.volume(average(days, |d| d.ml), 0),
+        average(days, |d| d.outings as f64),
+        format_duration(Duration::minutes(
+            average(days, |d| d.minutes_outside as f64) as i64
+        )),
+        report.trend.current.visits_per_day,
+        units.volume(report.trend.current.avg_ml, 1),
+        report.trend.baseline.visits_per_day,
+        units.volume(report.trend.baseline.avg_ml, 1),
+    );
+
+    html.push_str("<section><h2>Food each day</h2>\n");
+    html.push_str(&bar_chart(days.iter().map(|d| d.grams), "#e09f3e"));
+    html.push_str("</section>\n<section><h2>Water each day</h2>\n");
+    html.push_str(&bar_chart(days.iter().map(|d| d.ml), "#3a86c8"));
+    html.push_str("</section>\n<section><h2>Time outside each day</h2>\n");
+    html.push_str(&bar_chart(
+        days.iter().map(|d| d.minutes_outside as f64),
+        "#4c9a55",
+    ));
+    html.push_str("</section>\n");
+
+    html.push_str(
+        "<h2>Every day</h2>
+<table>
+<tr><th>Date</th><th>Meals</th><th>Food</th><th>Drinks</th><th>Water</th>\
+<th>Trips outside</th><th>Time outside</th></tr>
+",
+    );
+    for day in days {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+             <td>{}</td><td>{}</td></tr>",
+            units.date(&day.start),
+            day.meals,
+            units.weight(day.grams, 0),
+            day.drinks,
+            units.volume(day.ml, 0),
+            day.outings,
+            match day.minutes_outside {
+                0 => "-".to_string(),
+                minutes => format_duration(Duration::minutes(minutes)),
+            },
+        );
+    }
+    html.push_str("</table>\n</body></html>\n");
+    html
+}
+
+/// Bars for each day, oldest on the left, scaled to the highest, as inline
+/// SVG so the page stays a single file.
+fn bar_chart(values: impl Iterator<Item = f64>, colour: &str) -> String {
+    const WIDTH: f64 = 720.0;
+    const HEIGHT: f64 = 80.0;
+    let values: Vec<f64> = values.collect();
+    let max = values.iter().copied().fold(0.0, f64::max);
+    let bar = WIDTH / values.len().max(1) as f64;
+    let mut svg = format!(
+        "<svg width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\">\n",
+        WIDTH, HEIGHT
+    );
+    for (i, value) in values.iter().enumerate() {
+        let height = if max > 0.0 { value / max * HEIGHT } else { 0.0 };
+        let _ = writeln!(
+            svg,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+            i as f64 * bar,
+            HEIGHT - height,
+            (bar - 1.0).max(1.0),
+            height,
+            colour
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
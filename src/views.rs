@@ -0,0 +1,787 @@
+use crate::api::client::{Device, DrinkingEvent, FeedingEvent, Location, Pet, PetProfile, Signal};
+use crate::battery::{self, Outlook};
+use crate::compare::{Comparison, MetricKind};
+use crate::curfew::{self, Preview};
+use crate::device_info::{Activity, DeviceInfo};
+use crate::events::{EventHit, EventType};
+use crate::forecast::{self, Forecast};
+use crate::history::{GroupBy, Period};
+use crate::outings::{Heatmap, Outing, OutingStats};
+use crate::output::{self, OutputFormatter, PetAnomalies, Verbosity};
+use crate::overview::Overview;
+use crate::pager;
+use crate::pending::{Outcome, Pending, PendingOperation};
+use crate::pet_info::PetInfo;
+use crate::schedule::Schedule;
+use crate::search::SearchHit;
+use crate::signal::{self, SignalHistory};
+use crate::snooze::Snoozes;
+use crate::stats;
+use crate::table::{self, TableFormatter};
+use crate::units::Units;
+use chrono::{DateTime, Utc};
+use console::style;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Shared by the interactive views and the matching headless commands.
+pub fn print_pets(pets: &[Pet], units: &Units) {
+    let table = TableFormatter {
+        units,
+        compact: false,
+    }
+    .pets(pets);
+    pager::page(&format!("{}\n{}", style("Pets").bold(), table));
+}
+
+pub fn print_devices(devices: &[Device], units: &Units) {
+    let table = TableFormatter {
+        units,
+        compact: false,
+    }
+    .devices(devices);
+    pager::page(&format!("{}\n{}", style("Devices").bold(), table));
+}
+
+pub fn print_device_info(info: &DeviceInfo, units: &Units) {
+    let device = info.device;
+    println!("{}", style(&device.name).bold());
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let battery = device.status.as_ref().and_then(|s| s.battery).map(|volts| {
+        match device.battery_percent() {
+            Some(percent) => format!("{:.2}V, {}%", volts, percent),
+            None => format!("{:.2}V, -", volts),
+        }
+    });
+    let online = device
+        .online()
+        .map(|online| if online { "online" } else { "offline" }.to_string());
+    let details = [
+        ("Id", Some(device.id.to_string())),
+        ("Serial", device.serial_number.clone()),
+        ("MAC", device.mac_address.clone()),
+        ("Hardware", device.hardware()),
+        (
+            "Firmware",
+            device.firmware().map(|firmware| {
+                if device.update_pending() {
+                    format!("{}, update waiting to install", firmware)
+                } else {
+                    firmware
+                }
+            }),
+        ),
+        ("Signal", device.signal().map(signal_text)),
+        ("Battery", battery),
+        ("Connection", online),
+        ("Lock", device.lock_mode().map(|m| m.to_string())),
+    ];
+    for (label, value) in details {
+        println!("  {:<11} {}", label, or_dash(value));
+    }
+    let curfews = device.control.iter().flat_map(|c| &c.curfew);
+    for curfew in curfews {
+        let text = curfew::describe(curfew, Utc::now(), units);
+        println!("  {:<11} {}", "Curfew", text);
+    }
+
+    if device.is_flap() {
+        println!("{}", style("Pets").bold());
+        let rows = info.permissions.iter().map(|(pet, profile)| {
+            let access = match profile {
+                Some(PetProfile::IndoorOnly) => "kept indoors",
+                Some(PetProfile::Outdoor) => "may go outdoors",
+                None => "-",
+            };
+            vec![pet.clone(), access.to_string()]
+        });
+        println!("{}", table::table(&["Pet", "Access"], rows));
+    }
+
+    println!("{}", style("Recent events").bold());
+    if info.events.is_empty() {
+        println!("None in the last week");
+    } else {
+        let rows = info.events.iter().map(|event| {
+            let what = activity_text(&event.activity, units);
+            vec![units.time(&event.at), event.pet.clone(), what]
+        });
+        println!("{}", table::table(&["Time", "Pet", "Event"], rows));
+    }
+    if info.partial {
+        println!(
+            "{}",
+            style("Some pets' history couldn't be fetched, so events may be missing").yellow()
+        );
+    }
+}
+
+pub fn print_pet_info(info: &PetInfo, units: &Units) {
+    let pet = &info.pet;
+    println!("{}", style(&pet.name).bold());
+    let location = match (pet.location.map(Location::from), info.minutes_in_location) {
+        (Some(location), Some(minutes)) => format!(
+            "{} for {}",
+            location,
+            stats::format_duration(chrono::Duration::minutes(minutes))
+        ),
+        _ => "unknown".to_string(),
+    };
+    println!("  {:<11} {}", "Location", location);
+    for access in &info.access {
+        let profile = match access.profile {
+            Some(PetProfile::IndoorOnly) => "kept indoors",
+            Some(PetProfile::Outdoor) => "may go outdoors",
+            None => "-",
+        };
+        println!("  {:<11} {} by {}", "Access", profile, access.device);
+    }
+    println!(
+        "  {:<11} ate {}, drank {}",
+        "Today",
+        units.weight(info.today.eaten_g, 1),
+        units.volume(info.today.drunk_ml, 0)
+    );
+    let outside = &info.outside;
+    let duration = |minutes: i64| stats::format_duration(chrono::Duration::minutes(minutes));
+    let mut week = format!("{} outing(s) this week", outside.outings);
+    if let Some(average) = outside.average_minutes {
+        week.push_str(&format!(", {} on average", duration(average)));
+    }
+    if let Some(longest) = &outside.longest {
+        week.push_str(&format!(", longest {}", duration(longest.minutes)));
+    }
+    let today = match outside.days.last().map_or(0, |d| d.minutes) {
+        0 => "not yet".to_string(),
+        minutes => duration(minutes),
+    };
+    println!("  {:<11} {} today, {}", "Outside", today, week);
+    if !info.meal_windows.is_empty() {
+        let usual: Vec<_> = info.meal_windows.iter().map(|w| w.label(units)).collect();
+        println!("  {:<11} usually {}", "Meals", usual.join(", "));
+    }
+    for alert in &info.alerts {
+        println!("  {}", style(format!("{:<11} {}", "Alert", alert)).yellow());
+    }
+
+    println!("{}", style("Recent events").bold());
+    if info.events.is_empty() {
+        println!("None recorded");
+        return;
+    }
+    let rows = info.events.iter().map(|event| {
+        let device = event.device.clone().unwrap_or_else(|| "-".to_string());
+        vec![
+            units.time(&event.at),
+            device,
+            activity_text(&event.activity, units),
+        ]
+    });
+    println!("{}", table::table(&["Time", "Device", "Event"], rows));
+}
+
+pub fn activity_text(activity: &Activity, units: &Units) -> String {
+    match activity {
+        Activity::Ate(grams) => format!("ate {}", units.weight(*grams, 1)),
+        Activity::Drank(ml) => format!("drank {}", units.volume(*ml, 0)),
+        Activity::Outside(Some(back)) => format!("out until {}", units.time(back)),
+        Activity::Outside(None) => "went out, not back yet".to_string(),
+    }
+}
+
+fn signal_text(signal: &Signal) -> String {
+    let dbm = |rssi: Option<f64>| {
+        rssi.map_or("-".to_string(), |r| {
+            format!("{:.0} dBm {}", r, signal::quality(r))
+        })
+    };
+    format!("{}, hub {}", dbm(signal.device_rssi), dbm(signal.hub_rssi))
+}
+
+/// Every meal in `events`, then the totals and how much came from each
+/// feeder.
+pub fn print_feeding_history(
+    pet: &Pet,
+    events: &[FeedingEvent],
+    device_names: &HashMap<u32, String>,
+    units: &Units,
+    compact: bool,
+) {
+    print_feeding_history_page(pet, events, events, device_names, units, compact);
+}
+
+/// The meals on `page`, with the totals and each feeder's share still over
+/// `all` of them.
+pub fn print_feeding_history_page(
+    pet: &Pet,
+    page: &[FeedingEvent],
+    all: &[FeedingEvent],
+    device_names: &HashMap<u32, String>,
+    units: &Units,
+    compact: bool,
+) {
+    let title = style(format!("Feeding history for {}", pet.name)).bold();
+    let table = TableFormatter { units, compact }.feeding_history(page, all, device_names);
+    pager::page(&format!("{}\n{}", title, table));
+}
+
+pub fn print_drinking_history(
+    pet: &Pet,
+    events: &[DrinkingEvent],
+    all: &[DrinkingEvent],
+    device_names: &HashMap<u32, String>,
+    units: &Units,
+    compact: bool,
+) {
+    let title = style(format!("Drinking history for {}", pet.name)).bold();
+    let table = TableFormatter { units, compact }.drinking_history(events, all, device_names);
+    pager::page(&format!("{}\n{}", title, table));
+}
+
+pub fn print_activity_history(
+    pet: &Pet,
+    outings: &[Outing],
+    all: &[Outing],
+    unpaired: usize,
+    units: &Units,
+    compact: bool,
+) {
+    let title = style(format!("Trips outside for {}", pet.name)).bold();
+    let table = TableFormatter { units, compact }.activity_history(outings, all, unpaired);
+    pager::page(&format!("{}\n{}", title, table));
+}
+
+pub fn print_history_periods(
+    title: &str,
+    pet: &Pet,
+    periods: &[Period],
+    all: &[Period],
+    by: GroupBy,
+    event_type: EventType,
+    units: &Units,
+) {
+    let title = style(format!("{} for {}", title, pet.name)).bold();
+    let table = TableFormatter {
+        units,
+        compact: false,
+    }
+    .history_periods(periods, all, by, event_type);
+    pager::page(&format!("{}\n{}", title, table));
+}
+
+/// Say when rows were left out by --limit or [headless] max_history_items.
+pub fn print_shown(shown: usize, total: usize) {
+    if shown < total && output::verbosity() > Verbosity::Quiet {
+        println!(
+            "{}",
+            style(format!(
+                "Showing {} of {}, --limit and --offset show more",
+                shown, total
+            ))
+            .dim()
+        );
+    }
+}
+
+pub fn print_outing_stats(pet: &Pet, outings: &OutingStats, units: &Units) {
+    let duration = |minutes: i64| match minutes {
+        0 => "-".to_string(),
+        _ => stats::format_duration(chrono::Duration::minutes(minutes)),
+    };
+    println!(
+        "{}",
+        style(format!(
+            "Time outside for {} over {} days",
+            pet.name,
+            outings.days.len()
+        ))
+        .bold()
+    );
+    for day in &outings.days {
+        println!(
+            "  {:<12} {:>7}  {} outing(s)",
+            units.date(&day.start),
+            duration(day.minutes),
+            day.outings
+        );
+    }
+    println!(
+        "  {:<12} {} in {} outing(s)",
+        "Total",
+        duration(outings.total_minutes),
+        outings.outings
+    );
+    if let Some(average) = outings.average_minutes {
+        println!("  {:<12} {}", "Average", duration(average));
+    }
+    if let Some(longest) = &outings.longest {
+        println!(
+            "  {:<12} {}, from {}",
+            "Longest",
+            duration(longest.minutes),
+            units.time(&longest.from)
+        );
+    }
+    if outings.unpaired > 0 {
+        println!(
+            "  {}",
+            style(format!(
+                "{} trip(s) left out, the return wasn't scanned",
+                outings.unpaired
+            ))
+            .dim()
+        );
+    }
+}
+
+/// Two characters an hour, shaded from the quietest hour to the busiest.
+pub fn print_heatmap(pet: &Pet, heatmap: &Heatmap, days: i64, units: &Units) {
+    const SHADES: [char; 4] = ['░', '▒', '▓', '█'];
+    let busiest = heatmap.counts.iter().flatten().copied().max().unwrap_or(0);
+    println!(
+        "{}",
+        style(format!("Flap use for {} over {} days", pet.name, days)).bold()
+    );
+    let mut header = "     ".to_string();
+    for hour in heatmap.hours.iter().step_by(3) {
+        header.push_str(&format!("{:<6}", units.hour(*hour)));
+    }
+    println!("{}", header.trim_end());
+    for (day, counts) in heatmap.days.iter().zip(&heatmap.counts) {
+        let cells: String = counts
+            .iter()
+            .map(|count| match *count {
+                0 => "  ".to_string(),
+                n => {
+                    let shade = (n * SHADES.len()).div_ceil(busiest).clamp(1, SHADES.len());
+                    SHADES[shade - 1].to_string().repeat(2)
+                }
+            })
+            .collect();
+        println!("{:<5}{}", day, cells);
+    }
+    if busiest == 0 {
+        println!("No trips through a flap");
+        return;
+    }
+    println!(
+        "{}",
+        style(format!(
+            "{} quietest to {} busiest, {} passage(s) in an hour",
+            SHADES[0],
+            SHADES[SHADES.len() - 1],
+            busiest
+        ))
+        .dim()
+    );
+}
+
+pub fn print_comparison(comparison: &Comparison, units: &Units) {
+    println!(
+        "{}",
+        style(format!(
+            "A day on average over the last {} days",
+            comparison.days
+        ))
+        .bold()
+    );
+    let mut header = vec![""];
+    header.extend(comparison.pets.iter().map(String::as_str));
+    header.push("Spread");
+    let rows = comparison.metrics.iter().map(|metric| {
+        let mut row = vec![metric.name.to_string()];
+        row.extend(metric.values.iter().map(|value| match metric.kind {
+            MetricKind::Count => format!("{:.1}", value),
+            MetricKind::Weight => units.weight(*value, 0),
+            MetricKind::Volume => units.volume(*value, 0),
+            MetricKind::Minutes => stats::format_duration(chrono::Duration::minutes(*value as i64)),
+        }));
+        row.push(match metric.ratio {
+            Some(ratio) => format!("{:.1}x", ratio),
+            None => "-".to_string(),
+        });
+        row
+    });
+    println!("{}", table::table(&header, rows));
+    for divergence in &comparison.divergences {
+        println!("{}", style(divergence).yellow());
+    }
+}
+
+pub fn print_forecast(forecast: &Forecast, units: &Units) {
+    println!("{}", style("Forecast").bold());
+    for line in forecast::describe(forecast, units) {
+        println!("  {}", line);
+    }
+    if forecast.partial {
+        println!(
+            "{}",
+            style("Some pets' history couldn't be fetched, so food use is low").yellow()
+        );
+    }
+}
+
+/// How long a battery has left and its level at the end of each day since
+/// `from`.
+pub fn print_battery(outlook: &Outlook, from: DateTime<Utc>, now: DateTime<Utc>, units: &Units) {
+    println!("{}", style(format!("{} battery", outlook.device)).bold());
+    println!("  {:<10} {}%", "Now", outlook.battery);
+    match outlook.percent_per_day {
+        Some(rate) if rate < 0.0 => {
+            let since = match outlook.replaced {
+                Some(replaced) => {
+                    format!("since the batteries were changed {}", units.date(&replaced))
+                }
+                None => format!("since {}", units.date(&from)),
+            };
+            let drain = format!("{:.1}% a day {}", -rate, since);
+            if outlook.draining_fast {
+                println!(
+                    "  {:<10} {}",
+                    "Draining",
+                    style(format!("{}, fast", drain)).yellow()
+                );
+            } else {
+                println!("  {:<10} {}", "Draining", drain);
+            }
+        }
+        Some(_) => println!("  {:<10} not draining", "Draining"),
+        None => println!(
+            "  {:<10} {}",
+            "Draining",
+            style("not enough levels recorded yet, it takes a day or more").dim()
+        ),
+    }
+    if let Some(low) = outlook.low {
+        println!(
+            "  {:<10} around {}, {}",
+            format!("Below {}%", outlook.threshold),
+            units.date(&low),
+            battery::in_days(now, low)
+        );
+    }
+    if let Some(flat) = outlook.flat {
+        println!(
+            "  {:<10} around {}, {}",
+            "Flat",
+            units.date(&flat),
+            battery::in_days(now, flat)
+        );
+    }
+
+    println!();
+    if outlook.levels.is_empty() {
+        println!("{}", style("No levels recorded in this period").dim());
+        return;
+    }
+    for (date, level) in battery::daily(&outlook.levels, now, units) {
+        println!(
+            "  {}  {} {:>3.0}%",
+            units.date(&units.start_of_date(date)),
+            battery::bar(level),
+            level
+        );
+    }
+}
+
+/// Each flap's curfews as they stand, and any snooze holding them off.
+pub fn print_curfews(flaps: &[&Device], snoozes: &Snoozes, units: &Units) {
+    let now = Utc::now();
+    for flap in flaps {
+        println!("{}", style(&flap.name).bold());
+        let lock = flap.lock_mode().map_or("-".to_string(), |m| m.to_string());
+        println!("  {:<9} {}", "Lock", lock);
+        let curfews: Vec<_> = flap.control.iter().flat_map(|c| &c.curfew).collect();
+        if curfews.is_empty() {
+            println!("  {:<9} none set", "Curfew");
+        }
+        for curfew in curfews {
+            println!("  {:<9} {}", "Curfew", curfew::describe(curfew, now, units));
+        }
+        if let Some(snooze) = snoozes.snoozes.iter().find(|s| s.device_id == flap.id) {
+            println!(
+                "  {}",
+                style(format!(
+                    "{:<9} until {}, then back to {}",
+                    "Snoozed",
+                    units.time(&snooze.until),
+                    snooze.describe()
+                ))
+                .yellow()
+            );
+        }
+    }
+}
+
+pub fn print_signal(history: &SignalHistory, interval: u64, units: &Units) {
+    println!(
+        "{}",
+        style(format!(
+            "Signal strength at {}, every {}s",
+            units.time(&Utc::now()),
+            interval
+        ))
+        .bold()
+    );
+    print!("{}", history.render());
+    println!(
+        "{}",
+        style("Move the hub and watch the bars rise or fall · Ctrl-C to stop").dim()
+    );
+}
+
+pub fn print_curfew_preview(preview: &Preview) {
+    for line in &preview.lines {
+        println!("  {}", line);
+    }
+    for warning in &preview.warnings {
+        println!(
+            "  {}",
+            style(format!("{:<9} {}", "Warning", warning)).yellow()
+        );
+    }
+}
+
+pub fn print_overview(overview: &Overview, units: &Units) {
+    println!(
+        "{}",
+        style(format!(
+            "Household at {}",
+            units.time(&overview.generated_at)
+        ))
+        .bold()
+    );
+    let mut pets = format!("{} inside, {} outside", overview.inside, overview.outside);
+    if overview.unknown > 0 {
+        pets.push_str(&format!(", {} unknown", overview.unknown));
+    }
+    println!("  {:<9} {}", "Pets", pets);
+
+    let devices = &overview.devices;
+    let mut health = format!("{} of {} online", devices.online, devices.total);
+    if !devices.offline.is_empty() {
+        health.push_str(&format!(", offline: {}", devices.offline.join(", ")));
+    }
+    if !devices.low_battery.is_empty() {
+        health.push_str(&format!(
+            ", low battery: {}",
+            devices.low_battery.join(", ")
+        ));
+    }
+    if !devices.update_pending.is_empty() {
+        health.push_str(&format!(
+            ", firmware update waiting: {}",
+            devices.update_pending.join(", ")
+        ));
+    }
+    println!("  {:<9} {}", "Devices", health);
+
+    for curfew in &overview.curfews {
+        println!(
+            "  {:<9} {} {} to {}{}",
+            "Curfew",
+            curfew.device,
+            curfew.lock_time,
+            curfew.unlock_time,
+            if curfew.locked_now {
+                ", locked now"
+            } else {
+                ""
+            }
+        );
+    }
+
+    println!("{}", style("Today").bold());
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let rows = overview.pets.iter().map(|pet| {
+        vec![
+            pet.name.clone(),
+            or_dash(pet.location.clone()),
+            or_dash(pet.grams.map(|g| units.weight(g, 0))),
+            or_dash(pet.ml.map(|ml| units.volume(ml, 0))),
+        ]
+    });
+    println!(
+        "{}",
+        table::table(&["Pet", "Location", "Food", "Water"], rows)
+    );
+    if overview.partial {
+        println!("Some pets' history could not be fetched");
+    }
+}
+
+pub fn print_anomalies(pets: &[PetAnomalies], partial: bool, units: &Units) {
+    for pet in pets {
+        println!("{}", style(&pet.name).bold());
+        if !pet.meal_windows.is_empty() {
+            let usual: Vec<_> = pet.meal_windows.iter().map(|w| w.label(units)).collect();
+            println!("  {:<11} usually {}", "Meals", usual.join(", "));
+        }
+        for anomaly in &pet.anomalies {
+            println!(
+                "  {}",
+                style(format!("{:<11} {}", "Alert", anomaly)).yellow()
+            );
+        }
+        if pet.anomalies.is_empty() {
+            println!("  {}", style("Nothing unusual").green());
+        }
+    }
+    if partial {
+        println!(
+            "{}",
+            style("Some pets' history couldn't be fetched, so they are left out").yellow()
+        );
+    }
+}
+
+pub fn print_pending(pending: &Pending, units: &Units) {
+    if pending.operations.is_empty() {
+        println!("No changes are waiting for the API");
+        return;
+    }
+    println!("{}", style("Waiting for the API").bold());
+    for operation in &pending.operations {
+        println!(
+            "  {}  {}",
+            units.time(&operation.queued_at()),
+            operation.describe()
+        );
+    }
+}
+
+pub fn print_replay(outcomes: &[(PendingOperation, Outcome)]) {
+    for (operation, outcome) in outcomes {
+        match outcome {
+            Outcome::Applied => println!("Done: {}", operation.describe()),
+            Outcome::Conflict(reason) => println!(
+                "{} {}: {}",
+                style("Skipped").yellow(),
+                operation.describe(),
+                reason
+            ),
+            Outcome::Failed(e) => {
+                println!("{} {}: {}", style("Failed").red(), operation.describe(), e)
+            }
+            Outcome::Unreachable => println!(
+                "Still waiting: {}, the API can't be reached",
+                operation.describe()
+            ),
+        }
+    }
+}
+
+/// Warn that what was just shown is the last data fetched before the API
+/// became unreachable. On stderr, so it shows even above piped output.
+pub fn print_offline(as_of: Option<DateTime<Utc>>, units: &Units) {
+    let Some(as_of) = as_of else {
+        return;
+    };
+    eprintln!(
+        "{}",
+        style(format!("Data as of {}, offline", units.time(&as_of))).yellow()
+    );
+}
+
+/// The requests a dry run would have sent, on stderr when stdout is kept
+/// for JSON.
+pub fn print_planned(planned: &[String], to_stderr: bool) {
+    for request in planned {
+        if to_stderr {
+            eprintln!("{}", request);
+        } else {
+            println!("{}", request);
+        }
+    }
+}
+
+pub fn print_schedules(schedules: &[Schedule], now: DateTime<Utc>, units: &Units) {
+    if schedules.is_empty() {
+        println!("Nothing is scheduled, add [[schedule]] tables to rules.toml");
+        return;
+    }
+    let rows = schedules.iter().map(|schedule| {
+        let actions: Vec<String> = schedule.then.iter().map(|a| a.to_string()).collect();
+        vec![
+            schedule.name.clone(),
+            schedule.at.format("%H:%M").to_string(),
+            schedule.days_text(),
+            actions.join(", "),
+            schedule
+                .next_after(now, units)
+                .map_or("-".to_string(), |at| {
+                    format!(
+                        "{} {}",
+                        units.weekday_of(&at),
+                        units.time_of_day(&at).format("%H:%M")
+                    )
+                }),
+        ]
+    });
+    println!(
+        "{}",
+        table::table(&["Schedule", "At", "Days", "Then", "Next"], rows)
+    );
+}
+
+/// `wanted` is what was searched for, as [`crate::search::describe`] puts it.
+pub fn print_search_hits(hits: &[SearchHit], wanted: &str) {
+    for hit in hits {
+        println!("{:<6}  {}", hit.kind, hit.line);
+    }
+    if hits.is_empty() {
+        println!("Nothing matched {}", wanted);
+    }
+}
+
+pub fn print_event_hits(hits: &[EventHit], units: &Units) {
+    if hits.is_empty() {
+        println!("No events matched");
+        return;
+    }
+    let mut lines = Vec::new();
+    for hit in hits {
+        let amount = match hit.event_type {
+            EventType::Feeding => units.weight(hit.amount, 1),
+            EventType::Drinking => units.volume(hit.amount, 0),
+            EventType::Activity => format!(
+                "{} for {}m{}",
+                hit.location.as_deref().unwrap_or_default(),
+                hit.amount.round(),
+                if hit.to.is_none() { " so far" } else { "" }
+            ),
+        };
+        lines.push(format!(
+            "{}  {}  {}  {}",
+            units.time(&hit.from),
+            hit.pet,
+            amount,
+            hit.device.as_deref().unwrap_or_default()
+        ));
+    }
+    pager::page(&lines.join("\n"));
+}
+
+/// Note that what was just shown came from the cache, and how to get live data.
+pub fn print_cache_age(age: Option<Duration>, refresh_hint: &str) {
+    let Some(age) = age else {
+        return;
+    };
+    if output::verbosity() == Verbosity::Quiet {
+        return;
+    }
+    let secs = age.as_secs();
+    let age = match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h", secs / 3600),
+    };
+    println!(
+        "{}",
+        style(format!(
+            "Cached data from {} ago, {} for live data",
+            age, refresh_hint
+        ))
+        .dim()
+    );
+}
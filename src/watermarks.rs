@@ -0,0 +1,201 @@
+use crate::config;
+use crate::export::ExportData;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+const WATERMARKS_FILE: &str = "export_watermarks.toml";
+
+/// The newest event of each type already exported for a pet.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct PetWatermarks {
+    pub pet_id: u32,
+    pub pet: String,
+    pub feeding: Option<DateTime<Utc>>,
+    pub drinking: Option<DateTime<Utc>>,
+    /// Trips starting after this may not have been exported, or may have
+    /// been exported while the pet was still out
+    pub movement: Option<DateTime<Utc>>,
+}
+
+/// How far `export --incremental` has got, so the next run only fetches
+/// and appends newer events.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Watermarks {
+    #[serde(default, rename = "pet")]
+    pub pets: Vec<PetWatermarks>,
+}
+
+/// Every output directory's marks, so an incremental export to a new
+/// directory starts afresh rather than from where another one got to.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Targets {
+    #[serde(default, rename = "target")]
+    targets: Vec<Target>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Target {
+    output_dir: PathBuf,
+    #[serde(default, rename = "pet")]
+    pets: Vec<PetWatermarks>,
+}
+
+impl Targets {
+    fn load() -> std::io::Result<Self> {
+        let path = watermarks_path()?;
+        if !path.exists() {
+            return Ok(Targets::default());
+        }
+        toml::from_str(&fs::read_to_string(path)?).map_err(Error::other)
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let contents = toml::to_string_pretty(self).map_err(Error::other)?;
+        fs::write(watermarks_path()?, contents)
+    }
+
+    fn marks(&self, output_dir: &Path) -> Watermarks {
+        let target = self.targets.iter().find(|t| t.output_dir == output_dir);
+        Watermarks {
+            pets: target.map(|t| t.pets.clone()).unwrap_or_default(),
+        }
+    }
+
+    fn set(&mut self, output_dir: &Path, marks: &Watermarks) {
+        match self.targets.iter_mut().find(|t| t.output_dir == output_dir) {
+            Some(target) => target.pets = marks.pets.clone(),
+            None => self.targets.push(Target {
+                output_dir: output_dir.to_path_buf(),
+                pets: marks.pets.clone(),
+            }),
+        }
+    }
+}
+
+impl PetWatermarks {
+    /// Where fetching has to start for every event type to be caught up.
+    /// Types never exported, such as drinking for a household without a
+    /// water station, don't hold it back.
+    pub fn oldest(&self) -> Option<DateTime<Utc>> {
+        [self.feeding, self.drinking, self.movement]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
+
+impl Watermarks {
+    /// How far incremental exports to `output_dir` have got.
+    pub fn load(output_dir: &Path) -> std::io::Result<Self> {
+        Ok(Targets::load()?.marks(&target(output_dir)?))
+    }
+
+    /// Save as the marks of `output_dir`, keeping other directories' marks.
+    pub fn save(&self, output_dir: &Path) -> std::io::Result<()> {
+        let mut targets = Targets::load()?;
+        targets.set(&target(output_dir)?, self);
+        targets.save()
+    }
+
+    pub fn pet(&self, pet_id: u32) -> Option<&PetWatermarks> {
+        self.pets.iter().find(|p| p.pet_id == pet_id)
+    }
+
+    /// Advance the marks past everything in `data`. A trip still under way
+    /// holds the movement mark back, so it is exported once it ends.
+    pub fn update(&mut self, data: &ExportData) {
+        for pet in &data.pets {
+            let marks = match self.pets.iter().position(|p| p.pet_id == pet.id) {
+                Some(i) => &mut self.pets[i],
+                None => {
+                    self.pets.push(PetWatermarks {
+                        pet_id: pet.id,
+                        ..Default::default()
+                    });
+                    self.pets.last_mut().unwrap()
+                }
+            };
+            marks.pet = pet.name.clone();
+
+            let feeding = data
+                .feeding
+                .iter()
+                .filter(|r| r.pet_id == pet.id)
+                .map(|r| r.timestamp)
+                .max();
+            marks.feeding = marks.feeding.max(feeding);
+            let drinking = data
+                .drinking
+                .iter()
+                .filter(|r| r.pet_id == pet.id)
+                .map(|r| r.timestamp)
+                .max();
+            marks.drinking = marks.drinking.max(drinking);
+
+            let trips: Vec<_> = data
+                .movements
+                .iter()
+                .filter(|r| r.pet_id == pet.id)
+                .collect();
+            let still_out = trips
+                .iter()
+                .filter(|r| r.outside_to.is_none())
+                .map(|r| r.outside_from)
+                .min();
+            let finished = trips
+                .iter()
+                .filter(|r| still_out.is_none_or(|out| r.outside_from < out))
+                .map(|r| r.outside_from)
+                .max();
+            marks.movement = marks.movement.max(finished);
+        }
+    }
+}
+
+fn watermarks_path() -> std::io::Result<PathBuf> {
+    Ok(config::config_dir()?.join(WATERMARKS_FILE))
+}
+
+/// The same directory however it's named on the command line.
+fn target(output_dir: &Path) -> std::io::Result<PathBuf> {
+    fs::canonicalize(output_dir).or_else(|_| std::path::absolute(output_dir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marks(feeding: &str) -> Watermarks {
+        Watermarks {
+            pets: vec![PetWatermarks {
+                pet_id: 1,
+                pet: "Tom".to_string(),
+                feeding: Some(feeding.parse().unwrap()),
+                ..Default::default()
+            }],
+        }
+    }
+
+    #[test]
+    fn each_output_dir_keeps_its_own_marks() {
+        let mut targets = Targets::default();
+        targets.set(Path::new("/exports/a"), &marks("2024-03-01T08:00:00Z"));
+        targets.set(Path::new("/exports/b"), &marks("2024-02-01T08:00:00Z"));
+        targets.set(Path::new("/exports/a"), &marks("2024-03-02T08:00:00Z"));
+
+        let targets: Targets = toml::from_str(&toml::to_string_pretty(&targets).unwrap()).unwrap();
+        assert_eq!(targets.targets.len(), 2);
+        assert_eq!(
+            targets.marks(Path::new("/exports/a")).pets,
+            marks("2024-03-02T08:00:00Z").pets
+        );
+        assert_eq!(
+            targets.marks(Path::new("/exports/b")).pets,
+            marks("2024-02-01T08:00:00Z").pets
+        );
+        assert!(targets.marks(Path::new("/exports/c")).pets.is_empty());
+    }
+}
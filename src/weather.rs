@@ -0,0 +1,162 @@
+use crate::config;
+use crate::outings::DayOutside;
+use crate::units::Units;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::io::Error;
+
+/// Open-Meteo only keeps this many past days on the forecast endpoint.
+const MAX_PAST_DAYS: i64 = 92;
+/// A day with at least this much rain counts as rainy.
+const RAINY_MM: f64 = 1.0;
+/// Fewest rainy and dry days each for a comparison to mean anything.
+const MIN_DAYS: usize = 3;
+/// Smallest difference between rainy and dry days worth mentioning.
+const NOTABLE_PERCENT: f64 = 20.0;
+/// Weakest correlation with temperature worth mentioning.
+const NOTABLE_CORRELATION: f64 = 0.5;
+
+/// One day's weather at the [weather] location.
+#[derive(Debug)]
+pub struct DayWeather {
+    pub date: NaiveDate,
+    pub precipitation_mm: f64,
+    pub temperature_max: f64,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    daily: Daily,
+}
+
+#[derive(Deserialize)]
+struct Daily {
+    time: Vec<NaiveDate>,
+    precipitation_sum: Vec<Option<f64>>,
+    temperature_2m_max: Vec<Option<f64>>,
+}
+
+/// Fetch daily rain and highs for the last `days` days and today, in the
+/// [units] timezone so they line up with the pets' days. Days Open-Meteo
+/// has no figures for are left out.
+pub async fn daily(
+    http: &reqwest::Client,
+    cfg: &config::Weather,
+    days: i64,
+    units: &Units,
+) -> std::io::Result<Vec<DayWeather>> {
+    let timezone = units.timezone.map_or("auto", |tz| tz.name());
+    let response: Response = http
+        .get(&cfg.url)
+        .query(&[
+            ("latitude", cfg.latitude.to_string()),
+            ("longitude", cfg.longitude.to_string()),
+            ("daily", "precipitation_sum,temperature_2m_max".to_string()),
+            ("past_days", days.clamp(0, MAX_PAST_DAYS).to_string()),
+            ("forecast_days", "1".to_string()),
+            ("timezone", timezone.to_string()),
+        ])
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| Error::other(e.without_url()))?
+        .json()
+        .await
+        .map_err(|e| Error::other(e.without_url()))?;
+
+    let daily = response.daily;
+    Ok(daily
+        .time
+        .into_iter()
+        .zip(daily.precipitation_sum)
+        .zip(daily.temperature_2m_max)
+        .filter_map(|((date, rain), high)| {
+            Some(DayWeather {
+                date,
+                precipitation_mm: rain?,
+                temperature_max: high?,
+            })
+        })
+        .collect())
+}
+
+/// How `pet`'s time outside goes with the weather, as sentences such as
+/// "Tom's outings drop 70% on rainy days". Today is left out as it isn't
+/// over, and nothing is said without enough days to compare.
+pub fn trends(
+    pet: &str,
+    days: &[DayOutside],
+    weather: &[DayWeather],
+    units: &Units,
+) -> Vec<String> {
+    let past = &days[..days.len().saturating_sub(1)];
+    let paired: Vec<(&DayOutside, &DayWeather)> = past
+        .iter()
+        .filter_map(|day| {
+            let date = units.day_of(&day.start);
+            Some((day, weather.iter().find(|w| w.date == date)?))
+        })
+        .collect();
+
+    let mut trends = Vec::new();
+    let (rainy, dry): (Vec<_>, Vec<_>) = paired
+        .iter()
+        .partition(|(_, w)| w.precipitation_mm >= RAINY_MM);
+    if rainy.len() >= MIN_DAYS && dry.len() >= MIN_DAYS {
+        let average = |days: &[&(&DayOutside, &DayWeather)], value: fn(&DayOutside) -> f64| {
+            days.iter().fold(0.0, |sum, (d, _)| sum + value(d)) / days.len() as f64
+        };
+        let outings = |d: &DayOutside| d.outings as f64;
+        let minutes = |d: &DayOutside| d.minutes as f64;
+        let change = |value| {
+            let (on_rainy, on_dry) = (average(&rainy, value), average(&dry, value));
+            (on_dry > 0.0)
+                .then(|| (on_rainy - on_dry) / on_dry * 100.0)
+                .filter(|change| change.abs() >= NOTABLE_PERCENT)
+        };
+        if let Some(change) = change(outings) {
+            trends.push(format!(
+                "{}'s outings {} {:.0}% on rainy days",
+                pet,
+                if change < 0.0 { "drop" } else { "rise" },
+                change.abs()
+            ));
+        }
+        if let Some(change) = change(minutes) {
+            trends.push(format!(
+                "{} spends {:.0}% {} time outside on rainy days",
+                pet,
+                change.abs(),
+                if change < 0.0 { "less" } else { "more" }
+            ));
+        }
+    }
+
+    let temperatures: Vec<(f64, f64)> = paired
+        .iter()
+        .map(|(d, w)| (w.temperature_max, d.minutes as f64))
+        .collect();
+    if temperatures.len() >= MIN_DAYS * 2 {
+        if let Some(r) = correlation(&temperatures).filter(|r| r.abs() >= NOTABLE_CORRELATION) {
+            trends.push(format!(
+                "{} spends more time outside on {} days (correlation {:.2})",
+                pet,
+                if r > 0.0 { "warmer" } else { "cooler" },
+                r
+            ));
+        }
+    }
+    trends
+}
+
+/// Pearson's correlation, missing when either side doesn't vary.
+fn correlation(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().fold(0.0, |sum, (x, _)| sum + x) / n;
+    let mean_y = points.iter().fold(0.0, |sum, (_, y)| sum + y) / n;
+    let (covariance, var_x, var_y) = points.iter().fold((0.0, 0.0, 0.0), |(c, vx, vy), (x, y)| {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        (c + dx * dy, vx + dx * dx, vy + dy * dy)
+    });
+    (var_x > 0.0 && var_y > 0.0).then(|| covariance / (var_x * var_y).sqrt())
+}